@@ -1,3 +1,5 @@
+use turbocow_core::models::pixel::Pixel;
+
 pub struct DIBHeader {
     pub width: i32,
     pub height: i32,
@@ -9,12 +11,17 @@ pub struct DIBHeader {
     pub green_mask: u32,
     pub blue_mask: u32,
     pub alpha_mask: u32,
+
+    // follows DIB header, only used with 8 or less bits per pixel
+    pub color_table: Vec<Pixel>,
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum Compression {
     Uncompressed,
     Bitfields,
+    Rle8,
+    Rle4,
 }
 
 impl Compression {
@@ -24,6 +31,8 @@ impl Compression {
 
         match self {
             Uncompressed => 0,
+            Rle8 => 1,
+            Rle4 => 2,
             Bitfields => 3, 
         }
     }
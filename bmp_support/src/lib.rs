@@ -8,6 +8,8 @@ use reader::BMPReader;
 use writer::BMPWriter;
 
 mod common;
+mod quantization;
+mod rle;
 pub mod reader;
 pub mod writer;
 
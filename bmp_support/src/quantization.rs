@@ -0,0 +1,62 @@
+use turbocow_core::{models::pixel::Pixel, quantization::{Quantizer, quantize}};
+
+// Colors for the color table: all colors of the image if they fit, otherwise the ones selected by the quantizer.
+// Sorted, so that the same image always gets the same color table.
+pub fn select_colors(pixels: &[Pixel], max_colors: usize, quantizer: Quantizer) -> Vec<Pixel> {
+    let mut colors: Vec<(u8, u8, u8)> = quantize(pixels, max_colors, quantizer).iter()
+        .map(|color| (color.red, color.green, color.blue))
+        .collect();
+    colors.sort();
+    colors.dedup();
+
+    colors.into_iter().map(|(red, green, blue)| Pixel::from_rgb(red, green, blue)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use turbocow_core::quantization::color_indexes;
+
+    fn squared_distance(a: &Pixel, b: &Pixel) -> u32 {
+        (a.red as i32 - b.red as i32).pow(2) as u32
+            + (a.green as i32 - b.green as i32).pow(2) as u32
+            + (a.blue as i32 - b.blue as i32).pow(2) as u32
+    }
+
+    #[test]
+    fn test_select_colors() {
+        let pixels: Vec<Pixel> = (0..64 * 64)
+            .map(|i| Pixel::from_rgb((i % 64 * 4) as u8, (i / 64 * 4) as u8, 128))
+            .collect();
+
+        for max_colors in &[2, 16, 256] {
+            let colors = select_colors(&pixels, *max_colors, Quantizer::MedianCut);
+            assert_eq!(colors.len(), *max_colors);
+            assert_eq!(colors, select_colors(&pixels, *max_colors, Quantizer::MedianCut));
+
+            // closest color is never too far away
+            let indexes = color_indexes(&pixels, &colors);
+            let max_distance = (256.0 / (*max_colors as f64).sqrt()) as u32;
+            for (pixel, index) in pixels.iter().zip(&indexes) {
+                assert!(squared_distance(pixel, &colors[*index as usize]) <= 2 * max_distance * max_distance);
+            }
+        }
+
+        for quantizer in &[Quantizer::Octree, Quantizer::KMeans] {
+            let colors = select_colors(&pixels, 16, *quantizer);
+            assert!(colors.len() <= 16 && colors.len() > 8, "{:?} selected {} colors", quantizer, colors.len());
+            assert_eq!(colors, select_colors(&pixels, 16, *quantizer));
+        }
+    }
+
+    #[test]
+    fn test_select_colors_exact() {
+        let pixels = vec![Pixel::white(), Pixel::black(), Pixel::white(), Pixel::from_rgb(1, 2, 3)];
+        let colors = select_colors(&pixels, 16, Quantizer::MedianCut);
+
+        assert_eq!(colors, vec![Pixel::black(), Pixel::from_rgb(1, 2, 3), Pixel::white()]);
+        assert_eq!(color_indexes(&pixels, &colors), vec![2, 0, 2, 1]);
+    }
+}
@@ -5,7 +5,7 @@ use byteorder::{ByteOrder, LittleEndian};
 
 use turbocow_core::models::{image::Image, io::{ImageIOError, ImageReader}, pixel::Pixel};
 
-use crate::common::{Compression, DIBHeader, offset_to_far_right};
+use crate::{common::{Compression, DIBHeader, offset_to_far_right}, rle::rle_decode};
 
 custom_error! {pub BMPReaderError
     InvalidHeader {description: String} = "Invalid header: {description}",
@@ -79,9 +79,10 @@ fn read_dib_header(header: &[u8]) -> Result<DIBHeader, BMPReaderError> {
     // 0 - 4 bytes - size of this header
     let size_of_header = LittleEndian::read_u32(&header[0..4]);
 
-    if size_of_header != 108 && size_of_header != 124 {
+    // BITMAPINFOHEADER is still common for RLE compressed bitmaps saved by older Windows software
+    if size_of_header != 40 && size_of_header != 108 && size_of_header != 124 {
         return Err(BMPReaderError::InvalidDIBHeader {
-            description: format!("Unexpected length of DIB header: {} (only BMPv4 and BMPv5 are supported)", size_of_header),
+            description: format!("Unexpected length of DIB header: {} (only BITMAPINFOHEADER, BMPv4 and BMPv5 are supported)", size_of_header),
         });
     }
 
@@ -91,7 +92,7 @@ fn read_dib_header(header: &[u8]) -> Result<DIBHeader, BMPReaderError> {
     let _planes = LittleEndian::read_u16(&header[12..14]);
     let bit_count = LittleEndian::read_u16(&header[14..16]);
 
-    if bit_count != 32 && bit_count != 24 && bit_count != 16 {
+    if bit_count != 32 && bit_count != 24 && bit_count != 16 && bit_count != 8 && bit_count != 4 && bit_count != 1 {
         return Err(BMPReaderError::NotImplemented {
             description: format!("this image uses {} bits", bit_count),
         });
@@ -99,13 +100,9 @@ fn read_dib_header(header: &[u8]) -> Result<DIBHeader, BMPReaderError> {
 
     let compression = match LittleEndian::read_u32(&header[16..20]) {
         0x0000 => Compression::Uncompressed,
+        0x0001 => Compression::Rle8,
+        0x0002 => Compression::Rle4,
         0x0003 => Compression::Bitfields,
-        0x0001 => return Err(BMPReaderError::NotImplemented {
-            description: "v4 RLE8".to_string(),
-        }),
-        0x0002 => return Err(BMPReaderError::NotImplemented {
-            description: "v4 RLE4".to_string(),
-        }),
         0x0004 => return Err(BMPReaderError::NotImplemented {
             description: "v4 JPEG".to_string(),
         }),
@@ -129,13 +126,40 @@ fn read_dib_header(header: &[u8]) -> Result<DIBHeader, BMPReaderError> {
     let _xpels_per_meter = LittleEndian::read_i32(&header[24..28]);
     let _ypel_per_meter = LittleEndian::read_i32(&header[28..32]);
 
-    let _clr_used = LittleEndian::read_u32(&header[32..36]);
+    match (compression, bit_count) {
+        (Compression::Rle8, 8) | (Compression::Rle4, 4) => {},
+        (Compression::Rle8, _) | (Compression::Rle4, _) => return Err(BMPReaderError::UnexpectedConfiguration {
+            description: format!("{:?} compression can not be used with {} bits per pixel", compression, bit_count),
+        }),
+        (Compression::Bitfields, 8) | (Compression::Bitfields, 4) | (Compression::Bitfields, 1) => return Err(BMPReaderError::UnexpectedConfiguration {
+            description: format!("bitfields can not be used with {} bits per pixel", bit_count),
+        }),
+        _ => {},
+    }
+
+    let clr_used = LittleEndian::read_u32(&header[32..36]);
     let _crl_important = LittleEndian::read_u32(&header[36..40]);
     
-    let red_mask = LittleEndian::read_u32(&header[40..44]);
-    let green_mask = LittleEndian::read_u32(&header[44..48]);
-    let blue_mask = LittleEndian::read_u32(&header[48..52]);
-    let alpha_mask = LittleEndian::read_u32(&header[52..56]);
+    // BITMAPINFOHEADER is followed by masks when bitfields are used, later versions include them
+    let has_masks = size_of_header >= 56 || compression == Compression::Bitfields;
+    let (red_mask, green_mask, blue_mask) = if has_masks {
+        (
+            LittleEndian::read_u32(&header[40..44]),
+            LittleEndian::read_u32(&header[44..48]),
+            LittleEndian::read_u32(&header[48..52]),
+        )
+    } else {
+        (0, 0, 0)
+    };
+    let alpha_mask = if size_of_header >= 56 { LittleEndian::read_u32(&header[52..56]) } else { 0 };
+
+    let color_table = if bit_count <= 8 {
+        let color_table_offset = if size_of_header == 40 && has_masks { 52 } else { size_of_header as usize };
+        let entries = if clr_used == 0 { 1 << bit_count } else { clr_used as usize };
+        read_color_table(&header[color_table_offset..], entries)?
+    } else {
+        Vec::new()
+    };
 
     Ok(DIBHeader {
         width,
@@ -148,14 +172,73 @@ fn read_dib_header(header: &[u8]) -> Result<DIBHeader, BMPReaderError> {
         green_mask,
         blue_mask,
         alpha_mask,
+
+        color_table,
     })
 }
 
+// every entry is blue, green, red and a reserved byte
+fn read_color_table(data: &[u8], entries: usize) -> Result<Vec<Pixel>, BMPReaderError> {
+    if data.len() < entries * 4 {
+        return Err(BMPReaderError::InvalidDIBHeader {
+            description: format!("Expected color table with {} entries, but there are {} bytes only", entries, data.len()),
+        });
+    }
+
+    Ok(data[..entries * 4].chunks(4)
+        .map(|entry| Pixel::from_rgb(entry[2], entry[1], entry[0]))
+        .collect())
+}
+
 fn read_pixel_array(data: &[u8], dib_header: &DIBHeader) -> Result<Image, BMPReaderError> {
     match dib_header.compression {
-        Compression::Uncompressed => read_pixel_array_uncompressed(&data, &dib_header),
-        Compression::Bitfields => read_pixel_array_bitfields(&data, &dib_header),
+        Compression::Uncompressed if dib_header.bit_count <= 8 => Ok(read_pixel_array_indexed(data, dib_header)),
+        Compression::Uncompressed => read_pixel_array_uncompressed(data, dib_header),
+        Compression::Bitfields => read_pixel_array_bitfields(data, dib_header),
+        Compression::Rle8 | Compression::Rle4 => Ok(read_pixel_array_rle(data, dib_header)),
+    }
+}
+
+// Indexes are packed starting from the most significant bits, rows are padded to 4 bytes.
+// Indexes outside of color table and pixels missing from truncated data are black.
+fn read_pixel_array_indexed(data: &[u8], dib_header: &DIBHeader) -> Image {
+    let (width, height) = (dib_header.width as usize, dib_header.height as usize);
+    let bits_per_pixel = dib_header.bit_count as usize;
+    let row_size = (width * bits_per_pixel).div_ceil(32) * 4;
+    let mask = ((1u16 << bits_per_pixel) - 1) as u8;
+    let mut image = Image::new(width, height);
+
+    for y in 0..height {
+        for x in 0..width {
+            let bit_offset = x * bits_per_pixel;
+            let pixel = data.get(y * row_size + bit_offset / 8)
+                .map(|byte| (byte >> (8 - bits_per_pixel - bit_offset % 8)) & mask)
+                .and_then(|index| dib_header.color_table.get(index as usize).cloned())
+                .unwrap_or(Pixel::black());
+            image.set_pixel_bottom_left_origin(x, y, pixel);
+        }
+    }
+
+    image
+}
+
+// Pixels skipped by RLE escapes are left transparent, like browsers do. Indexes outside of color table are black.
+fn read_pixel_array_rle(data: &[u8], dib_header: &DIBHeader) -> Image {
+    let (width, height) = (dib_header.width as usize, dib_header.height as usize);
+    let mut image = Image::new(width, height);
+    let indexes = rle_decode(data, width, height, dib_header.bit_count);
+
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = match indexes[y * width + x] {
+                Some(index) => dib_header.color_table.get(index as usize).cloned().unwrap_or(Pixel::black()),
+                None => Pixel::from_rgba(0, 0, 0, 0),
+            };
+            image.set_pixel_bottom_left_origin(x, y, pixel);
+        }
     }
+
+    image
 }
 
 fn read_pixel_array_bitfields(data: &[u8], dib_header: &DIBHeader) -> Result<Image, BMPReaderError> {
@@ -310,4 +393,60 @@ mod tests {
         assert_eq!(image.get_pixel(0, 506), Pixel::from_rgba(0, 0, 0, 0));
         assert_eq!(image.get_pixel(1150, 180), Pixel::from_rgba(0, 114, 255, 58));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_read_rle8() {
+        // BITMAPINFOHEADER, pixels are encoded with runs, absolute mode, delta and end of line escapes
+        let data = read("assets/rle8.bmp").expect("failed to read test asset");
+        let image = &BMPReader::new().read(&data).expect("failed to read test image")[0];
+
+        assert_eq!((image.width, image.height), (20, 3));
+        let color = |index: u8| Pixel::from_rgb(index, 255 - index, index.wrapping_mul(2));
+        let transparent = Pixel::from_rgba(0, 0, 0, 0);
+
+        assert_eq!(image.get_pixel(0, 2), color(0x04));
+        assert_eq!(image.get_pixel(3, 2), color(0x06));
+        assert_eq!(image.get_pixel(8, 2), color(0x45));
+        assert_eq!(image.get_pixel(10, 2), color(0x67));
+        assert_eq!(image.get_pixel(12, 2), color(0x78));
+        assert_eq!(image.get_pixel(13, 2), transparent);
+        assert_eq!(image.get_pixel(17, 1), transparent);
+        assert_eq!(image.get_pixel(18, 1), color(0x78));
+        assert_eq!(image.get_pixel(19, 1), color(0x78));
+        assert_eq!(image.get_pixel(8, 0), color(0x1E));
+        assert_eq!(image.get_pixel(9, 0), transparent);
+    }
+
+    #[test]
+    fn test_read_rle4() {
+        let data = read("assets/rle4.bmp").expect("failed to read test asset");
+        let image = &BMPReader::new().read(&data).expect("failed to read test image")[0];
+
+        assert_eq!((image.width, image.height), (28, 3));
+        let color = |index: u8| Pixel::from_rgb(index * 16, 255 - index * 16, index);
+
+        let first_row: Vec<Pixel> = [0, 4, 0, 0, 6, 0, 6, 0, 4, 5, 5, 6, 6, 7, 7, 8, 7, 8].iter().map(|index| color(*index)).collect();
+        assert_eq!(&image.pixels[2 * 28..2 * 28 + 18], &first_row[..]);
+        assert_eq!(image.get_pixel(18, 2), Pixel::from_rgba(0, 0, 0, 0));
+        assert_eq!(image.get_pixel(23, 1), color(7));
+        assert_eq!(image.get_pixel(26, 1), color(8));
+        assert_eq!(image.get_pixel(0, 0), color(1));
+        assert_eq!(image.get_pixel(1, 0), color(0xE));
+        assert_eq!(image.get_pixel(8, 0), color(1));
+    }
+
+    #[test]
+    fn test_read_1_bit() {
+        // BITMAPINFOHEADER, 10x3 checkerboard, rows are padded to 4 bytes
+        let data = read("assets/checkerboard_1_bit.bmp").expect("failed to read test asset");
+        let image = &BMPReader::new().read(&data).expect("failed to read test image")[0];
+
+        assert_eq!((image.width, image.height), (10, 3));
+        let (dark, light) = (Pixel::from_rgb(20, 40, 60), Pixel::from_rgb(250, 240, 230));
+        for y in 0..3 {
+            for x in 0..10 {
+                assert_eq!(image.get_pixel(x, y), if (x + y) % 2 == 0 { dark } else { light }, "pixel {}, {}", x, y);
+            }
+        }
+    }
+}
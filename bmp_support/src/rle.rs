@@ -0,0 +1,237 @@
+// see:
+// https://docs.microsoft.com/en-us/windows/win32/gdi/bitmap-compression
+
+const ESCAPE: u8 = 0;
+const END_OF_LINE: u8 = 0;
+const END_OF_BITMAP: u8 = 1;
+const DELTA: u8 = 2;
+// absolute mode needs at least 3 indexes, smaller values are escapes
+const MIN_ABSOLUTE_LENGTH: usize = 3;
+const MAX_RUN_LENGTH: usize = 255;
+
+// Expands RLE8 (bits_per_index = 8) or RLE4 (bits_per_index = 4) data into color indexes, bottom row first.
+// Pixels skipped with delta or end of line escapes have no index. Data is allowed to end early.
+pub fn rle_decode(data: &[u8], width: usize, height: usize, bits_per_index: u16) -> Vec<Option<u8>> {
+    let mut indexes = vec![None; width * height];
+    let (mut x, mut y) = (0, 0);
+    let mut offset = 0;
+
+    let mut put = |x: usize, y: usize, index: u8| {
+        if x < width && y < height {
+            indexes[y * width + x] = Some(index);
+        }
+    };
+
+    while offset + 1 < data.len() && y < height {
+        let (first, second) = (data[offset], data[offset + 1]);
+        offset += 2;
+
+        if first != ESCAPE {
+            // encoded mode: with 4 bits per index, two indexes of the byte are repeated one after another
+            for i in 0..first as usize {
+                put(x + i, y, nibble_or_byte(second, i, bits_per_index));
+            }
+            x += first as usize;
+            continue;
+        }
+
+        match second {
+            END_OF_LINE => {
+                x = 0;
+                y += 1;
+            },
+            END_OF_BITMAP => break,
+            DELTA => {
+                if offset + 1 >= data.len() {
+                    break;
+                }
+                x += data[offset] as usize;
+                y += data[offset + 1] as usize;
+                offset += 2;
+            },
+            length => {
+                let length = length as usize;
+                let bytes = if bits_per_index == 4 { length.div_ceil(2) } else { length };
+                let absolute = &data[offset..(offset + bytes).min(data.len())];
+
+                for i in 0..length {
+                    let byte = match absolute.get(if bits_per_index == 4 { i / 2 } else { i }) {
+                        Some(byte) => *byte,
+                        None => break,
+                    };
+                    put(x + i, y, nibble_or_byte(byte, i, bits_per_index));
+                }
+
+                x += length;
+                // absolute runs are padded to 16 bits
+                offset += bytes + bytes % 2;
+            },
+        }
+    }
+
+    indexes
+}
+
+fn nibble_or_byte(byte: u8, position: usize, bits_per_index: u16) -> u8 {
+    match (bits_per_index, position % 2) {
+        (4, 0) => byte >> 4,
+        (4, _) => byte & 0x0F,
+        _ => byte,
+    }
+}
+
+// Compresses color indexes, bottom row first, as RLE8 or RLE4. Repeated indexes become runs,
+// everything in between is written in absolute mode.
+pub fn rle_encode(indexes: &[u8], width: usize, height: usize, bits_per_index: u16) -> Vec<u8> {
+    let mut data = Vec::new();
+
+    for y in 0..height {
+        let row = &indexes[y * width..(y + 1) * width];
+        let mut x = 0;
+
+        while x < row.len() {
+            let run = run_length(&row[x..]);
+            if run >= MIN_ABSOLUTE_LENGTH || run == row.len() - x {
+                write_run(&mut data, row[x], run, bits_per_index);
+                x += run;
+                continue;
+            }
+
+            // indexes which are not repeated enough to be worth a run of their own
+            let mut literal = 0;
+            while x + literal < row.len() && literal < MAX_RUN_LENGTH {
+                if run_length(&row[x + literal..]) >= MIN_ABSOLUTE_LENGTH {
+                    break;
+                }
+                literal += 1;
+            }
+            let literal = &row[x..x + literal];
+
+            if literal.len() < MIN_ABSOLUTE_LENGTH {
+                for index in literal {
+                    write_run(&mut data, *index, 1, bits_per_index);
+                }
+            } else {
+                write_absolute(&mut data, literal, bits_per_index);
+            }
+            x += literal.len();
+        }
+
+        data.push(ESCAPE);
+        data.push(if y == height - 1 { END_OF_BITMAP } else { END_OF_LINE });
+    }
+
+    if height == 0 {
+        data.push(ESCAPE);
+        data.push(END_OF_BITMAP);
+    }
+
+    data
+}
+
+fn run_length(indexes: &[u8]) -> usize {
+    indexes.iter()
+        .take(MAX_RUN_LENGTH)
+        .take_while(|index| **index == indexes[0])
+        .count()
+}
+
+fn write_run(data: &mut Vec<u8>, index: u8, length: usize, bits_per_index: u16) {
+    data.push(length as u8);
+    data.push(if bits_per_index == 4 { (index << 4) | index } else { index });
+}
+
+fn write_absolute(data: &mut Vec<u8>, indexes: &[u8], bits_per_index: u16) {
+    data.push(ESCAPE);
+    data.push(indexes.len() as u8);
+
+    let bytes: Vec<u8> = if bits_per_index == 4 {
+        indexes.chunks(2)
+            .map(|pair| (pair[0] << 4) | pair.get(1).cloned().unwrap_or(0))
+            .collect()
+    } else {
+        indexes.to_vec()
+    };
+
+    data.extend_from_slice(&bytes);
+    if bytes.len() % 2 != 0 {
+        data.push(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_rle8_decode() {
+        // example from https://docs.microsoft.com/en-us/windows/win32/gdi/bitmap-compression
+        let data = vec![
+            0x03, 0x04, 0x05, 0x06, 0x00, 0x03, 0x45, 0x56, 0x67, 0x00, 0x02, 0x78, 0x00, 0x02, 0x05, 0x01,
+            0x02, 0x78, 0x00, 0x00, 0x09, 0x1E, 0x00, 0x01,
+        ];
+        let indexes = rle_decode(&data, 20, 3, 8);
+
+        let mut first_row = vec![Some(0x04); 3];
+        first_row.extend_from_slice(&[Some(0x06); 5]);
+        first_row.extend_from_slice(&[Some(0x45), Some(0x56), Some(0x67), Some(0x78), Some(0x78)]);
+        first_row.extend_from_slice(&[None; 7]);
+        assert_eq!(&indexes[0..20], &first_row[..]);
+
+        // delta moved 5 pixels right and one row up
+        assert!(indexes[20..38].iter().all(|index| index.is_none()));
+        assert_eq!(&indexes[38..40], &[Some(0x78), Some(0x78)]);
+
+        assert_eq!(&indexes[40..49], &[Some(0x1E); 9]);
+        assert!(indexes[49..60].iter().all(|index| index.is_none()));
+    }
+
+    #[test]
+    fn test_rle4_decode() {
+        // example from https://docs.microsoft.com/en-us/windows/win32/gdi/bitmap-compression
+        let data = vec![
+            0x03, 0x04, 0x05, 0x06, 0x00, 0x06, 0x45, 0x56, 0x67, 0x00, 0x04, 0x78, 0x00, 0x02, 0x05, 0x01,
+            0x04, 0x78, 0x00, 0x00, 0x09, 0x1E, 0x00, 0x01,
+        ];
+        let indexes: Vec<u8> = rle_decode(&data, 28, 3, 4).iter().map(|index| index.unwrap_or(0xFF)).collect();
+
+        assert_eq!(&indexes[0..18], &[0, 4, 0, 0, 6, 0, 6, 0, 4, 5, 5, 6, 6, 7, 7, 8, 7, 8]);
+        assert!(indexes[18..28].iter().all(|index| *index == 0xFF));
+
+        // delta moved 5 pixels right and one row up
+        assert!(indexes[28..51].iter().all(|index| *index == 0xFF));
+        assert_eq!(&indexes[51..56], &[7, 8, 7, 8, 0xFF]);
+
+        assert_eq!(&indexes[56..65], &[1, 0xE, 1, 0xE, 1, 0xE, 1, 0xE, 1]);
+    }
+
+    #[test]
+    fn test_rle_encode_decode() {
+        let (width, height) = (37, 5);
+        let indexes: Vec<u8> = (0..width * height)
+            .map(|i| if i % width < 12 { 3 } else if i % width < 20 { (i * 7 % 11) as u8 } else { (i / 4 % 3) as u8 })
+            .collect();
+
+        for bits_per_index in &[4, 8] {
+            let data = rle_encode(&indexes, width, height, *bits_per_index);
+            let decoded: Vec<u8> = rle_decode(&data, width, height, *bits_per_index).iter()
+                .map(|index| index.expect("all pixels should be present"))
+                .collect();
+
+            assert_eq!(decoded, indexes);
+            assert_eq!(&data[data.len() - 2..], &[ESCAPE, END_OF_BITMAP]);
+        }
+    }
+
+    #[test]
+    fn test_rle8_encode() {
+        let indexes = vec![1, 1, 1, 1, 2, 3, 4, 5, 5, 6, 6, 6];
+        assert_eq!(rle_encode(&indexes, 12, 1, 8), vec![
+            4, 1, // run
+            0, 5, 2, 3, 4, 5, 5, 0, // absolute, padded
+            3, 6,
+            0, 1,
+        ]);
+    }
+}
@@ -1,11 +1,16 @@
-use turbocow_core::models::{image::Image, io::{ImageIOError, ImageWriter, ImageWriterOptions}, pixel::Pixel};
+use turbocow_core::{models::{image::Image, io::{ImageIOError, ImageWriter, ImageWriterOptions}, pixel::Pixel}, quantization::{DEFAULT_QUANTIZER, Quantizer, color_indexes}};
 
 use byteorder::{LittleEndian, ByteOrder};
 
-use crate::common::{Compression, DIBHeader, offset_to_far_right};
+use crate::{common::{Compression, DIBHeader, offset_to_far_right}, quantization::select_colors, rle::rle_encode};
 
 pub const OPTION_BITS_PER_PIXEL: &str = "bits_per_pixel";
 pub const OPTION_USE_ALPHA_CHANNEL: &str = "alpha_channel";
+pub const OPTION_COMPRESSION: &str = "compression";
+pub const OPTION_QUANTIZER: &str = "quantizer";
+
+pub const COMPRESSION_NONE: &str = "none";
+pub const COMPRESSION_RLE: &str = "rle";
 
 pub const BITFIELDS_16_RED_MASK: u32 = 0b1111100000000000;
 pub const BITFIELDS_16_GREEN_MASK: u32 = 0b11111100000;
@@ -36,11 +41,13 @@ impl ImageWriter for BMPWriter {
 
         let mut output = vec![];
 
-        let mut dib_header_bytes = write_dib_header(&image, &dib_header)?;
+        let mut pixel_array = write_pixel_array(&image, &dib_header)?;
+
+        let mut dib_header_bytes = write_dib_header(&image, &dib_header, pixel_array.len())?;
         let dib_header_size = dib_header_bytes.len() as u32;
         output.append(&mut dib_header_bytes);
         
-        output.append(&mut write_pixel_array(&image, &dib_header)?);
+        output.append(&mut pixel_array);
         
         let mut header = write_header(&output, dib_header_size);
         header.append(&mut output);
@@ -80,6 +87,7 @@ fn make_dib_header(image: &Image, options: &ImageWriterOptions) -> Result<DIBHea
     let bytes_per_pixel = bit_count / 8;
 
     let compression = choose_compression_method(&options)?;
+    let quantizer = Quantizer::from_option(&options.get_string(OPTION_QUANTIZER, DEFAULT_QUANTIZER))?;
 
     let (red_mask, green_mask, blue_mask, alpha_mask) = if compression == Compression::Bitfields {
         if bytes_per_pixel == 2 {
@@ -95,6 +103,13 @@ fn make_dib_header(image: &Image, options: &ImageWriterOptions) -> Result<DIBHea
         (0, 0, 0, 0)
     };
 
+    // images with more colors than fit into color table are quantized
+    let color_table = if bit_count <= 8 {
+        select_colors(&image.pixels, 1 << bit_count, quantizer)
+    } else {
+        Vec::new()
+    };
+
     Ok(DIBHeader {
         width: image.width as i32,
         height: image.height as i32,
@@ -106,10 +121,13 @@ fn make_dib_header(image: &Image, options: &ImageWriterOptions) -> Result<DIBHea
         green_mask,
         blue_mask,
         alpha_mask,
+
+        color_table,
     })
 }
 
-fn write_dib_header(image: &Image, header_data: &DIBHeader) -> Result<Vec<u8>, ImageIOError> {
+// Color table follows DIB header, so it is written together with it.
+fn write_dib_header(image: &Image, header_data: &DIBHeader, pixel_array_size: usize) -> Result<Vec<u8>, ImageIOError> {
     let header_len = 108;
     let mut header = vec![0; header_len];
 
@@ -122,9 +140,15 @@ fn write_dib_header(image: &Image, header_data: &DIBHeader) -> Result<Vec<u8>, I
     LittleEndian::write_u16(&mut header[12..14], 1);
     LittleEndian::write_u16(&mut header[14..16], header_data.bit_count);
     LittleEndian::write_u32(&mut header[16..20], header_data.compression.to_dib_header_value());
-    LittleEndian::write_u32(&mut header[20..24], image.width as u32 * image.height as u32 * bytes_per_pixel as u32); // image_size
+    let image_size = if header_data.bit_count <= 8 {
+        pixel_array_size as u32
+    } else {
+        image.width as u32 * image.height as u32 * bytes_per_pixel as u32
+    };
+    LittleEndian::write_u32(&mut header[20..24], image_size);
     LittleEndian::write_i32(&mut header[24..28], 11811); // xpels_per_meter
     LittleEndian::write_i32(&mut header[28..32], 11811); // ypels_per_meter
+    LittleEndian::write_u32(&mut header[32..36], header_data.color_table.len() as u32); // clr_used
     
     LittleEndian::write_u32(&mut header[40..44], header_data.red_mask);
     LittleEndian::write_u32(&mut header[44..48], header_data.green_mask);
@@ -133,14 +157,54 @@ fn write_dib_header(image: &Image, header_data: &DIBHeader) -> Result<Vec<u8>, I
         LittleEndian::write_u32(&mut header[52..56], BITFIELDS_32_ALPHA_MASK);
     }
 
+    for color in &header_data.color_table {
+        header.extend_from_slice(&[color.blue, color.green, color.red, 0]);
+    }
+
     Ok(header)
 }
 
 fn write_pixel_array(image: &Image, dib_header: &DIBHeader) -> Result<Vec<u8>, ImageIOError> {
     match dib_header.compression {
-        Compression::Uncompressed => Ok(write_pixel_array_uncompressed(&image)),
-        Compression::Bitfields => write_pixel_array_bitfields(&image, &dib_header),
+        Compression::Uncompressed if dib_header.bit_count <= 8 => Ok(write_pixel_array_indexed(image, dib_header)),
+        Compression::Uncompressed => Ok(write_pixel_array_uncompressed(image)),
+        Compression::Bitfields => write_pixel_array_bitfields(image, dib_header),
+        Compression::Rle8 | Compression::Rle4 => Ok(write_pixel_array_rle(image, dib_header)),
+    }
+}
+
+fn write_pixel_array_rle(image: &Image, dib_header: &DIBHeader) -> Vec<u8> {
+    let indexes = color_indexes_bottom_left_origin(image, &dib_header.color_table);
+    rle_encode(&indexes, image.width, image.height, dib_header.bit_count)
+}
+
+// Indexes are packed starting from the most significant bits, rows are padded to 4 bytes.
+fn write_pixel_array_indexed(image: &Image, dib_header: &DIBHeader) -> Vec<u8> {
+    let bits_per_pixel = dib_header.bit_count as usize;
+    let row_size = (image.width * bits_per_pixel).div_ceil(32) * 4;
+    let mut pixel_array = vec![0u8; row_size * image.height];
+
+    let indexes = color_indexes_bottom_left_origin(image, &dib_header.color_table);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let bit_offset = x * bits_per_pixel;
+            let shift = 8 - bits_per_pixel - bit_offset % 8;
+            pixel_array[y * row_size + bit_offset / 8] |= indexes[y * image.width + x] << shift;
+        }
     }
+
+    pixel_array
+}
+
+fn color_indexes_bottom_left_origin(image: &Image, color_table: &[Pixel]) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(image.width * image.height);
+    for y in 0..image.height {
+        for x in 0..image.width {
+            pixels.push(image.get_pixel_bottom_left_origin(x, y));
+        }
+    }
+
+    color_indexes(&pixels, color_table)
 }
 
 fn write_pixel_array_bitfields(image: &Image, dib_header: &DIBHeader) -> Result<Vec<u8>, ImageIOError> {
@@ -221,12 +285,26 @@ fn write_pixel_array_uncompressed(image: &Image) -> Vec<u8> {
 }
 
 fn choose_compression_method(options: &ImageWriterOptions) -> Result<Compression, ImageIOError> {
-    let bytes_per_pixel = choose_bits_per_pixel(&options)? / 8;
-    Ok(match bytes_per_pixel {
-        3 => Compression::Uncompressed,
-        2 | 4 => Compression::Bitfields,
+    let bits_per_pixel = choose_bits_per_pixel(&options)?;
+    let run_length_encoded = match options.get_string(OPTION_COMPRESSION, COMPRESSION_NONE).as_str() {
+        COMPRESSION_NONE => false,
+        COMPRESSION_RLE => true,
         other => return Err(ImageIOError::InvalidOptions {
-            description: format!("Unexpected bytes per pixel: {}. Don't know which compression to use", other),
+            description: format!("Unknown compression: {}, expected one of: {}, {}", other, COMPRESSION_NONE, COMPRESSION_RLE),
+        }),
+    };
+
+    Ok(match (bits_per_pixel, run_length_encoded) {
+        (24, false) => Compression::Uncompressed,
+        (16, false) | (32, false) => Compression::Bitfields,
+        (1, false) | (4, false) | (8, false) => Compression::Uncompressed,
+        (8, true) => Compression::Rle8,
+        (4, true) => Compression::Rle4,
+        (other, true) => return Err(ImageIOError::InvalidOptions {
+            description: format!("{} compression needs 4 or 8 bits per pixel, got: {}", COMPRESSION_RLE, other),
+        }),
+        (other, false) => return Err(ImageIOError::InvalidOptions {
+            description: format!("Unexpected bits per pixel: {}. Don't know which compression to use", other),
         })
     })
 }
@@ -237,6 +315,10 @@ fn choose_bits_per_pixel(options: &ImageWriterOptions) -> Result<u8, ImageIOErro
 
 #[cfg(test)]
 mod tests {
+    use turbocow_core::models::io::ImageReader;
+
+    use crate::reader::BMPReader;
+
     use super::*;
 
     #[test]
@@ -366,4 +448,154 @@ mod tests {
             0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0, 255
         ]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn write_rle() {
+        let test_image = Image::test_image();
+        let writer = BMPWriter::new();
+
+        for bits_per_pixel in &[4, 8] {
+            let options = ImageWriterOptions::default()
+                .with_option_u32(OPTION_BITS_PER_PIXEL, *bits_per_pixel)
+                .with_option(OPTION_COMPRESSION, COMPRESSION_RLE);
+            let data = writer.write(&test_image, &options)
+                .expect("failed to write test image");
+
+            let compression = if *bits_per_pixel == 8 { Compression::Rle8 } else { Compression::Rle4 };
+            assert_eq!(LittleEndian::read_u32(&data[14 + 16..14 + 20]), compression.to_dib_header_value());
+
+            // three colors in the color table, sorted
+            assert_eq!(LittleEndian::read_u32(&data[14 + 32..14 + 36]), 3);
+            assert_eq!(&data[14 + 108..14 + 108 + 12], &[229, 155, 3, 0, 47, 47, 221, 0, 255, 255, 255, 0]);
+            assert_eq!(LittleEndian::read_u32(&data[10..14]), 14 + 108 + 12);
+
+            let image_read = &BMPReader::new().read(&data).expect("failed to read test image")[0];
+            assert_eq!(image_read.pixels, test_image.pixels);
+        }
+    }
+
+    #[test]
+    fn write_rle8_compresses_runs() {
+        let mut image = Image::new(100, 10);
+        image.fill(Pixel::from_rgb(10, 20, 30));
+        image.set_pixel(50, 5, Pixel::from_rgb(200, 100, 0));
+
+        let options = ImageWriterOptions::default()
+            .with_option_u32(OPTION_BITS_PER_PIXEL, 8)
+            .with_option(OPTION_COMPRESSION, COMPRESSION_RLE);
+        let data = BMPWriter::new().write(&image, &options)
+            .expect("failed to write test image");
+
+        // runs of the same color plus end of line for each row, one row has three runs
+        assert_eq!(data.len(), 14 + 108 + 8 + 10 * 4 + 2 * 2);
+        assert_eq!(BMPReader::new().read(&data).expect("failed to read test image")[0].pixels, image.pixels);
+    }
+
+    #[test]
+    fn write_rle_invalid_options() {
+        let writer = BMPWriter::new();
+        let test_image = Image::test_image();
+
+        let options = ImageWriterOptions::default()
+            .with_option(OPTION_COMPRESSION, COMPRESSION_RLE);
+        assert!(writer.write(&test_image, &options).is_err());
+
+        let options = ImageWriterOptions::default()
+            .with_option_u32(OPTION_BITS_PER_PIXEL, 1)
+            .with_option(OPTION_COMPRESSION, COMPRESSION_RLE);
+        assert!(writer.write(&test_image, &options).is_err());
+
+        let options = ImageWriterOptions::default()
+            .with_option(OPTION_COMPRESSION, "zip");
+        assert!(writer.write(&test_image, &options).is_err());
+    }
+
+    #[test]
+    fn write_indexed() {
+        let test_image = Image::test_image();
+        let writer = BMPWriter::new();
+
+        for bits_per_pixel in &[4, 8] {
+            let options = ImageWriterOptions::default()
+                .with_option_u32(OPTION_BITS_PER_PIXEL, *bits_per_pixel);
+            let data = writer.write(&test_image, &options)
+                .expect("failed to write test image");
+
+            assert_eq!(LittleEndian::read_u32(&data[14 + 16..14 + 20]), Compression::Uncompressed.to_dib_header_value());
+            assert_eq!(LittleEndian::read_u32(&data[14 + 32..14 + 36]), 3);
+
+            // four pixels take 4 or 2 bytes, rows are padded to 4 bytes
+            assert_eq!(data.len(), 14 + 108 + 12 + 4 * 4);
+
+            let image_read = &BMPReader::new().read(&data).expect("failed to read test image")[0];
+            assert_eq!(image_read.pixels, test_image.pixels);
+        }
+
+        let options = ImageWriterOptions::default()
+            .with_option_u32(OPTION_BITS_PER_PIXEL, 4);
+        let data = writer.write(&test_image, &options)
+            .expect("failed to write test image");
+        // bottom row first, color table is blue, red, white
+        assert_eq!(&data[14 + 108 + 12..], &[
+            0x22, 0x22, 0, 0,
+            0x20, 0x12, 0, 0,
+            0x20, 0x02, 0, 0,
+            0x22, 0x22, 0, 0,
+        ]);
+    }
+
+    #[test]
+    fn write_indexed_quantized() {
+        let gradient = Image::test_gradient(37, 9);
+        let writer = BMPWriter::new();
+
+        // closest color is picked by weighted color difference, so it may be farther by plain channel values
+        for (bits_per_pixel, max_difference) in &[(1, 130), (4, 48), (8, 8)] {
+            let options = ImageWriterOptions::default()
+                .with_option_u32(OPTION_BITS_PER_PIXEL, *bits_per_pixel);
+            let data = writer.write(&gradient, &options)
+                .expect("failed to write test image");
+
+            assert_eq!(LittleEndian::read_u32(&data[14 + 32..14 + 36]), 1 << bits_per_pixel);
+
+            let image_read = &BMPReader::new().read(&data).expect("failed to read test image")[0];
+            assert_eq!((image_read.width, image_read.height), (37, 9));
+            for (a, b) in image_read.pixels.iter().zip(&gradient.pixels) {
+                let difference = (a.red as i32 - b.red as i32).abs().max((a.green as i32 - b.green as i32).abs());
+                assert!(difference <= *max_difference, "{} bits: {:?} vs {:?}", bits_per_pixel, a, b);
+            }
+        }
+
+        for quantizer in &["octree", "kmeans"] {
+            let options = ImageWriterOptions::default()
+                .with_option_u32(OPTION_BITS_PER_PIXEL, 4)
+                .with_option(OPTION_QUANTIZER, quantizer);
+            let data = writer.write(&gradient, &options)
+                .expect("failed to write test image");
+
+            let image_read = &BMPReader::new().read(&data).expect("failed to read test image")[0];
+            for (a, b) in image_read.pixels.iter().zip(&gradient.pixels) {
+                let difference = (a.red as i32 - b.red as i32).abs().max((a.green as i32 - b.green as i32).abs());
+                assert!(difference <= 48, "{}: {:?} vs {:?}", quantizer, a, b);
+            }
+        }
+
+        let options = ImageWriterOptions::default()
+            .with_option_u32(OPTION_BITS_PER_PIXEL, 4)
+            .with_option(OPTION_QUANTIZER, "popularity");
+        assert!(writer.write(&gradient, &options).is_err());
+
+        // quantized colors can be compressed too
+        let options = ImageWriterOptions::default()
+            .with_option_u32(OPTION_BITS_PER_PIXEL, 4)
+            .with_option(OPTION_COMPRESSION, COMPRESSION_RLE);
+        let rle_data = writer.write(&gradient, &options).expect("failed to write test image");
+        let options = ImageWriterOptions::default()
+            .with_option_u32(OPTION_BITS_PER_PIXEL, 4);
+        let data = writer.write(&gradient, &options).expect("failed to write test image");
+        assert_eq!(
+            BMPReader::new().read(&rle_data).expect("failed to read test image")[0].pixels,
+            BMPReader::new().read(&data).expect("failed to read test image")[0].pixels
+        );
+    }
+}
@@ -8,7 +8,7 @@ use std::path::Path;
 use std::io;
 
 use env_logger::Env;
-use core::{models::io::ImageWriterOptions, plugins::{ImageFormatSupportPlugin, PluginManager}};
+use core::{models::io::{ImageWriterOptions, LosslessTransform}, plugins::{ImageFormatSupportPlugin, PluginManager}};
 
 const DEFAULT_LOGGING_LEVEL: &str = "info";
 const PLUGINS_DIR: &str = "plugins";
@@ -48,6 +48,21 @@ fn main() {
         } else {
             error!("please specify plugins action, for example: download");
         }
+    } else if argument_present(&args, "source") && argument_present(&args, "transform") {
+        let from_file = argument_value(&args, "source")
+            .expect("expected from file to be present because checked that argument is present");
+        let transform_str = argument_value(&args, "transform")
+            .expect("expected transform to be present, because argument is present");
+
+        let transform = match LosslessTransform::from_option(&transform_str) {
+            Ok(v) => v,
+            Err(err) => {
+                error!("{}", err);
+                return;
+            }
+        };
+
+        transform_file(&plugins, &from_file, &transform);
     } else if argument_present(&args, "source") && argument_present(&args, "goal-format") {
         let from_file = argument_value(&args, "source")
             .expect("expected from file to be present because checked that argument is present");
@@ -61,15 +76,95 @@ fn main() {
             writer_options = writer_options.with_option_u32("max_colors", max_colors);
         }
         
+        if let Some(quantizer) = argument_value(&args, "quantizer") {
+            info!("Setting quantizer to: {}", quantizer);
+            writer_options = writer_options.with_option("quantizer", &quantizer);
+        }
+
+        if let Some(dither) = argument_value(&args, "dither") {
+            info!("Setting dithering to: {}", dither);
+            writer_options = writer_options.with_option("dither", &dither);
+        }
+
         if let Some(quality_str) = argument_value(&args, "quality") {
             let quality: u32 = quality_str.parse().expect("Invalid format for quality, expected u32");
             info!("Setting quality to: {}", quality);
             writer_options = writer_options.with_option_u32("quality", quality);
         }
 
+        if let Some(subsampling) = argument_value(&args, "subsampling") {
+            info!("Setting chroma subsampling to: {}", subsampling);
+            writer_options = writer_options.with_option("subsampling", &subsampling);
+        }
+
+        if let Some(optimize_huffman_str) = argument_value(&args, "optimize-huffman") {
+            let optimize_huffman: bool = optimize_huffman_str.parse().expect("Invalid format for optimize-huffman, expected bool");
+            info!("Setting optimize huffman to: {}", optimize_huffman);
+            writer_options = writer_options.with_option_bool("optimize_huffman", optimize_huffman);
+        }
+
+        if let Some(progressive_str) = argument_value(&args, "progressive") {
+            let progressive: bool = progressive_str.parse().expect("Invalid format for progressive, expected bool");
+            info!("Setting progressive to: {}", progressive);
+            writer_options = writer_options.with_option_bool("progressive", progressive);
+        }
+
+        if let Some(restart_interval_str) = argument_value(&args, "restart-interval") {
+            let restart_interval: u32 = restart_interval_str.parse().expect("Invalid format for restart-interval, expected u32");
+            info!("Setting restart interval to: {}", restart_interval);
+            writer_options = writer_options.with_option_u32("restart_interval", restart_interval);
+        }
+
+        if let Some(loop_count_str) = argument_value(&args, "loop-count") {
+            let loop_count: u32 = loop_count_str.parse().expect("Invalid format for loop-count, expected u32");
+            info!("Setting loop count to: {}", loop_count);
+            writer_options = writer_options.with_option_u32("loop_count", loop_count);
+        }
+
+        if let Some(frame_delay_str) = argument_value(&args, "frame-delay") {
+            let frame_delay: u32 = frame_delay_str.parse().expect("Invalid format for frame-delay, expected u32");
+            info!("Setting frame delay to: {}ms", frame_delay);
+            writer_options = writer_options.with_option_u32("frame_delay", frame_delay);
+        }
+
+        if let Some(local_color_tables_str) = argument_value(&args, "local-color-tables") {
+            let local_color_tables: bool = local_color_tables_str.parse().expect("Invalid format for local-color-tables, expected bool");
+            info!("Setting local color tables to: {}", local_color_tables);
+            writer_options = writer_options.with_option_bool("local_color_tables", local_color_tables);
+        }
+
+        if let Some(grayscale_str) = argument_value(&args, "grayscale") {
+            let grayscale: bool = grayscale_str.parse().expect("Invalid format for grayscale, expected bool");
+            info!("Setting grayscale to: {}", grayscale);
+            writer_options = writer_options.with_option_bool("grayscale", grayscale);
+        }
+
+        if let Some(bits_per_pixel_str) = argument_value(&args, "bits-per-pixel") {
+            let bits_per_pixel: u32 = bits_per_pixel_str.parse().expect("Invalid format for bits-per-pixel, expected u32");
+            info!("Setting bits per pixel to: {}", bits_per_pixel);
+            writer_options = writer_options.with_option_u32("bits_per_pixel", bits_per_pixel);
+        }
+
+        if let Some(compression_level_str) = argument_value(&args, "compression-level") {
+            let compression_level: u32 = compression_level_str.parse().expect("Invalid format for compression-level, expected u32");
+            info!("Setting compression level to: {}", compression_level);
+            writer_options = writer_options.with_option_u32("compression_level", compression_level);
+        }
+
+        if let Some(adaptive_filtering_str) = argument_value(&args, "adaptive-filtering") {
+            let adaptive_filtering: bool = adaptive_filtering_str.parse().expect("Invalid format for adaptive-filtering, expected bool");
+            info!("Setting adaptive filtering to: {}", adaptive_filtering);
+            writer_options = writer_options.with_option_bool("adaptive_filtering", adaptive_filtering);
+        }
+
+        if let Some(compression) = argument_value(&args, "compression") {
+            info!("Setting compression to: {}", compression);
+            writer_options = writer_options.with_option("compression", &compression);
+        }
+
         convert_file(&plugins, &from_file, &to_format, &writer_options);
     } else {
-        error!("please specify command:\nconverter --source=example.bmp --goal-format=gif\nconverter plugins install gif_support");
+        error!("please specify command:\nconverter --source=example.bmp --goal-format=gif\nconverter --source=example.jpg --transform=rotate-90\nconverter plugins install gif_support");
     }
 }
 
@@ -119,11 +214,30 @@ fn convert_file(plugins: &Plugins, from_file: &str, to_format: &str, writer_opti
     };
 
     info!("done reading {} image{}", images.len(), if images.len() > 1 { "s" } else { "" });
+
+    let writer = target_plugin.writer();
+    if images.len() > 1 && writer.supports_animation() {
+        info!("Converting animation to {}", &to_format);
+        let converted = match writer.write_animation(&images, writer_options) {
+            Ok(v) => v,
+            Err(err) => {
+                error!("Failed to convert animation to {}: {}", &to_format, err);
+                return;
+            }
+        };
+
+        let save_to = format!("./result_0.{}", to_format);
+        match fs::write(&save_to, &converted) {
+            Ok(_) => info!("Result saved to {}", &save_to),
+            Err(err) => error!("Failed to save result: {}", err),
+        };
+        return;
+    }
     
     let mut counter = 0;
     for image in images {
         info!("Converting image #{} to {}", counter, &to_format);
-        let converted = match target_plugin.writer().write(&image, writer_options) {
+        let converted = match writer.write(&image, writer_options) {
             Ok(v) => v,
             Err(err) => {
                 error!("Failed to convert image to {}: {}", &to_format, err);
@@ -144,6 +258,55 @@ fn convert_file(plugins: &Plugins, from_file: &str, to_format: &str, writer_opti
     }
 }
 
+fn transform_file(plugins: &Plugins, from_file: &str, transform: &LosslessTransform) {
+    info!("Transforming file {} with {:?}", from_file, transform);
+
+    let file = match fs::read(&from_file) {
+        Ok(v) => v,
+        Err(err) => {
+            error!("failed to read {}: {}", &from_file, err);
+            return
+        }
+    };
+
+    let extension = match Path::new(from_file).extension() {
+        Some(v) => v.to_string_lossy().to_lowercase(),
+        None => {
+            error!("failed to detect extension of file {}", from_file);
+            return;
+        }
+    };
+
+    let transformer = match plugins.iter()
+        .find(|v| v.format_name().eq_ignore_ascii_case(&extension)) {
+        Some(v) => match v.transformer() {
+            Some(v) => v,
+            None => {
+                error!("Lossless transforms are not supported for {}", extension);
+                return;
+            }
+        },
+        None => {
+            error!("Failed to find plugin to read {}. Did you install it? Try running \"converter plugins install {}_support\"", extension, extension);
+            return;
+        }
+    };
+
+    let transformed = match transformer.transform(&file, transform) {
+        Ok(v) => v,
+        Err(err) => {
+            error!("Failed to transform image: {}", err);
+            return;
+        }
+    };
+
+    let save_to = format!("./result_0.{}", extension);
+    match fs::write(&save_to, &transformed) {
+        Ok(_) => info!("Result saved to {}", &save_to),
+        Err(err) => error!("Failed to save result: {}", err),
+    };
+}
+
 fn argument_value(args: &Vec<String>, argument_name: &str) -> Option<String> {
     args.iter()
         .find(|s| s.starts_with(&format!("--{}=", argument_name)))
@@ -13,22 +13,8 @@ pub (crate) struct ImageData {
     pub pixels: Vec<Pixel>,
 }
 
-pub fn init_dictionary(dictionary: &mut Vec<Vec<Pixel>>, color_table: &ColorTable) -> (usize, usize) {
-    dictionary.clear();
-    
-    for i in 0..color_table.colors.len() {
-        dictionary.push(vec![color_table.colors[i].clone()]);
-    }
-
-    let clear_index = dictionary.len();
-    dictionary.push(Vec::new());
-
-    let end_index = dictionary.len();
-    dictionary.push(Vec::new());
-
-    (clear_index, end_index)
+// Browsers ignore background color from the header and start with transparent canvas, so do we.
+// GIF has no partial transparency, so it is the only color with alpha written and read.
+pub fn transparent_pixel() -> Pixel {
+    Pixel::from_rgba(0, 0, 0, 0)
 }
-
-pub fn should_increase_code_size(dictionary: &Vec<Vec<Pixel>>, code_size: u8) -> bool {
-    dictionary.len() == 2u32.pow(code_size as u32) as usize && code_size < 12
-}
\ No newline at end of file
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+
+use core::{models::{image::Image, io::ImageIOError, pixel::Pixel}, quantization::nearest_color};
+
+use crate::common::transparent_pixel;
+
+// see:
+// https://en.wikipedia.org/wiki/Floyd%E2%80%93Steinberg_dithering
+// https://en.wikipedia.org/wiki/Atkinson_dithering
+// https://en.wikipedia.org/wiki/Ordered_dithering
+
+// pixels less opaque than this become fully transparent
+pub const ALPHA_THRESHOLD: u8 = 128;
+
+// (dx, dy, weight), weights are divided by the divisor
+const FLOYD_STEINBERG: ([(i32, i32, f32); 4], f32) = ([(1, 0, 7.0), (-1, 1, 3.0), (0, 1, 5.0), (1, 1, 1.0)], 16.0);
+// only 6/8 of the error is spread, which keeps contrast higher
+const ATKINSON: ([(i32, i32, f32); 6], f32) = ([(1, 0, 1.0), (2, 0, 1.0), (-1, 1, 1.0), (0, 1, 1.0), (1, 1, 1.0), (0, 2, 1.0)], 8.0);
+
+const BAYER_MATRIX: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dithering {
+    None,
+    FloydSteinberg,
+    Atkinson,
+    Bayer,
+}
+
+impl Dithering {
+
+    pub fn from_option(value: &str) -> Result<Self, ImageIOError> {
+        match value.trim().to_lowercase().as_str() {
+            "none" => Ok(Dithering::None),
+            "floyd-steinberg" | "floyd_steinberg" => Ok(Dithering::FloydSteinberg),
+            "atkinson" => Ok(Dithering::Atkinson),
+            "bayer" | "ordered" => Ok(Dithering::Bayer),
+            other => Err(ImageIOError::InvalidOptions {
+                description: format!("Unknown dithering: {}, expected one of: none, floyd-steinberg, atkinson, bayer", other),
+            }),
+        }
+    }
+}
+
+pub fn is_transparent(pixel: &Pixel) -> bool {
+    pixel.alpha < ALPHA_THRESHOLD
+}
+
+// Maps every pixel to one of the colors. Transparent pixels become transparent_pixel() and neither
+// receive nor spread quantization error, colors with zero alpha are never picked for opaque ones.
+pub fn dither(image: &Image, colors: &Vec<Pixel>, dithering: Dithering) -> Image {
+    let mut palette = Palette::new(colors);
+
+    match dithering {
+        Dithering::None => map_pixels(image, |_, _, pixel| palette.nearest(pixel)),
+        Dithering::FloydSteinberg => diffuse_error(image, &mut palette, &FLOYD_STEINBERG.0, FLOYD_STEINBERG.1),
+        Dithering::Atkinson => diffuse_error(image, &mut palette, &ATKINSON.0, ATKINSON.1),
+        Dithering::Bayer => {
+            // threshold is spread over the distance between neighbouring colors of an evenly filled palette
+            let spread = 255.0 / (palette.len().max(2) as f32).cbrt();
+            map_pixels(image, |x, y, pixel| {
+                let offset = (BAYER_MATRIX[y % 8][x % 8] as f32 + 0.5) / 64.0 - 0.5;
+                palette.nearest((pixel.0 + offset * spread, pixel.1 + offset * spread, pixel.2 + offset * spread))
+            })
+        },
+    }
+}
+
+fn map_pixels<F>(image: &Image, mut map: F) -> Image where F: FnMut(usize, usize, (f32, f32, f32)) -> Pixel {
+    let mut result = Image::new(image.width, image.height);
+
+    for y in 0..image.height {
+        for x in 0..image.width {
+            let pixel = image.get_pixel(x, y);
+            result.set_pixel(x, y, if is_transparent(&pixel) {
+                transparent_pixel()
+            } else {
+                map(x, y, (pixel.red as f32, pixel.green as f32, pixel.blue as f32))
+            });
+        }
+    }
+
+    result
+}
+
+fn diffuse_error(image: &Image, palette: &mut Palette, weights: &[(i32, i32, f32)], divisor: f32) -> Image {
+    let mut result = Image::new(image.width, image.height);
+    let mut values: Vec<(f32, f32, f32)> = image.pixels.iter()
+        .map(|pixel| (pixel.red as f32, pixel.green as f32, pixel.blue as f32))
+        .collect();
+
+    for y in 0..image.height {
+        for x in 0..image.width {
+            if is_transparent(&image.get_pixel(x, y)) {
+                result.set_pixel(x, y, transparent_pixel());
+                continue;
+            }
+
+            let value = values[y * image.width + x];
+            let color = palette.nearest(value);
+            result.set_pixel(x, y, color);
+
+            let error = (value.0 - color.red as f32, value.1 - color.green as f32, value.2 - color.blue as f32);
+            for (dx, dy, weight) in weights {
+                let (target_x, target_y) = (x as i32 + dx, y as i32 + dy);
+                if target_x < 0 || target_x >= image.width as i32 || target_y >= image.height as i32 {
+                    continue;
+                }
+
+                let target = target_y as usize * image.width + target_x as usize;
+                if is_transparent(&image.pixels[target]) {
+                    continue;
+                }
+
+                let factor = weight / divisor;
+                let target_value = &mut values[target];
+                target_value.0 += error.0 * factor;
+                target_value.1 += error.1 * factor;
+                target_value.2 += error.2 * factor;
+            }
+        }
+    }
+
+    result
+}
+
+// Opaque colors of the color table, with nearest color of each value remembered.
+struct Palette {
+    colors: Vec<Pixel>,
+    nearest: HashMap<(u8, u8, u8), Pixel>,
+}
+
+impl Palette {
+
+    fn new(colors: &Vec<Pixel>) -> Self {
+        Palette {
+            colors: colors.iter().filter(|color| !is_transparent(color)).cloned().collect(),
+            nearest: HashMap::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    fn nearest(&mut self, value: (f32, f32, f32)) -> Pixel {
+        let key = (clamp(value.0), clamp(value.1), clamp(value.2));
+        let colors = &self.colors;
+
+        *self.nearest.entry(key).or_insert_with(|| {
+            nearest_color(colors, (key.0 as f64, key.1 as f64, key.2 as f64))
+                .map(|index| colors[index])
+                .unwrap_or(transparent_pixel())
+        })
+    }
+}
+
+fn clamp(value: f32) -> u8 {
+    value.round().max(0.0).min(255.0) as u8
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // average brightness of each 8x8 tile, as it looks from far away
+    fn tile_error(original: &Image, dithered: &Image) -> f64 {
+        let mut error = 0.0;
+        for tile_y in (0..original.height).step_by(8) {
+            for tile_x in (0..original.width).step_by(8) {
+                let mut difference = 0.0;
+                for y in tile_y..tile_y + 8 {
+                    for x in tile_x..tile_x + 8 {
+                        difference += original.get_pixel(x, y).red as f64 - dithered.get_pixel(x, y).red as f64;
+                    }
+                }
+                error += (difference / 64.0).abs();
+            }
+        }
+        error
+    }
+
+    #[test]
+    fn test_dither() {
+        // shades of gray, so that they are dithered with the gray palette
+        let mut image = Image::test_gradient(64, 16);
+        for pixel in image.pixels.iter_mut() {
+            *pixel = Pixel::from_rgb(pixel.red, pixel.red, pixel.red);
+        }
+        let colors = vec![Pixel::black(), Pixel::from_rgb(128, 128, 128), Pixel::white()];
+
+        let undithered = dither(&image, &colors, Dithering::None);
+        let undithered_error = tile_error(&image, &undithered);
+
+        for dithering in &[Dithering::FloydSteinberg, Dithering::Atkinson, Dithering::Bayer] {
+            let dithered = dither(&image, &colors, *dithering);
+            assert!(dithered.pixels.iter().all(|pixel| colors.contains(pixel)));
+
+            // gradient is kept instead of turning into three bands
+            let error = tile_error(&image, &dithered);
+            assert!(error < undithered_error / 2.0, "{:?}: {} vs {}", dithering, error, undithered_error);
+        }
+    }
+
+    #[test]
+    fn test_dither_transparent() {
+        let image = Image::test_image_with_alpha();
+        let colors = vec![Pixel::from_rgb(3, 155, 229), transparent_pixel(), Pixel::white()];
+
+        for dithering in &[Dithering::None, Dithering::FloydSteinberg, Dithering::Atkinson, Dithering::Bayer] {
+            let dithered = dither(&image, &colors, *dithering);
+            assert_eq!(dithered.get_pixel(0, 0), transparent_pixel());
+            assert_eq!(dithered.get_pixel(1, 2), transparent_pixel());
+            assert_eq!(dithered.get_pixel(2, 2), transparent_pixel());
+            assert_eq!(dithered.get_pixel(1, 1), Pixel::from_rgb(3, 155, 229));
+            assert_eq!(dithered.get_pixel(2, 1), Pixel::from_rgb(3, 155, 229));
+        }
+    }
+
+    #[test]
+    fn test_dithering_from_option() {
+        assert_eq!(Dithering::from_option("floyd-steinberg").unwrap(), Dithering::FloydSteinberg);
+        assert_eq!(Dithering::from_option("atkinson").unwrap(), Dithering::Atkinson);
+        assert_eq!(Dithering::from_option("bayer").unwrap(), Dithering::Bayer);
+        assert_eq!(Dithering::from_option("none").unwrap(), Dithering::None);
+        assert!(Dithering::from_option("random").is_err());
+    }
+}
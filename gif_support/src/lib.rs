@@ -1,5 +1,4 @@
 #![feature(box_syntax)]
-#![feature(destructuring_assignment)]
 
 #[macro_use] 
 extern crate log;
@@ -10,8 +9,10 @@ use core::{plugins::ImageFormatSupportPlugin, models::io::{ImageReader, ImageWri
 use reader::GIFReader;
 use writer::GIFWriter;
 
-mod clustering;
 mod common;
+pub mod dithering;
+mod lzw;
+pub mod quantization;
 pub mod reader;
 pub mod writer;
 
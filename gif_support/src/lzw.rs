@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+
+use custom_error::custom_error;
+
+// see:
+// https://www.w3.org/Graphics/GIF/spec-gif89a.txt (Appendix F)
+// https://giflib.sourceforge.net/whatsinagif/lzw_image_data.html
+
+// codes are at most 12 bits long, dictionary is reset once all of them are used
+pub const MAX_CODE_SIZE: u8 = 12;
+pub const MAX_CODES: usize = 1 << MAX_CODE_SIZE;
+
+custom_error! {pub LZWError
+    InvalidCode {code: u16, next_code: u16} = "Invalid code {code}, next code in dictionary is {next_code}",
+    InvalidMinCodeSize {min_code_size: u8} = "Invalid minimum code size: {min_code_size}"
+}
+
+// Packs codes of variable size, starting from the least significant bit of each byte.
+struct BitWriter {
+    data: Vec<u8>,
+    buffer: u32,
+    bits_in_buffer: u8,
+}
+
+impl BitWriter {
+
+    fn new() -> Self {
+        BitWriter {
+            data: Vec::new(),
+            buffer: 0,
+            bits_in_buffer: 0,
+        }
+    }
+
+    fn write(&mut self, code: u16, code_size: u8) {
+        self.buffer |= (code as u32) << self.bits_in_buffer;
+        self.bits_in_buffer += code_size;
+
+        while self.bits_in_buffer >= 8 {
+            self.data.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits_in_buffer -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits_in_buffer > 0 {
+            self.data.push(self.buffer as u8);
+        }
+
+        self.data
+    }
+}
+
+// Reads codes of variable size from a stream of bytes, starting from the least significant bit of each byte.
+pub struct BitReader<I: Iterator<Item = u8>> {
+    bytes: I,
+    buffer: u32,
+    bits_in_buffer: u8,
+}
+
+impl<I: Iterator<Item = u8>> BitReader<I> {
+
+    pub fn new(bytes: I) -> Self {
+        BitReader {
+            bytes,
+            buffer: 0,
+            bits_in_buffer: 0,
+        }
+    }
+
+    // None once there are not enough bits left for a whole code
+    pub fn read(&mut self, code_size: u8) -> Option<u16> {
+        while self.bits_in_buffer < code_size {
+            let byte = self.bytes.next()?;
+            self.buffer |= (byte as u32) << self.bits_in_buffer;
+            self.bits_in_buffer += 8;
+        }
+
+        let code = (self.buffer & ((1 << code_size) - 1)) as u16;
+        self.buffer >>= code_size;
+        self.bits_in_buffer -= code_size;
+
+        Some(code)
+    }
+}
+
+// Decodes color indexes. Every code is stored as code of its prefix plus the last index, so a string is
+// written out by following prefixes back to a root code. Data which ends without end code is not an error,
+// whatever was decoded until then is returned.
+pub fn lzw_decode<I: Iterator<Item = u8>>(bytes: I, min_code_size: u8, expected_length: usize) -> Result<Vec<u8>, LZWError> {
+    if min_code_size >= MAX_CODE_SIZE {
+        return Err(LZWError::InvalidMinCodeSize { min_code_size });
+    }
+
+    let clear_code = 1u16 << min_code_size;
+    let end_code = clear_code + 1;
+
+    let mut prefixes = [0u16; MAX_CODES];
+    let mut suffixes = [0u8; MAX_CODES];
+    // first index of the string, needed for the code which is not in dictionary yet
+    let mut first_indexes = [0u8; MAX_CODES];
+    for code in 0..clear_code {
+        suffixes[code as usize] = code as u8;
+        first_indexes[code as usize] = code as u8;
+    }
+
+    let mut reader = BitReader::new(bytes);
+    let mut indexes = Vec::with_capacity(expected_length);
+    let mut string: Vec<u8> = Vec::new();
+    let mut next_code = end_code + 1;
+    let mut code_size = min_code_size + 1;
+    let mut prev_code: Option<u16> = None;
+
+    while let Some(code) = reader.read(code_size) {
+        if code == clear_code {
+            next_code = end_code + 1;
+            code_size = min_code_size + 1;
+            prev_code = None;
+            continue;
+        }
+        if code == end_code {
+            break;
+        }
+
+        let first_index = match prev_code {
+            None if code < clear_code => code as u8,
+            Some(_) if code < next_code => first_indexes[code as usize],
+            // string which is being added right now: previous one plus its own first index
+            Some(prev_code) if code == next_code => first_indexes[prev_code as usize],
+            _ => return Err(LZWError::InvalidCode { code, next_code }),
+        };
+
+        if let Some(prev_code) = prev_code {
+            // once dictionary is full, it stays the same until the next clear code
+            if (next_code as usize) < MAX_CODES {
+                prefixes[next_code as usize] = prev_code;
+                suffixes[next_code as usize] = first_index;
+                first_indexes[next_code as usize] = first_indexes[prev_code as usize];
+                next_code += 1;
+
+                if next_code == 1 << code_size && code_size < MAX_CODE_SIZE {
+                    code_size += 1;
+                }
+            }
+        }
+
+        string.clear();
+        let mut current = code;
+        while current > end_code {
+            string.push(suffixes[current as usize]);
+            current = prefixes[current as usize];
+        }
+        string.push(current as u8);
+        indexes.extend(string.iter().rev());
+
+        // the rest of the stream is not needed, and a crafted one could expand into a huge amount of indexes
+        if indexes.len() >= expected_length {
+            indexes.truncate(expected_length);
+            break;
+        }
+
+        prev_code = Some(code);
+    }
+
+    Ok(indexes)
+}
+
+// Encodes color indexes. Every string in the dictionary is some shorter string plus one more index,
+// so it is stored as (prefix code, index) -> code and the longest match is found one index at a time.
+pub fn lzw_encode(indexes: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear_code = 1u16 << min_code_size;
+    let end_code = clear_code + 1;
+
+    let mut writer = BitWriter::new();
+    let mut dictionary: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next_code = end_code + 1;
+    let mut code_size = min_code_size + 1;
+
+    writer.write(clear_code, code_size);
+
+    let mut indexes = indexes.iter();
+    let mut prefix = match indexes.next() {
+        Some(index) => *index as u16,
+        None => {
+            writer.write(end_code, code_size);
+            return writer.finish();
+        }
+    };
+
+    for index in indexes {
+        if let Some(code) = dictionary.get(&(prefix, *index)) {
+            prefix = *code;
+            continue;
+        }
+
+        writer.write(prefix, code_size);
+
+        if (next_code as usize) < MAX_CODES {
+            // decoder adds entries one code later than encoder, so it switches to longer codes right after this one
+            if next_code == 1 << code_size {
+                code_size += 1;
+            }
+
+            dictionary.insert((prefix, *index), next_code);
+            next_code += 1;
+        } else {
+            writer.write(clear_code, code_size);
+            dictionary.clear();
+            next_code = end_code + 1;
+            code_size = min_code_size + 1;
+        }
+
+        prefix = *index as u16;
+    }
+
+    writer.write(prefix, code_size);
+    writer.write(end_code, code_size);
+
+    writer.finish()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_lzw_encode() {
+        // example from https://giflib.sourceforge.net/whatsinagif/lzw_image_data.html
+        let rows: [[u8; 10]; 4] = [
+            [1, 1, 1, 1, 1, 2, 2, 2, 2, 2],
+            [1, 1, 1, 0, 0, 0, 0, 2, 2, 2],
+            [2, 2, 2, 0, 0, 0, 0, 1, 1, 1],
+            [2, 2, 2, 2, 2, 1, 1, 1, 1, 1],
+        ];
+        let indexes: Vec<u8> = [0, 0, 0, 1, 1, 2, 2, 3, 3, 3].iter()
+            .flat_map(|row| rows[*row].iter().cloned())
+            .collect();
+
+        assert_eq!(lzw_encode(&indexes, 2), vec![
+            0x8C, 0x2D, 0x99, 0x87, 0x2A, 0x1C, 0xDC, 0x33, 0xA0, 0x02, 0x75, 0xEC, 0x95, 0xFA, 0xA8, 0xDE,
+            0x60, 0x8C, 0x04, 0x91, 0x4C, 0x01
+        ]);
+    }
+
+    #[test]
+    fn test_lzw_decode() {
+        let rows: [[u8; 10]; 4] = [
+            [1, 1, 1, 1, 1, 2, 2, 2, 2, 2],
+            [1, 1, 1, 0, 0, 0, 0, 2, 2, 2],
+            [2, 2, 2, 0, 0, 0, 0, 1, 1, 1],
+            [2, 2, 2, 2, 2, 1, 1, 1, 1, 1],
+        ];
+        let indexes: Vec<u8> = [0, 0, 0, 1, 1, 2, 2, 3, 3, 3].iter()
+            .flat_map(|row| rows[*row].iter().cloned())
+            .collect();
+
+        let data = vec![
+            0x8C, 0x2D, 0x99, 0x87, 0x2A, 0x1C, 0xDC, 0x33, 0xA0, 0x02, 0x75, 0xEC, 0x95, 0xFA, 0xA8, 0xDE,
+            0x60, 0x8C, 0x04, 0x91, 0x4C, 0x01
+        ];
+        assert_eq!(lzw_decode(data.into_iter(), 2, indexes.len()).unwrap(), indexes);
+    }
+
+    #[test]
+    fn test_lzw_encode_decode() {
+        // long enough to fill the dictionary a few times
+        let indexes: Vec<u8> = (0..100_000u64).map(|i| ((i * i / 7 + i / 13) % 256) as u8).collect();
+        let data = lzw_encode(&indexes, 8);
+        assert_eq!(lzw_decode(data.into_iter(), 8, indexes.len()).unwrap(), indexes);
+
+        let indexes = vec![3; 10_000];
+        let data = lzw_encode(&indexes, 2);
+        assert_eq!(lzw_decode(data.into_iter(), 2, indexes.len()).unwrap(), indexes);
+    }
+
+    #[test]
+    fn test_lzw_decode_stops_at_expected_length() {
+        let data = lzw_encode(&vec![1; 100_000], 2);
+        assert_eq!(lzw_decode(data.into_iter(), 2, 10).unwrap(), vec![1; 10]);
+    }
+
+    #[test]
+    fn test_lzw_decode_invalid_code() {
+        // clear code followed by code 6, which is not in dictionary yet
+        assert!(lzw_decode(vec![0b00110100].into_iter(), 2, 0).is_err());
+        assert!(lzw_decode(vec![0].into_iter(), 12, 0).is_err());
+    }
+
+    #[test]
+    fn test_bit_reader_offsets() {
+        let data: Vec<u8> = vec![
+            0b10000000,
+            0b00000001,
+            0b00000111,
+            0b00011100,
+        ];
+
+        let mut reader = BitReader::new(data.into_iter());
+        assert_eq!(reader.read(9), Some(0b110_000_000));
+        assert_eq!(reader.read(9), Some(0b110_000_000));
+        assert_eq!(reader.read(10), Some(0b110_000_000_1));
+        assert_eq!(reader.read(5), None);
+    }
+
+    #[test]
+    fn test_lzw_encode_empty() {
+        // clear code and end code, 3 bits each
+        assert_eq!(lzw_encode(&[], 2), vec![0b00101100]);
+    }
+}
@@ -0,0 +1,95 @@
+use core::models::pixel::Pixel;
+
+// see: https://en.wikipedia.org/wiki/Peak_signal-to-noise_ratio
+
+// Quality of color reduction in decibels, higher is better. Identical images give infinity.
+// Pixels which became fully transparent are not compared.
+pub fn peak_signal_to_noise_ratio(original: &[Pixel], reduced: &[Pixel]) -> f64 {
+    let compared: Vec<(&Pixel, &Pixel)> = original.iter().zip(reduced).filter(|(_, b)| b.alpha != 0).collect();
+    if compared.is_empty() {
+        return f64::INFINITY;
+    }
+
+    let squared_error: f64 = compared.iter()
+        .map(|(a, b)| {
+            (a.red as f64 - b.red as f64).powi(2)
+                + (a.green as f64 - b.green as f64).powi(2)
+                + (a.blue as f64 - b.blue as f64).powi(2)
+        })
+        .sum();
+    let mean_squared_error = squared_error / (compared.len() * 3) as f64;
+
+    10.0 * (255.0f64.powi(2) / mean_squared_error).log10()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use core::{models::{image::Image, io::ImageReader}, quantization::{Quantizer, cluster, distance, pixel_to_f64s, quantize}};
+    use std::fs::read;
+
+    use crate::{dithering::{Dithering, dither}, reader::GIFReader};
+
+    #[test]
+    fn test_quantizers() {
+        let blob = &GIFReader::new().read(&read("assets/blob.gif").expect("failed to read test image"))
+            .expect("failed to read test image")[3];
+
+        for image in &[Image::test_gradient(64, 64), blob.clone()] {
+            for quantizer in &[Quantizer::MedianCut, Quantizer::Octree, Quantizer::KMeans] {
+                let palette = quantize(&image.pixels, 16, *quantizer);
+                assert!(palette.len() <= 16 && palette.len() > 8, "{:?} selected {} colors", quantizer, palette.len());
+
+                // same image always gets the same palette
+                assert_eq!(palette, quantize(&image.pixels, 16, *quantizer));
+
+                let reduced = dither(&image, &palette, Dithering::None);
+                let quality = peak_signal_to_noise_ratio(&image.pixels, &reduced.pixels);
+                assert!(quality > 20.0, "{:?} quality is {}", quantizer, quality);
+            }
+        }
+    }
+
+    #[test]
+    fn test_quantize_few_colors() {
+        let image = Image::test_image();
+
+        for quantizer in &[Quantizer::MedianCut, Quantizer::Octree, Quantizer::KMeans] {
+            assert_eq!(quantize(&image.pixels, 4, *quantizer), vec![
+                Pixel::from_rgb(3, 155, 229),
+                Pixel::from_rgb(221, 47, 47),
+                Pixel::from_rgb(255, 255, 255),
+            ]);
+        }
+
+        assert_eq!(peak_signal_to_noise_ratio(&image.pixels, &image.pixels), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_quantizer_from_option() {
+        assert_eq!(Quantizer::from_option("median-cut").unwrap(), Quantizer::MedianCut);
+        assert_eq!(Quantizer::from_option("octree").unwrap(), Quantizer::Octree);
+        assert_eq!(Quantizer::from_option("k-means").unwrap(), Quantizer::KMeans);
+        assert!(Quantizer::from_option("popularity").is_err());
+    }
+
+    #[test]
+    fn test_clustering_simple() {
+        let image = &GIFReader::new().read(
+            &read("assets/sunrise.gif").expect("failed to read test image")
+            )
+            .expect("failed to read test image")[0];
+
+        let pixels = image.pixels.clone();
+        let centers = cluster(&pixels, 20, 0, 10, 100);
+
+        let image = dither(&image, &centers, Dithering::None);
+        
+        let sun_color_distance = distance(pixel_to_f64s(&image.get_pixel(590, 278)), (252.0, 161.0, 1.0));
+
+        assert_eq!(centers.len(), 20);
+        assert!(sun_color_distance < 200.0);
+    }
+}
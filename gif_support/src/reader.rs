@@ -1,16 +1,17 @@
 use byteorder::{LittleEndian, ByteOrder};
 use custom_error::custom_error;
-use bit_vec::BitVec;
 
-use core::models::{image::Image, io::{ImageIOError, ImageReader}, pixel::Pixel};
+use core::models::{animation::AnimationFrame, image::Image, io::{ImageIOError, ImageReader}, pixel::Pixel};
+use std::time::Duration;
 
-use crate::common::{ColorTable, ImageData, init_dictionary, should_increase_code_size};
+use crate::{common::{ColorTable, ImageData, transparent_pixel}, lzw::lzw_decode};
 
 // see:
 // https://www.fileformat.info/format/gif/egff.htm
 // https://www.mat.univie.ac.at/~kriegl/Skripten/CG/node47.html
 // https://commandlinefanatic.com/cgi-bin/showarticle.cgi?article=art011
 // http://commandlinefanatic.com/cgi-bin/showarticle.cgi?article=art010
+// https://www.w3.org/Graphics/GIF/spec-gif89a.txt
 
 custom_error! {pub GIFReaderError
     InvalidHeader {description: String} = "Invalid header: {description}",
@@ -26,6 +27,29 @@ struct GIF {
     _header: Header,
     _global_color_table: ColorTable,
     images: Vec<Image>,
+    frames: Vec<GIFFrame>,
+}
+
+// What happens to the canvas area covered by a frame before the next frame is drawn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DisposalMethod {
+    Unspecified,
+    DoNotDispose,
+    RestoreToBackground,
+    RestoreToPrevious,
+}
+
+// Frame as it is stored in the file. Images returned by the reader are the canvas after each frame is drawn onto it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GIFFrame {
+    pub left: usize,
+    pub top: usize,
+    pub width: usize,
+    pub height: usize,
+    pub delay: Duration,
+    pub disposal_method: DisposalMethod,
+    pub transparent_color_index: Option<u8>,
+    pub interlaced: bool,
 }
 
 struct Header {
@@ -41,7 +65,9 @@ struct Header {
 struct GraphicsControlExtension {
 
     size: usize, // size of this block in bytes
-    _delay_time: u16,
+    delay_time: u16, // in hundredths of a second
+    disposal_method: DisposalMethod,
+    transparent_color_index: Option<u8>,
 }
 
 struct CommentExtension {
@@ -58,6 +84,12 @@ struct LocalImageDescriptor {
 
     size: usize, // size of this block in bytes (including separator)
 
+    left: u16,
+    top: u16,
+    width: u16,
+    height: u16,
+    interlaced: bool,
+
     has_local_color_table: bool,
     number_of_local_color_table_entries: u32,
 }
@@ -67,6 +99,15 @@ impl GIFReader {
     pub fn new() -> Self {
         GIFReader {}
     }
+
+    // Same images as read() returns, together with frames they were composited from.
+    pub fn read_frames(&self, data: &Vec<u8>) -> Result<Vec<(Image, GIFFrame)>, ImageIOError> {
+        let gif = read_gif(&data).map_err(|err| ImageIOError::FailedToRead {
+            description: format!("failed to read gif: {}", err)
+        })?;
+
+        Ok(gif.images.into_iter().zip(gif.frames.into_iter()).collect())
+    }
 }
 
 impl ImageReader for GIFReader {
@@ -90,13 +131,22 @@ fn read_gif(data: &Vec<u8>) -> Result<GIF, GIFReaderError> {
     )?;
     let mut data = &data[global_color_table.size..];
 
+    let screen_width = header.screen_width as usize;
+    let screen_height = header.screen_height as usize;
+    let mut canvas = Image::from_pixels(screen_width, screen_height, vec![transparent_pixel(); screen_width * screen_height]);
+
     let mut images = Vec::new();
+    let mut frames = Vec::new();
 
     while data.len() > 1 {
+        // graphics control extension applies to the image which follows it only
+        let mut graphics_control_extension = None;
+
         while data[0] != 0x2C {
             if data[0] == 0x21 && data[1] == 0xF9 {
                 let block = read_graphics_control_extension(&data)?;
                 data = &data[block.size..];
+                graphics_control_extension = Some(block);
             } else if data[0] == 0x21 && data[1] == 0xFE {
                 let block = read_comment_extension(&data)?;
                 data = &data[block.size..];
@@ -113,7 +163,7 @@ fn read_gif(data: &Vec<u8>) -> Result<GIF, GIFReaderError> {
         let local_image_descriptor = read_local_image_descriptor(&data)?;
         data = &data[local_image_descriptor.size..];
     
-        let color_table = if local_image_descriptor.has_local_color_table {
+        let mut color_table = if local_image_descriptor.has_local_color_table {
             let color_table = read_color_table(
                 &data, 
                 local_image_descriptor.number_of_local_color_table_entries
@@ -125,23 +175,113 @@ fn read_gif(data: &Vec<u8>) -> Result<GIF, GIFReaderError> {
             global_color_table.clone()
         };
 
-        let image_data = read_image_data(&data, &color_table)?;
+        let frame = GIFFrame {
+            left: local_image_descriptor.left as usize,
+            top: local_image_descriptor.top as usize,
+            width: local_image_descriptor.width as usize,
+            height: local_image_descriptor.height as usize,
+            delay: Duration::from_millis(graphics_control_extension.as_ref().map(|v| v.delay_time as u64 * 10).unwrap_or(0)),
+            disposal_method: graphics_control_extension.as_ref().map(|v| v.disposal_method).unwrap_or(DisposalMethod::Unspecified),
+            transparent_color_index: graphics_control_extension.as_ref().and_then(|v| v.transparent_color_index),
+            interlaced: local_image_descriptor.interlaced,
+        };
+
+        // transparent pixels are decoded with zero alpha, so that they are easy to skip when drawing the frame
+        if let Some(transparent_color_index) = frame.transparent_color_index {
+            if let Some(color) = color_table.colors.get_mut(transparent_color_index as usize) {
+                *color = color.with_alpha_channel(0);
+            }
+        }
+
+        let image_data = read_image_data(&data, &color_table, frame.width * frame.height)?;
         data = &data[image_data.size..];
 
-        images.push(Image {
-            width: header.screen_width as usize,
-            height: header.screen_height as usize,
-            pixels: image_data.pixels,
-        });
+        let pixels = if frame.interlaced {
+            deinterlace(&image_data.pixels, frame.width, frame.height)
+        } else {
+            image_data.pixels
+        };
+
+        let previous_canvas = if frame.disposal_method == DisposalMethod::RestoreToPrevious {
+            Some(canvas.clone())
+        } else {
+            None
+        };
+
+        draw_frame(&mut canvas, &frame, &pixels);
+        images.push(canvas.clone());
+
+        match frame.disposal_method {
+            DisposalMethod::RestoreToBackground => clear_frame_area(&mut canvas, &frame),
+            DisposalMethod::RestoreToPrevious => if let Some(previous_canvas) = previous_canvas {
+                canvas = previous_canvas;
+            },
+            _ => {},
+        }
+
+        frames.push(frame);
+    }
+
+    if images.len() > 1 {
+        for (image, frame) in images.iter_mut().zip(&frames) {
+            image.animation = Some(AnimationFrame::with_delay(frame.delay));
+        }
     }
 
     Ok(GIF {
         _header: header,
         _global_color_table: global_color_table,
         images,
+        frames,
     })
 }
 
+fn draw_frame(canvas: &mut Image, frame: &GIFFrame, pixels: &[Pixel]) {
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            let (canvas_x, canvas_y) = (frame.left + x, frame.top + y);
+            if canvas_x >= canvas.width || canvas_y >= canvas.height {
+                continue;
+            }
+
+            // data may be truncated, then the rest of the frame is left as is
+            match pixels.get(y * frame.width + x) {
+                Some(pixel) if pixel.alpha != 0 => canvas.set_pixel(canvas_x, canvas_y, *pixel),
+                _ => {},
+            }
+        }
+    }
+}
+
+fn clear_frame_area(canvas: &mut Image, frame: &GIFFrame) {
+    for y in frame.top..(frame.top + frame.height).min(canvas.height) {
+        for x in frame.left..(frame.left + frame.width).min(canvas.width) {
+            canvas.set_pixel(x, y, transparent_pixel());
+        }
+    }
+}
+
+// Interlaced images store every 8th row starting from row 0, then every 8th row starting from row 4,
+// then every 4th row starting from row 2 and finally every 2nd row starting from row 1.
+fn deinterlace(pixels: &[Pixel], width: usize, height: usize) -> Vec<Pixel> {
+    let mut result = vec![transparent_pixel(); width * height];
+
+    let rows = (0..height).step_by(8)
+        .chain((4..height).step_by(8))
+        .chain((2..height).step_by(4))
+        .chain((1..height).step_by(2));
+
+    for (source_row, row) in rows.enumerate() {
+        for x in 0..width {
+            if let Some(pixel) = pixels.get(source_row * width + x) {
+                result[row * width + x] = *pixel;
+            }
+        }
+    }
+
+    result
+}
+
 fn read_header(data: &Vec<u8>) -> Result<Header, GIFReaderError> {
     match &data[0..3] {
         &[0x47, 0x49, 0x46] => {
@@ -219,17 +359,28 @@ fn read_graphics_control_extension(data: &[u8]) -> Result<GraphicsControlExtensi
     let block_size = data[2] as usize;
     let block_length = 2 + 1 + block_size + 1;
 
-    if data[block_size - 1] != 0 {
+    if data[block_length - 1] != 0 {
         return Err(GIFReaderError::InvalidBlock {
             description: format!("unexpect block terminator for graphics control extension block: {:x?}", data[block_length - 1]),
         });
     }
 
+    let packed = data[3];
+    let disposal_method = match (packed & 0b11100) >> 2 {
+        1 => DisposalMethod::DoNotDispose,
+        2 => DisposalMethod::RestoreToBackground,
+        3 => DisposalMethod::RestoreToPrevious,
+        _ => DisposalMethod::Unspecified,
+    };
+    let has_transparent_color = packed & 0b1 == 1;
+
     let delay_time = LittleEndian::read_u16(&data[4..6]);
 
     Ok(GraphicsControlExtension {
         size: block_length,
-        _delay_time: delay_time,
+        delay_time,
+        disposal_method,
+        transparent_color_index: if has_transparent_color { Some(data[6]) } else { None },
     })
 }
 
@@ -270,13 +421,14 @@ fn read_local_image_descriptor(data: &[u8]) -> Result<LocalImageDescriptor, GIFR
         });
     }
 
-    let _left = LittleEndian::read_u16(&data[1..3]);
-    let _top = LittleEndian::read_u16(&data[3..5]);
-    let _width = LittleEndian::read_u16(&data[5..7]);
-    let _height = LittleEndian::read_u16(&data[7..9]);
+    let left = LittleEndian::read_u16(&data[1..3]);
+    let top = LittleEndian::read_u16(&data[3..5]);
+    let width = LittleEndian::read_u16(&data[5..7]);
+    let height = LittleEndian::read_u16(&data[7..9]);
 
     let packed = &data[9];
-    let has_local_color_table = packed & 0b1 == 1;
+    let has_local_color_table = packed & 0b10000000 != 0;
+    let interlaced = packed & 0b01000000 != 0;
     let number_of_local_color_table_entries = if has_local_color_table {
         1 << ((packed & 0b11100000 >> 5) + 1)
     } else {
@@ -285,86 +437,48 @@ fn read_local_image_descriptor(data: &[u8]) -> Result<LocalImageDescriptor, GIFR
 
     Ok(LocalImageDescriptor {
         size: 10,
+        left,
+        top,
+        width,
+        height,
+        interlaced,
         has_local_color_table,
         number_of_local_color_table_entries,
     })
 }
 
-pub(crate) fn read_image_data(data: &[u8], color_table: &ColorTable) -> Result<ImageData, GIFReaderError> {
+pub(crate) fn read_image_data(data: &[u8], color_table: &ColorTable, expected_pixels: usize) -> Result<ImageData, GIFReaderError> {
     let mut size = 1;
 
-    let min_code_size_in_bits = data[0] + 1;
+    let min_code_size = data[0];
     let mut data = &data[1..];
-    let mut compressed_data: Vec<u8> = Vec::new();
+    let mut sub_blocks: Vec<&[u8]> = Vec::new();
 
     while data[0] != 0 {
         let sub_block_size = data[0] as usize;
         size += 1 + sub_block_size;
-        compressed_data.append(&mut data[1..sub_block_size + 1].to_vec());
+        sub_blocks.push(&data[1..sub_block_size + 1]);
         data = &data[sub_block_size + 1..];
     }
 
     size += 1;
 
-    if data[1] != 0x3B && data[1] != 0x21 {
+    // next block is either a trailer, an extension or an image descriptor of the next frame
+    if data.len() > 1 && data[1] != 0x3B && data[1] != 0x21 && data[1] != 0x2C {
         return Err(GIFReaderError::InvalidBlock {
-            description: format!("Expected to get 3B or 21 at the end of compressed data, got instead: {:x?}", data[1]),
+            description: format!("Expected to get 3B, 21 or 2C at the end of compressed data, got instead: {:x?}", data[1]),
         });
     }
 
-    let data = compressed_data;
-    let bits = bit_vec_for_source_bytes(&data);
-
-    let mut pixels: Vec<Pixel> = Vec::new();
-    let mut dictionary: Vec<Vec<Pixel>> = Vec::new(); // index is a key
-    let (mut clear_index, mut end_index) = init_dictionary(&mut dictionary, &color_table);
-    let mut code_size = min_code_size_in_bits;
-    let mut offset = 0;
-    let mut prev_code = None;
-    
-    while offset < bits.len() {
-        let code = read_bits(&bits, offset, code_size) as usize;
-
-        if code == clear_index {
-            (clear_index, end_index) = init_dictionary(&mut dictionary, &color_table);
+    let compressed_data = sub_blocks.iter().flat_map(|sub_block| sub_block.iter().cloned());
+    let indexes = lzw_decode(compressed_data, min_code_size, expected_pixels).map_err(|err| GIFReaderError::InvalidBlock {
+        description: format!("failed to decode image data: {}", err),
+    })?;
 
-            offset += code_size as usize;
-
-            code_size = min_code_size_in_bits;
-            prev_code = None;
-        } else if code == end_index {
-            break;
-        } else {
-            if code != dictionary.len() {
-                let this_code_value = dictionary[code].clone();
-                let first_pixel = this_code_value[0].clone();
-                pixels.append(&mut this_code_value.clone());
-
-                if let Some(prev_code) = prev_code {
-                    let mut prev_code_value = dictionary[prev_code as usize].clone();
-                    prev_code_value.push(first_pixel);
-                    dictionary.push(prev_code_value);
-                }
-            } else if let Some(prev_code) = prev_code {
-                // match to an entry that has just been encoded.
-                let mut prev_code_value = dictionary[prev_code as usize].clone();
-                prev_code_value.push(prev_code_value[0]);
-                dictionary.push(prev_code_value.clone());
-                pixels.append(&mut prev_code_value.clone());
-            } else {
-                return Err(GIFReaderError::InvalidBlock {
-                    description: "Expected prev code to be present when there is a match to entry which has just been encoded".to_string(),
-                })
-            }
-
-            prev_code = Some(code);
-            offset += code_size as usize;
-        }
-
-        if should_increase_code_size(&dictionary, code_size) {
-            code_size += 1;
-        }
-    }
+    // indexes outside of color table are decoded as black
+    let pixels = indexes.iter()
+        .map(|index| color_table.colors.get(*index as usize).cloned().unwrap_or(Pixel::black()))
+        .collect();
 
     Ok(ImageData {
         size,
@@ -372,25 +486,6 @@ pub(crate) fn read_image_data(data: &[u8], color_table: &ColorTable) -> Result<I
     })
 }
 
-fn read_bits(bits: &BitVec, offset: usize, total: u8) -> u16 {
-    let mut result = 0;
-
-    for i in 0..total {
-        result = result << 1;
-        let bit = if bits[offset + (total as usize - 1 - i as usize)] { 1 } else { 0 };
-        result = result | bit;
-    }
-    
-    result
-}
-
-fn bit_vec_for_source_bytes(data: &[u8]) -> BitVec {
-    BitVec::from_fn(data.len() as usize * 8, |x| {
-        let res = (data[x / 8] >> (x % 8)) & 0b1 == 1;
-        res
-    })
-}
-
 #[cfg(test)]
 mod tests {
     use core::models::pixel::Pixel;
@@ -398,29 +493,6 @@ mod tests {
 
     use super::*;
 
-    #[test]
-    fn test_bitvec_offsets() {
-        let data: Vec<u8> = vec![
-            0b10000000,
-            0b00000001,
-            0b00000111,
-            0b00011100,
-        ];
-
-        let bits = bit_vec_for_source_bytes(&data);
-
-        let offset = 0;
-        let code_size = 9;
-        assert_eq!(read_bits(&bits, offset, code_size), 0b110_000_000);
-
-        let offset = offset + code_size as usize;
-        assert_eq!(read_bits(&bits, offset, code_size), 0b110_000_000);
-
-        let offset = offset + code_size as usize;
-        let code_size = code_size + 1;
-        assert_eq!(read_bits(&bits, offset, code_size), 0b110_000_000_1);
-    }
-
     #[test]
     fn test_read_sunrise() {
         let sunrise = read("assets/sunrise.gif").expect("failed to read test asset");
@@ -487,4 +559,83 @@ mod tests {
         assert_eq!(images[14].get_pixel(78, 58), Pixel::from_rgb(243, 194, 28));
         assert_eq!(images[15].get_pixel(78, 58), Pixel::from_rgb(252, 194, 28));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_read_animation_delay() {
+        let blob = read("assets/blob.gif").expect("failed to read test asset");
+        let images = GIFReader::new().read(&blob).expect("failed to read test image");
+
+        assert!(images.iter().all(|image| image.animation == Some(AnimationFrame::with_delay(Duration::from_millis(50)))));
+    }
+
+    #[test]
+    fn test_read_composited() {
+        // 16x16 canvas: red background frame, blue frame with transparent first column which is restored to background,
+        // interlaced frame with a row of each color which is restored to previous, small frame with local color table.
+        let data = read("assets/composite.gif").expect("failed to read test asset");
+        let frames = GIFReader::new().read_frames(&data).expect("failed to read test image");
+        assert_eq!(frames.len(), 4);
+
+        let colors = [
+            Pixel::from_rgb(255, 0, 0),
+            Pixel::from_rgb(0, 0, 255),
+            Pixel::from_rgb(0, 255, 0),
+            Pixel::from_rgb(255, 255, 0),
+            Pixel::from_rgb(0, 255, 255),
+            Pixel::from_rgb(255, 0, 255),
+            Pixel::from_rgb(255, 255, 255),
+            Pixel::from_rgb(0, 0, 0),
+        ];
+        let (red, blue) = (colors[0], colors[1]);
+        let transparent = Pixel::from_rgba(0, 0, 0, 0);
+
+        for (index, (image, _)) in frames.iter().enumerate() {
+            assert_eq!(image.animation, Some(AnimationFrame::with_delay(Duration::from_millis(100 * (index as u64 + 1)))));
+        }
+
+        let (image, frame) = &frames[0];
+        assert_eq!(frame.disposal_method, DisposalMethod::DoNotDispose);
+        assert!(image.pixels.iter().all(|pixel| *pixel == red));
+
+        let (image, frame) = &frames[1];
+        assert_eq!(frame, &GIFFrame {
+            left: 4,
+            top: 4,
+            width: 8,
+            height: 8,
+            delay: Duration::from_millis(200),
+            disposal_method: DisposalMethod::RestoreToBackground,
+            transparent_color_index: Some(7),
+            interlaced: false,
+        });
+        assert_eq!(image.get_pixel(3, 3), red);
+        assert_eq!(image.get_pixel(4, 4), red);
+        assert_eq!(image.get_pixel(4, 11), red);
+        assert_eq!(image.get_pixel(5, 4), blue);
+        assert_eq!(image.get_pixel(11, 11), blue);
+        assert_eq!(image.get_pixel(12, 12), red);
+
+        let (image, frame) = &frames[2];
+        assert_eq!(frame.disposal_method, DisposalMethod::RestoreToPrevious);
+        assert!(frame.interlaced);
+        for y in 0..8 {
+            for x in 0..4 {
+                assert_eq!(image.get_pixel(x, y), colors[y]);
+            }
+        }
+        assert_eq!(image.get_pixel(4, 4), transparent);
+        assert_eq!(image.get_pixel(11, 11), transparent);
+        assert_eq!(image.get_pixel(12, 12), red);
+
+        let (image, frame) = &frames[3];
+        assert_eq!((frame.left, frame.top, frame.width, frame.height), (14, 14, 2, 2));
+        assert_eq!(image.get_pixel(0, 0), red);
+        assert_eq!(image.get_pixel(3, 7), red);
+        assert_eq!(image.get_pixel(5, 5), transparent);
+        assert_eq!(image.get_pixel(13, 13), red);
+        assert_eq!(image.get_pixel(14, 14), Pixel::from_rgb(10, 20, 30));
+        assert_eq!(image.get_pixel(15, 14), Pixel::from_rgb(40, 50, 60));
+        assert_eq!(image.get_pixel(14, 15), Pixel::from_rgb(40, 50, 60));
+        assert_eq!(image.get_pixel(15, 15), Pixel::from_rgb(10, 20, 30));
+    }
+}
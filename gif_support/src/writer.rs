@@ -1,12 +1,27 @@
-use core::models::{image::Image, pixel::Pixel, io::{ImageIOError, ImageWriter, ImageWriterOptions}};
-use std::{time::Duration, cmp::min};
+use core::{models::{image::Image, pixel::Pixel, io::{ImageIOError, ImageWriter, ImageWriterOptions}}, quantization::{DEFAULT_QUANTIZER, Quantizer, count_colors, quantize_counts}};
+use std::{collections::HashMap, time::Duration, cmp::{min, max}, slice};
 
-use bit_vec::BitVec;
 use byteorder::{ByteOrder, LittleEndian};
 
-use crate::{clustering::{cluster, reduce_colors}, common::{ColorTable, init_dictionary, should_increase_code_size}};
+use crate::{common::{ColorTable, transparent_pixel}, dithering::{Dithering, dither, is_transparent}, lzw::lzw_encode, quantization::peak_signal_to_noise_ratio};
 
 pub const OPTION_MAX_COLORS: &str = "max_colors";
+pub const OPTION_QUANTIZER: &str = "quantizer";
+pub const OPTION_DITHER: &str = "dither";
+pub const OPTION_LOOP_COUNT: &str = "loop_count";
+pub const OPTION_FRAME_DELAY: &str = "frame_delay";
+pub const OPTION_LOCAL_COLOR_TABLES: &str = "local_color_tables";
+
+// color table has at least two entries and an index should fit into a byte
+const MIN_COLORS: u32 = 2;
+const MAX_COLORS: u32 = 256;
+const DEFAULT_DITHER: &str = "none";
+// milliseconds, used for animation frames which do not have their own delay
+const DEFAULT_FRAME_DELAY: u32 = 100;
+// see: https://www.w3.org/Graphics/GIF/spec-gif89a.txt (23. Graphic Control Extension)
+const DISPOSAL_DO_NOT_DISPOSE: u8 = 1;
+const DISPOSAL_RESTORE_TO_BACKGROUND: u8 = 2;
+const TRAILER: u8 = 0x3B;
 
 pub struct GIFWriter {
 }
@@ -27,19 +42,132 @@ impl ImageWriter for GIFWriter {
         let mut data = vec![];
         let header = write_header(&image, &global_color_table)?;
         let color_table_data = write_color_table(&global_color_table);
-        let local_image_descriptor = write_local_image_descriptor(&image)?;
+        let local_image_descriptor = write_local_image_descriptor(&image, None)?;
         let image_data = write_image_data(&image, &global_color_table)?;
 
         data.append(&mut header.clone());
         data.append(&mut color_table_data.clone());
+        if let Some(transparent_color_index) = transparent_color_index(&global_color_table) {
+            data.append(&mut write_graphics_control_extension(0, DISPOSAL_DO_NOT_DISPOSE, Some(transparent_color_index)));
+        }
         data.append(&mut local_image_descriptor.clone());
         data.append(&mut image_data.clone());
+        data.push(TRAILER);
+
+        Ok(data)
+    }
+
+    // Every frame is written in full. Frames share global color table, unless local color tables are enabled:
+    // then the first frame uses global one and each of the rest gets its own palette.
+    fn write_animation(&self, frames: &[Image], options: &ImageWriterOptions) -> Result<Vec<u8>, ImageIOError> {
+        if frames.len() < 2 {
+            return match frames.first() {
+                Some(frame) => self.write(frame, options),
+                None => Err(ImageIOError::FailedToWrite {
+                    description: "animation has no frames".to_string(),
+                }),
+            };
+        }
+        let first_frame = &frames[0];
+        if frames.iter().any(|frame| frame.width != first_frame.width || frame.height != first_frame.height) {
+            return Err(ImageIOError::FailedToWrite {
+                description: "all animation frames should have the same size".to_string(),
+            });
+        }
+
+        let max_colors = max_colors_option(options)?;
+        let quantizer = Quantizer::from_option(&options.get_string(OPTION_QUANTIZER, DEFAULT_QUANTIZER))?;
+        let dithering = Dithering::from_option(&options.get_string(OPTION_DITHER, DEFAULT_DITHER))?;
+        let loop_count = options.get_u32(OPTION_LOOP_COUNT, 0)?;
+        let default_frame_delay = options.get_u32(OPTION_FRAME_DELAY, DEFAULT_FRAME_DELAY)?;
+        let local_color_tables = options.get_bool(OPTION_LOCAL_COLOR_TABLES, false)?;
+
+        let global_color_table = if local_color_tables {
+            select_color_table(slice::from_ref(first_frame), max_colors, quantizer)
+        } else {
+            select_color_table(frames, max_colors, quantizer)
+        };
+
+        // frames are written in full, so previous one can stay in place, unless it would show through transparent pixels
+        let disposal_method = if frames.iter().any(|frame| frame.pixels.iter().any(is_transparent)) {
+            DISPOSAL_RESTORE_TO_BACKGROUND
+        } else {
+            DISPOSAL_DO_NOT_DISPOSE
+        };
+
+        let mut data = write_header(&first_frame, &global_color_table)?;
+        data.append(&mut write_color_table(&global_color_table));
+        data.append(&mut write_loop_extension(loop_count));
+
+        for (index, frame) in frames.iter().enumerate() {
+            info!("writing frame #{}", index);
+
+            let local_color_table = if local_color_tables && index > 0 {
+                Some(select_color_table(slice::from_ref(frame), max_colors, quantizer))
+            } else {
+                None
+            };
+            let color_table = local_color_table.as_ref().unwrap_or(&global_color_table);
+            let frame_image = dither(&frame, &color_table.colors, dithering);
+            debug!("frame #{} quality: {:.2} dB", index, peak_signal_to_noise_ratio(&frame.pixels, &frame_image.pixels));
+
+            let delay = frame.animation.as_ref()
+                .map(|animation| animation.delay.as_millis() as u32)
+                .unwrap_or(default_frame_delay);
+
+            data.append(&mut write_graphics_control_extension(delay, disposal_method, transparent_color_index(color_table)));
+            data.append(&mut write_local_image_descriptor(&frame_image, local_color_table.as_ref())?);
+            if let Some(local_color_table) = &local_color_table {
+                data.append(&mut write_color_table(local_color_table));
+            }
+            data.append(&mut write_image_data(&frame_image, &color_table)?);
+        }
+
+        data.push(TRAILER);
 
         Ok(data)
     }
+
+    fn supports_animation(&self) -> bool {
+        true
+    }
+}
+
+// see: http://www.vurdalakov.net/misc/gif/netscape-looping-application-extension
+// loop count of 0 means that animation is repeated forever
+fn write_loop_extension(loop_count: u32) -> Vec<u8> {
+    let mut data = vec![0x21, 0xFF, 0x0B];
+    data.extend_from_slice(b"NETSCAPE2.0");
+    data.push(0x03); // sub-block size
+    data.push(0x01); // sub-block id
+    
+    let mut loop_count_data = [0; 2];
+    LittleEndian::write_u16(&mut loop_count_data, min(loop_count, u16::MAX as u32) as u16);
+    data.extend_from_slice(&loop_count_data);
+
+    data.push(0); // block terminator
+
+    data
+}
+
+fn write_graphics_control_extension(delay: u32, disposal_method: u8, transparent_color_index: Option<u8>) -> Vec<u8> {
+    let mut data: Vec<u8> = vec![0 as u8; 8];
+    data[0] = 0x21;
+    data[1] = 0xF9;
+    data[2] = 0x04; // block size
+
+    data[3] = (disposal_method << 2) | if transparent_color_index.is_some() { 1 } else { 0 };
+
+    // delay is set in hundredths of a second
+    LittleEndian::write_u16(&mut data[4..6], min((delay + 5) / 10, u16::MAX as u32) as u16);
+
+    data[6] = transparent_color_index.unwrap_or(0);
+    // data[7] is block terminator
+
+    data
 }
 
-fn write_local_image_descriptor(image: &Image) -> Result<Vec<u8>, ImageIOError> {
+fn write_local_image_descriptor(image: &Image, local_color_table: Option<&ColorTable>) -> Result<Vec<u8>, ImageIOError> {
     let mut data: Vec<u8> = vec![0 as u8; 10];
     data[0] = 0x2C;
 
@@ -48,7 +176,10 @@ fn write_local_image_descriptor(image: &Image) -> Result<Vec<u8>, ImageIOError>
     LittleEndian::write_u16(&mut data[5..7], image.width as u16);
     LittleEndian::write_u16(&mut data[7..9], image.height as u16);
 
-    let packed = 0; // no local color table, so 0
+    let packed = match local_color_table {
+        Some(color_table) => 0b10000000 | color_table_size_bits(color_table),
+        None => 0,
+    };
     data[9] = packed;
 
     Ok(data)
@@ -72,10 +203,7 @@ fn write_header(image: &Image, global_color_table: &ColorTable) -> Result<Vec<u8
 
     let mut packed: u8 = 0;
     
-    let number_of_global_color_table_entries = global_color_table.colors.len();
-
-    let size_of_global_color_table = (((number_of_global_color_table_entries as f32).log2() as u32) - 1) as u8;
-    packed = packed | size_of_global_color_table;
+    packed = packed | color_table_size_bits(global_color_table);
     packed = packed | (6 << 4); // color resolution
     packed = packed | 0b10000000; // use global color table 
     data[10] = packed;
@@ -86,6 +214,11 @@ fn write_header(image: &Image, global_color_table: &ColorTable) -> Result<Vec<u8
     Ok(data)
 }
 
+// color table has 2^(size + 1) entries
+fn color_table_size_bits(color_table: &ColorTable) -> u8 {
+    (((color_table.colors.len() as f32).log2() as u32) - 1) as u8
+}
+
 fn write_color_table(color_table: &ColorTable) -> Vec<u8> {
     let mut data = Vec::new();
 
@@ -101,50 +234,12 @@ fn write_color_table(color_table: &ColorTable) -> Vec<u8> {
 fn write_image_data(image: &Image, color_table: &ColorTable) -> Result<Vec<u8>, ImageIOError> {
     let mut data = vec![];
 
-    let mut pixels: &[Pixel] = &image.pixels;
-    let mut bits = BitVec::new();
-    let mut dictionary: Vec<Vec<Pixel>> = Vec::new(); // index is a key
-    let (mut clear_code, mut end_code) = init_dictionary(&mut dictionary, &color_table);
-
-    let min_code_size = ((dictionary.len() as f64).log2().floor() + 1.0) as u8;
-    data.push(min_code_size - 1);
-
-    let mut code_size = min_code_size;
-
-    // add clear code first
-    append_bits(&mut bits, clear_code as u16, code_size);
+    // color table has 4 entries at least, so minimum code size is 2 bits or more
+    let min_code_size = (color_table.colors.len() as f32).log2().ceil().max(2.0) as u8;
+    data.push(min_code_size);
 
-    while image.pixels.len() > 0 {
-        let code = find_longest_match(&dictionary, &pixels);
-        append_bits(&mut bits, code as u16, code_size);
-
-        pixels = &pixels[dictionary[code].len()..];
-
-        if pixels.len() == 0 {
-            break;
-        }
-
-        if should_increase_code_size(&dictionary, code_size) {
-            code_size += 1;
-        }
-
-        let mut new_dictionary_entry = dictionary[code].clone();
-        new_dictionary_entry.push(pixels[0].clone());
-        dictionary.push(new_dictionary_entry);
-
-        if code_size == 11 {
-            append_bits(&mut bits, clear_code as u16, code_size);
-            code_size = min_code_size;
-            (clear_code, end_code) = init_dictionary(&mut dictionary, &color_table);
-        }
-    }
-
-    append_bits(&mut bits, end_code as u16, code_size);
-
-    // bits to data
-    let compressed_data: Vec<u8> = bits.to_bytes().iter()
-        .map(|v| mirror_bits(*v))
-        .collect();
+    let indexes = color_indexes(&image, &color_table)?;
+    let compressed_data = lzw_encode(&indexes, min_code_size);
     let mut compressed_data: &[u8] = &compressed_data;
 
     // split compressed data into blocks
@@ -162,98 +257,88 @@ fn write_image_data(image: &Image, color_table: &ColorTable) -> Result<Vec<u8>,
     }
 
     data_blocks.push(0);
-    data_blocks.push(0x3B);
 
     data.append(&mut data_blocks);
 
     Ok(data)
 }
 
-// 0b10000000 -> 0b00000001
-fn mirror_bits(v: u8) -> u8 {
-    let mut v = v;
-    let mut result = 0;
-
-    for _ in 0..8 {
-        result = (result << 1) | (v & 0b1);
-        v = v >> 1;
+// Image is expected to have colors from color table only.
+fn color_indexes(image: &Image, color_table: &ColorTable) -> Result<Vec<u8>, ImageIOError> {
+    let mut index_by_color: HashMap<Pixel, u8> = HashMap::new();
+    for (index, color) in color_table.colors.iter().enumerate().rev() {
+        index_by_color.insert(*color, index as u8);
     }
 
-    result
+    image.pixels.iter()
+        .map(|pixel| index_by_color.get(pixel).cloned().ok_or(ImageIOError::FailedToWrite {
+            description: format!("color {:?} is not present in color table", pixel),
+        }))
+        .collect()
 }
 
-fn append_bits(bits: &mut BitVec, code: u16, code_size: u8) {
-    for i in 0..code_size {
-        bits.push(((code >> i) & 0b1) == 1);
-    }
+fn transparent_color_index(color_table: &ColorTable) -> Option<u8> {
+    color_table.colors.iter().position(|color| *color == transparent_pixel()).map(|index| index as u8)
 }
 
-fn find_longest_match(dictionary: &Vec<Vec<Pixel>>, pixels: &[Pixel]) -> usize {
-    let mut best_match = 0;
-    let mut best_match_length = 0;
+fn adjust_image_with_options(image: &Image, options: &ImageWriterOptions) -> Result<(Image, ColorTable), ImageIOError> {
+    let max_colors = max_colors_option(options)?;
+    let quantizer = Quantizer::from_option(&options.get_string(OPTION_QUANTIZER, DEFAULT_QUANTIZER))?;
+    let dithering = Dithering::from_option(&options.get_string(OPTION_DITHER, DEFAULT_DITHER))?;
+    let color_table = select_color_table(slice::from_ref(image), max_colors, quantizer);
 
-    // TODO: idea to speed things up: start from the end
+    info!("color palette selected, converting image with {:?} dithering...", dithering);
+    let reduced = dither(&image, &color_table.colors, dithering);
+    info!("image converted, quality: {:.2} dB", peak_signal_to_noise_ratio(&image.pixels, &reduced.pixels));
+    let image = reduced;
 
-    for entry_index in 0..dictionary.len() {
-        let entry = &dictionary[entry_index];
+    Ok((image, color_table))
+}
 
-        let match_length = match_length(&entry, &pixels);
-        if match_length > best_match_length {
-            best_match_length = match_length;
-            best_match = entry_index;
-        }
+fn max_colors_option(options: &ImageWriterOptions) -> Result<usize, ImageIOError> {
+    let max_colors = options.get_u32(OPTION_MAX_COLORS, MAX_COLORS)?;
+    if max_colors < MIN_COLORS || max_colors > MAX_COLORS {
+        return Err(ImageIOError::InvalidOptions {
+            description: format!("Max colors should be between {} and {}, got: {}", MIN_COLORS, MAX_COLORS, max_colors),
+        });
     }
 
-    best_match
+    Ok(max_colors as usize)
 }
 
-fn match_length(a: &[Pixel], b: &[Pixel]) -> usize {
-    let mut counter = 0;
+// Colors are counted image by image, so that frames of an animation share a palette without copying their pixels.
+fn select_color_table(images: &[Image], max_colors: usize, quantizer: Quantizer) -> ColorTable {
+    info!("reducing colors to {} with {:?}", max_colors, quantizer);
 
-    for i in 0..min(a.len(), b.len()) {
-        if a[i] == b[i] {
-            counter += 1;
-        } else {
-            break;
-        }
+    // transparent pixels take one entry of color table, the rest is left for opaque ones
+    let mut counts = HashMap::new();
+    for image in images {
+        count_colors(&mut counts, image.pixels.iter().filter(|pixel| !is_transparent(pixel)));
     }
+    let has_transparency = images.iter().any(|image| image.pixels.iter().any(is_transparent));
+    let max_opaque_colors = if has_transparency { max(max_colors, 2) - 1 } else { max_colors };
 
-    counter
-}
+    let mut colors = quantize_counts(&counts, max_opaque_colors, quantizer);
+    if has_transparency {
+        colors.push(transparent_pixel());
+    }
 
-fn adjust_image_with_options(image: &Image, options: &ImageWriterOptions) -> Result<(Image, ColorTable), ImageIOError> {
-    let max_colors = options.get_u32(OPTION_MAX_COLORS, 256)? as usize;
-
-    info!("reducing colors to {}", max_colors);
-    let mut colors = cluster(
-        &image.pixels, 
-        max_colors, 
-        1, 
-        10, 
-        100, 
-        Duration::from_secs(10)
-    );
-
-    let target_size = min(2_i32.pow((colors.len() as f32).log2().ceil() as u32), 256) as usize;
+    // color table size is a power of two. Minimum LZW code size is 2 bits, so that clear code is 4 at least:
+    // with fewer entries it would not follow the color table right away.
+    let target_size = min(2_i32.pow((colors.len() as f32).log2().ceil().max(2.0) as u32), 256) as usize;
     while colors.len() < target_size {
         colors.push(Pixel::black());
     }
 
-    let color_table = ColorTable {
+    ColorTable {
         size: colors.len() * 3,
         colors,
-    };
-    
-    info!("color palette selected, converting image...");
-    let image = reduce_colors(&image, &color_table.colors);
-    info!("image converted");
-
-    Ok((image, color_table))
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use core::models::{image::Image, io::ImageReader};
+    use core::models::{animation::AnimationFrame, image::Image, io::ImageReader};
     use std::fs::read;
 
     use crate::reader::{GIFReader, read_image_data};
@@ -282,7 +367,7 @@ mod tests {
             .expect("failed to write image data");
 
         // try reading it
-        let image_data_read = read_image_data(&image_data, &global_color_table)
+        let image_data_read = read_image_data(&image_data, &global_color_table, image.pixels.len())
             .expect("failed to read image data");
 
         assert_eq!(image_data_read.pixels, image.pixels);
@@ -304,7 +389,7 @@ mod tests {
             .expect("failed to write image data");
 
         // try reading it
-        let image_data_read = read_image_data(&image_data, &global_color_table)
+        let image_data_read = read_image_data(&image_data, &global_color_table, image.pixels.len())
             .expect("failed to read image data");
 
         assert_eq!(image_data_read.pixels, image.pixels);
@@ -335,4 +420,132 @@ mod tests {
         assert_eq!(new_image.height, image.height);
         assert_eq!(new_image.pixels, image.pixels);
     }
+
+    #[test]
+    fn test_write_image_with_transparency() {
+        let image = Image::test_image_with_alpha();
+        let options = ImageWriterOptions::default().with_option(OPTION_DITHER, "floyd-steinberg");
+
+        let data = GIFWriter::new().write(&image, &options)
+            .expect("failed to write test image");
+
+        // transparent color is the one after opaque colors
+        let graphics_control_extension = [0x21, 0xF9, 0x04, 0b101, 0, 0, 1, 0];
+        assert!(data.windows(graphics_control_extension.len()).any(|v| v == graphics_control_extension));
+
+        let image_read = &GIFReader::new().read(&data).expect("failed to read test image")[0];
+        let mut expected = Image::new(4, 4);
+        expected.fill(transparent_pixel());
+        expected.set_pixel(1, 1, Pixel::from_rgb(3, 155, 229));
+        expected.set_pixel(2, 1, Pixel::from_rgb(3, 155, 229));
+        assert_eq!(image_read.pixels, expected.pixels);
+    }
+
+    #[test]
+    fn test_write_image_with_dithering() {
+        let sunrise = &GIFReader::new().read(&read("assets/sunrise.gif").expect("failed to read test asset"))
+            .expect("failed to read test image")[0];
+
+        for dithering in &["none", "floyd-steinberg", "atkinson", "bayer"] {
+            let options = ImageWriterOptions::default()
+                .with_option_u32(OPTION_MAX_COLORS, 16)
+                .with_option(OPTION_QUANTIZER, "kmeans")
+                .with_option(OPTION_DITHER, dithering);
+            let data = GIFWriter::new().write(&sunrise, &options)
+                .expect("failed to write test image");
+
+            let image_read = &GIFReader::new().read(&data).expect("failed to read test image")[0];
+            assert!(peak_signal_to_noise_ratio(&sunrise.pixels, &image_read.pixels) > 20.0);
+        }
+
+        let options = ImageWriterOptions::default().with_option(OPTION_DITHER, "random");
+        assert!(GIFWriter::new().write(&sunrise, &options).is_err());
+    }
+
+    #[test]
+    fn test_write_max_colors() {
+        let test_image = Image::test_image();
+        let writer = GIFWriter::new();
+
+        for max_colors in &[2, 256] {
+            let options = ImageWriterOptions::default().with_option_u32(OPTION_MAX_COLORS, *max_colors);
+            assert!(writer.write(&test_image, &options).is_ok());
+            assert!(writer.write_animation(&[test_image.clone(), test_image.clone()], &options).is_ok());
+        }
+
+        for max_colors in &[0, 1, 257] {
+            let options = ImageWriterOptions::default().with_option_u32(OPTION_MAX_COLORS, *max_colors);
+            assert!(writer.write(&test_image, &options).is_err());
+            assert!(writer.write_animation(&[test_image.clone(), test_image.clone()], &options).is_err());
+        }
+    }
+
+    #[test]
+    fn test_write_read_animation() {
+        let blob = read("assets/blob.gif").expect("failed to read test asset");
+        let frames = GIFReader::new().read(&blob).expect("failed to read test image");
+
+        let options = ImageWriterOptions::default().with_option_u32(OPTION_LOOP_COUNT, 3);
+        let data = GIFWriter::new().write_animation(&frames, &options)
+            .expect("failed to write animation");
+
+        let mut loop_extension = vec![0x21, 0xFF, 0x0B];
+        loop_extension.extend_from_slice(b"NETSCAPE2.0");
+        loop_extension.extend_from_slice(&[0x03, 0x01, 3, 0, 0]);
+        assert_eq!(&data[13 + 256 * 3..13 + 256 * 3 + loop_extension.len()], &loop_extension[..]);
+
+        // delays of frames are preserved
+        let delay = [0x21, 0xF9, 0x04, 0b100, 5, 0, 0, 0];
+        assert_eq!(data.windows(delay.len()).filter(|v| *v == delay).count(), 16);
+
+        // blob has more colors than fit into a palette, so they are only close to original ones
+        let frames_read = GIFReader::new().read(&data).expect("failed to read animation");
+        assert_eq!(frames_read.len(), frames.len());
+        for i in 0..frames.len() {
+            let difference: i64 = frames_read[i].pixels.iter().zip(&frames[i].pixels)
+                .map(|(a, b)| (a.red as i64 - b.red as i64).abs() + (a.green as i64 - b.green as i64).abs() + (a.blue as i64 - b.blue as i64).abs())
+                .sum();
+            let mean_difference = difference / frames[i].pixels.len() as i64;
+            assert!(mean_difference < 20, "frame {} differs by {} on average", i, mean_difference);
+        }
+    }
+
+    #[test]
+    fn test_write_animation_with_local_color_tables() {
+        let first = Image::test_image();
+        let mut second = Image::new(4, 4);
+        second.fill(Pixel::from_rgb(10, 200, 10));
+        second.set_pixel(0, 0, Pixel::from_rgb(200, 10, 200));
+        second.animation = Some(AnimationFrame::with_delay(Duration::from_millis(250)));
+
+        let options = ImageWriterOptions::default()
+            .with_option_u32(OPTION_MAX_COLORS, 4)
+            .with_option_bool(OPTION_LOCAL_COLOR_TABLES, true);
+        let data = GIFWriter::new().write_animation(&[first.clone(), second.clone()], &options)
+            .expect("failed to write animation");
+
+        // frames without delay get the default one
+        let default_delay = [0x21, 0xF9, 0x04, 0b100, 10, 0, 0, 0];
+        assert!(data.windows(default_delay.len()).any(|v| v == default_delay));
+        let delay = [0x21, 0xF9, 0x04, 0b100, 25, 0, 0, 0];
+        assert!(data.windows(delay.len()).any(|v| v == delay));
+
+        // colors of the second frame are not present in the global color table
+        let frames_read = GIFReader::new().read(&data).expect("failed to read animation");
+        assert_eq!(frames_read.len(), 2);
+        assert_eq!(frames_read[0].pixels, first.pixels);
+        assert_eq!(frames_read[1].pixels, second.pixels);
+    }
+
+    #[test]
+    fn test_write_animation_with_single_frame() {
+        let image = Image::test_image();
+        let options = ImageWriterOptions::default();
+        assert_eq!(
+            GIFWriter::new().write_animation(&[image.clone()], &options).unwrap(),
+            GIFWriter::new().write(&image, &options).unwrap()
+        );
+        assert!(GIFWriter::new().write_animation(&[], &options).is_err());
+        assert!(GIFWriter::new().write_animation(&[image, Image::new(2, 2)], &options).is_err());
+    }
 }
@@ -0,0 +1,63 @@
+#![feature(test)]
+
+extern crate test;
+
+use std::fs::read;
+use test::Bencher;
+use core::models::io::{ImageReader, ImageWriter, ImageWriterOptions};
+use jpeg_support::dct::{forward_dct, forward_dct_scales, inverse_dct, inverse_dct_scales};
+use jpeg_support::reader::JPEGReader;
+use jpeg_support::writer::{JPEGWriter, OPTION_PROGRESSIVE};
+
+fn test_block() -> [i32; 64] {
+    let mut block = [0i32; 64];
+    for i in 0..64 {
+        block[i] = ((i * 37 + (i / 8) * 11) % 256) as i32;
+    }
+    block
+}
+
+#[bench]
+fn bench_forward_dct(b: &mut Bencher) {
+    let block = test_block();
+    let scales = forward_dct_scales(&[16; 64]);
+    b.iter(|| forward_dct(test::black_box(&block), &scales));
+}
+
+#[bench]
+fn bench_inverse_dct(b: &mut Bencher) {
+    let coefficients = forward_dct(&test_block(), &forward_dct_scales(&[16; 64]));
+    let scales = inverse_dct_scales(&[16; 64]);
+    b.iter(|| inverse_dct(test::black_box(&coefficients), &scales));
+}
+
+#[bench]
+fn bench_read_bridge(b: &mut Bencher) {
+    let jpeg = read("assets/bridge.jpg").expect("Failed to load assets/bridge.jpg");
+    let reader = JPEGReader::new();
+    b.iter(|| reader.read(&jpeg).unwrap());
+}
+
+#[bench]
+fn bench_read_progressive(b: &mut Bencher) {
+    let jpeg = read("assets/balloon_progressive.jpg").expect("Failed to load assets/balloon_progressive.jpg");
+    let reader = JPEGReader::new();
+    b.iter(|| reader.read(&jpeg).unwrap());
+}
+
+#[bench]
+fn bench_write_balloon(b: &mut Bencher) {
+    let jpeg = read("assets/balloon.jpg").expect("Failed to load assets/balloon.jpg");
+    let image = &JPEGReader::new().read(&jpeg).unwrap()[0];
+    let writer = JPEGWriter::new();
+    b.iter(|| writer.write(&image, &ImageWriterOptions::default()).unwrap());
+}
+
+#[bench]
+fn bench_write_balloon_progressive(b: &mut Bencher) {
+    let jpeg = read("assets/balloon.jpg").expect("Failed to load assets/balloon.jpg");
+    let image = &JPEGReader::new().read(&jpeg).unwrap()[0];
+    let writer = JPEGWriter::new();
+    let options = ImageWriterOptions::default().with_option_bool(OPTION_PROGRESSIVE, true);
+    b.iter(|| writer.write(&image, &options).unwrap());
+}
@@ -6,7 +6,7 @@ use core::models::pixel::Pixel;
 
 use crate::errors::JPEGReaderError;
 
-const ZIGZAG_ORDER: [i32; 64] = [
+pub const ZIGZAG_ORDER: [i32; 64] = [
      0,  1,  8, 16,  9,  2,  3, 10, 
     17, 24, 32, 25, 18, 11,  4,  5, 
     12, 19, 26, 33, 40, 48, 41, 34, 
@@ -91,6 +91,13 @@ pub fn rgb_to_ycbcr(pixel: &Pixel) -> (i32, i32, i32) {
     )
 }
 
+// Adobe applications store CMYK inverted, so 0 means full ink.
+pub fn cmyk_to_rgb(c: i32, m: i32, y: i32, k: i32) -> (u8, u8, u8) {
+    let k = k.max(0).min(255);
+    let convert = |value: i32| (value.max(0).min(255) * k / 255) as u8;
+    (convert(c), convert(m), convert(y))
+}
+
 pub fn ycbcr_to_rgb(y: i32, cb: i32, cr: i32) -> (u8, u8, u8) {
     let r = ((y as f32 + 1.402 * (cr as f32 - 128.0)).round() as i32).max(0).min(255) as u8;
     let g = ((y as f32 - 0.34414 * (cb as f32 - 128.0) - 0.71414 * (cr as f32 - 128.0)).round() as i32).max(0).min(255) as u8;
@@ -118,49 +125,105 @@ pub fn unzigzag_64(matrix: &[i32; 64]) -> [i32; 64] {
     result
 }
 
+// Each MCU contains horizontal_sampling * vertical_sampling blocks of every channel, in the order channels are listed.
+// Returns encoded data of each restart interval, restart_interval = 0 means that restarts are disabled.
 pub fn write_huffman_encoded_channels_data(
-    data: &Vec<[[i32; 64]; 3]>,
+    data: &Vec<Vec<[i32; 64]>>,
+    channels: &[Channel],
     huffman_tables: &HashMap<(HuffmanTableType, ChannelID), HuffmanTable>,
-) -> Vec<u8> {
-    let mut block_data = BitVec::new();
-    let mut prev_dc = [0i32; 3];
+    restart_interval: usize,
+) -> Vec<Vec<u8>> {
+    let mut result = Vec::new();
 
-    for mcu in data {
-        for channel_id in 1..=(mcu.len() as ChannelID) {
-            let channel = mcu[channel_id as usize - 1];
-            let dc_huffman_table = &huffman_tables[&(HuffmanTableType::DC, channel_id)];
-            let ac_huffman_table = &huffman_tables[&(HuffmanTableType::AC, channel_id)];
+    for interval in restart_intervals(data, restart_interval) {
+        let mut block_data = BitVec::new();
+        let mut prev_dc = vec![0i32; channels.len()];
+
+        for mcu in interval {
+            let mut blocks = mcu.iter();
+            for (channel_index, channel) in channels.iter().enumerate() {
+                let dc_huffman_table = &huffman_tables[&(HuffmanTableType::DC, channel.id)];
+                let ac_huffman_table = &huffman_tables[&(HuffmanTableType::AC, channel.id)];
+
+                for _ in 0..(channel.horizontal_sampling * channel.vertical_sampling) {
+                    let block = blocks.next().expect("Expected MCU to contain a block for every sampling unit of every channel");
+                    write_huffman_encoded_matrix(&mut block_data, &mut prev_dc[channel_index], &dc_huffman_table, &ac_huffman_table, block);
+                }
+            }
+        }
 
-            write_huffman_encoded_matrix(&mut block_data, &mut prev_dc, &dc_huffman_table, &ac_huffman_table, channel_id, &channel);
+        while block_data.len() % 8 != 0 {
+            block_data.push(true);
         }
+
+        result.push(block_data.to_bytes());
     }
 
-    while block_data.len() % 8 != 0 {
-        block_data.push(true);
+    result
+}
+
+// DC predictors are reset at the start of each restart interval, so that decoding can continue after a damaged one.
+pub fn restart_intervals<T>(units: &[T], restart_interval: usize) -> std::slice::Chunks<'_, T> {
+    units.chunks(if restart_interval == 0 { units.len().max(1) } else { restart_interval })
+}
+
+// Counts how many times each symbol is encoded with Huffman tables of each channel, so that optimal tables can be built.
+pub fn count_huffman_symbols(
+    data: &Vec<Vec<[i32; 64]>>,
+    channels: &[Channel],
+    restart_interval: usize
+) -> HashMap<(HuffmanTableType, ChannelID), HashMap<u8, usize>> {
+    let mut counts: HashMap<(HuffmanTableType, ChannelID), HashMap<u8, usize>> = HashMap::new();
+
+    for interval in restart_intervals(data, restart_interval) {
+        let mut prev_dc = vec![0i32; channels.len()];
+
+        for mcu in interval {
+            let mut blocks = mcu.iter();
+            for (channel_index, channel) in channels.iter().enumerate() {
+                for _ in 0..(channel.horizontal_sampling * channel.vertical_sampling) {
+                    let block = blocks.next().expect("Expected MCU to contain a block for every sampling unit of every channel");
+                    let factors = matrix_factors(&mut prev_dc[channel_index], block);
+
+                    for (index, (factor, following_zeros)) in factors.into_iter().enumerate() {
+                        let table_type = if index == 0 { HuffmanTableType::DC } else { HuffmanTableType::AC };
+                        *counts.entry((table_type, channel.id)).or_insert_with(HashMap::new)
+                            .entry(factor_symbol(factor, following_zeros)).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
     }
 
-    block_data.to_bytes()
+    counts
 }
 
 fn write_huffman_encoded_matrix(
     block_data: &mut BitVec, 
-    prev_dc: &mut [i32; 3],
+    prev_dc: &mut i32,
     dc_huffman_table: &HuffmanTable,
     ac_huffman_table: &HuffmanTable,
-    channel_id: ChannelID, 
     channel: &[i32; 64]
 ) {
     let dc_huffman_table = dc_huffman_table.vk_table();
     let ac_huffman_table = ac_huffman_table.vk_table();
 
-    //println!("write matrix: {:?}", channel);
+    for (index, (factor, following_zeros)) in matrix_factors(prev_dc, channel).into_iter().enumerate() {
+        let huffman_table = if index == 0 { &dc_huffman_table } else { &ac_huffman_table };
+        write_factor(block_data, huffman_table, factor, following_zeros);
+    }
+}
+
+// Splits matrix into (factor, following_zeros) pairs in the order they are encoded: DC difference first, AC run-lengths after it.
+fn matrix_factors(prev_dc: &mut i32, channel: &[i32; 64]) -> Vec<(i32, u8)> {
+    let mut factors = Vec::new();
     let mut channel = zigzag(&channel);
 
     let prev_value = channel[0];
-    channel[0] = channel[0] - prev_dc[channel_id as usize - 1];
-    prev_dc[channel_id as usize - 1] = prev_value;
+    channel[0] = channel[0] - *prev_dc;
+    *prev_dc = prev_value;
 
-    write_factor(block_data, &dc_huffman_table, channel[0], 0);
+    factors.push((channel[0], 0));
 
     let mut offset = 1;
     while offset < 64 {
@@ -174,7 +237,7 @@ fn write_huffman_encoded_matrix(
         }
 
         if offset + following_zeros == 64 {
-            write_factor(block_data, &ac_huffman_table, 0, 0);
+            factors.push((0, 0));
             break;
         }
 
@@ -183,10 +246,16 @@ fn write_huffman_encoded_matrix(
 
         let ac = channel[offset];
 
-        write_factor(block_data, &ac_huffman_table, ac, following_zeros as u8);
+        factors.push((ac, following_zeros as u8));
 
         offset += 1;
     }
+
+    factors
+}
+
+fn factor_symbol(factor: i32, following_zeros: u8) -> u8 {
+    count_non_zero_digits(factor.abs()) | (following_zeros << 4)
 }
 
 fn write_factor(output_bitvec: &mut BitVec, huffman_table: &HashMap<u8, (u16, u16)>, factor: i32, following_zeros: u8) {
@@ -227,35 +296,32 @@ fn count_non_zero_digits(value: i32) -> u8 {
     result
 }
 
+// Channels are listed in the order they appear in the scan, each MCU contains
+// horizontal_sampling * vertical_sampling blocks of every channel.
 pub fn read_huffman_encoded_channels_data(
     bitvec: &BitVec, 
     total_mcus: usize, 
-    channels: &HashMap<ChannelID, Channel>, 
+    channels: &[Channel],
     huffman_tables_by_channel: &HashMap<(HuffmanTableType, ChannelID), HuffmanTable>,
 ) -> Result<Vec<[i32; 64]>, JPEGReaderError> {
-    let total_channels = channels.len();
-
     let mut result = Vec::new();
-    let mut prev_dc = [0i32; 3];
+    let mut prev_dc = vec![0i32; channels.len()];
     let mut offset = 0;
 
     for _ in 0..total_mcus {
-        for channel_id in 1..=(total_channels as ChannelID) {
-            let channel = &channels[&channel_id];
-
-            let dc_huffman_table = &huffman_tables_by_channel[&(HuffmanTableType::DC, channel_id)];
-            let ac_huffman_table = &huffman_tables_by_channel[&(HuffmanTableType::AC, channel_id)];
+        for (channel_index, channel) in channels.iter().enumerate() {
+            let dc_huffman_table = &huffman_tables_by_channel[&(HuffmanTableType::DC, channel.id)];
+            let ac_huffman_table = &huffman_tables_by_channel[&(HuffmanTableType::AC, channel.id)];
 
             for _ in 0..channel.vertical_sampling {
                 for _ in 0..channel.horizontal_sampling {
                     let (matrix, new_offset) = read_huffman_encoded_matrix(
                         &bitvec, 
                         offset, 
-                        &mut prev_dc, 
-                        channel_id, 
+                        &mut prev_dc[channel_index], 
                         &dc_huffman_table, 
                         &ac_huffman_table
-                    );
+                    )?;
 
                     result.push(matrix);
                     offset = new_offset;
@@ -270,11 +336,10 @@ pub fn read_huffman_encoded_channels_data(
 fn read_huffman_encoded_matrix(
     bitvec: &BitVec, 
     offset: usize, 
-    prev_dc: &mut [i32; 3], 
-    channel_id: ChannelID,
+    prev_dc: &mut i32, 
     dc_huffman_table: &HuffmanTable,
     ac_huffman_table: &HuffmanTable
-) -> ([i32; 64], usize) {
+) -> Result<([i32; 64], usize), JPEGReaderError> {
     let dc_huffman_table = &dc_huffman_table.table;
     let ac_huffman_table = &ac_huffman_table.table;
 
@@ -288,9 +353,14 @@ fn read_huffman_encoded_matrix(
     let mut factor_offset = 0;
 
     while factor_offset < 64 {
-        bitgroup = (bitgroup << 1) | (if bitvec[offset] { 1 } else { 0 });
+        bitgroup = (bitgroup << 1) | (if bit_at(bitvec, offset)? { 1 } else { 0 });
         offset += 1;
         bitgroup_length += 1;
+        if bitgroup_length > 16 {
+            return Err(JPEGReaderError::InvalidEncodedData {
+                description: format!("Invalid Huffman code at bit offset {}", offset),
+            });
+        }
 
         if !dc_factor_read {
             if dc_huffman_table.contains_key(&(bitgroup, bitgroup_length)) {
@@ -305,10 +375,10 @@ fn read_huffman_encoded_matrix(
                     let mut first_bit_is_one = false;
                     for i in 0..value {
                         if i == 0 {
-                            first_bit_is_one = bitvec[offset];
+                            first_bit_is_one = bit_at(bitvec, offset)?;
                         }
                         
-                        factor = (factor << 1) | (if bitvec[offset] { 1 } else { 0 });
+                        factor = (factor << 1) | (if bit_at(bitvec, offset)? { 1 } else { 0 });
                         offset += 1;
                     }
 
@@ -333,15 +403,20 @@ fn read_huffman_encoded_matrix(
                     let number_of_zeros = value >> 4;
                     let factor_length = value & 0b1111;
                     factor_offset += number_of_zeros as usize;
+                    if factor_offset >= 64 {
+                        return Err(JPEGReaderError::InvalidEncodedData {
+                            description: format!("AC coefficient run goes past the end of block at bit offset {}", offset),
+                        });
+                    }
 
                     let mut factor: i32 = 0;
                     let mut first_bit_is_one = false;
                     for i in 0..factor_length {
                         if i == 0 {
-                            first_bit_is_one = bitvec[offset];
+                            first_bit_is_one = bit_at(bitvec, offset)?;
                         }
                         
-                        factor = (factor << 1) | (if bitvec[offset] { 1 } else { 0 });
+                        factor = (factor << 1) | (if bit_at(bitvec, offset)? { 1 } else { 0 });
                         offset += 1;
                     }
 
@@ -360,12 +435,18 @@ fn read_huffman_encoded_matrix(
         }
     }
 
-    factor_vals[0] += prev_dc[channel_id as usize - 1];
-    prev_dc[channel_id as usize - 1] = factor_vals[0];
+    factor_vals[0] += *prev_dc;
+    *prev_dc = factor_vals[0];
     let matrix = unzigzag_64(&factor_vals);
     //println!("read matrix: {:?}", matrix);
 
-    (matrix, offset)
+    Ok((matrix, offset))
+}
+
+fn bit_at(bitvec: &BitVec, offset: usize) -> Result<bool, JPEGReaderError> {
+    bitvec.get(offset).ok_or(JPEGReaderError::InvalidEncodedData {
+        description: "Unexpected end of encoded data".to_string(),
+    })
 }
 
 fn encode_negative(number: i32) -> (i32, u8) {
@@ -384,28 +465,62 @@ fn decode_negative(number: i32, code_length: u8) -> i32 {
     number - 2i32.pow(code_length as u32) + 1
 }
 
-pub fn unescape_image_data(data: &[u8]) -> Result<(Vec<u8>, usize), JPEGReaderError> {
+// Reads entropy-coded data up to the next marker, removing stuffed zero bytes.
+// Returns the data and the offset of the marker which follows it.
+pub fn read_entropy_coded_segment(data: &[u8]) -> (Vec<u8>, usize) {
     let mut new_data = Vec::with_capacity(data.len());
     let mut offset = 0;
     while offset < data.len() {
         let byte = data[offset];
-        if byte == 0xFF {
-            if data[offset + 1] == 0x00 {
-                offset += 1;
-            } else if data[offset + 1] == 0xD9 {
+        if byte == 0xFF && offset + 1 < data.len() {
+            if data[offset + 1] != 0x00 {
                 break;
-            } else {
-                return Err(JPEGReaderError::InvalidEncodedData {
-                    description: format!("Unexpected marker in encoded data: {:x?} {:x?}", data[offset], data[offset + 1])
-                });
             }
+            offset += 1;
         }
         new_data.push(byte);
-
         offset += 1;
     }
 
-    Ok((new_data, offset))
+    (new_data, offset)
+}
+
+// Reads entropy-coded data of a scan, which may be split into restart intervals by RSTn markers.
+// Intervals are placed according to marker numbers, so intervals lost in a damaged file are left empty.
+// Returns the data of each interval and the offset of the marker which follows the scan.
+pub fn read_restart_intervals(data: &[u8]) -> (Vec<Vec<u8>>, usize) {
+    let (interval, mut offset) = read_entropy_coded_segment(data);
+    let mut intervals = vec![interval];
+
+    while offset + 1 < data.len() && data[offset] == 0xFF && (0xD0..=0xD7).contains(&data[offset + 1]) {
+        // marker RSTn follows the interval with index n modulo 8
+        let next_interval = (data[offset + 1] - 0xD0 + 1) as usize % 8;
+        while intervals.len() % 8 != next_interval {
+            intervals.push(Vec::new());
+        }
+        offset += 2;
+
+        let (interval, length) = read_entropy_coded_segment(&data[offset..]);
+        intervals.push(interval);
+        offset += length;
+    }
+
+    (intervals, offset)
+}
+
+// Escapes data of each restart interval and separates intervals with RSTn markers.
+pub fn write_restart_intervals(intervals: &[Vec<u8>]) -> Vec<u8> {
+    let mut result = Vec::new();
+
+    for (index, interval) in intervals.iter().enumerate() {
+        if index > 0 {
+            result.push(0xFF);
+            result.push(0xD0 + ((index - 1) % 8) as u8);
+        }
+        result.append(&mut escape_image_data(interval));
+    }
+
+    result
 }
 
 pub fn escape_image_data(data: &[u8]) -> Vec<u8> {
@@ -475,7 +590,7 @@ mod tests {
             117, 189, 199, 162, 122, 31
         ];
     
-        let (unescaped, _) = unescape_image_data(&test_data_escaped).expect("Failed to unescape test image data");
+        let (unescaped, _) = read_entropy_coded_segment(&test_data_escaped);
 
         let escaped = escape_image_data(&unescaped);
 
@@ -490,7 +605,7 @@ mod tests {
         ];
     
         let mut encoded = BitVec::new();
-        let mut prev_dc = [0i32; 3];
+        let mut prev_dc = 0;
         let dc_huffman_table = HuffmanTable::from_vk(0, HuffmanTableType::DC, &DC_HUFFMAN_Y);
         let ac_huffman_table = HuffmanTable::from_vk(0, HuffmanTableType::AC, &AC_HUFFAN_Y);
 
@@ -499,20 +614,18 @@ mod tests {
             &mut prev_dc, 
             &dc_huffman_table, 
             &ac_huffman_table, 
-            1, 
             &matrix
         );
 
-        let mut prev_dc = [0i32; 3];
+        let mut prev_dc = 0;
 
         let (matrix_decoded, _) = read_huffman_encoded_matrix(
             &encoded, 
             0, 
             &mut prev_dc, 
-            1, 
             &dc_huffman_table, 
             &ac_huffman_table
-        );
+        ).expect("failed to decode matrix");
 
         assert_eq!(matrix_decoded, matrix);
     }
@@ -0,0 +1,286 @@
+use std::f64::consts::PI;
+
+// Arai, Agui and Nakajima scaled DCT: a 1-D transform takes only 5 multiplications, because
+// the remaining per-coefficient scale factors are folded into quantization tables.
+// see:
+// https://github.com/libjpeg-turbo/libjpeg-turbo/blob/main/src/jfdctfst.c
+// https://github.com/libjpeg-turbo/libjpeg-turbo/blob/main/src/jidctfst.c
+// https://github.com/libjpeg-turbo/libjpeg-turbo/blob/main/src/jcdctmgr.c
+
+const CONST_BITS: u32 = 12;
+// extra precision kept in intermediate values
+const FORWARD_PASS_BITS: u32 = 2;
+const INVERSE_PASS_BITS: u32 = 4;
+// precision of scales derived from quantization tables
+const FORWARD_SCALE_BITS: u32 = 24;
+const INVERSE_SCALE_BITS: u32 = 12;
+// dequantized coefficients of 8-bit samples fit into 11 bits, anything larger comes from corrupted data
+const MAX_COEFFICIENT: i64 = 1 << 11;
+
+const FIX_0_382683433: i32 = 1567;
+const FIX_0_541196100: i32 = 2217;
+const FIX_0_707106781: i32 = 2896;
+const FIX_1_082392200: i32 = 4433;
+const FIX_1_306562965: i32 = 5352;
+const FIX_1_414213562: i32 = 5793;
+const FIX_1_847759065: i32 = 7568;
+const FIX_2_613125930: i32 = 10703;
+
+// Reciprocals of quantization table values, combined with scale factors of forward transform.
+pub fn forward_dct_scales(quantization_table: &[i32; 64]) -> [i32; 64] {
+    let mut scales = [0i32; 64];
+
+    for i in 0..64 {
+        let divisor = quantization_table[i] as f64 * aan_scale(i) * 8.0 * (1 << FORWARD_PASS_BITS) as f64;
+        scales[i] = ((1u64 << FORWARD_SCALE_BITS) as f64 / divisor).round() as i32;
+    }
+
+    scales
+}
+
+// Quantization table values, combined with scale factors of inverse transform.
+pub fn inverse_dct_scales(quantization_table: &[i32; 64]) -> [i32; 64] {
+    let mut scales = [0i32; 64];
+
+    for i in 0..64 {
+        scales[i] = (quantization_table[i] as f64 * aan_scale(i) * (1 << INVERSE_SCALE_BITS) as f64).round() as i32;
+    }
+
+    scales
+}
+
+// Takes samples of a block, returns quantized coefficients.
+pub fn forward_dct(block: &[i32; 64], scales: &[i32; 64]) -> [i32; 64] {
+    let mut data = [0i32; 64];
+    for i in 0..64 {
+        data[i] = (block[i] - 128) << FORWARD_PASS_BITS;
+    }
+
+    for row in 0..8 {
+        forward_dct_1d(&mut data, row * 8, 1);
+    }
+    for column in 0..8 {
+        forward_dct_1d(&mut data, column, 8);
+    }
+
+    let mut result = [0i32; 64];
+    for i in 0..64 {
+        let value = data[i] as i64 * scales[i] as i64;
+        let quantized = ((value.abs() + (1 << (FORWARD_SCALE_BITS - 1))) >> FORWARD_SCALE_BITS) as i32;
+        result[i] = if value < 0 { -quantized } else { quantized };
+    }
+
+    result
+}
+
+// Takes quantized coefficients of a block, returns samples.
+pub fn inverse_dct(coefficients: &[i32; 64], scales: &[i32; 64]) -> [i32; 64] {
+    let shift = INVERSE_SCALE_BITS - INVERSE_PASS_BITS;
+    let limit = (MAX_COEFFICIENT * 2) << INVERSE_PASS_BITS;
+
+    let mut data = [0i32; 64];
+    for i in 0..64 {
+        if coefficients[i] != 0 {
+            let value = (coefficients[i] as i64 * scales[i] as i64 + (1 << (shift - 1))) >> shift;
+            data[i] = value.max(-limit).min(limit) as i32;
+        }
+    }
+
+    for column in 0..8 {
+        // most of the columns have no AC coefficients at all
+        if (1..8).all(|row| data[row * 8 + column] == 0) {
+            for row in 1..8 {
+                data[row * 8 + column] = data[column];
+            }
+            continue;
+        }
+
+        inverse_dct_1d(&mut data, column, 8);
+    }
+    for row in 0..8 {
+        inverse_dct_1d(&mut data, row * 8, 1);
+    }
+
+    let mut result = [0i32; 64];
+    for i in 0..64 {
+        let value = (data[i] + (1 << (INVERSE_PASS_BITS + 2))) >> (INVERSE_PASS_BITS + 3);
+        result[i] = (value + 128).max(0).min(255);
+    }
+
+    result
+}
+
+// Output is scaled by 8 and by scale factors of the corresponding coefficients.
+fn forward_dct_1d(data: &mut [i32; 64], offset: usize, step: usize) {
+    let d = |i: usize| data[offset + i * step];
+
+    let tmp0 = d(0) + d(7);
+    let tmp7 = d(0) - d(7);
+    let tmp1 = d(1) + d(6);
+    let tmp6 = d(1) - d(6);
+    let tmp2 = d(2) + d(5);
+    let tmp5 = d(2) - d(5);
+    let tmp3 = d(3) + d(4);
+    let tmp4 = d(3) - d(4);
+
+    // even part
+    let tmp10 = tmp0 + tmp3;
+    let tmp13 = tmp0 - tmp3;
+    let tmp11 = tmp1 + tmp2;
+    let tmp12 = tmp1 - tmp2;
+
+    let z1 = multiply(tmp12 + tmp13, FIX_0_707106781);
+    data[offset] = tmp10 + tmp11;
+    data[offset + 4 * step] = tmp10 - tmp11;
+    data[offset + 2 * step] = tmp13 + z1;
+    data[offset + 6 * step] = tmp13 - z1;
+
+    // odd part
+    let tmp10 = tmp4 + tmp5;
+    let tmp11 = tmp5 + tmp6;
+    let tmp12 = tmp6 + tmp7;
+
+    let z5 = multiply(tmp10 - tmp12, FIX_0_382683433);
+    let z2 = multiply(tmp10, FIX_0_541196100) + z5;
+    let z4 = multiply(tmp12, FIX_1_306562965) + z5;
+    let z3 = multiply(tmp11, FIX_0_707106781);
+
+    let z11 = tmp7 + z3;
+    let z13 = tmp7 - z3;
+
+    data[offset + 5 * step] = z13 + z2;
+    data[offset + 3 * step] = z13 - z2;
+    data[offset + step] = z11 + z4;
+    data[offset + 7 * step] = z11 - z4;
+}
+
+// Input is expected to be multiplied by scale factors of the corresponding coefficients.
+fn inverse_dct_1d(data: &mut [i32; 64], offset: usize, step: usize) {
+    let d = |i: usize| data[offset + i * step];
+
+    // even part
+    let tmp10 = d(0) + d(4);
+    let tmp11 = d(0) - d(4);
+    let tmp13 = d(2) + d(6);
+    let tmp12 = multiply(d(2) - d(6), FIX_1_414213562) - tmp13;
+
+    let tmp0 = tmp10 + tmp13;
+    let tmp3 = tmp10 - tmp13;
+    let tmp1 = tmp11 + tmp12;
+    let tmp2 = tmp11 - tmp12;
+
+    // odd part
+    let z13 = d(5) + d(3);
+    let z10 = d(5) - d(3);
+    let z11 = d(1) + d(7);
+    let z12 = d(1) - d(7);
+
+    let tmp7 = z11 + z13;
+    let tmp11 = multiply(z11 - z13, FIX_1_414213562);
+
+    let z5 = multiply(z10 + z12, FIX_1_847759065);
+    let tmp10 = multiply(z12, FIX_1_082392200) - z5;
+    let tmp12 = z5 - multiply(z10, FIX_2_613125930);
+
+    let tmp6 = tmp12 - tmp7;
+    let tmp5 = tmp11 - tmp6;
+    let tmp4 = tmp10 + tmp5;
+
+    data[offset] = tmp0 + tmp7;
+    data[offset + 7 * step] = tmp0 - tmp7;
+    data[offset + step] = tmp1 + tmp6;
+    data[offset + 6 * step] = tmp1 - tmp6;
+    data[offset + 2 * step] = tmp2 + tmp5;
+    data[offset + 5 * step] = tmp2 - tmp5;
+    data[offset + 4 * step] = tmp3 + tmp4;
+    data[offset + 3 * step] = tmp3 - tmp4;
+}
+
+// products are computed in 64 bits, so that corrupted data can not overflow
+fn multiply(value: i32, constant: i32) -> i32 {
+    ((value as i64 * constant as i64 + (1 << (CONST_BITS - 1))) >> CONST_BITS) as i32
+}
+
+fn aan_scale(index: usize) -> f64 {
+    let factor = |k: usize| if k == 0 {
+        1.0
+    } else {
+        (k as f64 * PI / 16.0).cos() * 2f64.sqrt()
+    };
+
+    factor(index / 8) * factor(index % 8)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn reference_dct(block: &[i32; 64]) -> [f64; 64] {
+        let mut result = [0f64; 64];
+
+        for u in 0..8 {
+            for v in 0..8 {
+                let mut sum = 0f64;
+                for y in 0..8 {
+                    for x in 0..8 {
+                        sum += (block[y * 8 + x] - 128) as f64
+                            * ((2 * y + 1) as f64 * u as f64 * PI / 16.0).cos()
+                            * ((2 * x + 1) as f64 * v as f64 * PI / 16.0).cos();
+                    }
+                }
+
+                let cu = if u == 0 { 1.0 / 2f64.sqrt() } else { 1.0 };
+                let cv = if v == 0 { 1.0 / 2f64.sqrt() } else { 1.0 };
+                result[u * 8 + v] = sum * cu * cv / 4.0;
+            }
+        }
+
+        result
+    }
+
+    #[test]
+    fn test_forward_dct() {
+        let block = [
+            234, 212, 153, 111, 110, 153, 209, 224, 235, 207, 134, 79, 73, 119, 188, 216, 238, 206, 121,
+            50, 39, 86, 162, 207, 248, 216, 133, 64, 53, 95, 164, 214, 248, 227, 165, 115, 110, 140, 190,
+            230, 207, 201, 179, 162, 167, 184, 205, 224, 121, 135, 160, 182, 197, 201, 194, 190, 49, 78,
+            135, 185, 207, 202, 176, 156
+        ];
+
+        let expected = reference_dct(&block);
+        let encoded = forward_dct(&block, &forward_dct_scales(&[1; 64]));
+        for i in 0..64 {
+            assert!((encoded[i] as f64 - expected[i]).abs() <= 1.0, "coefficient {}: {} != {}", i, encoded[i], expected[i]);
+        }
+
+        let table: [i32; 64] = [16; 64];
+        let quantized = forward_dct(&block, &forward_dct_scales(&table));
+        for i in 0..64 {
+            assert!((quantized[i] as f64 - expected[i] / 16.0).abs() <= 1.0);
+        }
+    }
+
+    #[test]
+    fn test_inverse_dct() {
+        let block: Vec<i32> = (0..64).map(|i| (i * 37 + (i / 8) * 11) % 256).collect();
+        let mut source = [0i32; 64];
+        source.copy_from_slice(&block);
+
+        let scales = [1; 64];
+        let decoded = inverse_dct(&forward_dct(&source, &forward_dct_scales(&scales)), &inverse_dct_scales(&scales));
+        for i in 0..64 {
+            assert!((source[i] - decoded[i]).abs() <= 1, "sample {}: {} != {}", i, source[i], decoded[i]);
+        }
+
+        // only dc coefficient gives flat block
+        let mut coefficients = [0i32; 64];
+        coefficients[0] = 10;
+        let decoded = inverse_dct(&coefficients, &inverse_dct_scales(&[8; 64]));
+        assert!(decoded.iter().all(|sample| *sample == 128 + 10));
+
+        // corrupted data is clamped instead of overflowing
+        let decoded = inverse_dct(&[i32::MAX; 64], &inverse_dct_scales(&[255; 64]));
+        assert!(decoded.iter().all(|sample| *sample >= 0 && *sample <= 255));
+    }
+}
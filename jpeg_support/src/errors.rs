@@ -4,4 +4,5 @@ custom_error! {pub JPEGReaderError
     InvalidHeader {description: String} = "Invalid header: {description}",
     InvalidSegment {description: String} = "Invalid segment: {description}",
     InvalidEncodedData {description: String} = "Invalid encoded data: {description}",
+    UnsupportedFeature {description: String} = "Unsupported feature: {description}",
 }
\ No newline at end of file
@@ -0,0 +1,346 @@
+use std::collections::HashSet;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use core::models::{image::Image, metadata::{Exif, ExifEntry, ExifIfd, ExifValue, EXIF_TAG_ORIENTATION}};
+
+use crate::errors::JPEGReaderError;
+
+// see:
+// https://www.cipa.jp/std/documents/e/DC-X008-Translation-2019-E.pdf
+// https://www.itu.int/itudoc/itu-t/com16/tiff-fx/docs/tiff6.pdf (section 2)
+
+pub const EXIF_HEADER: &'static [u8] = b"Exif\0\0";
+
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_INTEROPERABILITY_IFD: u16 = 0xA005;
+
+const TYPE_SHORT: u16 = 3;
+
+struct TiffReader<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> TiffReader<'a> {
+
+    fn new(data: &'a [u8]) -> Result<Self, JPEGReaderError> {
+        let little_endian = match data.get(0..2) {
+            Some(b"II") => true,
+            Some(b"MM") => false,
+            _ => return Err(JPEGReaderError::InvalidSegment {
+                description: "Unknown TIFF byte order in EXIF data".to_string(),
+            }),
+        };
+
+        let reader = TiffReader { data, little_endian };
+        if reader.u16(2)? != 42 {
+            return Err(JPEGReaderError::InvalidSegment {
+                description: "Unexpected TIFF magic number in EXIF data".to_string(),
+            });
+        }
+
+        Ok(reader)
+    }
+
+    fn bytes(&self, offset: usize, length: usize) -> Result<&'a [u8], JPEGReaderError> {
+        self.data.get(offset..offset.saturating_add(length)).ok_or(JPEGReaderError::InvalidSegment {
+            description: format!("EXIF data is truncated, expected {} bytes at offset {}", length, offset),
+        })
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16, JPEGReaderError> {
+        let bytes = self.bytes(offset, 2)?;
+        Ok(if self.little_endian { LittleEndian::read_u16(bytes) } else { BigEndian::read_u16(bytes) })
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32, JPEGReaderError> {
+        let bytes = self.bytes(offset, 4)?;
+        Ok(if self.little_endian { LittleEndian::read_u32(bytes) } else { BigEndian::read_u32(bytes) })
+    }
+
+    fn u64(&self, offset: usize) -> Result<u64, JPEGReaderError> {
+        let bytes = self.bytes(offset, 8)?;
+        Ok(if self.little_endian { LittleEndian::read_u64(bytes) } else { BigEndian::read_u64(bytes) })
+    }
+
+    // offset of the value of IFD entry, values of up to 4 bytes are stored in the entry itself
+    fn value_offset(&self, entry_offset: usize, value_length: usize) -> Result<usize, JPEGReaderError> {
+        if value_length <= 4 {
+            Ok(entry_offset + 8)
+        } else {
+            Ok(self.u32(entry_offset + 8)? as usize)
+        }
+    }
+
+    fn value(&self, value_type: u16, count: usize, offset: usize) -> Result<Option<ExifValue>, JPEGReaderError> {
+        let items = |size: usize| (0..count).map(move |i| offset + i * size);
+
+        Ok(Some(match value_type {
+            1 => ExifValue::Byte(self.bytes(offset, count)?.to_vec()),
+            2 => {
+                let bytes = self.bytes(offset, count)?;
+                let text = bytes.split(|byte| *byte == 0).next().unwrap_or(&[]);
+                ExifValue::Ascii(String::from_utf8_lossy(text).to_string())
+            },
+            3 => ExifValue::Short(items(2).map(|offset| self.u16(offset)).collect::<Result<_, _>>()?),
+            4 => ExifValue::Long(items(4).map(|offset| self.u32(offset)).collect::<Result<_, _>>()?),
+            5 => ExifValue::Rational(items(8)
+                .map(|offset| Ok((self.u32(offset)?, self.u32(offset + 4)?)))
+                .collect::<Result<_, _>>()?),
+            6 => ExifValue::SignedByte(self.bytes(offset, count)?.iter().map(|byte| *byte as i8).collect()),
+            7 => ExifValue::Undefined(self.bytes(offset, count)?.to_vec()),
+            8 => ExifValue::SignedShort(items(2).map(|offset| self.u16(offset).map(|v| v as i16)).collect::<Result<_, _>>()?),
+            9 => ExifValue::SignedLong(items(4).map(|offset| self.u32(offset).map(|v| v as i32)).collect::<Result<_, _>>()?),
+            10 => ExifValue::SignedRational(items(8)
+                .map(|offset| Ok((self.u32(offset)? as i32, self.u32(offset + 4)? as i32)))
+                .collect::<Result<_, _>>()?),
+            11 => ExifValue::Float(items(4).map(|offset| self.u32(offset).map(f32::from_bits)).collect::<Result<_, _>>()?),
+            12 => ExifValue::Double(items(8).map(|offset| self.u64(offset).map(f64::from_bits)).collect::<Result<_, _>>()?),
+            // readers should skip entries of unknown types
+            _ => return Ok(None),
+        }))
+    }
+
+    fn read_ifd(
+        &self,
+        offset: usize,
+        ifd: ExifIfd,
+        entries: &mut Vec<ExifEntry>,
+        visited: &mut HashSet<usize>
+    ) -> Result<(), JPEGReaderError> {
+        // damaged files may have IFDs pointing at each other
+        if !visited.insert(offset) {
+            return Err(JPEGReaderError::InvalidSegment {
+                description: format!("EXIF IFD at offset {} is referenced more than once", offset),
+            });
+        }
+
+        let total_entries = self.u16(offset)? as usize;
+        for i in 0..total_entries {
+            let entry_offset = offset + 2 + i * 12;
+            let tag = self.u16(entry_offset)?;
+            let value_type = self.u16(entry_offset + 2)?;
+            let count = self.u32(entry_offset + 4)? as usize;
+
+            let sub_ifd = match tag {
+                TAG_EXIF_IFD if ifd == ExifIfd::Primary => Some(ExifIfd::Exif),
+                TAG_GPS_IFD if ifd == ExifIfd::Primary => Some(ExifIfd::Gps),
+                TAG_INTEROPERABILITY_IFD if ifd == ExifIfd::Exif => Some(ExifIfd::Interoperability),
+                _ => None,
+            };
+            if let Some(sub_ifd) = sub_ifd {
+                self.read_ifd(self.u32(entry_offset + 8)? as usize, sub_ifd, entries, visited)?;
+                continue;
+            }
+
+            let value_length = count.saturating_mul(type_size(value_type));
+            let value_offset = self.value_offset(entry_offset, value_length)?;
+            if let Some(value) = self.value(value_type, count, value_offset)? {
+                entries.push(ExifEntry { ifd, tag, value });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn find_entry(&self, offset: usize, tag: u16) -> Result<Option<usize>, JPEGReaderError> {
+        let total_entries = self.u16(offset)? as usize;
+        for i in 0..total_entries {
+            let entry_offset = offset + 2 + i * 12;
+            if self.u16(entry_offset)? == tag {
+                return Ok(Some(entry_offset));
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn type_size(value_type: u16) -> usize {
+    match value_type {
+        3 | 8 => 2,
+        4 | 9 | 11 => 4,
+        5 | 10 | 12 => 8,
+        _ => 1,
+    }
+}
+
+// Parses TIFF structure of APP1 EXIF segment (data following the EXIF header). Thumbnail IFD is skipped.
+pub fn read_exif(data: &[u8]) -> Result<Exif, JPEGReaderError> {
+    let reader = TiffReader::new(data)?;
+    let mut entries = Vec::new();
+    reader.read_ifd(reader.u32(4)? as usize, ExifIfd::Primary, &mut entries, &mut HashSet::new())?;
+
+    Ok(Exif {
+        entries,
+        data: data.to_vec(),
+    })
+}
+
+// Updates orientation both in the entries and in the TIFF structure, so that writers store the new value.
+pub fn set_orientation(exif: &mut Exif, orientation: u16) -> Result<(), JPEGReaderError> {
+    let reader = TiffReader::new(&exif.data)?;
+    let entry_offset = match reader.find_entry(reader.u32(4)? as usize, EXIF_TAG_ORIENTATION)? {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+    if reader.u16(entry_offset + 2)? != TYPE_SHORT {
+        return Err(JPEGReaderError::InvalidSegment {
+            description: "EXIF orientation is expected to be a short value".to_string(),
+        });
+    }
+
+    let little_endian = reader.little_endian;
+    let value = &mut exif.data[(entry_offset + 8)..(entry_offset + 10)];
+    if little_endian {
+        LittleEndian::write_u16(value, orientation);
+    } else {
+        BigEndian::write_u16(value, orientation);
+    }
+
+    for entry in &mut exif.entries {
+        if entry.ifd == ExifIfd::Primary && entry.tag == EXIF_TAG_ORIENTATION {
+            entry.value = ExifValue::Short(vec![orientation]);
+        }
+    }
+
+    Ok(())
+}
+
+// Transforms image stored with given EXIF orientation so that it is displayed the right way up.
+pub fn apply_orientation(image: &Image, orientation: u16) -> Image {
+    let (width, height) = (image.width, image.height);
+    // orientations 5-8 swap width and height
+    let transposed = orientation >= 5 && orientation <= 8;
+    let (new_width, new_height) = if transposed { (height, width) } else { (width, height) };
+
+    let mut result = Image::new(new_width, new_height);
+    result.metadata = image.metadata.clone();
+    result.animation = image.animation.clone();
+
+    for y in 0..new_height {
+        for x in 0..new_width {
+            let (source_x, source_y) = match orientation {
+                2 => (width - 1 - x, y), // mirrored horizontally
+                3 => (width - 1 - x, height - 1 - y), // rotated by 180 degrees
+                4 => (x, height - 1 - y), // mirrored vertically
+                5 => (y, x), // mirrored along the top left to bottom right diagonal
+                6 => (y, height - 1 - x), // should be rotated by 90 degrees clockwise
+                7 => (width - 1 - y, height - 1 - x), // mirrored along the top right to bottom left diagonal
+                8 => (width - 1 - y, x), // should be rotated by 90 degrees counterclockwise
+                _ => (x, y),
+            };
+            result.set_pixel(x, y, image.get_pixel(source_x, source_y));
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::models::pixel::Pixel;
+
+    // IFD0 with orientation and camera make, Exif IFD with exposure time
+    fn test_exif_data(little_endian: bool, orientation: u16) -> Vec<u8> {
+        let mut data = vec![0u8; 76];
+        let write_u16 = |data: &mut Vec<u8>, offset: usize, value: u16| if little_endian {
+            LittleEndian::write_u16(&mut data[offset..offset + 2], value)
+        } else {
+            BigEndian::write_u16(&mut data[offset..offset + 2], value)
+        };
+        let write_u32 = |data: &mut Vec<u8>, offset: usize, value: u32| if little_endian {
+            LittleEndian::write_u32(&mut data[offset..offset + 4], value)
+        } else {
+            BigEndian::write_u32(&mut data[offset..offset + 4], value)
+        };
+
+        data[0..2].copy_from_slice(if little_endian { b"II" } else { b"MM" });
+        write_u16(&mut data, 2, 42);
+        write_u32(&mut data, 4, 8);
+
+        write_u16(&mut data, 8, 3);
+        // make, ascii value is stored after IFDs
+        write_u16(&mut data, 10, 0x010F);
+        write_u16(&mut data, 12, 2);
+        write_u32(&mut data, 14, 4);
+        data[18..22].copy_from_slice(b"Cow\0");
+        // orientation
+        write_u16(&mut data, 22, EXIF_TAG_ORIENTATION);
+        write_u16(&mut data, 24, 3);
+        write_u32(&mut data, 26, 1);
+        write_u16(&mut data, 30, orientation);
+        // pointer to Exif IFD
+        write_u16(&mut data, 34, TAG_EXIF_IFD);
+        write_u16(&mut data, 36, 4);
+        write_u32(&mut data, 38, 1);
+        write_u32(&mut data, 42, 50);
+        // next IFD
+        write_u32(&mut data, 46, 0);
+
+        write_u16(&mut data, 50, 1);
+        // exposure time
+        write_u16(&mut data, 52, 0x829A);
+        write_u16(&mut data, 54, 5);
+        write_u32(&mut data, 56, 1);
+        write_u32(&mut data, 60, 68);
+        // next IFD
+        write_u32(&mut data, 64, 0);
+        write_u32(&mut data, 68, 1);
+        write_u32(&mut data, 72, 250);
+
+        data
+    }
+
+    #[test]
+    fn test_read_exif() {
+        for little_endian in &[true, false] {
+            let exif = read_exif(&test_exif_data(*little_endian, 6)).expect("failed to read exif");
+            assert_eq!(exif.entries.len(), 3);
+            assert_eq!(exif.orientation(), Some(6));
+            assert_eq!(exif.get(ExifIfd::Primary, 0x010F), Some(&ExifValue::Ascii("Cow".to_string())));
+            assert_eq!(exif.get(ExifIfd::Exif, 0x829A), Some(&ExifValue::Rational(vec![(1, 250)])));
+        }
+    }
+
+    #[test]
+    fn test_read_invalid_exif() {
+        let data = test_exif_data(true, 1);
+        assert!(read_exif(&data[0..40]).is_err());
+        assert!(read_exif(b"XX\x2A\x00").is_err());
+
+        // Exif IFD pointing at IFD0
+        let mut looped = data.clone();
+        LittleEndian::write_u32(&mut looped[42..46], 8);
+        assert!(read_exif(&looped).is_err());
+    }
+
+    #[test]
+    fn test_set_orientation() {
+        let mut exif = read_exif(&test_exif_data(false, 8)).expect("failed to read exif");
+        set_orientation(&mut exif, 1).expect("failed to set orientation");
+        assert_eq!(exif.orientation(), Some(1));
+        assert_eq!(read_exif(&exif.data).expect("failed to read updated exif"), exif);
+    }
+
+    #[test]
+    fn test_apply_orientation() {
+        // 1 2 3
+        // 4 5 6
+        let mut image = Image::new(3, 2);
+        for i in 0..6 {
+            image.pixels[i] = Pixel::from_rgb(i as u8 + 1, 0, 0);
+        }
+        let values = |image: &Image| (image.width, image.height, image.pixels.iter().map(|p| p.red).collect::<Vec<u8>>());
+
+        assert_eq!(values(&apply_orientation(&image, 1)), (3, 2, vec![1, 2, 3, 4, 5, 6]));
+        assert_eq!(values(&apply_orientation(&image, 2)), (3, 2, vec![3, 2, 1, 6, 5, 4]));
+        assert_eq!(values(&apply_orientation(&image, 3)), (3, 2, vec![6, 5, 4, 3, 2, 1]));
+        assert_eq!(values(&apply_orientation(&image, 4)), (3, 2, vec![4, 5, 6, 1, 2, 3]));
+        assert_eq!(values(&apply_orientation(&image, 5)), (2, 3, vec![1, 4, 2, 5, 3, 6]));
+        assert_eq!(values(&apply_orientation(&image, 6)), (2, 3, vec![4, 1, 5, 2, 6, 3]));
+        assert_eq!(values(&apply_orientation(&image, 7)), (2, 3, vec![6, 3, 5, 2, 4, 1]));
+        assert_eq!(values(&apply_orientation(&image, 8)), (2, 3, vec![3, 6, 2, 5, 1, 4]));
+    }
+}
@@ -218,112 +218,242 @@ impl HuffmanTreeNodeElement {
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+pub struct HuffmanTreeBuilder {
 
-    pub struct HuffmanTreeBuilder {
+    values: HashMap<u8, usize>,
+}
 
-        values: HashMap<u8, usize>,
+#[derive(Debug)]
+struct HuffmanTreeBuilderEntry {
+
+    frequency: usize,
+    node: HuffmanTreeNodeElement,
+}
+
+impl PartialEq for HuffmanTreeBuilderEntry {
+
+    fn eq(&self, other: &Self) -> bool {
+        self.frequency == other.frequency && self.node == other.node
     }
-    
-    #[derive(Debug)]
-    pub struct HuffmanTreeBuilderEntry {
-    
-        frequency: usize,
-        node: HuffmanTreeNodeElement,
+}
+
+impl Eq for HuffmanTreeBuilderEntry {
+}
+
+impl Ord for HuffmanTreeBuilderEntry {
+
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.frequency.cmp(&self.frequency)
     }
-    
-    impl PartialEq for HuffmanTreeBuilderEntry {
-    
-        fn eq(&self, other: &Self) -> bool {
-            self.frequency == other.frequency && self.node == other.node
+}
+
+impl PartialOrd for HuffmanTreeBuilderEntry {
+
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self.frequency == other.frequency {
+            return if self.node.is_link() && other.node.is_value() {
+                Some(Ordering::Less)
+            } else if self.node.is_value() && other.node.is_link() {
+                Some(Ordering::Greater)
+            } else {
+                Some(Ordering::Equal)
+            }
         }
+
+        Some(other.frequency.cmp(&self.frequency))
     }
-    
-    impl Eq for HuffmanTreeBuilderEntry {
-    }
-    
-    impl Ord for HuffmanTreeBuilderEntry {
-    
-        fn cmp(&self, other: &Self) -> Ordering {
-            other.frequency.cmp(&self.frequency)
+}
+
+impl HuffmanTreeBuilderEntry {
+
+    fn new(frequency: usize, node: HuffmanTreeNodeElement) -> Self {
+        Self {
+            frequency,
+            node,
         }
     }
-    
-    impl PartialOrd for HuffmanTreeBuilderEntry {
-    
-        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-            if self.frequency == other.frequency {
-                return if self.node.is_link() && other.node.is_value() {
-                    Some(Ordering::Less)
-                } else if self.node.is_value() && other.node.is_link() {
-                    Some(Ordering::Greater)
-                } else {
-                    Some(Ordering::Equal)
-                }
-            }
-    
-            Some(other.frequency.cmp(&self.frequency))
+}
+
+impl HuffmanTreeBuilder {
+
+    pub fn new() -> Self {
+        HuffmanTreeBuilder {
+            values: HashMap::new(),
         }
     }
-    
-    impl HuffmanTreeBuilderEntry {
-    
-        fn new(frequency: usize, node: HuffmanTreeNodeElement) -> Self {
-            Self {
-                frequency,
-                node,
-            }
+
+    pub fn append_count(&mut self, value: u8, inc: usize) {
+        let counter = match self.values.get(&value) {
+            Some(v) => *v,
+            None => 0
+        } + inc;
+
+        self.values.insert(value, counter);
+    }
+
+    pub fn build(&self) -> HuffmanTree {
+        let mut heap: BinaryHeap<HuffmanTreeBuilderEntry> = BinaryHeap::new();
+
+        for (entry, frequency) in &self.values {
+            heap.push(HuffmanTreeBuilderEntry::new(*frequency, HuffmanTreeNodeElement::Value(*entry)));
         }
+
+        while heap.len() > 1 {
+            let lowest = heap.pop().unwrap();
+            let second_lowest = heap.pop().unwrap();
+
+            heap.push(HuffmanTreeBuilderEntry::new(
+                lowest.frequency + second_lowest.frequency,
+                HuffmanTreeNodeElement::Link(HuffmanTreeNode::with_two_subnodes(
+                    lowest.node,
+                    second_lowest.node,
+                ))
+            ));
+        }
+
+        HuffmanTree::with_root(match heap.pop().unwrap().node {
+            HuffmanTreeNodeElement::Link(linked_node) => linked_node,
+            HuffmanTreeNodeElement::Value(_) => panic!("this cannot be value"),
+        })
     }
 
-    impl HuffmanTreeBuilder {
+    // Builds a table suitable for JPEG: no code is longer than 16 bits and the code consisting of all ones
+    // is never used. Returns the number of codes of each length (BITS) and values sorted by code length (HUFFVAL).
+    // see:
+    // https://www.w3.org/Graphics/JPEG/itu-t81.pdf (Annex K.2)
+    pub fn build_jpeg_table(&self) -> ([u8; 16], Vec<u8>) {
+        let mut values: Vec<(u8, usize)> = self.values.iter().map(|(value, frequency)| (*value, *frequency)).collect();
+        // most frequent values get the shortest codes, ties are broken by value to keep output deterministic
+        values.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        if values.len() < 2 {
+            // a single value gets code "0", which is not all ones
+            let mut bits = [0u8; 16];
+            bits[0] = values.len() as u8;
+            return (bits, values.into_iter().map(|(value, _)| value).collect());
+        }
+
+        // lengths in the map produced by the tree are one less than actual code lengths
+        let mut code_lengths: Vec<usize> = self.build().to_map().keys().map(|(_, length)| *length as usize + 1).collect();
+        code_lengths.sort();
+
+        let max_length = *code_lengths.last().unwrap();
+        let mut bits = vec![0usize; max_length.max(16) + 2];
+        for length in code_lengths {
+            bits[length] += 1;
+        }
 
-        pub fn new() -> Self {
-            HuffmanTreeBuilder {
-                values: HashMap::new(),
+        // reserve a code for a dummy value, which takes the place of the all-ones code
+        bits[max_length] -= 1;
+        bits[max_length + 1] += 2;
+
+        // move the longest codes up the tree while keeping it full
+        let mut length = bits.len() - 1;
+        while length > 16 {
+            while bits[length] > 0 {
+                let mut shorter = length - 2;
+                while bits[shorter] == 0 {
+                    shorter -= 1;
+                }
+
+                bits[length] -= 2;
+                bits[length - 1] += 1;
+                bits[shorter + 1] += 2;
+                bits[shorter] -= 1;
             }
+            length -= 1;
         }
-    
-        pub fn _append(&mut self, value: u8) {
-            self.append_count(value, 1)
-        } 
-    
-        pub fn append_count(&mut self, value: u8, inc: usize) {
-            let counter = match self.values.get(&value) {
-                Some(v) => *v,
-                None => 0
-            } + inc;
-    
-            self.values.insert(value, counter);
+
+        // remove the dummy value
+        while bits[length] == 0 {
+            length -= 1;
         }
-    
-        pub fn build(&self) -> HuffmanTree {
-            let mut heap: BinaryHeap<HuffmanTreeBuilderEntry> = BinaryHeap::new();
-    
-            for (entry, frequency) in &self.values {
-                heap.push(HuffmanTreeBuilderEntry::new(*frequency, HuffmanTreeNodeElement::Value(*entry)));
-            }
-    
-            while heap.len() > 1 {
-                let lowest = heap.pop().unwrap();
-                let second_lowest = heap.pop().unwrap();
-                
-                heap.push(HuffmanTreeBuilderEntry::new(
-                    lowest.frequency + second_lowest.frequency,
-                    HuffmanTreeNodeElement::Link(HuffmanTreeNode::with_two_subnodes(
-                        lowest.node,
-                        second_lowest.node,
-                    ))
-                ));
-            }
-    
-            HuffmanTree::with_root(match heap.pop().unwrap().node {
-                HuffmanTreeNodeElement::Link(linked_node) => linked_node,
-                HuffmanTreeNodeElement::Value(_) => panic!("this cannot be value"),
-            })
+        bits[length] -= 1;
+
+        let mut result = [0u8; 16];
+        for length in 1..=16 {
+            result[length - 1] = bits[length] as u8;
+        }
+
+        (result, values.into_iter().map(|(value, _)| value).collect())
+    }
+}
+
+// Canonical codes for a table described by the number of codes of each length and values in code order.
+// Returns value => (code, code_length), the same representation the writer uses for static tables.
+pub fn canonical_codes(bits: &[u8; 16], values: &[u8]) -> HashMap<u8, (u16, u16)> {
+    let mut codes = HashMap::new();
+    let mut values = values.iter();
+    let mut code: u16 = 0;
+
+    for length in 1..=16 {
+        for _ in 0..bits[length - 1] {
+            let value = values.next().expect("Expected a value for every code");
+            codes.insert(*value, (code, length as u16));
+            code += 1;
         }
+        code <<= 1;
+    }
+
+    codes
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code_length_sum(bits: &[u8; 16]) -> f64 {
+        bits.iter().enumerate().map(|(index, count)| *count as f64 / 2f64.powi(index as i32 + 1)).sum()
+    }
+
+    #[test]
+    fn test_build_jpeg_table() {
+        let mut builder = HuffmanTreeBuilder::new();
+        builder.append_count(0, 10);
+        builder.append_count(1, 5);
+        builder.append_count(2, 1);
+
+        let (bits, values) = builder.build_jpeg_table();
+        assert_eq!(values, vec![0, 1, 2]);
+        assert_eq!(&bits[0..3], &[1, 1, 1]);
+
+        let codes = canonical_codes(&bits, &values);
+        assert_eq!(codes[&0], (0b0, 1));
+        assert_eq!(codes[&1], (0b10, 2));
+        assert_eq!(codes[&2], (0b110, 3));
+    }
+
+    #[test]
+    fn test_build_jpeg_table_single_value() {
+        let mut builder = HuffmanTreeBuilder::new();
+        builder.append_count(42, 1);
+
+        let (bits, values) = builder.build_jpeg_table();
+        assert_eq!(values, vec![42]);
+        assert_eq!(canonical_codes(&bits, &values)[&42], (0, 1));
+    }
+
+    #[test]
+    fn test_build_jpeg_table_length_limit() {
+        // fibonacci frequencies produce the deepest possible tree
+        let mut builder = HuffmanTreeBuilder::new();
+        let (mut a, mut b) = (1usize, 1usize);
+        for value in 0..30 {
+            builder.append_count(value, a);
+            let next = a + b;
+            a = b;
+            b = next;
+        }
+
+        let (bits, values) = builder.build_jpeg_table();
+        assert_eq!(values.len(), 30);
+        assert_eq!(bits.iter().map(|count| *count as usize).sum::<usize>(), 30);
+        // the table is valid and leaves room for the reserved all-ones code
+        assert!(code_length_sum(&bits) < 1.0);
+
+        let codes = canonical_codes(&bits, &values);
+        assert!(codes.values().all(|(code, length)| *length <= 16 && (*code as u32) != (1u32 << *length) - 1));
     }
 
     #[test]
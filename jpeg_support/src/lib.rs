@@ -1,9 +1,10 @@
 #![feature(box_syntax)]
 #![feature(destructuring_assignment)]
 
-use core::{models::io::ImageReader, plugins::ImageFormatSupportPlugin, models::io::ImageWriter};
+use core::{models::io::ImageReader, plugins::ImageFormatSupportPlugin, models::io::{ImageTransformer, ImageWriter}};
 
 use reader::JPEGReader;
+use transform::JPEGTransformer;
 use writer::JPEGWriter;
 
 #[macro_use]
@@ -13,9 +14,13 @@ extern crate lazy_static;
 extern crate maplit;
 
 mod common;
+pub mod dct;
 pub mod errors;
+pub mod exif;
 mod huffman;
+mod progressive;
 pub mod reader;
+pub mod transform;
 pub mod writer;
 
 pub struct JPEGSupportPlugin {
@@ -35,12 +40,16 @@ impl ImageFormatSupportPlugin for JPEGSupportPlugin {
     }
 
     fn reader(&self) -> Box<dyn ImageReader> {
-        box JPEGReader {}
+        box JPEGReader::new()
     }
 
     fn writer(&self) -> Box<dyn ImageWriter> {
         box JPEGWriter {}
     }
+
+    fn transformer(&self) -> Option<Box<dyn ImageTransformer>> {
+        Some(box JPEGTransformer {})
+    }
 }
 
 #[no_mangle]
@@ -0,0 +1,733 @@
+use std::collections::HashMap;
+
+use bit_vec::BitVec;
+
+use crate::{common::{Channel, HuffmanTable, HuffmanTableType, ZIGZAG_ORDER, restart_intervals}, errors::JPEGReaderError, huffman::{HuffmanTreeBuilder, canonical_codes}};
+
+// see:
+// https://www.w3.org/Graphics/JPEG/itu-t81.pdf (Annex G)
+// https://github.com/libjpeg-turbo/libjpeg-turbo/blob/main/src/jdphuff.c
+
+// Coefficients of all blocks of one component, in natural (not zigzag) order.
+// Blocks are stored for whole MCUs, so that interleaved scans can write the padding blocks too.
+#[derive(Clone)]
+pub struct ComponentCoefficients {
+    pub horizontal_sampling: usize,
+    pub vertical_sampling: usize,
+    pub blocks_per_line: usize,
+    // amount of blocks which actually cover the image, used by non-interleaved scans
+    pub width_in_blocks: usize,
+    pub height_in_blocks: usize,
+    pub blocks: Vec<[i32; 64]>,
+}
+
+impl ComponentCoefficients {
+
+    pub fn new(channel: &Channel, width: usize, height: usize, max_horizontal_sampling: usize, max_vertical_sampling: usize) -> Self {
+        let horizontal_sampling = channel.horizontal_sampling as usize;
+        let vertical_sampling = channel.vertical_sampling as usize;
+        let horizontal_mcus = ceil_div(width, 8 * max_horizontal_sampling);
+        let vertical_mcus = ceil_div(height, 8 * max_vertical_sampling);
+        let blocks_per_line = horizontal_mcus * horizontal_sampling;
+
+        ComponentCoefficients {
+            horizontal_sampling,
+            vertical_sampling,
+            blocks_per_line,
+            width_in_blocks: ceil_div(ceil_div(width * horizontal_sampling, max_horizontal_sampling), 8),
+            height_in_blocks: ceil_div(ceil_div(height * vertical_sampling, max_vertical_sampling), 8),
+            blocks: vec![[0i32; 64]; blocks_per_line * vertical_mcus * vertical_sampling],
+        }
+    }
+}
+
+pub struct ScanComponent {
+    // index of the component in the frame
+    pub index: usize,
+    pub dc_table: Option<HuffmanTable>,
+    pub ac_table: Option<HuffmanTable>,
+}
+
+pub struct Scan {
+    pub components: Vec<ScanComponent>,
+    pub spectral_start: usize,
+    pub spectral_end: usize,
+    pub successive_high: u8,
+    pub successive_low: u8,
+}
+
+impl Scan {
+
+    pub fn validate(&self) -> Result<(), JPEGReaderError> {
+        self.validate_parameters()?;
+
+        for component in &self.components {
+            let table_present = if self.spectral_start == 0 {
+                // refinement of DC does not use Huffman tables
+                self.successive_high != 0 || component.dc_table.is_some()
+            } else {
+                component.ac_table.is_some()
+            };
+
+            if !table_present {
+                return Err(JPEGReaderError::InvalidEncodedData {
+                    description: format!("Huffman table for component {} is not present", component.index),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn validate_parameters(&self) -> Result<(), JPEGReaderError> {
+        let valid = if self.spectral_start == 0 {
+            self.spectral_end == 0
+        } else {
+            // AC scans contain a single component only
+            self.spectral_start <= self.spectral_end && self.spectral_end < 64 && self.components.len() == 1
+        };
+
+        if !valid || self.successive_low > 13 || (self.successive_high != 0 && self.successive_high != self.successive_low + 1) {
+            return Err(JPEGReaderError::InvalidSegment {
+                description: format!(
+                    "Invalid progressive scan parameters: Ss={} Se={} Ah={} Al={} components={}",
+                    self.spectral_start, self.spectral_end, self.successive_high, self.successive_low, self.components.len()
+                ),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> BitReader<'a> {
+
+    fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            offset: 0,
+        }
+    }
+
+    // reading past the end of data produces zeros, as decoders usually do for truncated files
+    fn read_bit(&mut self) -> u16 {
+        let byte = self.data.get(self.offset / 8).cloned().unwrap_or(0);
+        let bit = (byte >> (7 - self.offset % 8)) & 1;
+        self.offset += 1;
+        bit as u16
+    }
+
+    fn read_bits(&mut self, total_bits: u8) -> i32 {
+        let mut result = 0;
+        for _ in 0..total_bits {
+            result = (result << 1) | self.read_bit() as i32;
+        }
+        result
+    }
+
+    // reads a value of given category, which is encoded as its magnitude bits
+    fn receive_extend(&mut self, total_bits: u8) -> i32 {
+        if total_bits == 0 {
+            return 0;
+        }
+
+        let value = self.read_bits(total_bits);
+        if value < (1 << (total_bits - 1)) {
+            value - (1 << total_bits) + 1
+        } else {
+            value
+        }
+    }
+
+    fn decode(&mut self, table: &HashMap<(u16, u16), u8>) -> Result<u8, JPEGReaderError> {
+        let mut code = 0;
+        for code_length in 1..=16 {
+            code = (code << 1) | self.read_bit();
+            if let Some(value) = table.get(&(code, code_length)) {
+                return Ok(*value);
+            }
+        }
+
+        Err(JPEGReaderError::InvalidEncodedData {
+            description: format!("Invalid Huffman code at bit offset {}", self.offset),
+        })
+    }
+}
+
+struct ScanDecoder<'a> {
+    scan: &'a Scan,
+    reader: BitReader<'a>,
+    prev_dc: Vec<i32>,
+    eobrun: u32,
+}
+
+impl<'a> ScanDecoder<'a> {
+
+    fn decode_block(&mut self, scan_component: usize, block: &mut [i32; 64]) -> Result<(), JPEGReaderError> {
+        match (self.scan.spectral_start, self.scan.successive_high) {
+            (0, 0) => self.decode_dc_first(scan_component, block),
+            (0, _) => {
+                self.decode_dc_refine(block);
+                Ok(())
+            },
+            (_, 0) => self.decode_ac_first(scan_component, block),
+            (_, _) => self.decode_ac_refine(scan_component, block),
+        }
+    }
+
+    fn decode_dc_first(&mut self, scan_component: usize, block: &mut [i32; 64]) -> Result<(), JPEGReaderError> {
+        let scan = self.scan;
+        let table = &scan.components[scan_component].dc_table.as_ref()
+            .expect("Expected DC table to be present, because scan was validated").table;
+        let category = self.reader.decode(table)?;
+        self.prev_dc[scan_component] += self.reader.receive_extend(category);
+        block[0] = self.prev_dc[scan_component] << self.scan.successive_low;
+        Ok(())
+    }
+
+    fn decode_dc_refine(&mut self, block: &mut [i32; 64]) {
+        if self.reader.read_bit() == 1 {
+            block[0] |= 1 << self.scan.successive_low;
+        }
+    }
+
+    fn decode_ac_first(&mut self, scan_component: usize, block: &mut [i32; 64]) -> Result<(), JPEGReaderError> {
+        if self.eobrun > 0 {
+            self.eobrun -= 1;
+            return Ok(());
+        }
+
+        let scan = self.scan;
+        let table = &scan.components[scan_component].ac_table.as_ref()
+            .expect("Expected AC table to be present, because scan was validated").table;
+
+        let mut k = self.scan.spectral_start;
+        while k <= self.scan.spectral_end {
+            let value = self.reader.decode(table)?;
+            let zeros = (value >> 4) as usize;
+            let category = value & 0b1111;
+
+            if category != 0 {
+                k += zeros;
+                if k > 63 {
+                    return Err(JPEGReaderError::InvalidEncodedData {
+                        description: "AC coefficient index is out of block".to_string(),
+                    });
+                }
+                block[ZIGZAG_ORDER[k] as usize] = self.reader.receive_extend(category) << self.scan.successive_low;
+            } else if zeros == 15 {
+                k += 15;
+            } else {
+                self.eobrun = (1 << zeros) - 1;
+                if zeros > 0 {
+                    self.eobrun += self.reader.read_bits(zeros as u8) as u32;
+                }
+                break;
+            }
+
+            k += 1;
+        }
+
+        Ok(())
+    }
+
+    fn decode_ac_refine(&mut self, scan_component: usize, block: &mut [i32; 64]) -> Result<(), JPEGReaderError> {
+        let scan = self.scan;
+        let table = &scan.components[scan_component].ac_table.as_ref()
+            .expect("Expected AC table to be present, because scan was validated").table;
+        let positive = 1 << self.scan.successive_low;
+        let negative = -1 << self.scan.successive_low;
+
+        let mut k = self.scan.spectral_start;
+        if self.eobrun == 0 {
+            while k <= self.scan.spectral_end {
+                let value = self.reader.decode(table)?;
+                let mut zeros = (value >> 4) as i32;
+                let category = value & 0b1111;
+
+                let mut new_value = 0;
+                if category != 0 {
+                    // newly non-zero coefficients always have magnitude of 1 at this bit position
+                    new_value = if self.reader.read_bit() == 1 { positive } else { negative };
+                } else if zeros != 15 {
+                    self.eobrun = 1 << zeros;
+                    if zeros > 0 {
+                        self.eobrun += self.reader.read_bits(zeros as u8) as u32;
+                    }
+                    break;
+                }
+
+                // skip zero coefficients (refining the non-zero ones on the way) up to the position of the new value
+                while k <= self.scan.spectral_end {
+                    let coefficient = &mut block[ZIGZAG_ORDER[k] as usize];
+                    if *coefficient != 0 {
+                        self.refine_coefficient(coefficient, positive, negative);
+                    } else {
+                        if zeros == 0 {
+                            break;
+                        }
+                        zeros -= 1;
+                    }
+                    k += 1;
+                }
+
+                if new_value != 0 && k <= self.scan.spectral_end {
+                    block[ZIGZAG_ORDER[k] as usize] = new_value;
+                }
+                k += 1;
+            }
+        }
+
+        if self.eobrun > 0 {
+            // the rest of the band has no new non-zero coefficients, but existing ones still get refined
+            while k <= self.scan.spectral_end {
+                let coefficient = &mut block[ZIGZAG_ORDER[k] as usize];
+                if *coefficient != 0 {
+                    self.refine_coefficient(coefficient, positive, negative);
+                }
+                k += 1;
+            }
+            self.eobrun -= 1;
+        }
+
+        Ok(())
+    }
+
+    fn refine_coefficient(&mut self, coefficient: &mut i32, positive: i32, negative: i32) {
+        if self.reader.read_bit() == 1 && (*coefficient & positive) == 0 {
+            *coefficient += if *coefficient >= 0 { positive } else { negative };
+        }
+    }
+}
+
+// Lists (scan component, component index, block index) in the order blocks are coded in the scan, grouped by MCU.
+// Restart intervals are counted in these MCUs, which are single blocks for non-interleaved scans.
+fn scan_blocks(scan: &Scan, components: &[ComponentCoefficients], horizontal_mcus: usize, vertical_mcus: usize) -> Vec<Vec<(usize, usize, usize)>> {
+    let mut result = Vec::new();
+
+    if scan.components.len() == 1 {
+        // non-interleaved scan goes through blocks of a single component, ignoring MCU structure
+        let component_index = scan.components[0].index;
+        let component = &components[component_index];
+        for block_y in 0..component.height_in_blocks {
+            for block_x in 0..component.width_in_blocks {
+                result.push(vec![(0, component_index, block_y * component.blocks_per_line + block_x)]);
+            }
+        }
+    } else {
+        for mcu_y in 0..vertical_mcus {
+            for mcu_x in 0..horizontal_mcus {
+                let mut mcu = Vec::new();
+                for (scan_component, component) in scan.components.iter().enumerate() {
+                    let component_index = component.index;
+                    let component = &components[component_index];
+                    for v in 0..component.vertical_sampling {
+                        for h in 0..component.horizontal_sampling {
+                            let block_y = mcu_y * component.vertical_sampling + v;
+                            let block_x = mcu_x * component.horizontal_sampling + h;
+                            mcu.push((scan_component, component_index, block_y * component.blocks_per_line + block_x));
+                        }
+                    }
+                }
+                result.push(mcu);
+            }
+        }
+    }
+
+    result
+}
+
+// Decodes a scan, which data is split into restart intervals (a single one when restart_interval = 0).
+// Damaged or missing restart intervals are skipped, so that the rest of the image is still decoded.
+pub fn decode_scan(
+    intervals: &[Vec<u8>],
+    restart_interval: usize,
+    scan: &Scan,
+    components: &mut [ComponentCoefficients],
+    horizontal_mcus: usize,
+    vertical_mcus: usize
+) -> Result<(), JPEGReaderError> {
+    scan.validate()?;
+
+    let mcus = scan_blocks(scan, components, horizontal_mcus, vertical_mcus);
+    for (interval_index, interval) in restart_intervals(&mcus, restart_interval).enumerate() {
+        let data: &[u8] = intervals.get(interval_index).map(|data| data.as_slice()).unwrap_or(&[]);
+        if restart_interval != 0 && data.is_empty() {
+            warn!("restart interval {} is missing, skipping it", interval_index);
+            continue;
+        }
+
+        let mut decoder = ScanDecoder {
+            scan,
+            reader: BitReader::new(data),
+            prev_dc: vec![0; scan.components.len()],
+            eobrun: 0,
+        };
+
+        for (scan_component, component_index, block_index) in interval.iter().flatten() {
+            let result = decoder.decode_block(*scan_component, &mut components[*component_index].blocks[*block_index]);
+            match result {
+                Err(err) if restart_interval != 0 => {
+                    warn!("failed to decode restart interval {}, skipping it: {}", interval_index, err);
+                    break;
+                },
+                other => other?,
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Lists blocks in the same order as they appear in a baseline interleaved scan: by MCU, then by component.
+pub fn blocks_in_mcu_order(components: &[ComponentCoefficients], horizontal_mcus: usize, vertical_mcus: usize) -> Vec<[i32; 64]> {
+    let mut result = Vec::new();
+
+    for mcu_y in 0..vertical_mcus {
+        for mcu_x in 0..horizontal_mcus {
+            for component in components {
+                for v in 0..component.vertical_sampling {
+                    for h in 0..component.horizontal_sampling {
+                        let block_y = mcu_y * component.vertical_sampling + v;
+                        let block_x = mcu_x * component.horizontal_sampling + h;
+                        result.push(component.blocks[block_y * component.blocks_per_line + block_x]);
+                    }
+                }
+            }
+        }
+    }
+
+    result
+}
+
+// Inverse of blocks_in_mcu_order.
+pub fn components_from_mcu_blocks(channels: &[Channel], width: usize, height: usize, blocks: &[[i32; 64]]) -> Vec<ComponentCoefficients> {
+    let max_horizontal_sampling = channels.iter().map(|c| c.horizontal_sampling as usize).max().unwrap_or(1);
+    let max_vertical_sampling = channels.iter().map(|c| c.vertical_sampling as usize).max().unwrap_or(1);
+    let horizontal_mcus = ceil_div(width, 8 * max_horizontal_sampling);
+
+    let mut components: Vec<ComponentCoefficients> = channels.iter()
+        .map(|channel| ComponentCoefficients::new(channel, width, height, max_horizontal_sampling, max_vertical_sampling))
+        .collect();
+
+    let blocks_per_mcu: usize = components.iter()
+        .map(|component| component.horizontal_sampling * component.vertical_sampling)
+        .sum();
+
+    for (mcu_index, mcu) in blocks.chunks(blocks_per_mcu).enumerate() {
+        let mcu_x = mcu_index % horizontal_mcus;
+        let mcu_y = mcu_index / horizontal_mcus;
+        let mut blocks = mcu.iter();
+
+        for component in components.iter_mut() {
+            for v in 0..component.vertical_sampling {
+                for h in 0..component.horizontal_sampling {
+                    let block_y = mcu_y * component.vertical_sampling + v;
+                    let block_x = mcu_x * component.horizontal_sampling + h;
+                    let block_index = block_y * component.blocks_per_line + block_x;
+                    component.blocks[block_index] = *blocks.next().expect("Expected MCU to contain blocks for all components");
+                }
+            }
+        }
+    }
+
+    components
+}
+
+struct ScanEncoder<'a> {
+    scan: &'a Scan,
+    // codes for each scan component, None while gathering statistics
+    codes: Option<Vec<HashMap<u8, (u16, u16)>>>,
+    counts: Vec<HashMap<u8, usize>>,
+    bits: BitVec,
+    prev_dc: Vec<i32>,
+    eobrun: u32,
+    // correction bits of blocks which are part of the current EOB run
+    correction_bits: Vec<bool>,
+    intervals: Vec<Vec<u8>>,
+}
+
+impl<'a> ScanEncoder<'a> {
+
+    fn new(scan: &'a Scan, codes: Option<Vec<HashMap<u8, (u16, u16)>>>) -> Self {
+        ScanEncoder {
+            scan,
+            codes,
+            counts: vec![HashMap::new(); scan.components.len()],
+            bits: BitVec::new(),
+            prev_dc: vec![0; scan.components.len()],
+            eobrun: 0,
+            correction_bits: Vec::new(),
+            intervals: Vec::new(),
+        }
+    }
+
+    // ends the current restart interval, which is padded to a whole byte
+    fn restart(&mut self) {
+        self.emit_eobrun();
+        self.prev_dc = vec![0; self.scan.components.len()];
+
+        if self.codes.is_some() {
+            while self.bits.len() % 8 != 0 {
+                self.bits.push(true);
+            }
+            let bits = std::mem::replace(&mut self.bits, BitVec::new());
+            self.intervals.push(bits.to_bytes());
+        }
+    }
+
+    fn emit_symbol(&mut self, scan_component: usize, symbol: u8) {
+        match &self.codes {
+            Some(codes) => {
+                let (code, code_length) = codes[scan_component][&symbol];
+                self.emit_bits(code as i32, code_length as u8);
+            },
+            None => *self.counts[scan_component].entry(symbol).or_insert(0) += 1,
+        }
+    }
+
+    fn emit_bits(&mut self, value: i32, total_bits: u8) {
+        if self.codes.is_some() {
+            for i in (0..total_bits).rev() {
+                self.bits.push((value >> i) & 1 == 1);
+            }
+        }
+    }
+
+    // magnitude category and bits of a value, negative values are stored as ones' complement
+    fn emit_value(&mut self, scan_component: usize, zeros: u8, value: i32) {
+        let category = bit_length(value.abs());
+        self.emit_symbol(scan_component, (zeros << 4) | category);
+        self.emit_bits(if value < 0 { value - 1 } else { value }, category);
+    }
+
+    fn emit_correction_bits(&mut self, bits: &[bool]) {
+        for bit in bits {
+            self.emit_bits(*bit as i32, 1);
+        }
+    }
+
+    fn emit_eobrun(&mut self) {
+        if self.eobrun > 0 {
+            let category = bit_length(self.eobrun as i32) - 1;
+            self.emit_symbol(0, category << 4);
+            self.emit_bits(self.eobrun as i32, category);
+            self.eobrun = 0;
+
+            let correction_bits = std::mem::replace(&mut self.correction_bits, Vec::new());
+            self.emit_correction_bits(&correction_bits);
+        }
+    }
+
+    fn encode_block(&mut self, scan_component: usize, block: &[i32; 64]) {
+        match (self.scan.spectral_start, self.scan.successive_high) {
+            (0, 0) => {
+                // arithmetic shift is the point transform of DC coefficients
+                let value = block[0] >> self.scan.successive_low;
+                let diff = value - self.prev_dc[scan_component];
+                self.prev_dc[scan_component] = value;
+                self.emit_value(scan_component, 0, diff);
+            },
+            (0, _) => self.emit_bits(block[0] >> self.scan.successive_low, 1),
+            (_, 0) => self.encode_ac_first(block),
+            (_, _) => self.encode_ac_refine(block),
+        }
+    }
+
+    fn encode_ac_first(&mut self, block: &[i32; 64]) {
+        let mut zeros = 0;
+        for k in self.scan.spectral_start..=self.scan.spectral_end {
+            // AC coefficients are divided keeping their sign
+            let coefficient = block[ZIGZAG_ORDER[k] as usize];
+            let value = coefficient.signum() * (coefficient.abs() >> self.scan.successive_low);
+            if value == 0 {
+                zeros += 1;
+                continue;
+            }
+
+            self.emit_eobrun();
+            while zeros > 15 {
+                self.emit_symbol(0, 0xF0);
+                zeros -= 16;
+            }
+            self.emit_value(0, zeros, value);
+            zeros = 0;
+        }
+
+        if zeros > 0 {
+            self.eobrun += 1;
+            if self.eobrun == MAX_EOBRUN {
+                self.emit_eobrun();
+            }
+        }
+    }
+
+    fn encode_ac_refine(&mut self, block: &[i32; 64]) {
+        let spectral_start = self.scan.spectral_start;
+        let spectral_end = self.scan.spectral_end;
+        let values: Vec<i32> = (0..64)
+            .map(|k| block[ZIGZAG_ORDER[k] as usize].abs() >> self.scan.successive_low)
+            .collect();
+        // position of the last coefficient which becomes non-zero in this scan
+        let last_new = (spectral_start..=spectral_end).rev().find(|k| values[*k] == 1);
+
+        let mut zeros = 0;
+        let mut block_correction_bits = Vec::new();
+        for k in spectral_start..=spectral_end {
+            let value = values[k];
+            if value == 0 {
+                zeros += 1;
+                continue;
+            }
+
+            while zeros > 15 && last_new.map(|last| k <= last).unwrap_or(false) {
+                self.emit_eobrun();
+                self.emit_symbol(0, 0xF0);
+                zeros -= 16;
+                self.emit_correction_bits(&block_correction_bits);
+                block_correction_bits.clear();
+            }
+
+            if value > 1 {
+                // coefficient was non-zero before, only one more bit of it is sent
+                block_correction_bits.push(value & 1 == 1);
+                continue;
+            }
+
+            self.emit_eobrun();
+            self.emit_symbol(0, (zeros << 4) | 1);
+            self.emit_bits(if block[ZIGZAG_ORDER[k] as usize] < 0 { 0 } else { 1 }, 1);
+            self.emit_correction_bits(&block_correction_bits);
+            block_correction_bits.clear();
+            zeros = 0;
+        }
+
+        if zeros > 0 || !block_correction_bits.is_empty() {
+            self.eobrun += 1;
+            self.correction_bits.append(&mut block_correction_bits);
+            // keep the buffer of correction bits bounded, the same way libjpeg does
+            if self.eobrun == MAX_EOBRUN || self.correction_bits.len() > MAX_CORRECTION_BITS - 63 {
+                self.emit_eobrun();
+            }
+        }
+    }
+}
+
+const MAX_EOBRUN: u32 = 0x7FFF;
+const MAX_CORRECTION_BITS: usize = 1000;
+
+// Encodes a scan of a progressive image. Scan is encoded twice: first to gather symbol statistics and then
+// with Huffman tables built for them, as standard tables lack symbols for EOB runs. Scan components with the same
+// table id share a table. Returns the tables (to be written before the scan) and the entropy-coded data of each
+// restart interval.
+pub fn encode_scan(
+    scan: &Scan,
+    table_ids: &[u8],
+    components: &[ComponentCoefficients],
+    horizontal_mcus: usize,
+    vertical_mcus: usize,
+    restart_interval: usize
+) -> (Vec<HuffmanTable>, Vec<Vec<u8>>) {
+    let mcus = scan_blocks(scan, components, horizontal_mcus, vertical_mcus);
+    let encode = |encoder: &mut ScanEncoder| {
+        for interval in restart_intervals(&mcus, restart_interval) {
+            for (scan_component, component_index, block_index) in interval.iter().flatten() {
+                encoder.encode_block(*scan_component, &components[*component_index].blocks[*block_index]);
+            }
+            encoder.restart();
+        }
+    };
+
+    let mut tables = Vec::new();
+    if scan.spectral_start != 0 || scan.successive_high == 0 {
+        let mut statistics = ScanEncoder::new(scan, None);
+        encode(&mut statistics);
+
+        let table_type = if scan.spectral_start == 0 { HuffmanTableType::DC } else { HuffmanTableType::AC };
+        for (scan_component, table_id) in table_ids.iter().enumerate() {
+            if tables.iter().any(|table: &HuffmanTable| table.id == *table_id) {
+                continue;
+            }
+
+            let mut builder = HuffmanTreeBuilder::new();
+            for other in (scan_component..table_ids.len()).filter(|other| table_ids[*other] == *table_id) {
+                for (symbol, count) in &statistics.counts[other] {
+                    builder.append_count(*symbol, *count);
+                }
+            }
+
+            let (bits, values) = builder.build_jpeg_table();
+            tables.push(HuffmanTable::from_vk(*table_id, table_type.clone(), &canonical_codes(&bits, &values)));
+        }
+    }
+
+    let codes = table_ids.iter()
+        .map(|table_id| tables.iter().find(|table| table.id == *table_id).map(|table| table.vk_table()).unwrap_or(HashMap::new()))
+        .collect();
+    let mut encoder = ScanEncoder::new(scan, Some(codes));
+    encode(&mut encoder);
+
+    (tables, encoder.intervals)
+}
+
+fn bit_length(value: i32) -> u8 {
+    (32 - value.leading_zeros()) as u8
+}
+
+fn ceil_div(a: usize, b: usize) -> usize {
+    (a + b - 1) / b
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_receive_extend() {
+        // category 3: 000..011 are -7..-4, 100..111 are 4..7
+        let data = [0b0001_1110];
+        let mut reader = BitReader::new(&data);
+        assert_eq!(reader.receive_extend(3), -7);
+        assert_eq!(reader.receive_extend(3), 7);
+        assert_eq!(reader.receive_extend(0), 0);
+    }
+
+    #[test]
+    fn test_decode_dc_refine() {
+        let scan = Scan {
+            components: vec![ScanComponent { index: 0, dc_table: None, ac_table: None }],
+            spectral_start: 0,
+            spectral_end: 0,
+            successive_high: 1,
+            successive_low: 0,
+        };
+        let channel = Channel { id: 1, horizontal_sampling: 1, vertical_sampling: 1, quantization_table_id: 0 };
+        let mut components = vec![ComponentCoefficients::new(&channel, 16, 8, 1, 1)];
+        components[0].blocks[0][0] = 4;
+        components[0].blocks[1][0] = -6;
+
+        decode_scan(&[vec![0b1100_0000]], 0, &scan, &mut components, 2, 1).unwrap();
+        assert_eq!(components[0].blocks[0][0], 5);
+        assert_eq!(components[0].blocks[1][0], -5);
+    }
+
+    #[test]
+    fn test_invalid_scan() {
+        let scan = Scan {
+            components: vec![
+                ScanComponent { index: 0, dc_table: None, ac_table: None },
+                ScanComponent { index: 1, dc_table: None, ac_table: None },
+            ],
+            spectral_start: 1,
+            spectral_end: 5,
+            successive_high: 0,
+            successive_low: 0,
+        };
+        assert!(scan.validate().is_err());
+    }
+}
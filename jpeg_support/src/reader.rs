@@ -1,115 +1,164 @@
-use std::f32::consts::PI;
 use byteorder::{BigEndian, ByteOrder};
-use lazy_static::lazy_static;
 use bit_vec::BitVec;
+use rayon::prelude::*;
 
-use core::models::{image::Image, pixel::Pixel, io::{ImageIOError, ImageReader}};
+use core::models::{image::Image, pixel::Pixel, io::{ImageIOError, ImageReader}, metadata::Exif};
 use std::collections::HashMap;
 
-use crate::{common::{Channel, HuffmanTable, HuffmanTableType}, common::read_huffman_encoded_channels_data, common::{ChannelID, ycbcr_to_rgb}, common::unzigzag_64, errors::JPEGReaderError, huffman::HuffmanTree, common::unescape_image_data};
+use crate::{common::{Channel, HuffmanTable, HuffmanTableType}, common::read_huffman_encoded_channels_data, common::{ChannelID, ycbcr_to_rgb, cmyk_to_rgb}, common::unzigzag_64, errors::JPEGReaderError, huffman::HuffmanTree, common::read_restart_intervals, dct::{inverse_dct, inverse_dct_scales}, exif::{EXIF_HEADER, read_exif, set_orientation, apply_orientation}, progressive::{ComponentCoefficients, Scan, ScanComponent, decode_scan, blocks_in_mcu_order, components_from_mcu_blocks}};
 
 // see:
 // https://habr.com/ru/post/102521/
 
-lazy_static! {
-    static ref DCT_PRECOMPUTED: [f32; 4096] = precompute_dct();
-}
+const ADOBE_HEADER: &'static [u8] = b"Adobe";
+
+// see: https://exiftool.org/TagNames/JPEG.html#Adobe
+const ADOBE_TRANSFORM_NONE: u8 = 0;
+const ADOBE_TRANSFORM_YCCK: u8 = 2;
 
 pub struct JPEGReader {
+    // images are rotated and mirrored according to EXIF orientation, unless disabled
+    apply_orientation: bool,
 }
 
 impl JPEGReader {
 
     pub fn new() -> Self {
-        JPEGReader {}
+        JPEGReader {
+            apply_orientation: true,
+        }
+    }
+
+    pub fn with_apply_orientation(mut self, apply_orientation: bool) -> Self {
+        self.apply_orientation = apply_orientation;
+        self
     }
 }
 
 #[derive(Clone)]
-struct JPEG {
+pub(crate) struct JPEG {
 
-    image: Option<Image>,
+    // all image data is read, the rest of segments can be skipped
+    complete: bool,
 
-    width: u16,
-    height: u16,
-    quantization_tables: Vec<QuantizationTable>,
-    channels: Vec<Channel>,
+    pub(crate) width: u16,
+    pub(crate) height: u16,
+    pub(crate) quantization_tables: Vec<QuantizationTable>,
+    pub(crate) channels: Vec<Channel>,
     huffman_tables: Vec<HuffmanTable>,
+    pub(crate) progressive: bool,
+    pub(crate) coefficients: Vec<ComponentCoefficients>,
+    // in MCUs, 0 means that restart markers are not used
+    restart_interval: u16,
+    pub(crate) exif: Option<Exif>,
+    // color transform from Adobe APP14 segment
+    pub(crate) adobe_transform: Option<u8>,
 }
 
 impl JPEG {
 
     fn new() -> Self {
         JPEG {
-            image: None,
+            complete: false,
             width: 0,
             height: 0,
             quantization_tables: Vec::new(),
             channels: Vec::new(),
             huffman_tables: Vec::new(),
+            progressive: false,
+            coefficients: Vec::new(),
+            restart_interval: 0,
+            exif: None,
+            adobe_transform: None,
         }
     }
 
-    fn with_quantization_table(&self, table: QuantizationTable) -> Self {
+    fn with_quantization_table(self, table: QuantizationTable) -> Self {
+        let mut quantization_tables: Vec<QuantizationTable> = self.quantization_tables.into_iter()
+            .filter(|t| t.id != table.id)
+            .collect();
+        quantization_tables.push(table);
+
         JPEG {
-            image: self.image.clone(),
-            width: self.width,
-            height: self.height,
-            quantization_tables: {
-                let mut tables = self.quantization_tables.clone();
-                tables.push(table);
-                tables
-            },
-            channels: self.channels.clone(),
-            huffman_tables: self.huffman_tables.clone(),
+            quantization_tables,
+            ..self
         }
     }
 
-    fn with_baseline_dct(&self, width: u16, height: u16, channels: Vec<Channel>) -> Self {
+    fn with_baseline_dct(self, width: u16, height: u16, channels: Vec<Channel>) -> Self {
         JPEG {
-            image: self.image.clone(),
             width,
             height,
-            quantization_tables: self.quantization_tables.clone(),
             channels,
-            huffman_tables: self.huffman_tables.clone(),
+            ..self
         }
     }
 
-    fn with_huffman_table(&self, table: HuffmanTable) -> Self {
+    fn with_huffman_table(self, table: HuffmanTable) -> Self {
+        // tables can be redefined between scans
+        let mut huffman_tables: Vec<HuffmanTable> = self.huffman_tables.into_iter()
+            .filter(|t| !(t.table_type == table.table_type && t.id == table.id))
+            .collect();
+        huffman_tables.push(table);
+
         JPEG {
-            image: self.image.clone(),
-            width: self.width,
-            height: self.height,
-            quantization_tables: self.quantization_tables.clone(),
-            channels: self.channels.clone(),
-            huffman_tables: {
-                let mut tables = self.huffman_tables.clone();
-                tables.push(table);
-                tables
-            },
+            huffman_tables,
+            ..self
         }
     }
 
-    fn with_image(&self, image: Image) -> Self {
+    fn with_progressive_coefficients(self) -> Self {
+        let (max_horizontal_sampling, max_vertical_sampling, _, _) = self.mcu_dimensions();
+        let coefficients = self.channels.iter()
+            .map(|channel| ComponentCoefficients::new(
+                channel,
+                self.width as usize,
+                self.height as usize,
+                max_horizontal_sampling,
+                max_vertical_sampling
+            ))
+            .collect();
+
         JPEG {
-            image: Some(image),
-            width: self.width,
-            height: self.height,
-            quantization_tables: self.quantization_tables.clone(),
-            channels: self.channels.clone(),
-            huffman_tables: self.huffman_tables.clone(),
+            progressive: true,
+            coefficients,
+            ..self
         }
     }
 
-    fn channels_as_map(&self) -> HashMap<ChannelID, Channel> {
-        let mut result = HashMap::new();
+    fn with_coefficients(self, coefficients: Vec<ComponentCoefficients>) -> Self {
+        JPEG {
+            coefficients,
+            ..self
+        }
+    }
 
-        for channel in &self.channels {
-            result.insert(channel.id, channel.clone());
+    fn with_restart_interval(self, restart_interval: u16) -> Self {
+        JPEG {
+            restart_interval,
+            ..self
         }
+    }
 
-        result
+    fn with_exif(self, exif: Exif) -> Self {
+        JPEG {
+            exif: Some(exif),
+            ..self
+        }
+    }
+
+    fn with_adobe_transform(self, adobe_transform: u8) -> Self {
+        JPEG {
+            adobe_transform: Some(adobe_transform),
+            ..self
+        }
+    }
+
+    fn with_complete(self) -> Self {
+        JPEG {
+            complete: true,
+            ..self
+        }
     }
 
     fn huffman_table_by_type(&self, table_type: HuffmanTableType, id: u8) -> Option<HuffmanTable> {
@@ -118,6 +167,15 @@ impl JPEG {
             .map(|v| v.clone())
     }
 
+    // maximum sampling factors and image dimensions in MCUs
+    pub(crate) fn mcu_dimensions(&self) -> (usize, usize, usize, usize) {
+        let max_horizontal_sampling = self.channels.iter().map(|c| c.horizontal_sampling as usize).max().unwrap_or(1);
+        let max_vertical_sampling = self.channels.iter().map(|c| c.vertical_sampling as usize).max().unwrap_or(1);
+        let horizontal_mcus = (self.width as usize + 8 * max_horizontal_sampling - 1) / (8 * max_horizontal_sampling);
+        let vertical_mcus = (self.height as usize + 8 * max_vertical_sampling - 1) / (8 * max_vertical_sampling);
+        (max_horizontal_sampling, max_vertical_sampling, horizontal_mcus, vertical_mcus)
+    }
+
     fn quantization_table_by_id(&self, id: u8) -> Option<QuantizationTable> {
         self.quantization_tables.iter()
             .find(|t| t.id == id)
@@ -126,34 +184,72 @@ impl JPEG {
 }
 
 #[derive(Clone)]
-struct QuantizationTable {
-    id: u8,
-    data: [i32; 64],
+pub(crate) struct QuantizationTable {
+    pub(crate) id: u8,
+    pub(crate) data: [i32; 64],
 }
 
 impl ImageReader for JPEGReader {
     
     fn read(&self, data: &Vec<u8>) -> Result<Vec<Image>, ImageIOError> {
-        let magic = (data[0], data[1]);
-        match magic {
-            (0xFF, 0xD8) => {},
-            other => panic!("Unexpected header magic: {:?}", other),
-        };
+        let jpeg = read_coefficients(data).map_err(|err| ImageIOError::FailedToRead {
+            description: err.to_string(),
+        })?;
+
+        let (_, _, horizontal_mcus, vertical_mcus) = jpeg.mcu_dimensions();
+        let mut image = decode_image(&jpeg, &blocks_in_mcu_order(&jpeg.coefficients, horizontal_mcus, vertical_mcus))
+            .map_err(|err| ImageIOError::FailedToRead {
+                description: format!("Failed to decode image: {}", err),
+            })?;
+        if let Some(mut exif) = jpeg.exif {
+            let orientation = exif.orientation().unwrap_or(1);
+            if self.apply_orientation && orientation != 1 {
+                image = apply_orientation(&image, orientation);
+                // pixels are in normal orientation now, so it should not be applied again when the image is written
+                if let Err(err) = set_orientation(&mut exif, 1) {
+                    warn!("failed to reset exif orientation: {}", err);
+                }
+            }
+            image.metadata.exif = Some(exif);
+        }
 
-        let mut jpeg = JPEG::new();
-        let mut data = &data[2..];
+        Ok(vec![image])
+    }
+}
 
-        while jpeg.image.is_none() {
-            let (image, offset) = read_segment(&data, &mut jpeg).expect("failed to read segment");
-            jpeg = image;
-            data = &data[offset..];
+// Reads all segments up to the end of image data, leaving quantized coefficients as they are.
+pub(crate) fn read_coefficients(data: &[u8]) -> Result<JPEG, JPEGReaderError> {
+    if data.len() < 2 || data[0] != 0xFF || data[1] != 0xD8 {
+        return Err(JPEGReaderError::InvalidHeader {
+            description: format!("Unexpected header magic: {:x?}", &data[0..data.len().min(2)]),
+        });
+    }
+
+    let mut jpeg = JPEG::new();
+    let mut data = &data[2..];
+
+    while !jpeg.complete {
+        if data.is_empty() {
+            return Err(JPEGReaderError::InvalidEncodedData {
+                description: "Unexpected end of data, image was not decoded".to_string(),
+            });
         }
 
-        Ok(vec![jpeg.image.expect("expected image to be present, because checked for it previously")])
+        let (next, offset) = read_segment(&data, jpeg)?;
+        jpeg = next;
+        data = &data[offset.min(data.len())..];
     }
+
+    Ok(jpeg)
 }
 
-fn read_segment(data: &[u8], jpeg: &JPEG) -> Result<(JPEG, usize), JPEGReaderError> {
+fn read_segment(data: &[u8], jpeg: JPEG) -> Result<(JPEG, usize), JPEGReaderError> {
+    if data.len() < 2 {
+        return Err(JPEGReaderError::InvalidSegment {
+            description: "Segment is truncated".to_string(),
+        });
+    }
+
     let marker = (data[0], data[1]);
     
     if marker.0 != 0xFF {
@@ -162,284 +258,402 @@ fn read_segment(data: &[u8], jpeg: &JPEG) -> Result<(JPEG, usize), JPEGReaderErr
         });
     }
 
+    // markers may be preceded by any amount of fill bytes
+    if marker.1 == 0xFF {
+        return Ok((jpeg, 1));
+    }
+
    let data = &data[2..];
 
+    let standalone_marker = marker.1 == 0xD9 || (0xD0..=0xD7).contains(&marker.1);
+    if !standalone_marker {
+        if data.len() < 2 {
+            return Err(JPEGReaderError::InvalidSegment {
+                description: format!("Segment {:x?} is truncated", marker.1),
+            });
+        }
+
+        // segment length includes the two bytes of the length itself
+        let block_length = BigEndian::read_u16(&data[0..2]) as usize;
+        if block_length < 2 || block_length > data.len() {
+            return Err(JPEGReaderError::InvalidSegment {
+                description: format!("Segment {:x?} has invalid length {}", marker.1, block_length),
+            });
+        }
+    }
+
     match marker.1 {
-        0xE0..=0xEF => read_application_specific_data(&data).map(|v| (jpeg.clone(), v)),
-        0xFE => read_text_comment(&data).map(|v| (jpeg.clone(), v)),
-        0xDB => read_quantization_table(&data).map(|v| (jpeg.with_quantization_table(v.0), v.1)),
-        0xC0 => read_baseline_dct(&data, &jpeg),
-        0xC4 => read_huffman_table(&data).map(|v| (jpeg.with_huffman_table(v.0), v.1)),
-        0xDA => read_start_of_scan(&data, &jpeg),
-        0xD9 => Ok((jpeg.clone(), 2)),
+        0xE1 if data[2..].starts_with(EXIF_HEADER) => read_exif_segment(&data, jpeg),
+        0xEE if data[2..].starts_with(ADOBE_HEADER) => read_adobe_segment(&data).map(|(adobe_transform, offset)| (
+            jpeg.with_adobe_transform(adobe_transform),
+            offset
+        )),
+        0xE0..=0xEF => read_application_specific_data(&data).map(|v| (jpeg, v)),
+        0xFE => read_text_comment(&data).map(|v| (jpeg, v)),
+        0xDB => read_quantization_table(&data).map(|(tables, offset)| (
+            tables.into_iter().fold(jpeg, |jpeg, table| jpeg.with_quantization_table(table)),
+            offset
+        )),
+        // extended sequential frames are decoded the same way as baseline ones when precision is 8 bits
+        0xC0 | 0xC1 => read_baseline_dct(&data, jpeg),
+        0xC2 => read_baseline_dct(&data, jpeg).map(|(jpeg, offset)| (jpeg.with_progressive_coefficients(), offset)),
+        0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => Err(JPEGReaderError::UnsupportedFeature {
+            description: format!("Frame type {:x?} is not supported, only baseline, extended sequential and progressive Huffman-coded frames are", marker.1),
+        }),
+        0xC4 => read_huffman_table(&data).map(|(tables, offset)| (
+            tables.into_iter().fold(jpeg, |jpeg, table| jpeg.with_huffman_table(table)),
+            offset
+        )),
+        0xDA if jpeg.progressive => read_progressive_scan(&data, jpeg),
+        0xDA => read_start_of_scan(&data, jpeg),
+        0xD9 if jpeg.progressive => Ok((jpeg.with_complete(), 2)),
+        0xD9 => Ok((jpeg, 2)),
+        0xDD => read_restart_interval(&data).map(|(restart_interval, offset)| (jpeg.with_restart_interval(restart_interval), offset)),
+        // restart markers outside of entropy-coded data are left from damaged scans
+        0xD0..=0xD7 => Ok((jpeg, 2)),
         _ => Err(JPEGReaderError::InvalidSegment {
             description: format!("Unknown segment marker: {:x?}", marker)
         })
     }
 }
 
-fn read_start_of_scan(data: &[u8], jpeg: &JPEG) -> Result<(JPEG, usize), JPEGReaderError> {
+fn read_start_of_scan(data: &[u8], jpeg: JPEG) -> Result<(JPEG, usize), JPEGReaderError> {
     let block_length = BigEndian::read_u16(&data[0..2]) as usize;
-    let data = &data[2..];
+    let header = &data[2..block_length];
 
-    let mut jpeg = jpeg.clone();
-    
-    let total_channels = data[0];
-    if total_channels != 3 {
+    let total_channels = *header.first().unwrap_or(&0) as usize;
+    if header.len() < 1 + total_channels * 2 + 3 {
         return Err(JPEGReaderError::InvalidSegment {
-            description: format!("Unexpected number of channels in start of scan segment: {}", total_channels),
+            description: format!("Start of scan segment is too short for {} channels", total_channels),
         });
     }
-    let mut data = &data[1..];
-
-    let mut huffman_ac_by_channel: HashMap<u8, u8> = HashMap::new();
-    let mut huffman_dc_by_channel: HashMap<u8, u8> = HashMap::new();
-
-    for _ in 0..total_channels {
-        let channel_id = data[0];
-        let huffman_dc_id = data[1] >> 4;
-        let huffman_ac_id = data[1] & 0b1111;
-        data = &data[2..];
-
-        huffman_dc_by_channel.insert(channel_id, huffman_dc_id);
-        huffman_ac_by_channel.insert(channel_id, huffman_ac_id);
+    if total_channels != jpeg.channels.len() {
+        return Err(JPEGReaderError::UnsupportedFeature {
+            description: format!(
+                "Sequential scans which contain {} out of {} channels are not supported",
+                total_channels,
+                jpeg.channels.len()
+            ),
+        });
     }
+    let mut header = &header[1..];
 
-    let _start_of_spectral_or_predictor_selection = data[0];
-    let _end_of_spectral_selection = data[1];
-    let _successive_approximation_bit_position = data[2];
-    let data = &data[3..];
-    
-    let data = &data[0..data.len()-2];
-    let (data, data_length) = unescape_image_data(&data)?;
-
-    // reading encoded bits
-    let bitvec = BitVec::from_bytes(&data);
-
-    trace!("bitvec length is {}", bitvec.len());
-
-    trace!("image dimensions: {} {}", jpeg.width, jpeg.height);
-    let max_horizontal_sampling = 8 * jpeg.channels.iter().map(|c| c.horizontal_sampling).max()
-        .expect("expected at least one channel to be present");
-    let max_vertical_sampling = 8 * jpeg.channels.iter().map(|c| c.vertical_sampling).max()
-        .expect("expected at least one channel to be present");
-    trace!("max sampling: {} {}", max_horizontal_sampling, max_vertical_sampling);
-    let horizontal_mcus = ((jpeg.width as f32) / (max_horizontal_sampling as f32)).ceil() as usize;
-    let vertical_mcus = ((jpeg.height as f32) / (max_vertical_sampling as f32)).ceil() as usize;
-    trace!("image dimensions in MCUs: {} {}", horizontal_mcus, vertical_mcus);
+    // channels are listed in the same order as in the frame
+    let mut channels = Vec::new();
+    let mut huffman_table_by_channel: HashMap<(HuffmanTableType, ChannelID), HuffmanTable> = HashMap::new();
+    for _ in 0..total_channels {
+        let channel_id = header[0];
+        let huffman_dc_id = header[1] >> 4;
+        let huffman_ac_id = header[1] & 0b1111;
+        header = &header[2..];
 
-    let mut image = Image::new(
-        ((jpeg.width as f32 / max_horizontal_sampling as f32).ceil() * max_horizontal_sampling as f32) as usize, 
-        ((jpeg.height as f32 / max_vertical_sampling as f32).ceil() * max_vertical_sampling as f32) as usize, 
-    );
+        channels.push(jpeg.channels.iter().find(|channel| channel.id == channel_id).cloned()
+            .ok_or(JPEGReaderError::InvalidSegment {
+                description: format!("Scan refers to unknown channel {}", channel_id),
+            })?);
 
-    let channels = jpeg.channels_as_map();
-    
-    let mut huffman_table_by_channel: HashMap<(HuffmanTableType, ChannelID), HuffmanTable> = HashMap::new();
-    for channel_id in 1..=total_channels {
         huffman_table_by_channel.insert(
             (HuffmanTableType::DC, channel_id), 
-            jpeg.huffman_table_by_type(HuffmanTableType::DC, huffman_dc_by_channel[&(channel_id as u8)])
+            jpeg.huffman_table_by_type(HuffmanTableType::DC, huffman_dc_id)
                 .ok_or(JPEGReaderError::InvalidEncodedData {
-                    description: format!("DC Huffman table with id = {} is not present", channel_id)
+                    description: format!("DC Huffman table with id = {} is not present", huffman_dc_id)
                 })?
         );
 
         huffman_table_by_channel.insert(
             (HuffmanTableType::AC, channel_id),
-            jpeg.huffman_table_by_type(HuffmanTableType::AC ,huffman_ac_by_channel[&(channel_id as u8)])
+            jpeg.huffman_table_by_type(HuffmanTableType::AC, huffman_ac_id)
                 .ok_or(JPEGReaderError::InvalidEncodedData {
-                    description: format!("DC Huffman table with id = {} is not present", channel_id)
+                    description: format!("AC Huffman table with id = {} is not present", huffman_ac_id)
                 })?
         );
     }
-    
-    let all_matrices = read_huffman_encoded_channels_data(
-        &bitvec,
-        vertical_mcus * horizontal_mcus,
-        &channels,
-        &huffman_table_by_channel,
-    )?;
-    let mut matrix_offset = 0;
 
-    for row in 0..vertical_mcus {
-        for col in 0..horizontal_mcus {
-            let mut channel_data: HashMap<u8, Vec<i32>> = HashMap::new();
+    let _start_of_spectral_or_predictor_selection = header[0];
+    let _end_of_spectral_selection = header[1];
+    let _successive_approximation_bit_position = header[2];
+    
+    let (intervals, data_length) = read_restart_intervals(&data[block_length..]);
 
-            for channel_id in (1 as ChannelID)..=(total_channels as ChannelID) {
-                let channel = &channels[&channel_id];
-                let mut matrices: Vec<[i32; 64]> = Vec::new();
-                for _ in 0..(channel.vertical_sampling * channel.horizontal_sampling) {
-                    matrices.push(all_matrices[matrix_offset].clone());
-                    matrix_offset += 1;
-                }
+    let (_, _, horizontal_mcus, vertical_mcus) = jpeg.mcu_dimensions();
+    trace!("image dimensions in MCUs: {} {}", horizontal_mcus, vertical_mcus);
 
-                // quantization
-                let quantization_table: [i32; 64] = jpeg.quantization_table_by_id(channel.quantization_table_id)
-                    .ok_or(JPEGReaderError::InvalidEncodedData {
-                        description: format!("Quantization table with id {} not found", channel.quantization_table_id)
-                    })?
-                    .data;
-                
-                // discrete cosine transform 
-                let matrices: Vec<[i32; 64]> = matrices.iter()
-                    .map(|m| multiply_64(&m, &quantization_table))
-                    .map(|m| dct_decode(&m))
-                    .collect();
-                
-                // how many pixels should each unit take
-                let v_ratio = max_vertical_sampling / channel.vertical_sampling;
-                let h_ratio = max_horizontal_sampling / channel.horizontal_sampling;
-                // scaling (i.e. 1 unit pixel = x actual pixels)
-                let v_scaling = v_ratio / 8;
-                let h_scaling = h_ratio / 8;
-
-                let mut scale_result: Vec<i32> = vec![0; max_horizontal_sampling as usize * max_vertical_sampling as usize];
-                let mut offset_x: usize = 0;
-                let mut offset_y: usize = 0;
-                for matrix_index in 0..matrices.len() {
-                    let matrix = &matrices[matrix_index];
-                                                            
-                    for y in 0..8 {
-                        for x in 0..8 {
-                            let value = matrix[y * 8 + x];
-
-                            for hs in 0..h_scaling {
-                                for vs in 0..v_scaling {
-                                    let pos = (y * v_scaling as usize + vs as usize + offset_y) * max_horizontal_sampling as usize + 
-                                        (x * h_scaling as usize + hs as usize + offset_x);
-                                    scale_result[pos] = value;
-                                }
-                            }
-                        }
-                    }
+    let total_mcus = vertical_mcus * horizontal_mcus;
+    let blocks_per_mcu: usize = jpeg.channels.iter()
+        .map(|channel| (channel.horizontal_sampling * channel.vertical_sampling) as usize)
+        .sum();
+    let restart_interval = if jpeg.restart_interval == 0 { total_mcus.max(1) } else { jpeg.restart_interval as usize };
+
+    let mut all_matrices = Vec::with_capacity(total_mcus * blocks_per_mcu);
+    for (interval_index, interval_start) in (0..total_mcus).step_by(restart_interval).enumerate() {
+        let interval_mcus = restart_interval.min(total_mcus - interval_start);
+        let interval_data = intervals.get(interval_index).cloned().unwrap_or(Vec::new());
+        trace!("restart interval {} data length is {}", interval_index, interval_data.len());
+
+        let matrices = read_huffman_encoded_channels_data(
+            &BitVec::from_bytes(&interval_data),
+            interval_mcus,
+            &channels,
+            &huffman_table_by_channel,
+        );
 
-                    offset_x += h_ratio as usize;
-                    if offset_x == max_horizontal_sampling as usize {
-                        offset_x = 0;
-                        offset_y += v_ratio as usize;
-                    }
-                }
+        match matrices {
+            Ok(mut matrices) => all_matrices.append(&mut matrices),
+            // restart markers allow to continue decoding after a damaged interval, which is left blank
+            Err(err) if jpeg.restart_interval != 0 => {
+                warn!("failed to decode restart interval {}, skipping it: {}", interval_index, err);
+                all_matrices.append(&mut vec![[0i32; 64]; interval_mcus * blocks_per_mcu]);
+            },
+            Err(err) => return Err(err),
+        }
+    }
+    let coefficients = components_from_mcu_blocks(&jpeg.channels, jpeg.width as usize, jpeg.height as usize, &all_matrices);
 
-                channel_data.insert(channel_id, scale_result);
-            }
+    trace!("done reading image data");
+    Ok((jpeg.with_coefficients(coefficients).with_complete(), block_length + 2 + data_length))
+}
 
-            let image_x_offset = col * max_horizontal_sampling as usize;
-            let image_y_offset = row * max_vertical_sampling as usize;
-
-            let y_channel = channel_data.get(&1).expect("Expected channel with id = 1 to be present, becuase already checked for that");
-            let cb_channel = channel_data.get(&2).expect("Expected channel with id = 2 to be present, becuase already checked for that");
-            let cr_channel = channel_data.get(&3).expect("Expected channel with id = 3 to be present, becuase already checked for that");
-            for y in 0..max_vertical_sampling {
-                for x in 0..max_horizontal_sampling {
-                    let (r, g, b) = ycbcr_to_rgb(
-                        y_channel[(y * max_horizontal_sampling + x) as usize], 
-                        cb_channel[(y * max_horizontal_sampling + x) as usize], 
-                        cr_channel[(y * max_horizontal_sampling + x) as usize]
-                    );
-
-                    image.set_pixel(image_x_offset + x as usize, image_y_offset + y as usize, Pixel::from_rgb(r, g, b));
-                }
-            }
+// Turns quantized coefficients of all MCUs (blocks are listed by MCU, then by channel) into an image.
+fn decode_image(jpeg: &JPEG, all_matrices: &[[i32; 64]]) -> Result<Image, JPEGReaderError> {
+    trace!("image dimensions: {} {}", jpeg.width, jpeg.height);
+    let (max_horizontal_sampling, max_vertical_sampling, horizontal_mcus, vertical_mcus) = jpeg.mcu_dimensions();
+    let mcu_width = 8 * max_horizontal_sampling;
+    let mcu_height = 8 * max_vertical_sampling;
+    trace!("max sampling: {} {}", max_horizontal_sampling, max_vertical_sampling);
 
+    // quantization is folded into scale factors of inverse dct
+    let scales = jpeg.channels.iter()
+        .map(|channel| jpeg.quantization_table_by_id(channel.quantization_table_id)
+            .map(|table| inverse_dct_scales(&table.data))
+            .ok_or(JPEGReaderError::InvalidEncodedData {
+                description: format!("Quantization table with id {} not found", channel.quantization_table_id)
+            })
+        )
+        .collect::<Result<Vec<[i32; 64]>, JPEGReaderError>>()?;
+
+    let blocks_per_mcu: usize = jpeg.channels.iter()
+        .map(|channel| (channel.horizontal_sampling * channel.vertical_sampling) as usize)
+        .sum();
+
+    // MCUs are independent from each other, so they are decoded in parallel
+    let mcus: Vec<Vec<Pixel>> = all_matrices.par_chunks(blocks_per_mcu)
+        .take(horizontal_mcus * vertical_mcus)
+        .map(|matrices| decode_mcu(jpeg, matrices, &scales, mcu_width, mcu_height))
+        .collect();
+
+    let mut image = Image::new(horizontal_mcus * mcu_width, vertical_mcus * mcu_height);
+    for (mcu_index, mcu) in mcus.iter().enumerate() {
+        let image_x_offset = (mcu_index % horizontal_mcus) * mcu_width;
+        let image_y_offset = (mcu_index / horizontal_mcus) * mcu_height;
+
+        for y in 0..mcu_height {
+            let offset = (image_y_offset + y) * image.width + image_x_offset;
+            image.pixels[offset..offset + mcu_width].copy_from_slice(&mcu[y * mcu_width..(y + 1) * mcu_width]);
         }
     }
 
-    jpeg = jpeg.with_image(image);
-    trace!("done reading image data");
-    Ok((jpeg, block_length + 2 + data_length))
+    Ok(crop(&image, jpeg.width as usize, jpeg.height as usize))
 }
 
-fn precompute_dct() -> [f32; 4096] {
-    let mut res = [0f32; 4096];
-
-    for y in 0..8 {
-        for x in 0..8 {
-            for v in 0..8 {
-                for u in 0..8 {
-                    let mut m: f32 = if u == 0 { 1.0/2f32.sqrt() } else { 1.0 };
-                    m *= if v == 0 { 1.0/2f32.sqrt() } else { 1.0 };
-                    m *= (((2.0 * (x as f32) + 1.0) * (u as f32) * PI) / 16.0).cos();
-                    m *= (((2.0 * (y as f32) + 1.0) * (v as f32) * PI) / 16.0).cos();
-                    res[y * 512 + x * 64 + v * 8 + u] = m / 4.0;
+fn decode_mcu(jpeg: &JPEG, matrices: &[[i32; 64]], scales: &[[i32; 64]], mcu_width: usize, mcu_height: usize) -> Vec<Pixel> {
+    let mut matrix_offset = 0;
+    let mut channel_data: Vec<Vec<i32>> = Vec::with_capacity(jpeg.channels.len());
+
+    for (channel, scales) in jpeg.channels.iter().zip(scales) {
+        let total_blocks = (channel.vertical_sampling * channel.horizontal_sampling) as usize;
+        let blocks: Vec<[i32; 64]> = matrices[matrix_offset..matrix_offset + total_blocks].iter()
+            .map(|m| inverse_dct(m, scales))
+            .collect();
+        matrix_offset += total_blocks;
+
+        // how many pixels should each unit take
+        let v_ratio = mcu_height / channel.vertical_sampling as usize;
+        let h_ratio = mcu_width / channel.horizontal_sampling as usize;
+        // scaling (i.e. 1 unit pixel = x actual pixels)
+        let v_scaling = v_ratio / 8;
+        let h_scaling = h_ratio / 8;
+
+        let mut scale_result: Vec<i32> = vec![0; mcu_width * mcu_height];
+        let mut offset_x: usize = 0;
+        let mut offset_y: usize = 0;
+        for block in &blocks {
+            for y in 0..8 {
+                for x in 0..8 {
+                    let value = block[y * 8 + x];
+
+                    for hs in 0..h_scaling {
+                        for vs in 0..v_scaling {
+                            let pos = (y * v_scaling + vs + offset_y) * mcu_width + (x * h_scaling + hs + offset_x);
+                            scale_result[pos] = value;
+                        }
+                    }
                 }
             }
+
+            offset_x += h_ratio;
+            if offset_x == mcu_width {
+                offset_x = 0;
+                offset_y += v_ratio;
+            }
         }
+
+        channel_data.push(scale_result);
     }
 
-    res
+    (0..mcu_width * mcu_height)
+        .map(|position| {
+            let (r, g, b) = match (channel_data.len(), jpeg.adobe_transform) {
+                (1, _) => {
+                    let luma = channel_data[0][position] as u8;
+                    (luma, luma, luma)
+                },
+                (3, Some(ADOBE_TRANSFORM_NONE)) => (
+                    channel_data[0][position] as u8,
+                    channel_data[1][position] as u8,
+                    channel_data[2][position] as u8,
+                ),
+                (3, _) => ycbcr_to_rgb(channel_data[0][position], channel_data[1][position], channel_data[2][position]),
+                (_, Some(ADOBE_TRANSFORM_YCCK)) => {
+                    let (c, m, y) = ycbcr_to_rgb(channel_data[0][position], channel_data[1][position], channel_data[2][position]);
+                    cmyk_to_rgb(255 - c as i32, 255 - m as i32, 255 - y as i32, channel_data[3][position])
+                },
+                // without Adobe segment four channels are assumed to be CMYK
+                _ => cmyk_to_rgb(
+                    channel_data[0][position],
+                    channel_data[1][position],
+                    channel_data[2][position],
+                    channel_data[3][position]
+                ),
+            };
+
+            Pixel::from_rgb(r, g, b)
+        })
+        .collect()
 }
 
-pub(crate) fn dct_decode(matrix: &[i32; 64]) -> [i32; 64] {
-    let mut result  = [0f32; 8 * 8];
+// MCUs cover the image with padding, which is not part of the image.
+fn crop(image: &Image, width: usize, height: usize) -> Image {
+    if image.width == width && image.height == height {
+        return image.clone();
+    }
 
-    for y in 0..8 {
-        for x in 0..8 {
-            let mut sum: f32 = 0.0;
-            let offset = y * 512 + x * 64;
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in 0..height {
+        pixels.extend_from_slice(&image.pixels[(y * image.width)..(y * image.width + width)]);
+    }
+    Image::from_pixels(width, height, pixels)
+}
 
-            for (a, b) in matrix.iter().zip(&DCT_PRECOMPUTED[offset..offset+64]) {
-                sum += *a as f32 * *b;
-            }
+fn read_progressive_scan(data: &[u8], mut jpeg: JPEG) -> Result<(JPEG, usize), JPEGReaderError> {
+    let block_length = BigEndian::read_u16(&data[0..2]) as usize;
+    let header = &data[2..block_length];
 
-            result[y * 8 + x] = sum;
-        }
+    let total_components = *header.get(0).unwrap_or(&0) as usize;
+    if total_components == 0 || header.len() != 1 + total_components * 2 + 3 {
+        return Err(JPEGReaderError::InvalidSegment {
+            description: format!("Invalid start of scan segment length {} for {} components", block_length, total_components),
+        });
     }
 
-    let mut result_rounded = [128; 64];
-    for i in 0..64 {
-        result_rounded[i] += (result[i] as i32).max(-128).min(128);
+    let mut components = Vec::new();
+    for i in 0..total_components {
+        let channel_id = header[1 + i * 2];
+        let tables = header[2 + i * 2];
+        let index = jpeg.channels.iter().position(|c| c.id == channel_id)
+            .ok_or(JPEGReaderError::InvalidSegment {
+                description: format!("Scan refers to unknown component {}", channel_id),
+            })?;
+
+        components.push(ScanComponent {
+            index,
+            dc_table: jpeg.huffman_table_by_type(HuffmanTableType::DC, tables >> 4),
+            ac_table: jpeg.huffman_table_by_type(HuffmanTableType::AC, tables & 0b1111),
+        });
     }
 
-    result_rounded
-}
+    let parameters = &header[(1 + total_components * 2)..];
+    let scan = Scan {
+        components,
+        spectral_start: parameters[0] as usize,
+        spectral_end: parameters[1] as usize,
+        successive_high: parameters[2] >> 4,
+        successive_low: parameters[2] & 0b1111,
+    };
 
-fn multiply_64(a: &[i32; 64], b: &[i32; 64]) -> [i32; 64] {
-    let mut result = [0i32; 64];
+    let (intervals, data_length) = read_restart_intervals(&data[block_length..]);
 
-    for i in 0..64 {
-        result[i] = a[i] * b[i];
-    }
+    let (_, _, horizontal_mcus, vertical_mcus) = jpeg.mcu_dimensions();
+    decode_scan(&intervals, jpeg.restart_interval as usize, &scan, &mut jpeg.coefficients, horizontal_mcus, vertical_mcus)?;
 
-    result
+    Ok((jpeg, block_length + 2 + data_length))
 }
 
-fn read_huffman_table(data: &[u8]) -> Result<(HuffmanTable, usize), JPEGReaderError> {
+// A single segment may define several tables.
+fn read_huffman_table(data: &[u8]) -> Result<(Vec<HuffmanTable>, usize), JPEGReaderError> {
     trace!("reading huffman table");
     
     let block_length = BigEndian::read_u16(&data[0..2]) as usize;
-    let data = &data[2..];
-
-    let packed = data[0];
-    let table_type = match packed >> 4 {
-        0 => HuffmanTableType::DC,
-        1 => HuffmanTableType::AC,
-        other => return Err(JPEGReaderError::InvalidSegment {
-            description: format!("Unexpected huffman table type: {}", other)
-        })
-    };
-    let table_id = packed & 0b1111;
-    let data = &data[1..(block_length - 2)];
-
-    let mut tree = HuffmanTree::new();
-    let mut offset = 16;
-    for code_length in 1..=16 {
-        for _ in 0..data[code_length - 1] {
-            tree.insert_code(code_length as u8, data[offset]);
-            offset += 1;
+    if block_length < 2 + 17 {
+        return Err(JPEGReaderError::InvalidSegment {
+            description: format!("Huffman table segment is too short: {}", block_length),
+        });
+    }
+
+    let mut data = &data[2..block_length];
+    let mut tables = Vec::new();
+
+    while !data.is_empty() {
+        if data.len() < 17 {
+            return Err(JPEGReaderError::InvalidSegment {
+                description: "Huffman table is truncated".to_string(),
+            });
         }
+
+        let packed = data[0];
+        let table_type = match packed >> 4 {
+            0 => HuffmanTableType::DC,
+            1 => HuffmanTableType::AC,
+            other => return Err(JPEGReaderError::InvalidSegment {
+                description: format!("Unexpected huffman table type: {}", other)
+            })
+        };
+        let table_id = packed & 0b1111;
+        let code_lengths = &data[1..17];
+        let total_values = code_lengths.iter().map(|v| *v as usize).sum::<usize>();
+        if data.len() < 17 + total_values {
+            return Err(JPEGReaderError::InvalidSegment {
+                description: "Huffman table values are truncated".to_string(),
+            });
+        }
+
+        let mut tree = HuffmanTree::new();
+        let mut offset = 17;
+        for code_length in 1..=16 {
+            for _ in 0..code_lengths[code_length - 1] {
+                tree.insert_code(code_length as u8, data[offset]);
+                offset += 1;
+            }
+        }
+
+        tables.push(HuffmanTable {
+            id: table_id,
+            table_type: table_type,
+            table: tree.to_map(),
+        });
+        data = &data[offset..];
     }
 
-    Ok((HuffmanTable {
-        id: table_id,
-        table_type: table_type,
-        table: tree.to_map(),
-    }, block_length + 2))
+    Ok((tables, block_length + 2))
 }
 
-fn read_baseline_dct(data: &[u8], jpeg: &JPEG) -> Result<(JPEG, usize), JPEGReaderError> {   
+fn read_baseline_dct(data: &[u8], jpeg: JPEG) -> Result<(JPEG, usize), JPEGReaderError> {   
     let block_length = BigEndian::read_u16(&data[0..2]) as usize;
-    let data = &data[2..];
+    if block_length < 2 + 6 {
+        return Err(JPEGReaderError::InvalidSegment {
+            description: format!("Frame segment is too short: {}", block_length),
+        });
+    }
+    let data = &data[2..block_length];
 
     // preicion in bits for components
     let precision = data[0];
@@ -453,12 +667,18 @@ fn read_baseline_dct(data: &[u8], jpeg: &JPEG) -> Result<(JPEG, usize), JPEGRead
     let width = BigEndian::read_u16(&data[3..5]); // samples per line
     trace!("dimensions: {} {}", width, height);
 
+    // grayscale, YCbCr (or RGB) and CMYK (or YCCK)
     let total_channels: u8 = data[5];
-    if total_channels != 3 {
-        return Err(JPEGReaderError::InvalidSegment {
+    if ![1, 3, 4].contains(&total_channels) {
+        return Err(JPEGReaderError::UnsupportedFeature {
             description: format!("Unsupported number of channels: {}", total_channels),
         });
     }
+    if data.len() < 6 + total_channels as usize * 3 {
+        return Err(JPEGReaderError::InvalidSegment {
+            description: format!("Frame segment is too short for {} channels", total_channels),
+        });
+    }
 
     let mut data = &data[6..];
     let mut channels = Vec::new();
@@ -469,6 +689,12 @@ fn read_baseline_dct(data: &[u8], jpeg: &JPEG) -> Result<(JPEG, usize), JPEGRead
         let quantization_table_id = data[2];
         data = &data[3..];
 
+        if !(1..=4).contains(&horizontal_sampling) || !(1..=4).contains(&vertical_sampling) {
+            return Err(JPEGReaderError::InvalidSegment {
+                description: format!("Invalid sampling factors for channel {}: {}x{}", channel_id, horizontal_sampling, vertical_sampling),
+            });
+        }
+
         trace!(
             "channel: id={} horizontal_sampling={} vertical_sampling={} quantization_table={}", 
             channel_id, 
@@ -485,45 +711,106 @@ fn read_baseline_dct(data: &[u8], jpeg: &JPEG) -> Result<(JPEG, usize), JPEGRead
         });
     }
 
+    // a single channel is never interleaved, so each MCU is one block whatever the sampling is
+    if channels.len() == 1 {
+        channels[0].horizontal_sampling = 1;
+        channels[0].vertical_sampling = 1;
+    }
+
     Ok((jpeg.with_baseline_dct(width, height, channels), block_length + 2))
 }
 
-fn read_quantization_table(data: &[u8]) -> Result<(QuantizationTable, usize), JPEGReaderError> {
+// A single segment may define several tables.
+fn read_quantization_table(data: &[u8]) -> Result<(Vec<QuantizationTable>, usize), JPEGReaderError> {
     let block_length = BigEndian::read_u16(&data[0..2]) as usize;
-    let data = &data[2..];
-
-    let packed = data[0];
-    let entry_length = packed >> 4;
-    let table_id = packed & 0b1111;
-    let data = &data[1..];
-
-    if entry_length != 0 {
+    if block_length < 2 + 65 {
         return Err(JPEGReaderError::InvalidSegment {
-            description: format!("Quantization tables with entries of length {} are not supported", entry_length),
+            description: format!("Quantization table segment is too short: {}", block_length),
         });
     }
+
+    let mut data = &data[2..block_length];
+    let mut tables = Vec::new();
+
+    while !data.is_empty() {
+        let packed = data[0];
+        let entry_length = packed >> 4;
+        let table_id = packed & 0b1111;
+
+        if entry_length != 0 {
+            return Err(JPEGReaderError::InvalidSegment {
+                description: format!("Quantization tables with entries of length {} are not supported", entry_length),
+            });
+        }
+        if data.len() < 65 {
+            return Err(JPEGReaderError::InvalidSegment {
+                description: "Quantization table is truncated".to_string(),
+            });
+        }
     
-    let mut new_data = [0i32; 64];
-    for i in 0..64 {
-        new_data[i] = data[i] as i32;
-    }
+        let mut new_data = [0i32; 64];
+        for i in 0..64 {
+            new_data[i] = data[i + 1] as i32;
+        }
 
-    let data = unzigzag_64(&new_data);
+        let table_data = unzigzag_64(&new_data);
 
-    trace!("quantization table: {:?}", data);
+        trace!("quantization table: {:?}", table_data);
 
-    let table = QuantizationTable {
-        id: table_id,
-        data,
-    };
+        tables.push(QuantizationTable {
+            id: table_id,
+            data: table_data,
+        });
+        data = &data[65..];
+    }
 
-    Ok((table, block_length + 2))
+    Ok((tables, block_length + 2))
+}
+
+fn read_restart_interval(data: &[u8]) -> Result<(u16, usize), JPEGReaderError> {
+    let block_length = BigEndian::read_u16(&data[0..2]) as usize;
+    if block_length != 4 {
+        return Err(JPEGReaderError::InvalidSegment {
+            description: format!("Unexpected define restart interval segment length: {}", block_length),
+        });
+    }
+
+    Ok((BigEndian::read_u16(&data[2..4]), block_length + 2))
 }
 
 fn read_text_comment(data: &[u8]) -> Result<usize, JPEGReaderError> {
     Ok(BigEndian::read_u16(&data[0..2]) as usize + 2)
 }
 
+// Damaged EXIF data is ignored, as it does not prevent the image from being decoded.
+fn read_exif_segment(data: &[u8], jpeg: JPEG) -> Result<(JPEG, usize), JPEGReaderError> {
+    let block_length = BigEndian::read_u16(&data[0..2]) as usize;
+
+    match read_exif(&data[(2 + EXIF_HEADER.len()).min(block_length)..block_length]) {
+        Ok(exif) => Ok((jpeg.with_exif(exif), block_length + 2)),
+        Err(err) => {
+            warn!("failed to read exif data: {}", err);
+            Ok((jpeg, block_length + 2))
+        }
+    }
+}
+
+// see: https://exiftool.org/TagNames/JPEG.html#Adobe
+fn read_adobe_segment(data: &[u8]) -> Result<(u8, usize), JPEGReaderError> {
+    let block_length = BigEndian::read_u16(&data[0..2]) as usize;
+    if block_length < 2 + ADOBE_HEADER.len() + 7 {
+        return Err(JPEGReaderError::InvalidSegment {
+            description: format!("Adobe segment is too short: {}", block_length),
+        });
+    }
+
+    // version, flags0 and flags1 go before the transform
+    let transform = data[2 + ADOBE_HEADER.len() + 6];
+    trace!("adobe color transform: {}", transform);
+
+    Ok((transform, block_length + 2))
+}
+
 fn read_application_specific_data(data: &[u8]) -> Result<usize, JPEGReaderError> {
     Ok(BigEndian::read_u16(&data[0..2]) as usize + 2)
 }
@@ -550,7 +837,177 @@ mod tests {
         assert_eq!(images.len(), 1);
 
         let image = &images[0];
-        assert_eq!(Pixel::from_rgb(109, 115, 127), image.get_pixel(720, 700));
-        assert_eq!(Pixel::from_rgb(216, 148, 169), image.get_pixel(1290, 550));
+        assert_eq!(Pixel::from_rgb(108, 116, 127), image.get_pixel(720, 700));
+        assert_eq!(Pixel::from_rgb(218, 149, 168), image.get_pixel(1290, 550));
+    }
+
+    #[test]
+    fn test_read_progressive() {
+        let reader = JPEGReader::new();
+        let progressive = &reader.read(&read("assets/balloon_progressive.jpg").expect("failed to load test image"))
+            .expect("failed to read progressive image")[0];
+        // same image encoded with the same quantization tables as a sequential jpeg
+        let sequential = &reader.read(&read("assets/balloon_sequential.jpg").expect("failed to load test image"))
+            .expect("failed to read sequential image")[0];
+
+        assert_eq!(progressive.width, 101);
+        assert_eq!(progressive.height, 77);
+        assert_eq!(progressive.pixels, sequential.pixels);
+
+        let pixel = progressive.get_pixel(20, 60);
+        assert!((pixel.red as i32 - 198).abs() < 12);
+        assert!((pixel.green as i32 - 144).abs() < 12);
+        assert!((pixel.blue as i32 - 160).abs() < 12);
+    }
+
+    #[test]
+    fn test_read_invalid_data() {
+        let reader = JPEGReader::new();
+        assert!(reader.read(&vec![]).is_err());
+        assert!(reader.read(&vec![0x89, 0x50, 0x4E, 0x47]).is_err());
+        assert!(reader.read(&vec![0xFF, 0xD8, 0xFF, 0xDB, 0x00]).is_err());
+
+        // lossless frame
+        assert!(reader.read(&vec![0xFF, 0xD8, 0xFF, 0xC3, 0x00, 0x02]).is_err());
+
+        let mut truncated = read("assets/balloon_progressive.jpg").expect("failed to load test image");
+        truncated.truncate(1000);
+        assert!(reader.read(&truncated).is_err());
+    }
+
+    #[test]
+    fn test_read_truncated_segments() {
+        let reader = JPEGReader::new();
+        assert!(reader.read(&vec![0xFF, 0xD8, 0xFF, 0xC0, 0x00, 0x02]).is_err());
+        assert!(reader.read(&vec![0xFF, 0xD8, 0xFF, 0xC0, 0x00, 0x08, 0x08, 0x00, 0x10, 0x00, 0x10, 0x03]).is_err());
+        assert!(reader.read(&vec![0xFF, 0xD8, 0xFF, 0xC4, 0x00, 0x00]).is_err());
+        assert!(reader.read(&vec![0xFF, 0xD8, 0xFF, 0xC4, 0x00, 0x03, 0x00]).is_err());
+        assert!(reader.read(&vec![0xFF, 0xD8, 0xFF, 0xDB, 0x00, 0x01]).is_err());
+        assert!(reader.read(&vec![0xFF, 0xD8, 0xFF, 0xDB, 0x00, 0x03, 0x00]).is_err());
+        assert!(reader.read(&vec![0xFF, 0xD8, 0xFF, 0xDA, 0x00, 0x02]).is_err());
+        assert!(reader.read(&vec![0xFF, 0xD8, 0xFF, 0xDA, 0x00, 0x04, 0x01, 0x01]).is_err());
+        assert!(reader.read(&vec![0xFF, 0xD8, 0xFF, 0xDD, 0x00, 0x02]).is_err());
+
+        // progressive frame followed by a truncated scan
+        let mut progressive = vec![0xFF, 0xD8, 0xFF, 0xC2, 0x00, 0x0B, 0x08, 0x00, 0x10, 0x00, 0x10, 0x01, 0x01, 0x11, 0x00];
+        progressive.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02]);
+        assert!(reader.read(&progressive).is_err());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_read_restart_interval() {
+        let reader = JPEGReader::new();
+        let sequential = &reader.read(&read("assets/balloon_restart.jpg").expect("failed to load test image"))
+            .expect("failed to read image with restart markers")[0];
+        let progressive = &reader.read(&read("assets/balloon_restart_progressive.jpg").expect("failed to load test image"))
+            .expect("failed to read progressive image with restart markers")[0];
+
+        assert_eq!(sequential.width, 101);
+        assert_eq!(sequential.height, 77);
+        assert_eq!(sequential.pixels, progressive.pixels);
+
+        let pixel = sequential.get_pixel(20, 60);
+        assert!((pixel.red as i32 - 196).abs() < 12);
+        assert!((pixel.green as i32 - 146).abs() < 12);
+        assert!((pixel.blue as i32 - 158).abs() < 12);
+    }
+
+    #[test]
+    fn test_read_grayscale() {
+        let reader = JPEGReader::new();
+        let sequential = &reader.read(&read("assets/balloon_grayscale.jpg").expect("failed to load test image"))
+            .expect("failed to read grayscale image")[0];
+        let progressive = &reader.read(&read("assets/balloon_grayscale_progressive.jpg").expect("failed to load test image"))
+            .expect("failed to read progressive grayscale image")[0];
+
+        assert_eq!(sequential.width, 101);
+        assert_eq!(sequential.height, 77);
+        assert_eq!(sequential.pixels, progressive.pixels);
+
+        let pixel = sequential.get_pixel(20, 60);
+        assert_eq!(pixel.red, pixel.green);
+        assert_eq!(pixel.red, pixel.blue);
+        assert!((pixel.red as i32 - 63).abs() < 12);
+    }
+
+    #[test]
+    fn test_read_cmyk() {
+        let reader = JPEGReader::new();
+        let cmyk = &reader.read(&read("assets/balloon_cmyk.jpg").expect("failed to load test image"))
+            .expect("failed to read cmyk image")[0];
+        let ycck = &reader.read(&read("assets/balloon_ycck.jpg").expect("failed to load test image"))
+            .expect("failed to read ycck image")[0];
+
+        for image in &[cmyk, ycck] {
+            assert_eq!(image.width, 101);
+            assert_eq!(image.height, 77);
+
+            let pixel = image.get_pixel(20, 60);
+            assert!((pixel.red as i32 - 49).abs() < 12);
+            assert!((pixel.green as i32 - 65).abs() < 12);
+            assert!((pixel.blue as i32 - 91).abs() < 12);
+        }
+    }
+
+    #[test]
+    fn test_read_damaged_restart_interval() {
+        let reader = JPEGReader::new();
+        let image_data = read("assets/balloon_restart.jpg").expect("failed to load test image");
+        let image = &reader.read(&image_data).expect("failed to read image")[0];
+
+        // each restart interval is a row of 7 MCUs 16x16
+        let marker_position = |data: &[u8], marker: u8| data.windows(2).position(|w| w == [0xFF, marker])
+            .expect("expected restart marker to be present");
+        let rows_equal = |a: &Image, b: &Image, from: usize, to: usize| (from * a.width..to * a.width)
+            .all(|i| a.pixels[i] == b.pixels[i]);
+
+        let mut damaged = image_data.clone();
+        let position = marker_position(&damaged, 0xD1);
+        for byte in &mut damaged[(position - 20)..position] {
+            *byte = 0x00;
+        }
+        let damaged_image = &reader.read(&damaged).expect("failed to read damaged image")[0];
+        assert!(rows_equal(image, damaged_image, 0, 16));
+        assert!(rows_equal(image, damaged_image, 32, 77));
+
+        let mut missing_marker = image_data.clone();
+        let position = marker_position(&missing_marker, 0xD1);
+        missing_marker.drain(position..(position + 2));
+        let missing_marker_image = &reader.read(&missing_marker).expect("failed to read image with a missing marker")[0];
+        assert!(rows_equal(image, missing_marker_image, 0, 32));
+        assert!(rows_equal(image, missing_marker_image, 48, 77));
+    }
+
+    // inserts APP1 segment with EXIF data, which contains orientation only
+    fn with_exif_orientation(data: &[u8], orientation: u8) -> Vec<u8> {
+        let mut result = data[0..2].to_vec();
+        result.extend_from_slice(&[0xFF, 0xE1, 0, 34]);
+        result.extend_from_slice(b"Exif\0\0");
+        result.extend_from_slice(&[
+            b'M', b'M', 0, 42, 0, 0, 0, 8,
+            0, 1, 0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, orientation, 0, 0,
+            0, 0, 0, 0,
+        ]);
+        result.extend_from_slice(&data[2..]);
+        result
+    }
+
+    #[test]
+    fn test_read_exif_orientation() {
+        let image_data = read("assets/balloon_sequential.jpg").expect("failed to load test image");
+        let reader = JPEGReader::new();
+        let image = &reader.read(&image_data).expect("failed to read image")[0];
+        assert!(image.metadata.exif.is_none());
+
+        let rotated_data = with_exif_orientation(&image_data, 6);
+        let rotated = &reader.read(&rotated_data).expect("failed to read image with exif")[0];
+        assert_eq!((rotated.width, rotated.height), (image.height, image.width));
+        assert_eq!(rotated.get_pixel(0, 0), image.get_pixel(0, image.height - 1));
+        assert_eq!(rotated.get_pixel(rotated.width - 1, 0), image.get_pixel(0, 0));
+        assert_eq!(rotated.metadata.exif.as_ref().and_then(|exif| exif.orientation()), Some(1));
+
+        let not_rotated = &JPEGReader::new().with_apply_orientation(false).read(&rotated_data).expect("failed to read image with exif")[0];
+        assert_eq!(not_rotated.pixels, image.pixels);
+        assert_eq!(not_rotated.metadata.exif.as_ref().and_then(|exif| exif.orientation()), Some(6));
+    }
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+
+use core::models::io::{ImageIOError, ImageTransformer, LosslessTransform};
+
+use crate::{common::Channel, progressive::{ComponentCoefficients, blocks_in_mcu_order}, reader::{JPEG, read_coefficients}, writer::{JPEGCoefficients, write_coefficients}};
+
+// see:
+// https://github.com/libjpeg-turbo/libjpeg-turbo/blob/main/src/transupp.c
+// Blocks are moved around and their coefficients are transposed or negated, so that nothing is quantized again.
+
+pub struct JPEGTransformer {
+}
+
+impl ImageTransformer for JPEGTransformer {
+
+    fn transform(&self, data: &Vec<u8>, transform: &LosslessTransform) -> Result<Vec<u8>, ImageIOError> {
+        let jpeg = read_coefficients(data).map_err(|err| ImageIOError::FailedToRead {
+            description: err.to_string(),
+        })?;
+
+        let coefficients = transform_coefficients(&jpeg, transform)?;
+        Ok(write_coefficients(&coefficients, true, jpeg.progressive, 0))
+    }
+}
+
+fn transform_coefficients(jpeg: &JPEG, transform: &LosslessTransform) -> Result<JPEGCoefficients, ImageIOError> {
+    let (max_horizontal_sampling, max_vertical_sampling, _, _) = jpeg.mcu_dimensions();
+    let mcu_width = 8 * max_horizontal_sampling;
+    let mcu_height = 8 * max_vertical_sampling;
+    let width = jpeg.width as usize;
+    let height = jpeg.height as usize;
+
+    // partial MCUs can not be moved to the opposite edge, so they are trimmed (as jpegtran -trim does)
+    let trimmed_width = width / mcu_width * mcu_width;
+    let trimmed_height = height / mcu_height * mcu_height;
+
+    let (new_width, new_height) = match transform {
+        LosslessTransform::Rotate90 => (trimmed_height, width),
+        LosslessTransform::Rotate180 => (trimmed_width, trimmed_height),
+        LosslessTransform::Rotate270 => (height, trimmed_width),
+        LosslessTransform::FlipHorizontal => (trimmed_width, height),
+        LosslessTransform::FlipVertical => (width, trimmed_height),
+        LosslessTransform::Crop { x, y, width: crop_width, height: crop_height } => {
+            if x % mcu_width != 0 || y % mcu_height != 0 {
+                return Err(ImageIOError::InvalidOptions {
+                    description: format!("Crop offset should be aligned to MCU size of {}x{}, got: {}x{}", mcu_width, mcu_height, x, y),
+                });
+            }
+
+            if x + crop_width > width || y + crop_height > height {
+                return Err(ImageIOError::InvalidOptions {
+                    description: format!("Crop region {}x{}+{}+{} is out of image bounds {}x{}", crop_width, crop_height, x, y, width, height),
+                });
+            }
+
+            (*crop_width, *crop_height)
+        },
+    };
+
+    if new_width == 0 || new_height == 0 {
+        return Err(ImageIOError::InvalidOptions {
+            description: format!("Transformed image {}x{} would be empty", new_width, new_height),
+        });
+    }
+
+    let transposed = *transform == LosslessTransform::Rotate90 || *transform == LosslessTransform::Rotate270;
+    let channels: Vec<Channel> = jpeg.channels.iter()
+        .map(|channel| Channel {
+            id: channel.id,
+            horizontal_sampling: if transposed { channel.vertical_sampling } else { channel.horizontal_sampling },
+            vertical_sampling: if transposed { channel.horizontal_sampling } else { channel.vertical_sampling },
+            quantization_table_id: channel.quantization_table_id,
+        })
+        .collect();
+
+    let (new_max_horizontal_sampling, new_max_vertical_sampling) = if transposed {
+        (max_vertical_sampling, max_horizontal_sampling)
+    } else {
+        (max_horizontal_sampling, max_vertical_sampling)
+    };
+
+    let components: Vec<ComponentCoefficients> = jpeg.coefficients.iter().zip(&channels)
+        .map(|(source, channel)| {
+            let mut component = ComponentCoefficients::new(
+                channel,
+                new_width,
+                new_height,
+                new_max_horizontal_sampling,
+                new_max_vertical_sampling
+            );
+
+            // blocks which cover the (trimmed) source image, mirrored blocks are counted from its edges
+            let source_lines = source.blocks.len() / source.blocks_per_line;
+            let blocks_wide = trimmed_width * source.horizontal_sampling / mcu_width;
+            let blocks_high = trimmed_height * source.vertical_sampling / mcu_height;
+
+            let new_lines = component.blocks.len() / component.blocks_per_line;
+            for block_y in 0..new_lines {
+                for block_x in 0..component.blocks_per_line {
+                    let (source_x, source_y) = match transform {
+                        LosslessTransform::Rotate90 => (Some(block_y), (blocks_high - 1).checked_sub(block_x)),
+                        LosslessTransform::Rotate180 => ((blocks_wide - 1).checked_sub(block_x), (blocks_high - 1).checked_sub(block_y)),
+                        LosslessTransform::Rotate270 => ((blocks_wide - 1).checked_sub(block_y), Some(block_x)),
+                        LosslessTransform::FlipHorizontal => ((blocks_wide - 1).checked_sub(block_x), Some(block_y)),
+                        LosslessTransform::FlipVertical => (Some(block_x), (blocks_high - 1).checked_sub(block_y)),
+                        LosslessTransform::Crop { x, y, .. } => (
+                            Some(block_x + x / mcu_width * source.horizontal_sampling),
+                            Some(block_y + y / mcu_height * source.vertical_sampling)
+                        ),
+                    };
+
+                    // blocks which are only padding in the new image may have no source
+                    if let (Some(source_x), Some(source_y)) = (source_x, source_y) {
+                        if source_x < source.blocks_per_line && source_y < source_lines {
+                            component.blocks[block_y * component.blocks_per_line + block_x] = transform_block(
+                                &source.blocks[source_y * source.blocks_per_line + source_x],
+                                transform
+                            );
+                        }
+                    }
+                }
+            }
+
+            component
+        })
+        .collect();
+
+    let horizontal_mcus = (new_width + 8 * new_max_horizontal_sampling - 1) / (8 * new_max_horizontal_sampling);
+    let vertical_mcus = (new_height + 8 * new_max_vertical_sampling - 1) / (8 * new_max_vertical_sampling);
+    let blocks_per_mcu: usize = channels.iter()
+        .map(|channel| (channel.horizontal_sampling * channel.vertical_sampling) as usize)
+        .sum();
+
+    Ok(JPEGCoefficients {
+        width: new_width,
+        height: new_height,
+        // coefficients are transposed, so should be their quantization values
+        quantization_tables: jpeg.quantization_tables.iter()
+            .map(|table| (table.id, if transposed { transpose(&table.data) } else { table.data }))
+            .collect::<HashMap<u8, [i32; 64]>>(),
+        mcus: blocks_in_mcu_order(&components, horizontal_mcus, vertical_mcus)
+            .chunks(blocks_per_mcu)
+            .map(|mcu| mcu.to_vec())
+            .collect(),
+        channels,
+        exif: jpeg.exif.as_ref().map(|exif| exif.data.clone()),
+        adobe_transform: jpeg.adobe_transform,
+    })
+}
+
+// Coefficients are in natural order: row is vertical frequency, column is horizontal one.
+// Mirroring negates odd frequencies, rotation by 90 degrees is a transposition followed by mirroring.
+fn transform_block(block: &[i32; 64], transform: &LosslessTransform) -> [i32; 64] {
+    let mut result = [0i32; 64];
+
+    for u in 0..8 {
+        for v in 0..8 {
+            let sign = |odd: bool| if odd { -1 } else { 1 };
+            result[u * 8 + v] = match transform {
+                LosslessTransform::Rotate90 => block[v * 8 + u] * sign(v % 2 == 1),
+                LosslessTransform::Rotate180 => block[u * 8 + v] * sign((u + v) % 2 == 1),
+                LosslessTransform::Rotate270 => block[v * 8 + u] * sign(u % 2 == 1),
+                LosslessTransform::FlipHorizontal => block[u * 8 + v] * sign(v % 2 == 1),
+                LosslessTransform::FlipVertical => block[u * 8 + v] * sign(u % 2 == 1),
+                LosslessTransform::Crop { .. } => block[u * 8 + v],
+            };
+        }
+    }
+
+    result
+}
+
+fn transpose(table: &[i32; 64]) -> [i32; 64] {
+    let mut result = [0i32; 64];
+
+    for u in 0..8 {
+        for v in 0..8 {
+            result[u * 8 + v] = table[v * 8 + u];
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    use std::fs::read;
+
+    use core::models::{image::Image, io::ImageReader};
+
+    use crate::reader::JPEGReader;
+
+    // pixel of the source image which should end up at (x, y) of the transformed one
+    fn source_position(transform: &LosslessTransform, x: usize, y: usize, width: usize, height: usize) -> (usize, usize) {
+        match transform {
+            LosslessTransform::Rotate90 => (y, height - 1 - x),
+            LosslessTransform::Rotate180 => (width - 1 - x, height - 1 - y),
+            LosslessTransform::Rotate270 => (width - 1 - y, x),
+            LosslessTransform::FlipHorizontal => (width - 1 - x, y),
+            LosslessTransform::FlipVertical => (x, height - 1 - y),
+            LosslessTransform::Crop { x: offset_x, y: offset_y, .. } => (x + offset_x, y + offset_y),
+        }
+    }
+
+    fn assert_similar(image: &Image, x: usize, y: usize, source: &Image, source_x: usize, source_y: usize) {
+        let pixel = image.get_pixel(x, y);
+        let expected = source.get_pixel(source_x, source_y);
+        // rows and columns of rotated blocks are swapped for inverse DCT, so its rounding is slightly different
+        assert!(
+            (pixel.red as i32 - expected.red as i32).abs() <= 4
+                && (pixel.green as i32 - expected.green as i32).abs() <= 4
+                && (pixel.blue as i32 - expected.blue as i32).abs() <= 4,
+            "pixel ({}, {}) is {:?}, expected {:?}", x, y, pixel, expected
+        );
+    }
+
+    #[test]
+    fn test_transform() {
+        let reader = JPEGReader::new();
+        let transformer = JPEGTransformer {};
+
+        for path in &["assets/balloon_restart.jpg", "assets/balloon_restart_progressive.jpg"] {
+            let data = read(path).expect("failed to load test image");
+            let source = &reader.read(&data).expect("failed to read test image")[0];
+
+            // 4:2:0 subsampling, so MCU is 16x16 and 101x77 image is trimmed to 96x64 when edges are mirrored
+            let transforms = [
+                (LosslessTransform::Rotate90, 64, 101),
+                (LosslessTransform::Rotate180, 96, 64),
+                (LosslessTransform::Rotate270, 77, 96),
+                (LosslessTransform::FlipHorizontal, 96, 77),
+                (LosslessTransform::FlipVertical, 101, 64),
+            ];
+
+            for (transform, width, height) in &transforms {
+                let transformed = transformer.transform(&data, transform).expect("failed to transform image");
+                let image = &reader.read(&transformed).expect("failed to read transformed image")[0];
+                assert_eq!((image.width, image.height), (*width, *height));
+
+                // trimmed source is what gets transformed
+                let (source_width, source_height) = match transform {
+                    LosslessTransform::Rotate90 | LosslessTransform::Rotate270 => (*height, *width),
+                    _ => (*width, *height),
+                };
+
+                for y in (0..image.height).step_by(3) {
+                    for x in (0..image.width).step_by(3) {
+                        let (source_x, source_y) = source_position(transform, x, y, source_width, source_height);
+                        assert_similar(image, x, y, source, source_x, source_y);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_transform_is_lossless() {
+        let reader = JPEGReader::new();
+        let transformer = JPEGTransformer {};
+
+        let data = read("assets/balloon_restart.jpg").expect("failed to load test image");
+        let crop = LosslessTransform::Crop { x: 0, y: 0, width: 96, height: 64 };
+        let cropped = transformer.transform(&data, &crop).expect("failed to crop image");
+
+        // rotating four times gives the same coefficients back
+        let mut rotated = cropped.clone();
+        for _ in 0..4 {
+            rotated = transformer.transform(&rotated, &LosslessTransform::Rotate90).expect("failed to rotate image");
+        }
+
+        let expected = &reader.read(&cropped).expect("failed to read cropped image")[0];
+        let image = &reader.read(&rotated).expect("failed to read rotated image")[0];
+        assert_eq!(expected.pixels, image.pixels);
+    }
+
+    #[test]
+    fn test_crop() {
+        let reader = JPEGReader::new();
+        let transformer = JPEGTransformer {};
+
+        let data = read("assets/balloon_restart.jpg").expect("failed to load test image");
+        let source = &reader.read(&data).expect("failed to read test image")[0];
+
+        let crop = LosslessTransform::Crop { x: 16, y: 32, width: 50, height: 40 };
+        let cropped = transformer.transform(&data, &crop).expect("failed to crop image");
+        let image = &reader.read(&cropped).expect("failed to read cropped image")[0];
+
+        assert_eq!((image.width, image.height), (50, 40));
+        for y in 0..image.height {
+            for x in 0..image.width {
+                assert_eq!(image.get_pixel(x, y), source.get_pixel(x + 16, y + 32));
+            }
+        }
+
+        // offset should be aligned to MCU, region should be inside of the image
+        assert!(transformer.transform(&data, &LosslessTransform::Crop { x: 8, y: 0, width: 16, height: 16 }).is_err());
+        assert!(transformer.transform(&data, &LosslessTransform::Crop { x: 96, y: 64, width: 16, height: 16 }).is_err());
+    }
+}
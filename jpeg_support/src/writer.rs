@@ -1,64 +1,65 @@
-use std::f32::consts::PI;
-
 use lazy_static::lazy_static;
 use maplit::hashmap;
+use rayon::prelude::*;
 
-use core::models::{image::Image, pixel::Pixel, io::{ImageIOError, ImageWriter, ImageWriterOptions}};
+use core::models::{image::Image, io::{ImageIOError, ImageWriter, ImageWriterOptions}};
 use std::{collections::HashMap};
 
 use byteorder::{BigEndian, ByteOrder};
 
-use crate::{common::{Channel, HuffmanTable, HuffmanTableType}, common::{ChannelID, rgb_to_ycbcr}, common::write_huffman_encoded_channels_data, common::zigzag, common::escape_image_data};
+use crate::{common::{Channel, HuffmanTable, HuffmanTableType}, common::{ChannelID, rgb_to_ycbcr}, common::{write_huffman_encoded_channels_data, count_huffman_symbols}, huffman::{HuffmanTreeBuilder, canonical_codes}, common::zigzag, common::write_restart_intervals, dct::{forward_dct, forward_dct_scales}, exif::EXIF_HEADER, progressive::{Scan, ScanComponent, components_from_mcu_blocks, encode_scan}};
 
 const OPTION_QUALITY: &'static str = "quality";
-
-// These tables are used by GIMP when saving with 90% quality.
-const QUANTIZATION_TABLE_Y_90: [i32; 64] = [
-     3,  2,  2,  3,  5,  8, 10, 12, 
-     2,  2,  3,  4,  5, 12, 12, 11, 
-     3,  3,  3,  5,  8, 11, 14, 11, 
-     3,  3,  4,  6, 10, 17, 16, 12, 
-     4,  4,  7, 11, 14, 22, 21, 15, 
-     5,  7, 11, 13, 16, 21, 23, 18, 
-    10, 13, 16, 17, 21, 24, 24, 20, 
-    14, 18, 19, 20, 22, 20, 21, 20
-];
-
-const QUANTIZATION_TABLE_CB_CR_90: [i32; 64] = [
-     3,  4,  5,  9, 20, 20, 20, 20, 
-     4,  4,  5, 13, 20, 20, 20, 20, 
-     5,  5, 11, 20, 20, 20, 20, 20, 
-     9, 13, 20, 20, 20, 20, 20, 20, 
-    20, 20, 20, 20, 20, 20, 20, 20, 
-    20, 20, 20, 20, 20, 20, 20, 20, 
-    20, 20, 20, 20, 20, 20, 20, 20, 
-    20, 20, 20, 20, 20, 20, 20, 20
+pub const OPTION_SUBSAMPLING: &'static str = "subsampling";
+pub const OPTION_OPTIMIZE_HUFFMAN: &'static str = "optimize_huffman";
+pub const OPTION_PROGRESSIVE: &'static str = "progressive";
+pub const OPTION_RESTART_INTERVAL: &'static str = "restart_interval";
+pub const OPTION_GRAYSCALE: &'static str = "grayscale";
+
+// Scans of progressive image: channel indexes, spectral selection start and end, successive approximation high and low.
+// DC goes first, then AC bands with successive approximation, as in libjpeg's default progression.
+// see: https://github.com/libjpeg-turbo/libjpeg-turbo/blob/main/src/jcparam.c (jpeg_simple_progression)
+const PROGRESSIVE_SCAN_SCRIPT: [(&'static [usize], usize, usize, u8, u8); 10] = [
+    (&[0, 1, 2], 0, 0, 0, 1),
+    (&[0], 1, 5, 0, 2),
+    (&[2], 1, 63, 0, 1),
+    (&[1], 1, 63, 0, 1),
+    (&[0], 6, 63, 0, 2),
+    (&[0], 1, 63, 2, 1),
+    (&[0, 1, 2], 0, 0, 1, 0),
+    (&[2], 1, 63, 1, 0),
+    (&[1], 1, 63, 1, 0),
+    (&[0], 1, 63, 1, 0),
 ];
 
-// 100% quality
-const QUANTIZATION_TABLE_Y_100: [i32; 64] = [
-    1, 1, 1, 1, 1, 1, 1, 1, 
-    1, 1, 1, 1, 1, 1, 1, 1, 
-    1, 1, 1, 1, 1, 1, 1, 1, 
-    1, 1, 1, 1, 1, 1, 1, 1, 
-    1, 1, 1, 1, 1, 1, 1, 1, 
-    1, 1, 1, 1, 1, 1, 1, 1, 
-    1, 1, 1, 1, 1, 1, 1, 1, 
-    1, 1, 1, 1, 1, 1, 1, 1
-];
+// Images which are not YCbCr have the same progression for every channel.
+fn progressive_scan_script(total_channels: usize) -> Vec<(Vec<usize>, usize, usize, u8, u8)> {
+    if total_channels == 3 {
+        return PROGRESSIVE_SCAN_SCRIPT.iter()
+            .map(|(channel_indexes, spectral_start, spectral_end, successive_high, successive_low)| (
+                channel_indexes.to_vec(), *spectral_start, *spectral_end, *successive_high, *successive_low
+            ))
+            .collect();
+    }
 
-const QUANTIZATION_TABLE_CB_CR_100: [i32; 64] = [
-    1, 1, 1, 1, 1, 1, 1, 1, 
-    1, 1, 1, 1, 1, 1, 1, 1, 
-    1, 1, 1, 1, 1, 1, 1, 1, 
-    1, 1, 1, 1, 1, 1, 1, 1, 
-    1, 1, 1, 1, 1, 1, 1, 1, 
-    1, 1, 1, 1, 1, 1, 1, 1, 
-    1, 1, 1, 1, 1, 1, 1, 1, 
-    1, 1, 1, 1, 1, 1, 1, 1
-];
+    let all_channels: Vec<usize> = (0..total_channels).collect();
+    let mut script = vec![(all_channels.clone(), 0, 0, 0, 1)];
+    for index in 0..total_channels {
+        script.push((vec![index], 1, 5, 0, 2));
+        script.push((vec![index], 6, 63, 0, 2));
+    }
+    for index in 0..total_channels {
+        script.push((vec![index], 1, 63, 2, 1));
+    }
+    script.push((all_channels, 0, 0, 1, 0));
+    for index in 0..total_channels {
+        script.push((vec![index], 1, 63, 1, 0));
+    }
+    script
+}
 
-// 50% quality
+// Base tables from the JPEG standard (Annex K), which correspond to 50% quality.
+// Tables for other quality levels are derived from them with the same scaling as IJG libjpeg uses.
 const QUANTIZATION_TABLE_Y_50: [i32; 64] = [
     16, 11, 10, 16,  24,  40,  51,  61, 
     12, 12, 14, 19,  26,  58,  60,  55, 
@@ -81,29 +82,6 @@ const QUANTIZATION_TABLE_CB_CR_50: [i32; 64] = [
     99, 99, 99, 99, 99, 99, 99, 99
 ];
 
-// 25% quality
-const QUANTIZATION_TABLE_Y_25: [i32; 64] = [
-     32,  22,  20,  32,  48,  80, 102, 122, 
-     24,  24,  28,  38,  52, 116, 120, 110, 
-     28,  26,  32,  48,  80, 114, 138, 112, 
-     28,  34,  44,  58, 102, 174, 160, 124, 
-     36,  44,  74, 112, 136, 218, 206, 154, 
-     48,  70, 110, 128, 162, 208, 226, 184, 
-     98, 128, 156, 174, 206, 242, 240, 202, 
-    144, 184, 190, 196, 224, 200, 206, 198
-];
-
-const QUANTIZATION_TABLE_CB_CR_25: [i32; 64] = [
-     34,  36,  48,  94, 198, 198, 198, 198, 
-     36,  42,  52, 132, 198, 198, 198, 198, 
-     48,  52, 112, 198, 198, 198, 198, 198, 
-     94, 132, 198, 198, 198, 198, 198, 198, 
-    198, 198, 198, 198, 198, 198, 198, 198, 
-    198, 198, 198, 198, 198, 198, 198, 198, 
-    198, 198, 198, 198, 198, 198, 198, 198, 
-    198, 198, 198, 198, 198, 198, 198, 198
-];
-
 // it turns out that majority of jpeg encoders use pre-defined Huffman tables from standard, instead of
 // generating own tables. This approach actually provides good enough approximation. In this encoding
 // we will use the same approach. These tables are copied from GIMP when exporting at 90% quality.
@@ -468,14 +446,43 @@ lazy_static! {
         216 => (65514, 16),
         231 => (65522, 16),
     };
-
-    // For DCT
-    static ref COS_TABLE: [f32; 64] = precompute_cos_table();
 }
 
 pub struct JPEGWriter {
 }
 
+// see:
+// https://en.wikipedia.org/wiki/Chroma_subsampling
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ChromaSubsampling {
+    Ratio444,
+    Ratio422,
+    Ratio420,
+}
+
+impl ChromaSubsampling {
+
+    pub fn from_option(value: &str) -> Result<Self, ImageIOError> {
+        match value.trim() {
+            "4:4:4" | "444" => Ok(ChromaSubsampling::Ratio444),
+            "4:2:2" | "422" => Ok(ChromaSubsampling::Ratio422),
+            "4:2:0" | "420" => Ok(ChromaSubsampling::Ratio420),
+            other => Err(ImageIOError::InvalidOptions {
+                description: format!("Chroma subsampling is not supported: {}", other),
+            }),
+        }
+    }
+
+    // (horizontal, vertical) sampling factors of the luma channel. Chroma channels always have 1x1.
+    pub fn luma_sampling(&self) -> (u8, u8) {
+        match self {
+            ChromaSubsampling::Ratio444 => (1, 1),
+            ChromaSubsampling::Ratio422 => (2, 1),
+            ChromaSubsampling::Ratio420 => (2, 2),
+        }
+    }
+}
+
 impl JPEGWriter {
 
     pub fn new() -> Self {
@@ -489,27 +496,44 @@ impl ImageWriter for JPEGWriter {
     fn write(&self, image: &Image, options: &ImageWriterOptions) -> Result<Vec<u8>, ImageIOError> {
         // initialization
         let quality = options.get_u32(OPTION_QUALITY, 90)?;
+        let grayscale = options.get_bool(OPTION_GRAYSCALE, false)?;
+        // grayscale images have no chroma to subsample
+        let subsampling = if grayscale {
+            ChromaSubsampling::Ratio444
+        } else {
+            ChromaSubsampling::from_option(&options.get_string(OPTION_SUBSAMPLING, "4:4:4"))?
+        };
+        let optimize_huffman = options.get_bool(OPTION_OPTIMIZE_HUFFMAN, false)?;
+        let progressive = options.get_bool(OPTION_PROGRESSIVE, false)?;
+        let restart_interval = options.get_u32(OPTION_RESTART_INTERVAL, 0)?;
+
+        if quality < 1 || quality > 100 {
+            return Err(ImageIOError::InvalidOptions {
+                description: format!("Quality level should be between 1 and 100, got: {}", quality),
+            });
+        }
+
+        if restart_interval > u16::MAX as u32 {
+            return Err(ImageIOError::InvalidOptions {
+                description: format!("Restart interval should be at most {} MCUs, got: {}", u16::MAX, restart_interval),
+            });
+        }
+
+        // frame header stores dimensions in 16 bits, and there is nothing to split into MCUs for an empty image
+        let max_dimension = u16::MAX as usize;
+        if image.width == 0 || image.height == 0 || image.width > max_dimension || image.height > max_dimension {
+            return Err(ImageIOError::FailedToWrite {
+                description: format!("Image dimensions should be between 1 and {}, got: {}x{}", max_dimension, image.width, image.height),
+            });
+        }
 
-        let quantization_tables: HashMap<u8, [i32; 64]> = hashmap! {
-            0 => match quality {
-                90 => QUANTIZATION_TABLE_Y_90,
-                100 => QUANTIZATION_TABLE_Y_100,
-                50 => QUANTIZATION_TABLE_Y_50,
-                25 => QUANTIZATION_TABLE_Y_25,
-                other => return Err(ImageIOError::InvalidOptions {
-                    description: format!("Quality level is not supported: {}", other),
-                })
-            }.clone(),
-            1 => match quality {
-                90 => QUANTIZATION_TABLE_CB_CR_90,
-                100 => QUANTIZATION_TABLE_CB_CR_100,
-                50 => QUANTIZATION_TABLE_CB_CR_50,
-                25 => QUANTIZATION_TABLE_CB_CR_25,
-                other => return Err(ImageIOError::InvalidOptions {
-                    description: format!("Quality level is not supported: {}", other),
-                })
-            }.clone(),
+        let mut quantization_tables: HashMap<u8, [i32; 64]> = hashmap! {
+            0 => scale_quantization_table(&QUANTIZATION_TABLE_Y_50, quality),
+            1 => scale_quantization_table(&QUANTIZATION_TABLE_CB_CR_50, quality),
         };
+        if grayscale {
+            quantization_tables.remove(&1);
+        }
 
         let quantization_table_by_channel: HashMap<u8, u8> = hashmap! {
             1 => 0,
@@ -517,133 +541,336 @@ impl ImageWriter for JPEGWriter {
             3 => 1,
         };
 
-        let huffman_tables_by_channel: HashMap<(HuffmanTableType, ChannelID), HuffmanTable> = hashmap! {
-            (HuffmanTableType::DC, 1) => HuffmanTable::from_vk(0, HuffmanTableType::DC, &DC_HUFFMAN_Y),
-            (HuffmanTableType::DC, 2) => HuffmanTable::from_vk(1, HuffmanTableType::DC, &DC_HUFFMAN_CBCR),
-            (HuffmanTableType::DC, 3) => HuffmanTable::from_vk(1, HuffmanTableType::DC, &DC_HUFFMAN_CBCR),
-            (HuffmanTableType::AC, 1) => HuffmanTable::from_vk(0, HuffmanTableType::AC, &AC_HUFFAN_Y),
-            (HuffmanTableType::AC, 2) => HuffmanTable::from_vk(1, HuffmanTableType::AC, &AC_HUFFAN_CBCR),
-            (HuffmanTableType::AC, 3) => HuffmanTable::from_vk(1, HuffmanTableType::AC, &AC_HUFFAN_CBCR),
-        };
+        let total_channels = if grayscale { 1 } else { 3 };
+        let channels: Vec<Channel> = (1..=total_channels).map(|id| {
+            let (horizontal_sampling, vertical_sampling) = if id == 1 {
+                subsampling.luma_sampling()
+            } else {
+                (1, 1)
+            };
 
-        // given image, transform it into multiple blocks 8x8.
-        let mcus: Vec<[Pixel; 64]> = image_to_mcus(&image);
+            Channel {
+                id,
+                horizontal_sampling,
+                vertical_sampling,
+                quantization_table_id: *quantization_table_by_channel.get(&id)
+                    .expect("Expected quantization table to be present, because quatization tables were statically defined for 3 channels"),
+            }
+        }).collect();
+
+        // given image, transform it into MCUs, each consisting of several blocks 8x8 of every channel.
+        // Chroma blocks are left unused when writing grayscale image.
+        let mcus = image_to_mcus(&image, subsampling);
         trace!("total mcus: {}", mcus.len());
 
-        let mut matrices: Vec<[[i32; 64]; 3]> = Vec::new();
-        for mcu in mcus {
-            let mut mcu_pixels = [[0i32; 3]; 64];
+        // quantization is folded into scale factors of forward dct
+        let scales: HashMap<u8, [i32; 64]> = quantization_tables.iter()
+            .map(|(table_id, table)| (*table_id, forward_dct_scales(table)))
+            .collect();
 
-            for i in 0..mcu_pixels.len() {
-                let ycbcr = rgb_to_ycbcr(&mcu[i]);
-                mcu_pixels[i] = [ycbcr.0, ycbcr.1, ycbcr.2];
-            }
+        // MCUs are independent from each other, so they are encoded in parallel
+        let matrices: Vec<Vec<[i32; 64]>> = mcus.par_iter()
+            .map(|mcu| {
+                let mut blocks = mcu.iter();
+                let mut mcu_matrices = Vec::with_capacity(mcu.len());
 
-            let mut mcu_matrices = [[0i32; 64]; 3];
+                for channel in &channels {
+                    let scales = &scales[&channel.quantization_table_id];
 
-            for channel in 0..mcu_pixels[0].len() {
-                let channel_id = channel + 1;
-                let quantization_table_id = quantization_table_by_channel[&(channel_id as u8)];
-                let quantization_table = quantization_tables[&quantization_table_id];
+                    for _ in 0..(channel.horizontal_sampling * channel.vertical_sampling) {
+                        let block = blocks.next().expect("Expected MCU to contain blocks for all channels");
+                        mcu_matrices.push(forward_dct(block, scales));
+                    }
+                }
+
+                mcu_matrices
+            })
+            .collect();
+
+        Ok(write_coefficients(&JPEGCoefficients {
+            width: image.width,
+            height: image.height,
+            channels,
+            quantization_tables,
+            mcus: matrices,
+            exif: image.metadata.exif.as_ref().map(|exif| exif.data.clone()),
+            adobe_transform: None,
+        }, optimize_huffman, progressive, restart_interval as u16))
+    }
+}
+
+// Quantized coefficients of an image, which are entropy coded as is.
+pub(crate) struct JPEGCoefficients {
+    pub width: usize,
+    pub height: usize,
+    pub channels: Vec<Channel>,
+    pub quantization_tables: HashMap<u8, [i32; 64]>,
+    // blocks of each MCU, listed by channel
+    pub mcus: Vec<Vec<[i32; 64]>>,
+    pub exif: Option<Vec<u8>>,
+    pub adobe_transform: Option<u8>,
+}
+
+pub(crate) fn write_coefficients(coefficients: &JPEGCoefficients, optimize_huffman: bool, progressive: bool, restart_interval: u16) -> Vec<u8> {
+    let channels = &coefficients.channels;
+    let matrices = &coefficients.mcus;
+
+    // the first channel (luma) has its own tables, the rest share the second ones
+    let huffman_table_by_channel: HashMap<u8, u8> = channels.iter().enumerate()
+        .map(|(index, channel)| (channel.id, if index == 0 { 0 } else { 1 }))
+        .collect();
+
+    // writing
+    let mut data = vec![0xFF, 0xD8]; // start with magic
+    // writing exif metadata
+    if let Some(exif) = &coefficients.exif {
+        match write_exif(&exif) {
+            Some(exif) => data.append(&mut prepend_marker(0xE1, exif)),
+            None => warn!("exif data of {} bytes does not fit into a segment, skipping it", exif.len()),
+        }
+    }
+    // writing color transform
+    if let Some(adobe_transform) = coefficients.adobe_transform {
+        data.append(&mut prepend_marker(0xEE, write_adobe(adobe_transform)));
+    }
+    // writing quantization tables 
+    for (table_id, table) in &coefficients.quantization_tables {
+        data.append(&mut prepend_marker(0xDB, write_quantization_table(*table_id, table)));
+    }
+    // writing restart interval
+    if restart_interval != 0 {
+        data.append(&mut prepend_marker(0xDD, write_restart_interval(restart_interval)));
+    }
+
+    if progressive {
+        // writing progressive dct, which has the same structure as baseline
+        data.append(&mut prepend_marker(0xC2, write_baseline_dct(
+            coefficients.width as u16,
+            coefficients.height as u16,
+            &channels
+        )));
 
-                let channel = extract_channel(&mcu_pixels, channel);
-                let channel = dct_encode(&channel);
-                let channel = divide_64s(&channel, &quantization_table);
-                
-                mcu_matrices[channel_id - 1] = channel;
+        let components = components_from_mcu_blocks(&channels, coefficients.width, coefficients.height, &matrices.concat());
+        let max_horizontal_sampling = channels.iter().map(|channel| channel.horizontal_sampling as usize).max().unwrap_or(1);
+        let horizontal_mcus = (coefficients.width + 8 * max_horizontal_sampling - 1) / (8 * max_horizontal_sampling);
+        let vertical_mcus = matrices.len() / horizontal_mcus;
+
+        for (channel_indexes, spectral_start, spectral_end, successive_high, successive_low) in progressive_scan_script(channels.len()) {
+            let scan = Scan {
+                components: channel_indexes.iter().map(|index| ScanComponent {
+                    index: *index,
+                    dc_table: None,
+                    ac_table: None,
+                }).collect(),
+                spectral_start,
+                spectral_end,
+                successive_high,
+                successive_low,
+            };
+            let scan_channels: Vec<u8> = channel_indexes.iter().map(|index| channels[*index].id).collect();
+            let table_ids: Vec<u8> = scan_channels.iter().map(|id| huffman_table_by_channel[id]).collect();
+
+            let (huffman_tables, scan_data) = encode_scan(
+                &scan,
+                &table_ids,
+                &components,
+                horizontal_mcus,
+                vertical_mcus,
+                restart_interval as usize
+            );
+            for table in &huffman_tables {
+                data.append(&mut prepend_marker(0xC4, write_huffman_table(table)));
             }
 
-            matrices.push(mcu_matrices);
+            let scan_channels: Vec<(u8, u8, u8)> = scan_channels.iter().zip(table_ids.iter())
+                .map(|(id, table_id)| (*id, *table_id, *table_id))
+                .collect();
+            data.append(&mut prepend_marker(0xDA, write_start_of_scan(
+                &scan_channels,
+                spectral_start as u8,
+                spectral_end as u8,
+                successive_high << 4 | successive_low,
+                write_restart_intervals(&scan_data)
+            )));
+        }
+    } else {
+        let huffman_tables = if optimize_huffman {
+            optimized_huffman_tables(&matrices, &channels, &huffman_table_by_channel, restart_interval as usize)
+        } else {
+            vec![
+                HuffmanTable::from_vk(0, HuffmanTableType::DC, &DC_HUFFMAN_Y),
+                HuffmanTable::from_vk(1, HuffmanTableType::DC, &DC_HUFFMAN_CBCR),
+                HuffmanTable::from_vk(0, HuffmanTableType::AC, &AC_HUFFAN_Y),
+                HuffmanTable::from_vk(1, HuffmanTableType::AC, &AC_HUFFAN_CBCR),
+            ].into_iter()
+                .filter(|table| channels.iter().any(|channel| huffman_table_by_channel[&channel.id] == table.id))
+                .collect()
+        };
+
+        let mut huffman_tables_by_channel: HashMap<(HuffmanTableType, ChannelID), HuffmanTable> = HashMap::new();
+        for channel in channels {
+            for table in &huffman_tables {
+                if table.id == huffman_table_by_channel[&channel.id] {
+                    huffman_tables_by_channel.insert((table.table_type.clone(), channel.id), table.clone());
+                }
+            }
         }
 
         let image_data = write_huffman_encoded_channels_data(
             &matrices,
-            &huffman_tables_by_channel
+            &channels,
+            &huffman_tables_by_channel,
+            restart_interval as usize
         );
-        let image_data_encoded = escape_image_data(&image_data);
+        let image_data_encoded = write_restart_intervals(&image_data);
 
-        let channels: Vec<Channel> = (1..=3).map(|id| {
-            Channel {
-                id,
-                horizontal_sampling: 1,
-                vertical_sampling: 1,
-                quantization_table_id: *quantization_table_by_channel.get(&id)
-                    .expect("Expected quantization table to be present, because quatization tables were statically defined for 3 channels"),
-            }
-        }).collect();
-
-        // writing
-        let mut data = vec![0xFF, 0xD8]; // start with magic
-        // writing quantization tables 
-        for (table_id, table) in quantization_tables {
-            data.append(&mut prepend_marker(0xDB, write_quantization_table(table_id, &table)));
-        }
         // writing baseline dct
         data.append(&mut prepend_marker(0xC0, write_baseline_dct(
-            image.width as u16, 
-            image.height as u16,
+            coefficients.width as u16, 
+            coefficients.height as u16,
             &channels
         )));
         // writing huffman tables
-        data.append(&mut prepend_marker(0xC4, write_huffman_table(HuffmanTableType::DC, 0)));
-        data.append(&mut prepend_marker(0xC4, write_huffman_table(HuffmanTableType::DC, 1)));
-        data.append(&mut prepend_marker(0xC4, write_huffman_table(HuffmanTableType::AC, 0)));
-        data.append(&mut prepend_marker(0xC4, write_huffman_table(HuffmanTableType::AC, 1)));
+        for table in &huffman_tables {
+            data.append(&mut prepend_marker(0xC4, write_huffman_table(table)));
+        }
 
         // start of scan
-        data.append(&mut prepend_marker(0xDA, write_start_of_scan(image_data_encoded)));
+        let scan_channels: Vec<(u8, u8, u8)> = channels.iter()
+            .map(|channel| (channel.id, huffman_table_by_channel[&channel.id], huffman_table_by_channel[&channel.id]))
+            .collect();
+        data.append(&mut prepend_marker(0xDA, write_start_of_scan(&scan_channels, 0, 63, 0, image_data_encoded)));
+    }
 
-        // end of data
-        data.append(&mut prepend_marker(0xD9, Vec::new()));
+    // end of data
+    data.append(&mut prepend_marker(0xD9, Vec::new()));
 
-        Ok(data)
-    }
+    data
 }
 
-fn write_start_of_scan(data: Vec<u8>) -> Vec<u8> {
+// channels are (channel id, dc huffman table id, ac huffman table id)
+fn write_start_of_scan(
+    channels: &[(u8, u8, u8)],
+    spectral_start: u8,
+    spectral_end: u8,
+    successive_approximation: u8,
+    data: Vec<u8>
+) -> Vec<u8> {
     let mut flat_data = data;
 
     let mut data = Vec::new();
     // block length
-    data.push(0);
-    data.push(12);
-
-    data.push(3); // total channels
-    
-    // y
-    data.push(1); // channel id
-    data.push(0); // huffman tables ids
+    let block_length = 6 + 2 * channels.len() as u16;
+    data.push((block_length >> 8) as u8);
+    data.push(block_length as u8);
 
-    // cb
-    data.push(2); // channel id
-    data.push(1 << 4 | 1); // huffman tables ids
+    data.push(channels.len() as u8); // total channels
 
-    // cr
-    data.push(3); // channel id
-    data.push(1 << 4 | 1); // huffman tables ids
+    for (channel_id, dc_table_id, ac_table_id) in channels {
+        data.push(*channel_id);
+        data.push(dc_table_id << 4 | ac_table_id); // huffman tables ids
+    }
 
-    data.push(0); // start of spectral or predictor selection
-    data.push(0); // end of spectral selection
-    data.push(0); // successive approximation bhit position
+    data.push(spectral_start); // start of spectral or predictor selection
+    data.push(spectral_end); // end of spectral selection
+    data.push(successive_approximation); // successive approximation bit position
 
     data.append(&mut flat_data);
 
     data
 }
 
-fn write_huffman_table(table_type: HuffmanTableType, id: u8) -> Vec<u8> {
-    // pre-encoded for static tables we use
-    match (table_type, id) {
-        (HuffmanTableType::DC, 0) => vec![0, 31, 0, 0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
-        (HuffmanTableType::AC, 0) => vec![0, 181, 16, 0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 125, 1, 2, 3, 0, 4, 17, 5, 18, 33, 49, 65, 6, 19, 81, 97, 7, 34, 113, 20, 50, 129, 145, 161, 8, 35, 66, 177, 193, 21, 82, 209, 240, 36, 51, 98, 114, 130, 9, 10, 22, 23, 24, 25, 26, 37, 38, 39, 40, 41, 42, 52, 53, 54, 55, 56, 57, 58, 67, 68, 69, 70, 71, 72, 73, 74, 83, 84, 85, 86, 87, 88, 89, 90, 99, 100, 101, 102, 103, 104, 105, 106, 115, 116, 117, 118, 119, 120, 121, 122, 131, 132, 133, 134, 135, 136, 137, 138, 146, 147, 148, 149, 150, 151, 152, 153, 154, 162, 163, 164, 165, 166, 167, 168, 169, 170, 178, 179, 180, 181, 182, 183, 184, 185, 186, 194, 195, 196, 197, 198, 199, 200, 201, 202, 210, 211, 212, 213, 214, 215, 216, 217, 218, 225, 226, 227, 228, 229, 230, 231, 232, 233, 234, 241, 242, 243, 244, 245, 246, 247, 248, 249, 250],
-        (HuffmanTableType::DC, 1) => vec![0, 31, 1, 0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11],
-        (HuffmanTableType::AC, 1) => vec![0, 181, 17, 0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 119, 0, 1, 2, 3, 17, 4, 5, 33, 49, 6, 18, 65, 81, 7, 97, 113, 19, 34, 50, 129, 8, 20, 66, 145, 161, 177, 193, 9, 35, 51, 82, 240, 21, 98, 114, 209, 10, 22, 36, 52, 225, 37, 241, 23, 24, 25, 26, 38, 39, 40, 41, 42, 53, 54, 55, 56, 57, 58, 67, 68, 69, 70, 71, 72, 73, 74, 83, 84, 85, 86, 87, 88, 89, 90, 99, 100, 101, 102, 103, 104, 105, 106, 115, 116, 117, 118, 119, 120, 121, 122, 130, 131, 132, 133, 134, 135, 136, 137, 138, 146, 147, 148, 149, 150, 151, 152, 153, 154, 162, 163, 164, 165, 166, 167, 168, 169, 170, 178, 179, 180, 181, 182, 183, 184, 185, 186, 194, 195, 196, 197, 198, 199, 200, 201, 202, 210, 211, 212, 213, 214, 215, 216, 217, 218, 226, 227, 228, 229, 230, 231, 232, 233, 234, 242, 243, 244, 245, 246, 247, 248, 249, 250],
-        other => panic!("Unexpected Huffman table to write: {:?}", other),
+fn write_huffman_table(table: &HuffmanTable) -> Vec<u8> {
+    let mut codes: Vec<(u8, (u16, u16))> = table.vk_table().into_iter().collect();
+    codes.sort_by_key(|(_, (code, code_length))| (*code_length, *code));
+
+    let mut data = vec![0u8; 2 + 1 + 16];
+    data[2] = (match table.table_type {
+        HuffmanTableType::DC => 0,
+        HuffmanTableType::AC => 1,
+    } << 4) | table.id;
+
+    for (value, (_, code_length)) in codes {
+        data[3 + code_length as usize - 1] += 1;
+        data.push(value);
+    }
+
+    let block_length = data.len();
+    BigEndian::write_u16(&mut data[0..2], block_length as u16);
+
+    data
+}
+
+// Second pass of encoding: builds tables from statistics of symbols which are actually present in the image.
+// Channels sharing a table contribute to the same statistics.
+fn optimized_huffman_tables(
+    matrices: &Vec<Vec<[i32; 64]>>,
+    channels: &[Channel],
+    huffman_table_by_channel: &HashMap<u8, u8>,
+    restart_interval: usize
+) -> Vec<HuffmanTable> {
+    let counts = count_huffman_symbols(matrices, channels, restart_interval);
+
+    let mut table_ids: Vec<u8> = channels.iter().map(|channel| huffman_table_by_channel[&channel.id]).collect();
+    table_ids.sort();
+    table_ids.dedup();
+
+    let mut tables = Vec::new();
+    for table_type in &[HuffmanTableType::DC, HuffmanTableType::AC] {
+        for table_id in table_ids.iter().cloned() {
+            let mut builder = HuffmanTreeBuilder::new();
+            for channel in channels.iter().filter(|channel| huffman_table_by_channel[&channel.id] == table_id) {
+                if let Some(channel_counts) = counts.get(&(table_type.clone(), channel.id)) {
+                    for (symbol, count) in channel_counts {
+                        builder.append_count(*symbol, *count);
+                    }
+                }
+            }
+
+            let (bits, values) = builder.build_jpeg_table();
+            tables.push(HuffmanTable::from_vk(table_id, table_type.clone(), &canonical_codes(&bits, &values)));
+        }
     }
+
+    tables
+}
+
+fn write_exif(exif: &[u8]) -> Option<Vec<u8>> {
+    let block_length = 2 + EXIF_HEADER.len() + exif.len();
+    if block_length > u16::MAX as usize {
+        return None;
+    }
+
+    let mut data = vec![0u8; 2];
+    BigEndian::write_u16(&mut data[0..2], block_length as u16);
+    data.extend_from_slice(EXIF_HEADER);
+    data.extend_from_slice(exif);
+    Some(data)
+}
+
+// see: https://exiftool.org/TagNames/JPEG.html#Adobe
+fn write_adobe(transform: u8) -> Vec<u8> {
+    let mut data = vec![0u8; 2];
+    data.extend_from_slice(b"Adobe");
+    // version, flags0 and flags1
+    data.extend_from_slice(&[0, 100, 0, 0, 0, 0]);
+    data.push(transform);
+
+    let block_length = data.len();
+    BigEndian::write_u16(&mut data[0..2], block_length as u16);
+    data
+}
+
+fn write_restart_interval(restart_interval: u16) -> Vec<u8> {
+    let mut data = vec![0u8; 4];
+    let block_length = data.len();
+    BigEndian::write_u16(&mut data[0..2], block_length as u16);
+    BigEndian::write_u16(&mut data[2..4], restart_interval);
+    data
 }
 
 fn write_baseline_dct(width: u16, height: u16, channels: &Vec<Channel>) -> Vec<u8> {
-    let mut data = vec![0u8; 17];
+    let mut data = vec![0u8; 8 + 3 * channels.len()];
     
     let block_length = data.len();
     BigEndian::write_u16(&mut data[0..2], block_length as u16);
@@ -654,7 +881,7 @@ fn write_baseline_dct(width: u16, height: u16, channels: &Vec<Channel>) -> Vec<u
     BigEndian::write_u16(&mut data[3..5], height);
     BigEndian::write_u16(&mut data[5..7], width);
 
-    let total_channels = 3;
+    let total_channels = channels.len() as u8;
     data[7] = total_channels;
 
     for i in 0..total_channels {
@@ -694,95 +921,96 @@ fn write_quantization_table(table_id: u8, table: &[i32; 64]) -> Vec<u8> {
     data
 }
 
-fn divide_64s(a: &[i32; 64], b: &[i32; 64]) -> [i32; 64] {
-    let mut result = [0i32; 64];
+// see:
+// https://github.com/libjpeg-turbo/libjpeg-turbo/blob/main/src/jcparam.c (jpeg_quality_scaling)
+fn scale_quantization_table(base: &[i32; 64], quality: u32) -> [i32; 64] {
+    let quality = quality as i32;
+    let scale = if quality < 50 {
+        5000 / quality
+    } else {
+        200 - quality * 2
+    };
 
+    let mut table = [0i32; 64];
     for i in 0..64 {
-        result[i] = a[i] / b[i];
+        // baseline jpeg stores quantization values as 8-bit
+        table[i] = ((base[i] * scale + 50) / 100).max(1).min(255);
     }
+    table
+}
 
-    result
+fn image_to_mcus(image: &Image, subsampling: ChromaSubsampling) -> Vec<Vec<[i32; 64]>> {
+    let (horizontal_sampling, vertical_sampling) = subsampling.luma_sampling();
+    let mcu_width = 8 * horizontal_sampling as usize;
+    let mcu_height = 8 * vertical_sampling as usize;
+
+    let horizontal_mcus = (image.width + mcu_width - 1) / mcu_width;
+    let vertical_mcus = (image.height + mcu_height - 1) / mcu_height;
+
+    (0..horizontal_mcus * vertical_mcus).into_par_iter()
+        .map(|index| image_mcu(
+            &image,
+            (index % horizontal_mcus) * mcu_width,
+            (index / horizontal_mcus) * mcu_height,
+            horizontal_sampling as usize,
+            vertical_sampling as usize
+        ))
+        .collect()
 }
 
-fn dct_encode(values: &[i32; 64]) -> [i32; 64] {
-    let mut new_values = [0i32; 64];
-    for i in 0..64 {
-        new_values[i] = values[i] - 128;
+// Returns luma blocks (left to right, top to bottom) followed by a single Cb and a single Cr block.
+// Chroma is downsampled by averaging every horizontal_sampling x vertical_sampling group of pixels.
+fn image_mcu(image: &Image, offset_x: usize, offset_y: usize, horizontal_sampling: usize, vertical_sampling: usize) -> Vec<[i32; 64]> {
+    let mcu_width = 8 * horizontal_sampling;
+    let mcu_height = 8 * vertical_sampling;
+
+    let mut ycbcr = Vec::with_capacity(mcu_width * mcu_height);
+    for y in 0..mcu_height {
+        for x in 0..mcu_width {
+            // pixels outside of the image repeat the edge, so that they do not bleed into downsampled chroma
+            let pixel = image.get_pixel(
+                (offset_x + x).min(image.width - 1),
+                (offset_y + y).min(image.height - 1)
+            );
+            ycbcr.push(rgb_to_ycbcr(&pixel));
+        }
     }
-    let values = new_values;
-
-    let mut result = [0i32; 64];
-
-    for u in 0..8 {
-        for v in 0..8 {
-            let mut sum = 0 as f32;
 
+    let mut blocks = Vec::with_capacity(horizontal_sampling * vertical_sampling + 2);
+    for block_y in 0..vertical_sampling {
+        for block_x in 0..horizontal_sampling {
+            let mut block = [0i32; 64];
             for y in 0..8 {
                 for x in 0..8 {
-                    sum += (values[y * 8 + x] as f32) * COS_TABLE[y * 8 + u] * COS_TABLE[x * 8 + v];
+                    block[y * 8 + x] = ycbcr[(block_y * 8 + y) * mcu_width + block_x * 8 + x].0;
                 }
             }
-
-            let cu = if u == 0 { 1.0/2f32.sqrt() } else { 1.0 };
-            let cv = if v == 0 { 1.0/2f32.sqrt() } else { 1.0 };
-            result[u * 8 + v] = (sum * cu * cv / 4.0).round() as i32;
+            blocks.push(block);
         }
     }
 
-    result
-}
-
-fn precompute_cos_table() -> [f32; 64] {
-    let mut result = [0f32; 64];
-
-    for i in 0..8 {
-        for j in 0..8 {
-            result[i * 8 + j] = (((2 * i + 1) * j) as f32 * PI / 16.0).cos()
-        }
-    }
-
-    result
-}
-
-fn extract_channel(pixels: &[[i32; 3]; 64], channel_index: usize) -> [i32; 64] {
-    let mut channel = [0i32; 64];
-
-    for i in 0..pixels.len() {
-        channel[i] = pixels[i][channel_index];
-    }
-
-    channel
-}
-
-fn image_to_mcus(image: &Image) -> Vec<[Pixel; 64]> {
-    let mut mcus = Vec::new();
-
-    for y in 0..(image.height as f32 / 8.0).ceil() as usize {
-        for x in 0..(image.width as f32 / 8.0).ceil() as usize {
-            mcus.push(image_mcu(&image, y, x));
-        }
-    }
-
-    mcus
-}
-
-fn image_mcu(image: &Image, y: usize, x: usize) -> [Pixel; 64] {
-    let mut result = [Pixel::black(); 64];
-    
-    let offset_x = x * 8;
-    let offset_y = y * 8;
-
+    let samples_per_unit = (horizontal_sampling * vertical_sampling) as i32;
+    let mut cb = [0i32; 64];
+    let mut cr = [0i32; 64];
     for y in 0..8 {
         for x in 0..8 {
-            result[y * 8 + x] = if offset_x + x < image.width && offset_y + y < image.height {
-                image.get_pixel(offset_x + x, offset_y + y)
-            } else  {
-                Pixel::black()
-            };
+            let mut cb_sum = 0;
+            let mut cr_sum = 0;
+            for sample_y in 0..vertical_sampling {
+                for sample_x in 0..horizontal_sampling {
+                    let value = ycbcr[(y * vertical_sampling + sample_y) * mcu_width + x * horizontal_sampling + sample_x];
+                    cb_sum += value.1;
+                    cr_sum += value.2;
+                }
+            }
+            cb[y * 8 + x] = (cb_sum + samples_per_unit / 2) / samples_per_unit;
+            cr[y * 8 + x] = (cr_sum + samples_per_unit / 2) / samples_per_unit;
         }
     }
+    blocks.push(cb);
+    blocks.push(cr);
 
-    result
+    blocks
 }
 
 #[cfg(test)]
@@ -794,9 +1022,9 @@ mod tests {
 
     use bit_vec::BitVec;
 
-    use core::models::io::{ImageReader, ImageWriterOptions};
+    use core::models::{pixel::Pixel, io::{ImageReader, ImageWriterOptions}, metadata::{Exif, ExifEntry, ExifIfd, ExifValue, EXIF_TAG_ORIENTATION}};
 
-    use crate::{common::ChannelID, common::rgb_to_ycbcr, common::{read_huffman_encoded_channels_data, ycbcr_to_rgb}, reader::{JPEGReader, dct_decode}};
+    use crate::{common::ChannelID, common::rgb_to_ycbcr, common::{read_huffman_encoded_channels_data, ycbcr_to_rgb}, reader::JPEGReader, dct::inverse_dct, dct::inverse_dct_scales};
 
     #[test]
     fn test_rgb_to_ycbcr() {
@@ -827,8 +1055,248 @@ mod tests {
             .expect("failed to read new image");
         let new_image = &new_images[0];
 
-        assert_eq!(new_image.get_pixel(277, 276), Pixel::from_rgb(24, 28, 40));
-        assert_eq!(new_image.get_pixel(833, 386), Pixel::from_rgb(150, 28, 43));
+        assert_eq!(new_image.get_pixel(277, 276), Pixel::from_rgb(18, 25, 41));
+        assert_eq!(new_image.get_pixel(833, 386), Pixel::from_rgb(152, 26, 40));
+    }
+
+    #[test]
+    fn test_scale_quantization_table() {
+        assert_eq!(scale_quantization_table(&QUANTIZATION_TABLE_Y_50, 50), QUANTIZATION_TABLE_Y_50);
+        assert_eq!(scale_quantization_table(&QUANTIZATION_TABLE_Y_50, 100), [1; 64]);
+
+        // same values as GIMP uses at 90% quality
+        let table = scale_quantization_table(&QUANTIZATION_TABLE_Y_50, 90);
+        assert_eq!(&table[0..8], &[3, 2, 2, 3, 5, 8, 10, 12]);
+        let table = scale_quantization_table(&QUANTIZATION_TABLE_CB_CR_50, 90);
+        assert_eq!(&table[0..8], &[3, 4, 5, 9, 20, 20, 20, 20]);
+
+        let table = scale_quantization_table(&QUANTIZATION_TABLE_Y_50, 25);
+        assert_eq!(&table[0..4], &[32, 22, 20, 32]);
+
+        let table = scale_quantization_table(&QUANTIZATION_TABLE_Y_50, 1);
+        assert!(table.iter().all(|value| *value == 255));
+    }
+
+    #[test]
+    fn test_write_any_quality() {
+        let pixels = (0..256).map(|i| Pixel::from_rgb(i as u8, (i * 3) as u8, 255 - i as u8)).collect();
+        let image = Image::from_pixels(16, 16, pixels);
+        let writer = JPEGWriter::new();
+        let reader = JPEGReader::new();
+        for quality in &[1, 13, 37, 50, 75, 99, 100] {
+            let data = writer.write(&image, &ImageWriterOptions::default().with_option_u32(OPTION_QUALITY, *quality))
+                .expect("failed to write image");
+            let read = reader.read(&data).expect("failed to read written image");
+            assert_eq!(read[0].width, image.width);
+            assert_eq!(read[0].height, image.height);
+        }
+
+        assert!(writer.write(&image, &ImageWriterOptions::default().with_option_u32(OPTION_QUALITY, 0)).is_err());
+        assert!(writer.write(&image, &ImageWriterOptions::default().with_option_u32(OPTION_QUALITY, 101)).is_err());
+    }
+
+    #[test]
+    fn test_write_subsampled() {
+        let image_data = read("assets/balloon.jpg")
+            .expect("failed to load test image");
+
+        let reader = JPEGReader::new();
+        let image = &reader.read(&image_data)
+            .expect("failed to read test image")[0];
+
+        let writer = JPEGWriter::new();
+        let full = writer.write(&image, &ImageWriterOptions::default())
+            .expect("failed to write image");
+
+        for subsampling in &["4:2:2", "4:2:0"] {
+            let subsampled = writer.write(&image, &ImageWriterOptions::default().with_option(OPTION_SUBSAMPLING, subsampling))
+                .expect("failed to write subsampled image");
+            assert!(subsampled.len() < full.len());
+
+            let new_image = &reader.read(&subsampled)
+                .expect("failed to read subsampled image")[0];
+            for (x, y) in &[(277, 276), (833, 386)] {
+                let expected = image.get_pixel(*x, *y);
+                let actual = new_image.get_pixel(*x, *y);
+                assert!((expected.red as i32 - actual.red as i32).abs() < 16);
+                assert!((expected.green as i32 - actual.green as i32).abs() < 16);
+                assert!((expected.blue as i32 - actual.blue as i32).abs() < 16);
+            }
+        }
+
+        assert!(writer.write(&image, &ImageWriterOptions::default().with_option(OPTION_SUBSAMPLING, "4:1:1")).is_err());
+    }
+
+    #[test]
+    fn test_write_empty_image() {
+        let writer = JPEGWriter::new();
+        let options = ImageWriterOptions::default();
+
+        assert!(writer.write(&Image::new(0, 8), &options).is_err());
+        assert!(writer.write(&Image::new(8, 0), &options).is_err());
+        assert!(writer.write(&Image::new(0, 0), &options.with_option(OPTION_SUBSAMPLING, "4:2:0")).is_err());
+        assert!(writer.write(&Image::new(1, 1), &options).is_ok());
+    }
+
+    #[test]
+    fn test_image_mcu_downsamples_chroma() {
+        let mut image = Image::new(16, 16);
+        for y in 0..16 {
+            for x in 0..16 {
+                image.set_pixel(x, y, if x % 2 == 0 { Pixel::from_rgb(255, 0, 0) } else { Pixel::from_rgb(0, 0, 255) });
+            }
+        }
+
+        let blocks = image_mcu(&image, 0, 0, 2, 2);
+        assert_eq!(blocks.len(), 6);
+
+        let red = rgb_to_ycbcr(&Pixel::from_rgb(255, 0, 0));
+        let blue = rgb_to_ycbcr(&Pixel::from_rgb(0, 0, 255));
+        assert_eq!(blocks[0][0], red.0);
+        assert_eq!(blocks[3][1], blue.0);
+        assert!(blocks[4].iter().all(|cb| (*cb - (red.1 + blue.1) / 2).abs() <= 1));
+        assert!(blocks[5].iter().all(|cr| (*cr - (red.2 + blue.2) / 2).abs() <= 1));
+    }
+
+    #[test]
+    fn test_write_static_huffman_table() {
+        assert_eq!(
+            write_huffman_table(&HuffmanTable::from_vk(0, HuffmanTableType::DC, &DC_HUFFMAN_Y)),
+            vec![0, 31, 0, 0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]
+        );
+    }
+
+    #[test]
+    fn test_write_optimized_huffman() {
+        let image_data = read("assets/balloon.jpg")
+            .expect("failed to load test image");
+
+        let reader = JPEGReader::new();
+        let image = &reader.read(&image_data)
+            .expect("failed to read test image")[0];
+
+        let writer = JPEGWriter::new();
+        let standard = writer.write(&image, &ImageWriterOptions::default())
+            .expect("failed to write image");
+        let optimized = writer.write(&image, &ImageWriterOptions::default().with_option_bool(OPTION_OPTIMIZE_HUFFMAN, true))
+            .expect("failed to write image with optimized huffman tables");
+        assert!(optimized.len() < standard.len());
+
+        // only entropy coding differs, so decoded pixels are exactly the same
+        let standard_image = &reader.read(&standard).expect("failed to read image")[0];
+        let optimized_image = &reader.read(&optimized).expect("failed to read optimized image")[0];
+        assert_eq!(standard_image.pixels, optimized_image.pixels);
+    }
+
+    #[test]
+    fn test_write_progressive() {
+        let image_data = read("assets/balloon.jpg")
+            .expect("failed to load test image");
+
+        let reader = JPEGReader::new();
+        let image = &reader.read(&image_data)
+            .expect("failed to read test image")[0];
+
+        let writer = JPEGWriter::new();
+        for subsampling in &["4:4:4", "4:2:0"] {
+            let options = ImageWriterOptions::default().with_option(OPTION_SUBSAMPLING, subsampling);
+            let baseline = writer.write(&image, &options)
+                .expect("failed to write image");
+            let progressive = writer.write(&image, &options.with_option_bool(OPTION_PROGRESSIVE, true))
+                .expect("failed to write progressive image");
+            assert!(progressive.windows(2).any(|marker| marker == [0xFF, 0xC2]));
+
+            // coefficients are the same, only the order in which they are written differs
+            let baseline_image = &reader.read(&baseline).expect("failed to read image")[0];
+            let progressive_image = &reader.read(&progressive).expect("failed to read progressive image")[0];
+            assert_eq!(baseline_image.pixels, progressive_image.pixels);
+        }
+    }
+
+    #[test]
+    fn test_write_grayscale() {
+        let image_data = read("assets/balloon.jpg")
+            .expect("failed to load test image");
+
+        let reader = JPEGReader::new();
+        let image = &reader.read(&image_data)
+            .expect("failed to read test image")[0];
+
+        let writer = JPEGWriter::new();
+        let options = ImageWriterOptions::default().with_option_bool(OPTION_GRAYSCALE, true);
+        let baseline = writer.write(&image, &options)
+            .expect("failed to write grayscale image");
+        let progressive = writer.write(&image, &options.with_option_bool(OPTION_PROGRESSIVE, true))
+            .expect("failed to write progressive grayscale image");
+        assert!(baseline.len() < writer.write(&image, &ImageWriterOptions::default()).expect("failed to write image").len());
+
+        let baseline_image = &reader.read(&baseline).expect("failed to read grayscale image")[0];
+        let progressive_image = &reader.read(&progressive).expect("failed to read progressive grayscale image")[0];
+        assert_eq!(baseline_image.pixels, progressive_image.pixels);
+        assert_eq!(baseline_image.width, image.width);
+        assert_eq!(baseline_image.height, image.height);
+
+        for (x, y) in &[(0, 0), (640, 400), (1000, 800)] {
+            let original = image.get_pixel(*x, *y);
+            let pixel = baseline_image.get_pixel(*x, *y);
+            assert_eq!(pixel.red, pixel.green);
+            assert_eq!(pixel.red, pixel.blue);
+            assert!((pixel.red as i32 - rgb_to_ycbcr(&original).0).abs() < 12);
+        }
+    }
+
+    #[test]
+    fn test_write_restart_interval() {
+        let image_data = read("assets/balloon.jpg")
+            .expect("failed to load test image");
+
+        let reader = JPEGReader::new();
+        let image = &reader.read(&image_data)
+            .expect("failed to read test image")[0];
+
+        let writer = JPEGWriter::new();
+        let base_options = [
+            ImageWriterOptions::default().with_option_bool(OPTION_OPTIMIZE_HUFFMAN, true),
+            ImageWriterOptions::default().with_option(OPTION_SUBSAMPLING, "4:2:0").with_option_bool(OPTION_PROGRESSIVE, true),
+        ];
+        for options in &base_options {
+            let without_restarts = writer.write(&image, options)
+                .expect("failed to write image");
+            let with_restarts = writer.write(&image, &options.with_option_u32(OPTION_RESTART_INTERVAL, 5))
+                .expect("failed to write image with restart interval");
+            assert!(with_restarts.windows(2).any(|marker| marker == [0xFF, 0xDD]));
+            assert!(with_restarts.windows(2).any(|marker| marker == [0xFF, 0xD7]));
+
+            // restarts change only the entropy coding
+            let image_without_restarts = &reader.read(&without_restarts).expect("failed to read image")[0];
+            let image_with_restarts = &reader.read(&with_restarts).expect("failed to read image with restart markers")[0];
+            assert_eq!(image_without_restarts.pixels, image_with_restarts.pixels);
+        }
+
+        assert!(writer.write(&image, &ImageWriterOptions::default().with_option_u32(OPTION_RESTART_INTERVAL, 70000)).is_err());
+    }
+
+    #[test]
+    fn test_write_exif() {
+        let mut image = Image::new(16, 8);
+        image.metadata.exif = Some(Exif {
+            entries: vec![ExifEntry {
+                ifd: ExifIfd::Primary,
+                tag: EXIF_TAG_ORIENTATION,
+                value: ExifValue::Short(vec![1]),
+            }],
+            data: vec![
+                b'I', b'I', 42, 0, 8, 0, 0, 0,
+                1, 0, 0x12, 0x01, 3, 0, 1, 0, 0, 0, 1, 0, 0, 0,
+                0, 0, 0, 0,
+            ],
+        });
+
+        let written = JPEGWriter::new().write(&image, &ImageWriterOptions::default())
+            .expect("failed to write image with exif");
+        let read = &JPEGReader::new().read(&written).expect("failed to read written image")[0];
+        assert_eq!((read.width, read.height), (16, 8));
+        assert_eq!(read.metadata.exif, image.metadata.exif);
     }
 
     #[test]
@@ -839,8 +1307,8 @@ mod tests {
             82, 82, 81, 83, 78, 81, 80, 79, 83, 82, 80, 82, 81, 84, 82, 81, 84, 82, 78, 80
         ];
 
-        let encoded = dct_encode(&source);        
-        let decoded = dct_decode(&encoded);
+        let encoded = forward_dct(&source, &forward_dct_scales(&[1; 64]));
+        let decoded = inverse_dct(&encoded, &inverse_dct_scales(&[1; 64]));
 
         let mut diff = 0;
         for i in 0..64 {
@@ -865,7 +1333,7 @@ mod tests {
             0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0
         ];
 
-        let encoded = dct_encode(&source);
+        let encoded = forward_dct(&source, &forward_dct_scales(&[1; 64]));
         
         let mut diff = 0;
         for i in 0..64 {
@@ -920,6 +1388,7 @@ mod tests {
             (HuffmanTableType::AC, 3 as ChannelID) => HuffmanTable::from_vk(1, HuffmanTableType::AC, &AC_HUFFAN_CBCR),
         };
 
+        let channels: Vec<Channel> = (1..=3).map(|id| channels[&id].clone()).collect();
         let mut matrices = read_huffman_encoded_channels_data(
             &BitVec::from_bytes(&encoded_data),
             total_mcus,
@@ -927,16 +1396,12 @@ mod tests {
             &huffman_tables
         ).expect("failed to read test huffman encoded data");
 
-        let mut matrices_transformed: Vec<[[i32; 64]; 3]> = Vec::new();
+        let mut matrices_transformed: Vec<Vec<[i32; 64]>> = Vec::new();
         while matrices.len() > 0 {
-            matrices_transformed.push([
-                matrices.remove(0),
-                matrices.remove(0),
-                matrices.remove(0)
-            ]);
+            matrices_transformed.push(matrices.drain(0..3).collect());
         }
 
-        let encoded = write_huffman_encoded_channels_data(&matrices_transformed, &huffman_tables);
-        assert_eq!(encoded, encoded_data);
+        let encoded = write_huffman_encoded_channels_data(&matrices_transformed, &channels, &huffman_tables, 0);
+        assert_eq!(encoded, vec![encoded_data]);
     }
 }
\ No newline at end of file
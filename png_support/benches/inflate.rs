@@ -0,0 +1,34 @@
+#![feature(test)]
+
+extern crate test;
+
+use std::fs::read;
+use test::Bencher;
+use core::models::io::ImageReader;
+use png_support::chunk::read_chunks;
+use png_support::inflate::inflate_decompress;
+use png_support::reader::PNGReader;
+
+fn load_idat(path: &str) -> Vec<u8> {
+    let png = read(path).expect("Failed to load asset");
+    read_chunks(&png[8..], true).unwrap().idat.data
+}
+
+#[bench]
+fn bench_inflate_shisui(b: &mut Bencher) {
+    let idat = load_idat("assets/shisui.png");
+    b.iter(|| inflate_decompress(&idat).unwrap());
+}
+
+#[bench]
+fn bench_inflate_simple3(b: &mut Bencher) {
+    let idat = load_idat("assets/simple3.png");
+    b.iter(|| inflate_decompress(&idat).unwrap());
+}
+
+#[bench]
+fn bench_read_shisui(b: &mut Bencher) {
+    let png = read("assets/shisui.png").expect("Failed to load assets/shisui.png");
+    let reader = PNGReader::new();
+    b.iter(|| reader.read(&png).unwrap());
+}
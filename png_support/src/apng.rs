@@ -0,0 +1,198 @@
+use std::time::Duration;
+use core::models::{animation::AnimationFrame, image::Image, pixel::Pixel};
+use crate::chunk::{FCTLChunk, DisposeOp, BlendOp};
+use crate::reader::PNGReaderError;
+
+// see:
+// https://wiki.mozilla.org/APNG_Specification
+
+pub struct DecodedFrame {
+    pub fctl: FCTLChunk,
+    pub pixels: Vec<Pixel>,
+}
+
+pub fn frame_delay(fctl: &FCTLChunk) -> Duration {
+    // zero denominator means 1/100 of a second
+    let delay_den = if fctl.delay_den == 0 { 100 } else { fctl.delay_den as u64 };
+    Duration::from_nanos(fctl.delay_num as u64 * 1_000_000_000 / delay_den)
+}
+
+fn validate_region(fctl: &FCTLChunk, width: usize, height: usize) -> Result<(), PNGReaderError> {
+    let x_end = fctl.x_offset as u64 + fctl.width as u64;
+    let y_end = fctl.y_offset as u64 + fctl.height as u64;
+    if fctl.width == 0 || fctl.height == 0 || x_end > width as u64 || y_end > height as u64 {
+        return Result::Err(PNGReaderError::InvalidChunk {
+            description: format!(
+                "frame {}x{} at ({}, {}) does not fit into {}x{} canvas",
+                fctl.width, fctl.height, fctl.x_offset, fctl.y_offset, width, height
+            )
+        });
+    }
+    Result::Ok(())
+}
+
+// Renders frames onto the canvas the way APNG decoders display them, applying blend and dispose operations.
+pub fn compose_frames(width: usize, height: usize, frames: Vec<DecodedFrame>) -> Result<Vec<Image>, PNGReaderError> {
+    let transparent = Pixel::from_rgba(0, 0, 0, 0);
+    let mut canvas = vec![transparent; width * height];
+    let mut images = Vec::with_capacity(frames.len());
+
+    for (index, frame) in frames.iter().enumerate() {
+        let fctl = &frame.fctl;
+        validate_region(fctl, width, height)?;
+        let x_offset = fctl.x_offset as usize;
+        let y_offset = fctl.y_offset as usize;
+        let frame_width = fctl.width as usize;
+        let frame_height = fctl.height as usize;
+
+        let region_indexes = (0..frame_height).flat_map(|y| {
+            (0..frame_width).map(move |x| (y * frame_width + x, (y_offset + y) * width + x_offset + x))
+        });
+
+        let dispose_op = if index == 0 && fctl.dispose_op == DisposeOp::Previous {
+            DisposeOp::Background
+        } else {
+            fctl.dispose_op
+        };
+        let previous = if dispose_op == DisposeOp::Previous {
+            Some(canvas.clone())
+        } else {
+            None
+        };
+
+        for (frame_index, canvas_index) in region_indexes.clone() {
+            let pixel = frame.pixels[frame_index];
+            canvas[canvas_index] = match fctl.blend_op {
+                BlendOp::Source => pixel,
+                BlendOp::Over => pixel.blend_over(&canvas[canvas_index]),
+            };
+        }
+
+        let mut image = Image::from_pixels(width, height, canvas.clone());
+        image.animation = Some(AnimationFrame::with_delay(frame_delay(fctl)));
+        images.push(image);
+
+        match dispose_op {
+            DisposeOp::None => {},
+            DisposeOp::Background => {
+                for (_, canvas_index) in region_indexes {
+                    canvas[canvas_index] = transparent;
+                }
+            },
+            DisposeOp::Previous => {
+                canvas = previous.unwrap();
+            },
+        }
+    }
+
+    Result::Ok(images)
+}
+
+// Smallest rectangle (x, y, width, height) containing every pixel which differs between two frames of the same size.
+// Identical frames still need a non-empty region, so a single pixel is used for them.
+pub fn changed_region(previous: &Image, current: &Image) -> (usize, usize, usize, usize) {
+    let mut x_min = current.width;
+    let mut y_min = current.height;
+    let mut x_max = 0;
+    let mut y_max = 0;
+    for y in 0..current.height {
+        for x in 0..current.width {
+            if previous.get_pixel(x, y) != current.get_pixel(x, y) {
+                x_min = x_min.min(x);
+                y_min = y_min.min(y);
+                x_max = x_max.max(x);
+                y_max = y_max.max(y);
+            }
+        }
+    }
+
+    if x_min > x_max {
+        (0, 0, 1, 1)
+    } else {
+        (x_min, y_min, x_max - x_min + 1, y_max - y_min + 1)
+    }
+}
+
+pub fn crop(image: &Image, x_offset: usize, y_offset: usize, width: usize, height: usize) -> Image {
+    let mut pixels = Vec::with_capacity(width * height);
+    for y in y_offset..(y_offset + height) {
+        pixels.extend_from_slice(&image.pixels[(y * image.width + x_offset)..(y * image.width + x_offset + width)]);
+    }
+    Image::from_pixels(width, height, pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fctl(width: u32, height: u32, x_offset: u32, y_offset: u32, dispose_op: DisposeOp, blend_op: BlendOp) -> FCTLChunk {
+        FCTLChunk {
+            sequence_number: 0,
+            width,
+            height,
+            x_offset,
+            y_offset,
+            delay_num: 1,
+            delay_den: 10,
+            dispose_op,
+            blend_op,
+        }
+    }
+
+    #[test]
+    fn test_frame_delay() {
+        assert_eq!(frame_delay(&fctl(1, 1, 0, 0, DisposeOp::None, BlendOp::Source)), Duration::from_millis(100));
+
+        let mut without_denominator = fctl(1, 1, 0, 0, DisposeOp::None, BlendOp::Source);
+        without_denominator.delay_num = 5;
+        without_denominator.delay_den = 0;
+        assert_eq!(frame_delay(&without_denominator), Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_compose_frames() {
+        let red = Pixel::from_rgb(255, 0, 0);
+        let green = Pixel::from_rgb(0, 255, 0);
+        let half_blue = Pixel::from_rgba(0, 0, 255, 128);
+        let transparent = Pixel::from_rgba(0, 0, 0, 0);
+
+        let frames = vec![
+            DecodedFrame { fctl: fctl(2, 1, 0, 0, DisposeOp::None, BlendOp::Source), pixels: vec![red, red] },
+            DecodedFrame { fctl: fctl(1, 1, 1, 0, DisposeOp::Previous, BlendOp::Over), pixels: vec![half_blue] },
+            DecodedFrame { fctl: fctl(1, 1, 0, 0, DisposeOp::Background, BlendOp::Source), pixels: vec![green] },
+            DecodedFrame { fctl: fctl(1, 1, 1, 0, DisposeOp::None, BlendOp::Over), pixels: vec![transparent] },
+        ];
+        let images = compose_frames(2, 1, frames).unwrap();
+
+        assert_eq!(images[0].pixels, vec![red, red]);
+        assert_eq!(images[1].pixels, vec![red, Pixel::from_rgb(127, 0, 128)]);
+        // the blended pixel was disposed to the previous state
+        assert_eq!(images[2].pixels, vec![green, red]);
+        // green was disposed to transparent background
+        assert_eq!(images[3].pixels, vec![transparent, red]);
+        assert_eq!(images[3].animation, Some(AnimationFrame::with_delay(Duration::from_millis(100))));
+    }
+
+    #[test]
+    fn test_compose_frame_outside_of_canvas() {
+        let frames = vec![
+            DecodedFrame { fctl: fctl(2, 1, 1, 0, DisposeOp::None, BlendOp::Source), pixels: vec![Pixel::zero(); 2] },
+        ];
+        assert!(compose_frames(2, 1, frames).is_err());
+    }
+
+    #[test]
+    fn test_changed_region() {
+        let previous = Image::test_image();
+        assert_eq!(changed_region(&previous, &previous), (0, 0, 1, 1));
+
+        let mut current = previous.clone();
+        current.set_pixel(1, 2, Pixel::black());
+        current.set_pixel(3, 1, Pixel::black());
+        assert_eq!(changed_region(&previous, &current), (1, 1, 3, 2));
+
+        let cropped = crop(&current, 1, 1, 3, 2);
+        assert_eq!(cropped.get_pixel(0, 1), Pixel::black());
+        assert_eq!(cropped.get_pixel(2, 0), Pixel::black());
+    }
+}
@@ -0,0 +1,81 @@
+// see:
+// https://www.w3.org/TR/PNG/#D-CRCAppendix
+// https://tools.ietf.org/html/rfc1950#section-9
+
+const CRC_TABLE: [u32; 256] = make_crc_table();
+
+const ADLER_MODULO: u32 = 65521;
+
+// largest n such that 255 * n * (n + 1) / 2 + (n + 1) * (ADLER_MODULO - 1) fits into u32
+const ADLER_MAX_BLOCK: usize = 5552;
+
+const fn make_crc_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut n = 0;
+    while n < 256 {
+        let mut c = n as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 {
+                0xEDB88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[n] = c;
+        n += 1;
+    }
+    table
+}
+
+pub fn update_crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut c = crc;
+    for byte in data {
+        c = CRC_TABLE[((c ^ *byte as u32) & 0xFF) as usize] ^ (c >> 8);
+    }
+    c
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    update_crc32(0xFFFFFFFF, data) ^ 0xFFFFFFFF
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let mut a: u32 = 1;
+    let mut b: u32 = 0;
+    for block in data.chunks(ADLER_MAX_BLOCK) {
+        for byte in block {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= ADLER_MODULO;
+        b %= ADLER_MODULO;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xCBF43926);
+        assert_eq!(crc32(b"IEND"), 0xAE426082);
+    }
+
+    #[test]
+    fn test_update_crc32() {
+        let crc = update_crc32(0xFFFFFFFF, b"1234");
+        let crc = update_crc32(crc, b"56789");
+        assert_eq!(crc ^ 0xFFFFFFFF, 0xCBF43926);
+    }
+
+    #[test]
+    fn test_adler32() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+        assert_eq!(adler32(&vec![255; 100000]), 0x149A302C);
+    }
+}
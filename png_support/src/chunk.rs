@@ -1,8 +1,10 @@
 use byteorder::{ByteOrder, BigEndian};
 use std::str::from_utf8;
 use crate::reader::{PNGReaderError, PNGImage, PNGImageType};
+use crate::checksum::crc32;
+use crate::inflate::inflate_decompress;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct IHDRChunk {
     pub width: u32,
     pub height: u32,
@@ -13,21 +15,71 @@ pub struct IHDRChunk {
     pub interlace_method: u8,
 }
 
+#[derive(Debug)]
+pub struct PLTEChunk {
+    pub entries: Vec<[u8; 3]>,
+}
+
+#[derive(Debug)]
+pub struct TRNSChunk {
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct ACTLChunk {
+    pub num_frames: u32,
+    pub num_plays: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FCTLChunk {
+    pub sequence_number: u32,
+    pub width: u32,
+    pub height: u32,
+    pub x_offset: u32,
+    pub y_offset: u32,
+    pub delay_num: u16,
+    pub delay_den: u16,
+    pub dispose_op: DisposeOp,
+    pub blend_op: BlendOp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisposeOp {
+    None,
+    Background,
+    Previous,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlendOp {
+    Source,
+    Over,
+}
+
+// animation frame: fcTL followed by its image data, which comes either from IDAT (for the default image) or from fdAT chunks
+#[derive(Debug)]
+pub struct APNGFrame {
+    pub fctl: FCTLChunk,
+    pub data: IDATChunk,
+}
+
 #[derive(Debug)]
 pub struct SBITChunk {
-    sample_depths: Vec<u8>,
+    pub sample_depths: Vec<u8>,
 }
 
 #[derive(Debug)]
 pub struct TEXTChunk {
-    text: String
+    pub keyword: String,
+    pub text: String,
 }
 
 #[derive(Debug)]
 pub struct PHYSChunk {
-    pixels_per_unit_x: u32,
-    pixels_per_unit_y: u32,
-    unit_specifier: u8,
+    pub pixels_per_unit_x: u32,
+    pub pixels_per_unit_y: u32,
+    pub unit_specifier: u8,
 }
 
 #[derive(Debug)]
@@ -37,24 +89,126 @@ pub struct IDATChunk {
 
 #[derive(Debug)]
 pub struct ICCPChunk {
+    pub name: String,
+    pub profile: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct EXIFChunk {
     pub data: Vec<u8>,
 }
 
 #[derive(Debug)]
 pub struct TIMEChunk {
-    pub data: Vec<u8>,
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
 }
 
 #[derive(Debug)]
 pub struct ZTXTChunk {
-    data: Vec<u8>,
+    pub keyword: String,
+    pub text: String,
 }
 
-
 #[derive(Debug)]
 pub struct ITXTChunk {
-    data: Vec<u8>,
+    pub keyword: String,
+    pub compressed: bool,
+    pub language_tag: String,
+    pub translated_keyword: String,
+    pub text: String,
+}
+
+impl IHDRChunk {
+    pub fn bits_per_pixel(&self) -> usize {
+        self.colour_type.get_samples_amount() * self.bit_depth as usize
+    }
+
+    // filtering operates on bytes, so pixels narrower than a byte are treated as one byte wide
+    pub fn bytes_per_pixel(&self) -> usize {
+        std::cmp::max(1, self.bits_per_pixel() / 8)
+    }
+
+    pub fn scanline_length(&self, width: usize) -> usize {
+        (width * self.bits_per_pixel() + 7) / 8
+    }
+}
+
+fn validate_bit_depth(colour_type: PNGImageType, bit_depth: u8) -> Result<(), PNGReaderError> {
+    let allowed: &[u8] = match colour_type {
+        PNGImageType::Greyscale => &[1, 2, 4, 8, 16],
+        PNGImageType::IndexedColour => &[1, 2, 4, 8],
+        PNGImageType::Truecolour | PNGImageType::GreyscaleAlpha | PNGImageType::TruecolourAlpha => &[8, 16],
+    };
+    if allowed.contains(&bit_depth) {
+        Result::Ok(())
+    } else {
+        Result::Err(PNGReaderError::InvalidChunk {
+            description: format!("Bit depth {} is not allowed for colour type {:?}", bit_depth, colour_type)
+        })
+    }
+}
+
+impl DisposeOp {
+    pub fn from_number(number: u8) -> Result<DisposeOp, PNGReaderError> {
+        match number {
+            0 => Result::Ok(DisposeOp::None),
+            1 => Result::Ok(DisposeOp::Background),
+            2 => Result::Ok(DisposeOp::Previous),
+            _ => Result::Err(PNGReaderError::InvalidChunk {
+                description: format!("Unknown fcTL dispose op {}", number)
+            }),
+        }
+    }
+
+    pub fn to_number(&self) -> u8 {
+        match &self {
+            DisposeOp::None => 0,
+            DisposeOp::Background => 1,
+            DisposeOp::Previous => 2,
+        }
+    }
+}
+
+impl BlendOp {
+    pub fn from_number(number: u8) -> Result<BlendOp, PNGReaderError> {
+        match number {
+            0 => Result::Ok(BlendOp::Source),
+            1 => Result::Ok(BlendOp::Over),
+            _ => Result::Err(PNGReaderError::InvalidChunk {
+                description: format!("Unknown fcTL blend op {}", number)
+            }),
+        }
+    }
+
+    pub fn to_number(&self) -> u8 {
+        match &self {
+            BlendOp::Source => 0,
+            BlendOp::Over => 1,
+        }
+    }
+}
+
+impl FCTLChunk {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut data = vec![0; 26];
+        BigEndian::write_u32(&mut data[0..4], self.sequence_number);
+        BigEndian::write_u32(&mut data[4..8], self.width);
+        BigEndian::write_u32(&mut data[8..12], self.height);
+        BigEndian::write_u32(&mut data[12..16], self.x_offset);
+        BigEndian::write_u32(&mut data[16..20], self.y_offset);
+        BigEndian::write_u16(&mut data[20..22], self.delay_num);
+        BigEndian::write_u16(&mut data[22..24], self.delay_den);
+        data[24] = self.dispose_op.to_number();
+        data[25] = self.blend_op.to_number();
+        data
+    }
 }
+
 impl IDATChunk {
     pub const fn new() -> Self {
         IDATChunk {
@@ -72,9 +226,11 @@ impl IDATChunk {
 
 fn read_ihdr_chunk(data: &[u8]) -> Result<(IHDRChunk, &[u8]), PNGReaderError> {
     let length = BigEndian::read_u32(&data[0..4]) as usize;
-    println!("{}", length);
-    let chunk_type = from_utf8(&data[4..8]).unwrap();
-    println!("{}", chunk_type);
+    if length != 13 {
+        return Result::Err(PNGReaderError::InvalidChunk {
+            description: format!("IHDR chunk length must be 13 but got {}", length)
+        });
+    }
     let ihdr_chunk = IHDRChunk {
         width: BigEndian::read_u32(&data[8..12]),
         height: BigEndian::read_u32(&data[12..16]),
@@ -84,103 +240,259 @@ fn read_ihdr_chunk(data: &[u8]) -> Result<(IHDRChunk, &[u8]), PNGReaderError> {
         filter_method: data[19],
         interlace_method: data[20]
     };
-    println!("{:?}", ihdr_chunk);
+    validate_bit_depth(ihdr_chunk.colour_type, ihdr_chunk.bit_depth)?;
     Result::Ok((ihdr_chunk, &data[(length + 12)..]))
 }
 
+fn read_plte_chunk(data: &[u8]) -> Result<(PLTEChunk, &[u8]), PNGReaderError> {
+    let length = BigEndian::read_u32(&data[0..4]) as usize;
+    if length % 3 != 0 || length == 0 || length > 256 * 3 {
+        return Result::Err(PNGReaderError::InvalidChunk {
+            description: format!("PLTE chunk length must be a multiple of 3 up to 768 but got {}", length)
+        });
+    }
+    let entries = data[8..(8 + length)].chunks(3)
+        .map(|entry| [entry[0], entry[1], entry[2]])
+        .collect();
+    Result::Ok((PLTEChunk { entries }, &data[length + 12..]))
+}
+
+fn read_trns_chunk(data: &[u8]) -> Result<(TRNSChunk, &[u8]), PNGReaderError> {
+    let length = BigEndian::read_u32(&data[0..4]) as usize;
+    let chunk_data = data[8..(8 + length)].to_vec();
+    Result::Ok((TRNSChunk { data: chunk_data }, &data[length + 12..]))
+}
+
+fn read_actl_chunk(data: &[u8]) -> Result<(ACTLChunk, &[u8]), PNGReaderError> {
+    let length = BigEndian::read_u32(&data[0..4]) as usize;
+    if length != 8 {
+        return Result::Err(PNGReaderError::InvalidChunk {
+            description: format!("acTL chunk length must be 8 but got {}", length)
+        });
+    }
+    let actl_chunk = ACTLChunk {
+        num_frames: BigEndian::read_u32(&data[8..12]),
+        num_plays: BigEndian::read_u32(&data[12..16]),
+    };
+    Result::Ok((actl_chunk, &data[length + 12..]))
+}
+
+fn read_fctl_chunk(data: &[u8]) -> Result<(FCTLChunk, &[u8]), PNGReaderError> {
+    let length = BigEndian::read_u32(&data[0..4]) as usize;
+    if length != 26 {
+        return Result::Err(PNGReaderError::InvalidChunk {
+            description: format!("fcTL chunk length must be 26 but got {}", length)
+        });
+    }
+    let fctl_chunk = FCTLChunk {
+        sequence_number: BigEndian::read_u32(&data[8..12]),
+        width: BigEndian::read_u32(&data[12..16]),
+        height: BigEndian::read_u32(&data[16..20]),
+        x_offset: BigEndian::read_u32(&data[20..24]),
+        y_offset: BigEndian::read_u32(&data[24..28]),
+        delay_num: BigEndian::read_u16(&data[28..30]),
+        delay_den: BigEndian::read_u16(&data[30..32]),
+        dispose_op: DisposeOp::from_number(data[32])?,
+        blend_op: BlendOp::from_number(data[33])?,
+    };
+    Result::Ok((fctl_chunk, &data[length + 12..]))
+}
+
+// fdAT has the same contents as IDAT, prefixed by a sequence number
+fn read_fdat_chunk(data: &[u8]) -> Result<(IDATChunk, &[u8]), PNGReaderError> {
+    let length = BigEndian::read_u32(&data[0..4]) as usize;
+    if length < 4 {
+        return Result::Err(PNGReaderError::InvalidChunk {
+            description: format!("fdAT chunk is too short: {}", length)
+        });
+    }
+    let chunk_data = data[12..(8 + length)].to_vec();
+    Result::Ok((IDATChunk { data: chunk_data }, &data[length + 12..]))
+}
+
 fn read_sbit_chunk(data: &[u8]) -> Result<(SBITChunk, &[u8]), PNGReaderError> {
     let length = BigEndian::read_u32(&data[0..4]) as usize;
-    println!("{}", length);
-    let chunk_type = from_utf8(&data[4..8]).unwrap();
-    println!("{}", chunk_type);
     let sample_depths = &data[8..(8 + length)];
-    println!("{:?}", sample_depths);
     Result::Ok((SBITChunk { sample_depths: sample_depths.to_vec() }, &data[length + 12..]))
 }
 
 fn read_phys_chunk(data: &[u8]) -> Result<(PHYSChunk, &[u8]), PNGReaderError> {
     let length = BigEndian::read_u32(&data[0..4]) as usize;
-    println!("{}", length);
-    let chunk_type = from_utf8(&data[4..8]).unwrap();
-    println!("{}", chunk_type);
+    if length != 9 {
+        return Result::Err(PNGReaderError::InvalidChunk {
+            description: format!("pHYs chunk length must be 9 but got {}", length)
+        });
+    }
     let phys_chunk = PHYSChunk {
         pixels_per_unit_x: BigEndian::read_u32(&data[8..12]),
         pixels_per_unit_y: BigEndian::read_u32(&data[12..16]),
         unit_specifier: data[16],
     };
-    println!("{:?}", phys_chunk);
     Result::Ok((phys_chunk, &data[length + 12..]))
 }
 
+// splits null-terminated string from the start of data
+fn read_null_terminated(data: &[u8]) -> Result<(&[u8], &[u8]), PNGReaderError> {
+    match data.iter().position(|byte| *byte == 0) {
+        Some(position) => Result::Ok((&data[0..position], &data[(position + 1)..])),
+        None => Result::Err(PNGReaderError::InvalidChunk {
+            description: "null separator is missing".to_owned()
+        }),
+    }
+}
+
+pub fn latin1_to_string(data: &[u8]) -> String {
+    data.iter().map(|byte| *byte as char).collect()
+}
+
+fn utf8_to_string(data: &[u8]) -> Result<String, PNGReaderError> {
+    from_utf8(data).map(|text| text.to_owned()).map_err(|err| PNGReaderError::InvalidChunk {
+        description: format!("text is not valid UTF-8: {}", err)
+    })
+}
+
+fn split_compression_method(data: &[u8]) -> Result<(u8, &[u8]), PNGReaderError> {
+    match data.split_first() {
+        Some((compression_method, compressed)) => Result::Ok((*compression_method, compressed)),
+        None => Result::Err(PNGReaderError::InvalidChunk {
+            description: "compression method is missing".to_owned()
+        }),
+    }
+}
+
+fn decompress_text(compression_method: u8, compressed: &[u8]) -> Result<Vec<u8>, PNGReaderError> {
+    if compression_method != 0 {
+        return Result::Err(PNGReaderError::UnsupportedOption {
+            description: format!("compression method {} is not supported", compression_method)
+        });
+    }
+    inflate_decompress(compressed)
+}
+
 fn read_text_chunk(data: &[u8]) -> Result<(TEXTChunk, &[u8]), PNGReaderError> {
     let length = BigEndian::read_u32(&data[0..4]) as usize;
-    println!("{}", length);
-    let chunk_type = from_utf8(&data[4..8]).unwrap();
-    println!("{}", chunk_type);
-    let text = from_utf8(&data[8..(8 + length)]).unwrap();
-    println!("{}", text);
-    Result::Ok((TEXTChunk { text: text.to_owned() }, &data[length + 12..]))
+    let (keyword, text) = read_null_terminated(&data[8..(8 + length)])?;
+    let text_chunk = TEXTChunk {
+        keyword: latin1_to_string(keyword),
+        text: latin1_to_string(text),
+    };
+    Result::Ok((text_chunk, &data[length + 12..]))
 }
 
 fn read_idat_chunk(data: &[u8]) -> Result<(IDATChunk, &[u8]), PNGReaderError> {
     let length = BigEndian::read_u32(&data[0..4]) as usize;
-    println!("{}", length);
-    let chunk_type = from_utf8(&data[4..8]).unwrap();
-    println!("{}", chunk_type);
     let chunk_data = data[8..(8 + length)].to_vec();
     Result::Ok((IDATChunk { data: chunk_data }, &data[length + 12..]))
 }
 
 fn read_iend_chunk(data: &[u8]) -> Result<&[u8], PNGReaderError> {
     let length = BigEndian::read_u32(&data[0..4]) as usize;
-    println!("{}", length);
-    let chunk_type = from_utf8(&data[4..8]).unwrap();
-    println!("{}", chunk_type);
-    let len_rest = &data[length + 12..].len();
-    println!("rest: {}", len_rest);
     Result::Ok(&data[length + 12..])
 }
 
 fn read_iccp_chunk(data: &[u8]) -> Result<(ICCPChunk, &[u8]), PNGReaderError> {
     let length = BigEndian::read_u32(&data[0..4]) as usize;
-    println!("{}", length);
-    let chunk_type = from_utf8(&data[4..8]).unwrap();
-    println!("{}", chunk_type);
-    let chunk_data = data[8..(8 + length)].to_vec();
-    Result::Ok((ICCPChunk { data: chunk_data }, &data[length + 12..]))
+    let (name, rest) = read_null_terminated(&data[8..(8 + length)])?;
+    let (compression_method, compressed_profile) = split_compression_method(rest)?;
+    let iccp_chunk = ICCPChunk {
+        name: latin1_to_string(name),
+        profile: decompress_text(compression_method, compressed_profile)?,
+    };
+    Result::Ok((iccp_chunk, &data[length + 12..]))
 }
 
+fn read_exif_chunk(data: &[u8]) -> Result<(EXIFChunk, &[u8]), PNGReaderError> {
+    let length = BigEndian::read_u32(&data[0..4]) as usize;
+    let exif_chunk = EXIFChunk {
+        data: data[8..(8 + length)].to_vec(),
+    };
+    Result::Ok((exif_chunk, &data[length + 12..]))
+}
 
 fn read_time_chunk(data: &[u8]) -> Result<(TIMEChunk, &[u8]), PNGReaderError> {
     let length = BigEndian::read_u32(&data[0..4]) as usize;
-    println!("{}", length);
-    let chunk_type = from_utf8(&data[4..8]).unwrap();
-    println!("{}", chunk_type);
-    let chunk_data = data[8..(8 + length)].to_vec();
-    Result::Ok((TIMEChunk { data: chunk_data }, &data[length + 12..]))
+    if length != 7 {
+        return Result::Err(PNGReaderError::InvalidChunk {
+            description: format!("tIME chunk length must be 7 but got {}", length)
+        });
+    }
+    let time_chunk = TIMEChunk {
+        year: BigEndian::read_u16(&data[8..10]),
+        month: data[10],
+        day: data[11],
+        hour: data[12],
+        minute: data[13],
+        second: data[14],
+    };
+    Result::Ok((time_chunk, &data[length + 12..]))
 }
 
 fn read_ztxt_chunk(data: &[u8]) -> Result<(ZTXTChunk, &[u8]), PNGReaderError> {
     let length = BigEndian::read_u32(&data[0..4]) as usize;
-    println!("{}", length);
-    let chunk_type = from_utf8(&data[4..8]).unwrap();
-    println!("{}", chunk_type);
-    let chunk_data = data[8..(8 + length)].to_vec();
-    Result::Ok((ZTXTChunk { data: chunk_data }, &data[length + 12..]))
+    let (keyword, rest) = read_null_terminated(&data[8..(8 + length)])?;
+    let (compression_method, compressed_text) = split_compression_method(rest)?;
+    let ztxt_chunk = ZTXTChunk {
+        keyword: latin1_to_string(keyword),
+        text: latin1_to_string(&decompress_text(compression_method, compressed_text)?),
+    };
+    Result::Ok((ztxt_chunk, &data[length + 12..]))
 }
 
 fn read_itxt_chunk(data: &[u8]) -> Result<(ITXTChunk, &[u8]), PNGReaderError> {
     let length = BigEndian::read_u32(&data[0..4]) as usize;
-    println!("{}", length);
-    let chunk_type = from_utf8(&data[4..8]).unwrap();
-    println!("{}", chunk_type);
-    let chunk_data = data[8..(8 + length)].to_vec();
-    Result::Ok((ITXTChunk { data: chunk_data }, &data[length + 12..]))
+    let (keyword, rest) = read_null_terminated(&data[8..(8 + length)])?;
+    if rest.len() < 2 {
+        return Result::Err(PNGReaderError::InvalidChunk {
+            description: "iTXt compression flag and method are missing".to_owned()
+        });
+    }
+    let compressed = rest[0] == 1;
+    let compression_method = rest[1];
+    let (language_tag, rest) = read_null_terminated(&rest[2..])?;
+    let (translated_keyword, text) = read_null_terminated(rest)?;
+    let text = if compressed {
+        decompress_text(compression_method, text)?
+    } else {
+        text.to_vec()
+    };
+    let itxt_chunk = ITXTChunk {
+        keyword: latin1_to_string(keyword),
+        compressed,
+        language_tag: latin1_to_string(language_tag),
+        translated_keyword: utf8_to_string(translated_keyword)?,
+        text: utf8_to_string(&text)?,
+    };
+    Result::Ok((itxt_chunk, &data[length + 12..]))
 }
 
-pub fn read_chunks(mut data: &[u8]) -> Result<PNGImage, PNGReaderError> {
+// Checks that the chunk is complete and that its CRC matches. With lenient checksums, CRC mismatches are ignored.
+fn validate_chunk(data: &[u8], strict_checksums: bool) -> Result<(), PNGReaderError> {
+    if data.len() < 12 {
+        return Result::Err(PNGReaderError::InvalidChunk {
+            description: format!("chunk is truncated: {} bytes left but at least 12 are required", data.len())
+        });
+    }
+    let length = BigEndian::read_u32(&data[0..4]) as usize;
+    if data.len() - 12 < length {
+        return Result::Err(PNGReaderError::InvalidChunk {
+            description: format!("chunk {:?} is truncated: length is {} but only {} bytes left", &data[4..8], length, data.len() - 12)
+        });
+    }
+
+    let expected = BigEndian::read_u32(&data[(8 + length)..(12 + length)]);
+    let actual = crc32(&data[4..(8 + length)]);
+    if strict_checksums && expected != actual {
+        return Result::Err(PNGReaderError::ChecksumMismatch {
+            description: format!("CRC {:#010x} of chunk {:?} does not match computed {:#010x}", expected, &data[4..8], actual)
+        });
+    }
+    Result::Ok(())
+}
+
+pub fn read_chunks(mut data: &[u8], strict_checksums: bool) -> Result<PNGImage, PNGReaderError> {
     let mut image = PNGImage::new();
     loop {
+        validate_chunk(data, strict_checksums)?;
         match data[4..8] {
             [73, 69, 78, 68] => {
                 let chunks = read_iend_chunk(data)?;
@@ -199,12 +511,37 @@ pub fn read_chunks(mut data: &[u8]) -> Result<PNGImage, PNGReaderError> {
             [73, 68, 65, 84] => {
                 let (chunk, rest_data) = read_idat_chunk(data)?;
                 data = rest_data;
+                // fcTL before the first IDAT makes the default image the first frame of the animation
+                if image.frames.len() == 1 && !image.fdat_seen {
+                    image.frames[0].data.append_from_slice(&chunk.data);
+                }
                 image.idat.append_chunk(chunk);
             },
+            [97, 99, 84, 76] => {
+                let (chunk, rest_data) = read_actl_chunk(data)?;
+                data = rest_data;
+                image.actl = Option::from(chunk);
+            },
+            [102, 99, 84, 76] => {
+                let (chunk, rest_data) = read_fctl_chunk(data)?;
+                data = rest_data;
+                image.frames.push(APNGFrame { fctl: chunk, data: IDATChunk::new() });
+            },
+            [102, 100, 65, 84] => {
+                let (chunk, rest_data) = read_fdat_chunk(data)?;
+                data = rest_data;
+                image.fdat_seen = true;
+                match image.frames.last_mut() {
+                    Some(frame) => frame.data.append_chunk(chunk),
+                    None => return Result::Err(PNGReaderError::InvalidChunk {
+                        description: "fdAT chunk without a preceding fcTL chunk".to_owned()
+                    }),
+                }
+            },
             [116, 69, 88, 116] => {
                 let (chunk, rest_data) = read_text_chunk(data)?;
                 data = rest_data;
-                image.text = Option::from(chunk);
+                image.text.push(chunk);
             },
             [112, 72, 89, 115] => {
                 let (chunk, rest_data) = read_phys_chunk(data)?;
@@ -226,6 +563,11 @@ pub fn read_chunks(mut data: &[u8]) -> Result<PNGImage, PNGReaderError> {
                 data = rest_data;
                 image.iccp = Option::from(chunk);
             },
+            [101, 88, 73, 102] => {
+                let (chunk, rest_data) = read_exif_chunk(data)?;
+                data = rest_data;
+                image.exif = Option::from(chunk);
+            },
             [116, 73, 77, 69] => {
                 let (chunk, rest_data) = read_time_chunk(data)?;
                 data = rest_data;
@@ -234,12 +576,27 @@ pub fn read_chunks(mut data: &[u8]) -> Result<PNGImage, PNGReaderError> {
             [122, 84, 88, 116] => {
                 let (chunk, rest_data) = read_ztxt_chunk(data)?;
                 data = rest_data;
-                image.ztxt = Option::from(chunk);
+                image.ztxt.push(chunk);
             },
             [105, 84, 88, 116] => {
                 let (chunk, rest_data) = read_itxt_chunk(data)?;
                 data = rest_data;
-                image.itxt = Option::from(chunk);
+                image.itxt.push(chunk);
+            },
+            [80, 76, 84, 69] => {
+                let (chunk, rest_data) = read_plte_chunk(data)?;
+                data = rest_data;
+                image.plte = Option::from(chunk);
+            },
+            [116, 82, 78, 83] => {
+                let (chunk, rest_data) = read_trns_chunk(data)?;
+                data = rest_data;
+                image.trns = Option::from(chunk);
+            },
+            // ancillary chunks (lowercase first letter) which are not known can be safely skipped
+            [first, _, _, _] if first & 0x20 != 0 => {
+                let length = BigEndian::read_u32(&data[0..4]) as usize;
+                data = &data[length + 12..];
             },
             _ => {
                 return Result::Err(PNGReaderError::InvalidChunk {
@@ -248,4 +605,45 @@ pub fn read_chunks(mut data: &[u8]) -> Result<PNGImage, PNGReaderError> {
             }
         };
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::write_chunk;
+
+    fn chunks_with_iend(chunk_type: &[u8; 4], chunk_data: &[u8]) -> Vec<u8> {
+        let ihdr = [0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 0];
+        let mut data = Vec::new();
+        write_chunk(&mut data, b"IHDR", &ihdr);
+        write_chunk(&mut data, chunk_type, chunk_data);
+        write_chunk(&mut data, b"IEND", &[]);
+        data
+    }
+
+    #[test]
+    fn test_crc_mismatch() {
+        let mut data = chunks_with_iend(b"IDAT", &[1, 2, 3]);
+        let crc_position = 25 + 8 + 3;
+        data[crc_position] ^= 0xFF;
+
+        assert!(read_chunks(&data, true).is_err());
+        assert_eq!(read_chunks(&data, false).unwrap().idat.data, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_truncated_chunk() {
+        let data = chunks_with_iend(b"IDAT", &[1, 2, 3]);
+        assert!(read_chunks(&data[0..30], false).is_err());
+        assert!(read_chunks(&data[0..(data.len() - 1)], false).is_err());
+    }
+
+    #[test]
+    fn test_skip_unknown_ancillary_chunk() {
+        let data = chunks_with_iend(b"gAMA", &[0, 0, 177, 143]);
+        assert!(read_chunks(&data, true).is_ok());
+
+        let data = chunks_with_iend(b"ABCD", &[]);
+        assert!(read_chunks(&data, true).is_err());
+    }
+}
@@ -0,0 +1,745 @@
+use crate::checksum::adler32;
+use crate::inflate::{LEN_LOWER, DIS_LOWER, get_len_extra_bits_amount, get_dis_extra_bits_amount};
+use byteorder::{ByteOrder, BigEndian, LittleEndian};
+use std::cmp::min;
+
+// see:
+// https://tools.ietf.org/html/rfc1951
+// https://tools.ietf.org/html/rfc1950
+// https://en.wikipedia.org/wiki/Package-merge_algorithm
+
+const WINDOW_SIZE: usize = 32768;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+// matches of minimal length which are that far away usually take more bits than three literals
+const TOO_FAR: usize = 4096;
+
+const HASH_BITS: u32 = 15;
+const HASH_SIZE: usize = 1 << HASH_BITS;
+const NO_POSITION: usize = usize::MAX;
+
+const MAX_BLOCK_TOKENS: usize = 16384;
+const MAX_STORED_BLOCK_SIZE: usize = 65535;
+
+const END_OF_BLOCK: usize = 256;
+const LITERAL_LENGTH_CODES: usize = 286;
+const DISTANCE_CODES: usize = 30;
+const CODE_LENGTH_CODES: usize = 19;
+const MAX_CODE_LENGTH: usize = 15;
+const MAX_CODE_LENGTH_CODE_LENGTH: usize = 7;
+const CODE_LENGTH_ALPHABET_ORDER: [usize; CODE_LENGTH_CODES] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+pub const DEFAULT_COMPRESSION_LEVEL: u32 = 6;
+pub const MAX_COMPRESSION_LEVEL: u32 = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token {
+    Literal(u8),
+    Match { length: usize, distance: usize },
+}
+
+struct CompressionParameters {
+    max_chain: usize,
+    nice_length: usize,
+    lazy_matching: bool,
+}
+
+fn compression_parameters(level: u32) -> CompressionParameters {
+    let (max_chain, nice_length, lazy_matching) = match level {
+        1 => (4, 8, false),
+        2 => (8, 16, false),
+        3 => (32, 32, false),
+        4 => (16, 32, true),
+        5 => (32, 64, true),
+        6 => (128, 128, true),
+        7 => (256, 128, true),
+        8 => (1024, MAX_MATCH, true),
+        _ => (4096, MAX_MATCH, true),
+    };
+
+    CompressionParameters {
+        max_chain,
+        nice_length,
+        lazy_matching,
+    }
+}
+
+struct BitWriter {
+    data: Vec<u8>,
+    buffer: u64,
+    buffer_length: u32,
+}
+
+impl BitWriter {
+    fn new() -> Self {
+        BitWriter {
+            data: Vec::new(),
+            buffer: 0,
+            buffer_length: 0,
+        }
+    }
+
+    fn write_bits(&mut self, value: u32, amount: u32) {
+        self.buffer |= (value as u64) << self.buffer_length;
+        self.buffer_length += amount;
+        while self.buffer_length >= 8 {
+            self.data.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.buffer_length -= 8;
+        }
+    }
+
+    fn align_to_byte(&mut self) {
+        if self.buffer_length > 0 {
+            self.write_bits(0, 8 - self.buffer_length);
+        }
+    }
+
+    fn bit_position(&self) -> usize {
+        self.data.len() * 8 + self.buffer_length as usize
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.align_to_byte();
+        self.data
+    }
+}
+
+struct HuffmanEncoder {
+    // codes are stored with reversed bits, because deflate packs Huffman codes starting from the most significant bit
+    codes: Vec<u16>,
+    lengths: Vec<u8>,
+}
+
+impl HuffmanEncoder {
+    fn from_code_lengths(lengths: Vec<u8>) -> Self {
+        let codes = code_lengths_to_codes(&lengths).iter()
+            .zip(lengths.iter())
+            .map(|(code, length)| reverse_bits(*code, *length))
+            .collect();
+
+        HuffmanEncoder {
+            codes,
+            lengths,
+        }
+    }
+
+    fn write(&self, writer: &mut BitWriter, symbol: usize) {
+        writer.write_bits(self.codes[symbol] as u32, self.lengths[symbol] as u32);
+    }
+}
+
+fn reverse_bits(code: u16, length: u8) -> u16 {
+    let mut code = code;
+    let mut result = 0;
+    for _ in 0..length {
+        result = (result << 1) | (code & 1);
+        code >>= 1;
+    }
+    result
+}
+
+pub(crate) fn code_lengths_to_codes(lengths: &[u8]) -> Vec<u16> {
+    let mut length_count = [0u16; MAX_CODE_LENGTH + 1];
+    for length in lengths {
+        if *length > 0 {
+            length_count[*length as usize] += 1;
+        }
+    }
+
+    let mut next_code = [0u16; MAX_CODE_LENGTH + 1];
+    let mut code = 0;
+    for bits in 1..=MAX_CODE_LENGTH {
+        code = (code + length_count[bits - 1]) << 1;
+        next_code[bits] = code;
+    }
+
+    let mut codes = vec![0u16; lengths.len()];
+    for symbol in 0..lengths.len() {
+        let length = lengths[symbol] as usize;
+        if length > 0 {
+            codes[symbol] = next_code[length];
+            next_code[length] += 1;
+        }
+    }
+    codes
+}
+
+// Builds optimal prefix code lengths which do not exceed max_length using package-merge.
+// At least two symbols always get a code, so the resulting code is complete.
+pub(crate) fn build_code_lengths(frequencies: &[usize], max_length: usize) -> Vec<u8> {
+    let mut leaves: Vec<(usize, usize)> = frequencies.iter()
+        .enumerate()
+        .filter(|(_, frequency)| **frequency > 0)
+        .map(|(symbol, frequency)| (*frequency, symbol))
+        .collect();
+
+    let mut symbol = 0;
+    while leaves.len() < 2 && symbol < frequencies.len() {
+        if frequencies[symbol] == 0 {
+            leaves.push((0, symbol));
+        }
+        symbol += 1;
+    }
+    leaves.sort();
+
+    let mut lengths = vec![0u8; frequencies.len()];
+    if leaves.len() < 2 {
+        return lengths;
+    }
+
+    let leaf_items: Vec<(usize, Vec<usize>)> = leaves.iter()
+        .enumerate()
+        .map(|(index, (frequency, _))| (*frequency, vec![index]))
+        .collect();
+
+    let mut items = leaf_items.clone();
+    for _ in 1..max_length {
+        let packages: Vec<(usize, Vec<usize>)> = items.chunks_exact(2)
+            .map(|pair| {
+                let mut leaves = pair[0].1.clone();
+                leaves.extend_from_slice(&pair[1].1);
+                (pair[0].0 + pair[1].0, leaves)
+            })
+            .collect();
+
+        let mut merged = Vec::with_capacity(leaf_items.len() + packages.len());
+        let mut leaf_iter = leaf_items.iter().peekable();
+        let mut package_iter = packages.into_iter().peekable();
+        loop {
+            let take_leaf = match (leaf_iter.peek(), package_iter.peek()) {
+                (Some(leaf), Some(package)) => leaf.0 <= package.0,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => break,
+            };
+            if take_leaf {
+                merged.push(leaf_iter.next().unwrap().clone());
+            } else {
+                merged.push(package_iter.next().unwrap());
+            }
+        }
+        items = merged;
+    }
+
+    for item in items.iter().take(2 * leaves.len() - 2) {
+        for leaf in &item.1 {
+            lengths[leaves[*leaf].1] += 1;
+        }
+    }
+    lengths
+}
+
+fn fixed_literal_length_code_lengths() -> Vec<u8> {
+    let mut lengths = vec![8; 288];
+    for symbol in 144..=255 {
+        lengths[symbol] = 9;
+    }
+    for symbol in 256..=279 {
+        lengths[symbol] = 7;
+    }
+    lengths
+}
+
+fn fixed_distance_code_lengths() -> Vec<u8> {
+    vec![5; DISTANCE_CODES]
+}
+
+fn length_code(length: usize) -> usize {
+    match LEN_LOWER.binary_search(&length) {
+        Ok(index) => index,
+        Err(index) => index - 1,
+    }
+}
+
+fn distance_code(distance: usize) -> usize {
+    match DIS_LOWER.binary_search(&distance) {
+        Ok(index) => index,
+        Err(index) => index - 1,
+    }
+}
+
+struct MatchFinder<'a> {
+    data: &'a [u8],
+    head: Vec<usize>,
+    prev: Vec<usize>,
+}
+
+impl<'a> MatchFinder<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        MatchFinder {
+            data,
+            head: vec![NO_POSITION; HASH_SIZE],
+            prev: vec![NO_POSITION; WINDOW_SIZE],
+        }
+    }
+
+    fn hash(&self, position: usize) -> usize {
+        let value = (self.data[position] as u32) << 16 | (self.data[position + 1] as u32) << 8 | self.data[position + 2] as u32;
+        (value.wrapping_mul(0x1E35A7BD) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert(&mut self, position: usize) {
+        if position + MIN_MATCH > self.data.len() {
+            return;
+        }
+        let hash = self.hash(position);
+        self.prev[position % WINDOW_SIZE] = self.head[hash];
+        self.head[hash] = position;
+    }
+
+    fn longest_match(&self, position: usize, max_chain: usize, nice_length: usize) -> (usize, usize) {
+        if position + MIN_MATCH > self.data.len() {
+            return (0, 0);
+        }
+
+        let max_length = min(MAX_MATCH, self.data.len() - position);
+        let mut best_length = 0;
+        let mut best_distance = 0;
+        let mut candidate = self.head[self.hash(position)];
+        let mut chain = max_chain;
+
+        while candidate != NO_POSITION && chain > 0 {
+            let distance = position - candidate;
+            if distance > WINDOW_SIZE {
+                break;
+            }
+
+            if best_length == 0 || (best_length < max_length && self.data[candidate + best_length] == self.data[position + best_length]) {
+                let mut length = 0;
+                while length < max_length && self.data[candidate + length] == self.data[position + length] {
+                    length += 1;
+                }
+
+                if length > best_length {
+                    best_length = length;
+                    best_distance = distance;
+                    if length >= nice_length {
+                        break;
+                    }
+                }
+            }
+
+            let next = self.prev[candidate % WINDOW_SIZE];
+            if next == NO_POSITION || next >= candidate {
+                break;
+            }
+            candidate = next;
+            chain -= 1;
+        }
+
+        if best_length < MIN_MATCH || (best_length == MIN_MATCH && best_distance > TOO_FAR) {
+            (0, 0)
+        } else {
+            (best_length, best_distance)
+        }
+    }
+}
+
+fn find_tokens(data: &[u8], parameters: &CompressionParameters) -> Vec<Token> {
+    let mut finder = MatchFinder::new(data);
+    let mut tokens = Vec::new();
+    let mut position = 0;
+
+    while position < data.len() {
+        let (length, distance) = finder.longest_match(position, parameters.max_chain, parameters.nice_length);
+        finder.insert(position);
+
+        if length == 0 {
+            tokens.push(Token::Literal(data[position]));
+            position += 1;
+            continue;
+        }
+
+        if parameters.lazy_matching && length < parameters.nice_length {
+            let (next_length, _) = finder.longest_match(position + 1, parameters.max_chain, parameters.nice_length);
+            if next_length > length {
+                // a longer match starts at the next byte, so this one is better emitted as a literal
+                tokens.push(Token::Literal(data[position]));
+                position += 1;
+                continue;
+            }
+        }
+
+        tokens.push(Token::Match { length, distance });
+        for inserted_position in (position + 1)..(position + length) {
+            finder.insert(inserted_position);
+        }
+        position += length;
+    }
+
+    tokens
+}
+
+fn token_length(token: &Token) -> usize {
+    match token {
+        Token::Literal(_) => 1,
+        Token::Match { length, distance: _ } => *length,
+    }
+}
+
+fn encoded_tokens_size(tokens: &[Token], literal_lengths: &[u8], distance_lengths: &[u8]) -> usize {
+    let mut size = literal_lengths[END_OF_BLOCK] as usize;
+    for token in tokens {
+        size += match token {
+            Token::Literal(value) => literal_lengths[*value as usize] as usize,
+            Token::Match { length, distance } => {
+                let length_index = length_code(*length);
+                let distance_index = distance_code(*distance);
+                literal_lengths[257 + length_index] as usize
+                    + get_len_extra_bits_amount(length_index)
+                    + distance_lengths[distance_index] as usize
+                    + get_dis_extra_bits_amount(distance_index)
+            }
+        };
+    }
+    size
+}
+
+fn write_tokens(writer: &mut BitWriter, tokens: &[Token], literal_encoder: &HuffmanEncoder, distance_encoder: &HuffmanEncoder) {
+    for token in tokens {
+        match token {
+            Token::Literal(value) => literal_encoder.write(writer, *value as usize),
+            Token::Match { length, distance } => {
+                let length_index = length_code(*length);
+                literal_encoder.write(writer, 257 + length_index);
+                writer.write_bits((*length - LEN_LOWER[length_index]) as u32, get_len_extra_bits_amount(length_index) as u32);
+
+                let distance_index = distance_code(*distance);
+                distance_encoder.write(writer, distance_index);
+                writer.write_bits((*distance - DIS_LOWER[distance_index]) as u32, get_dis_extra_bits_amount(distance_index) as u32);
+            }
+        }
+    }
+    literal_encoder.write(writer, END_OF_BLOCK);
+}
+
+// run-length encodes code lengths with symbols 16, 17 and 18. Returns pairs of (symbol, extra bits value).
+fn encode_code_lengths(lengths: &[u8]) -> Vec<(usize, u32)> {
+    let mut result = Vec::new();
+    let mut index = 0;
+
+    while index < lengths.len() {
+        let length = lengths[index];
+        let mut run = 1;
+        while index + run < lengths.len() && lengths[index + run] == length {
+            run += 1;
+        }
+        index += run;
+
+        if length == 0 {
+            while run >= 11 {
+                let repeat = min(run, 138);
+                result.push((18, (repeat - 11) as u32));
+                run -= repeat;
+            }
+            if run >= 3 {
+                result.push((17, (run - 3) as u32));
+                run = 0;
+            }
+        } else {
+            result.push((length as usize, 0));
+            run -= 1;
+            while run >= 3 {
+                let repeat = min(run, 6);
+                result.push((16, (repeat - 3) as u32));
+                run -= repeat;
+            }
+        }
+
+        for _ in 0..run {
+            result.push((length as usize, 0));
+        }
+    }
+
+    result
+}
+
+fn code_length_extra_bits_amount(symbol: usize) -> u32 {
+    match symbol {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _ => 0,
+    }
+}
+
+struct DynamicHeader {
+    literal_codes_amount: usize,
+    distance_codes_amount: usize,
+    code_length_codes_amount: usize,
+    code_length_lengths: Vec<u8>,
+    encoded_lengths: Vec<(usize, u32)>,
+}
+
+impl DynamicHeader {
+    fn new(literal_lengths: &[u8], distance_lengths: &[u8]) -> Self {
+        let literal_codes_amount = literal_lengths.iter().rposition(|v| *v > 0).map(|v| v + 1).unwrap_or(0).max(257);
+        let distance_codes_amount = distance_lengths.iter().rposition(|v| *v > 0).map(|v| v + 1).unwrap_or(0).max(1);
+
+        let mut lengths = literal_lengths[0..literal_codes_amount].to_vec();
+        lengths.extend_from_slice(&distance_lengths[0..distance_codes_amount]);
+        let encoded_lengths = encode_code_lengths(&lengths);
+
+        let mut frequencies = vec![0; CODE_LENGTH_CODES];
+        for (symbol, _) in &encoded_lengths {
+            frequencies[*symbol] += 1;
+        }
+        let code_length_lengths = build_code_lengths(&frequencies, MAX_CODE_LENGTH_CODE_LENGTH);
+        let code_length_codes_amount = CODE_LENGTH_ALPHABET_ORDER.iter()
+            .rposition(|symbol| code_length_lengths[*symbol] > 0)
+            .map(|v| v + 1)
+            .unwrap_or(0)
+            .max(4);
+
+        DynamicHeader {
+            literal_codes_amount,
+            distance_codes_amount,
+            code_length_codes_amount,
+            code_length_lengths,
+            encoded_lengths,
+        }
+    }
+
+    fn size(&self) -> usize {
+        let mut size = 5 + 5 + 4 + 3 * self.code_length_codes_amount;
+        for (symbol, _) in &self.encoded_lengths {
+            size += self.code_length_lengths[*symbol] as usize + code_length_extra_bits_amount(*symbol) as usize;
+        }
+        size
+    }
+
+    fn write(&self, writer: &mut BitWriter) {
+        writer.write_bits((self.literal_codes_amount - 257) as u32, 5);
+        writer.write_bits((self.distance_codes_amount - 1) as u32, 5);
+        writer.write_bits((self.code_length_codes_amount - 4) as u32, 4);
+        for symbol in CODE_LENGTH_ALPHABET_ORDER.iter().take(self.code_length_codes_amount) {
+            writer.write_bits(self.code_length_lengths[*symbol] as u32, 3);
+        }
+
+        let encoder = HuffmanEncoder::from_code_lengths(self.code_length_lengths.clone());
+        for (symbol, extra_bits) in &self.encoded_lengths {
+            encoder.write(writer, *symbol);
+            writer.write_bits(*extra_bits, code_length_extra_bits_amount(*symbol));
+        }
+    }
+}
+
+fn write_stored_blocks(writer: &mut BitWriter, data: &[u8], is_final: bool) {
+    let mut chunks: Vec<&[u8]> = data.chunks(MAX_STORED_BLOCK_SIZE).collect();
+    if chunks.is_empty() {
+        chunks.push(&[]);
+    }
+
+    let total_chunks = chunks.len();
+    for (index, chunk) in chunks.into_iter().enumerate() {
+        let is_final_chunk = is_final && index == total_chunks - 1;
+        writer.write_bits(if is_final_chunk { 1 } else { 0 }, 1);
+        writer.write_bits(0, 2);
+        writer.align_to_byte();
+
+        let mut header = [0u8; 4];
+        LittleEndian::write_u16(&mut header[0..2], chunk.len() as u16);
+        LittleEndian::write_u16(&mut header[2..4], !(chunk.len() as u16));
+        for byte in header.iter().chain(chunk.iter()) {
+            writer.write_bits(*byte as u32, 8);
+        }
+    }
+}
+
+fn write_block(writer: &mut BitWriter, tokens: &[Token], data: &[u8], is_final: bool) {
+    let mut literal_frequencies = vec![0; LITERAL_LENGTH_CODES];
+    let mut distance_frequencies = vec![0; DISTANCE_CODES];
+    for token in tokens {
+        match token {
+            Token::Literal(value) => literal_frequencies[*value as usize] += 1,
+            Token::Match { length, distance } => {
+                literal_frequencies[257 + length_code(*length)] += 1;
+                distance_frequencies[distance_code(*distance)] += 1;
+            }
+        }
+    }
+    literal_frequencies[END_OF_BLOCK] += 1;
+
+    let literal_lengths = build_code_lengths(&literal_frequencies, MAX_CODE_LENGTH);
+    let distance_lengths = build_code_lengths(&distance_frequencies, MAX_CODE_LENGTH);
+    let dynamic_header = DynamicHeader::new(&literal_lengths, &distance_lengths);
+    let dynamic_size = 3 + dynamic_header.size() + encoded_tokens_size(tokens, &literal_lengths, &distance_lengths);
+
+    let fixed_literal_lengths = fixed_literal_length_code_lengths();
+    let fixed_distance_lengths = fixed_distance_code_lengths();
+    let fixed_size = 3 + encoded_tokens_size(tokens, &fixed_literal_lengths, &fixed_distance_lengths);
+
+    let stored_blocks = (data.len() + MAX_STORED_BLOCK_SIZE - 1) / MAX_STORED_BLOCK_SIZE;
+    let stored_size = 3 + (8 - (writer.bit_position() + 3) % 8) % 8 + stored_blocks.max(1) * 32 + (stored_blocks.max(1) - 1) * 8 + data.len() * 8;
+
+    if stored_size <= fixed_size && stored_size <= dynamic_size {
+        write_stored_blocks(writer, data, is_final);
+        return;
+    }
+
+    writer.write_bits(if is_final { 1 } else { 0 }, 1);
+    if fixed_size <= dynamic_size {
+        writer.write_bits(1, 2);
+        write_tokens(
+            writer,
+            tokens,
+            &HuffmanEncoder::from_code_lengths(fixed_literal_lengths),
+            &HuffmanEncoder::from_code_lengths(fixed_distance_lengths)
+        );
+    } else {
+        writer.write_bits(2, 2);
+        dynamic_header.write(writer);
+        write_tokens(
+            writer,
+            tokens,
+            &HuffmanEncoder::from_code_lengths(literal_lengths),
+            &HuffmanEncoder::from_code_lengths(distance_lengths)
+        );
+    }
+}
+
+fn compress(data: &[u8], level: u32) -> Vec<u8> {
+    let mut writer = BitWriter::new();
+
+    if level == 0 || data.is_empty() {
+        write_stored_blocks(&mut writer, data, true);
+        return writer.finish();
+    }
+
+    let tokens = find_tokens(data, &compression_parameters(level));
+    let total_blocks = (tokens.len() + MAX_BLOCK_TOKENS - 1) / MAX_BLOCK_TOKENS;
+    let mut data_offset = 0;
+    for (index, block_tokens) in tokens.chunks(MAX_BLOCK_TOKENS).enumerate() {
+        let block_length: usize = block_tokens.iter().map(token_length).sum();
+        write_block(
+            &mut writer,
+            block_tokens,
+            &data[data_offset..(data_offset + block_length)],
+            index == total_blocks - 1
+        );
+        data_offset += block_length;
+    }
+
+    writer.finish()
+}
+
+pub fn deflate_compress(data: &[u8], level: u32) -> Vec<u8> {
+    let level = min(level, MAX_COMPRESSION_LEVEL);
+
+    // compression method 8 (deflate) with 32K window
+    let compression_method_flag: u8 = 0x78;
+    let flevel_flag: u8 = match level {
+        0 | 1 => 0,
+        2..=5 => 1,
+        6 => 2,
+        _ => 3,
+    };
+    let additional_flags = flevel_flag << 6;
+    let fcheck = (31 - ((compression_method_flag as u16) << 8 | additional_flags as u16) % 31) % 31;
+    let additional_flags = additional_flags | fcheck as u8;
+
+    let mut result = vec![compression_method_flag, additional_flags];
+    result.append(&mut compress(data, level));
+
+    let mut checksum = [0u8; 4];
+    BigEndian::write_u32(&mut checksum, adler32(data));
+    result.extend_from_slice(&checksum);
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inflate::inflate_decompress;
+
+    fn test_data() -> Vec<u8> {
+        let mut data = Vec::new();
+        for i in 0..3000 {
+            data.push((i % 7) as u8);
+            data.push((i * i % 251) as u8);
+            if i % 5 == 0 {
+                data.extend_from_slice(b"turbocow");
+            }
+        }
+        data
+    }
+
+    #[test]
+    fn test_code_lengths_to_codes() {
+        // example from RFC 1951, section 3.2.2
+        let codes = code_lengths_to_codes(&[3, 3, 3, 3, 3, 2, 4, 4]);
+        assert_eq!(codes, vec![0b010, 0b011, 0b100, 0b101, 0b110, 0b00, 0b1110, 0b1111]);
+    }
+
+    #[test]
+    fn test_build_code_lengths() {
+        let lengths = build_code_lengths(&[10, 1, 1, 1, 1], 15);
+        assert_eq!(lengths, vec![1, 3, 3, 3, 3]);
+    }
+
+    #[test]
+    fn test_build_code_lengths_is_limited() {
+        let mut frequencies = vec![1usize, 1];
+        while frequencies.len() < 30 {
+            let next = frequencies[frequencies.len() - 1] + frequencies[frequencies.len() - 2];
+            frequencies.push(next);
+        }
+
+        let lengths = build_code_lengths(&frequencies, MAX_CODE_LENGTH);
+        assert_eq!(*lengths.iter().max().unwrap() as usize, MAX_CODE_LENGTH);
+
+        let kraft_sum: f64 = lengths.iter().map(|v| 0.5f64.powi(*v as i32)).sum();
+        assert_eq!(kraft_sum, 1.0);
+    }
+
+    #[test]
+    fn test_build_code_lengths_single_symbol() {
+        assert_eq!(build_code_lengths(&[0, 0, 5, 0], 15), vec![1, 0, 1, 0]);
+        assert_eq!(build_code_lengths(&[0, 0, 0], 15), vec![1, 1, 0]);
+    }
+
+    #[test]
+    fn test_encode_code_lengths() {
+        let encoded = encode_code_lengths(&[8, 8, 8, 8, 8, 8, 8, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 5, 0, 0]);
+        assert_eq!(encoded, vec![(8, 0), (16, 3), (8, 0), (18, 3), (5, 0), (0, 0), (0, 0)]);
+    }
+
+    #[test]
+    fn test_zlib_header() {
+        for level in 0..=MAX_COMPRESSION_LEVEL {
+            let compressed = deflate_compress(b"turbocow", level);
+            assert_eq!(compressed[0] & 0b1111, 8);
+            assert_eq!(((compressed[0] as u16) << 8 | compressed[1] as u16) % 31, 0);
+        }
+    }
+
+    #[test]
+    fn test_stored() {
+        assert_eq!(
+            deflate_compress(b"abc", 0),
+            vec![0x78, 0x01, 0x01, 0x03, 0x00, 0xFC, 0xFF, 0x61, 0x62, 0x63, 0x02, 0x4D, 0x01, 0x27]
+        );
+    }
+
+    #[test]
+    fn test_compress_decompress() {
+        let data = test_data();
+        for level in 1..=MAX_COMPRESSION_LEVEL {
+            let compressed = deflate_compress(&data, level);
+            assert!(compressed.len() < data.len() / 2);
+            assert_eq!(inflate_decompress(&compressed).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_compress_decompress_long_distances() {
+        let mut data: Vec<u8> = (0..20000).map(|i: u32| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+        let copy = data[0..5000].to_vec();
+        data.extend_from_slice(&copy);
+
+        let compressed = deflate_compress(&data, DEFAULT_COMPRESSION_LEVEL);
+        assert_eq!(inflate_decompress(&compressed).unwrap(), data);
+    }
+}
@@ -1,6 +1,46 @@
 use crate::reader::{PNGReaderError};
 use crate::chunk::IHDRChunk;
-use std::slice::Iter;
+
+// see:
+// https://www.w3.org/TR/PNG/#9Filters
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FilterType {
+    None,
+    Sub,
+    Up,
+    Average,
+    Paeth,
+}
+
+impl FilterType {
+    pub fn from_number(number: u8) -> Result<FilterType, PNGReaderError> {
+        match number {
+            0 => Result::Ok(FilterType::None),
+            1 => Result::Ok(FilterType::Sub),
+            2 => Result::Ok(FilterType::Up),
+            3 => Result::Ok(FilterType::Average),
+            4 => Result::Ok(FilterType::Paeth),
+            _ => Result::Err(PNGReaderError::BadImageData {
+                description: format!("Unsupported filter type {}", number)
+            }),
+        }
+    }
+
+    pub fn to_number(&self) -> u8 {
+        match &self {
+            FilterType::None => 0,
+            FilterType::Sub => 1,
+            FilterType::Up => 2,
+            FilterType::Average => 3,
+            FilterType::Paeth => 4,
+        }
+    }
+
+    pub fn all() -> [FilterType; 5] {
+        [FilterType::None, FilterType::Sub, FilterType::Up, FilterType::Average, FilterType::Paeth]
+    }
+}
 
 fn predict_paeth(a: u8, b: u8, c: u8) -> u8 {
     let a = a as i16;
@@ -19,70 +59,69 @@ fn predict_paeth(a: u8, b: u8, c: u8) -> u8 {
     }
 }
 
-fn get_left(width: usize, samples_amount: usize, data: &Vec<u8>) -> u8 {
-    if data.len() % (width * samples_amount) == 0 {
-        return 0;
+fn predict(filter_type: FilterType, left: u8, upper: u8, upper_left: u8) -> u8 {
+    match filter_type {
+        FilterType::None => 0,
+        FilterType::Sub => left,
+        FilterType::Up => upper,
+        FilterType::Average => ((left as u16 + upper as u16) / 2) as u8,
+        FilterType::Paeth => predict_paeth(left, upper, upper_left),
     }
-    data[data.len() - samples_amount]
 }
 
-fn get_upper(width: usize, samples_amount: usize, data: &Vec<u8>) -> u8 {
-    if data.len() < width * samples_amount {
-        println!("return 0. data.len: {}, width * sampes_amount: {}", data.len(), width * samples_amount);
-        return 0;
+fn unfilter_scanline(filter_type: FilterType, bytes_per_pixel: usize, scanline: &mut [u8], previous: &[u8]) {
+    for i in 0..scanline.len() {
+        let (left, upper_left) = if i >= bytes_per_pixel {
+            (scanline[i - bytes_per_pixel], previous[i - bytes_per_pixel])
+        } else {
+            (0, 0)
+        };
+        scanline[i] = scanline[i].wrapping_add(predict(filter_type, left, previous[i], upper_left));
     }
-    data[data.len() - samples_amount * width]
 }
 
-fn get_upper_left(pos: usize, width: usize, samples_amount: usize, data: &Vec<u8>) -> u8 {
-    if pos == 0 || data.len() < width * samples_amount {
-        return 0;
+fn filter_scanline(filter_type: FilterType, bytes_per_pixel: usize, scanline: &[u8], previous: &[u8], output: &mut Vec<u8>) {
+    output.push(filter_type.to_number());
+    for i in 0..scanline.len() {
+        let (left, upper_left) = if i >= bytes_per_pixel {
+            (scanline[i - bytes_per_pixel], previous[i - bytes_per_pixel])
+        } else {
+            (0, 0)
+        };
+        output.push(scanline[i].wrapping_sub(predict(filter_type, left, previous[i], upper_left)));
     }
-    data[data.len() - samples_amount * (width + 1)]
 }
 
-fn unfilter_none(width: usize, samples_amount: usize, iter: &mut Iter<u8>) -> Vec<u8> {
-    let mut res = Vec::new();
-    for _ in 0..(width * samples_amount) {
-        res.push(*iter.next().unwrap());
-    }
-    res
+// heuristic from the PNG specification: the filter producing the smallest sum of absolute signed differences usually compresses best
+fn filtered_scanline_cost(filtered: &[u8]) -> usize {
+    filtered[1..].iter().map(|v| (*v as i8).unsigned_abs() as usize).sum()
 }
 
-fn unfilter_sub(width: usize, samples_amount: usize, iter: &mut Iter<u8>, res: &mut Vec<u8>) {
-    for _ in 0..samples_amount {
-        res.push(*iter.next().unwrap());
-    }
-    for _ in 0..((width - 1) * samples_amount) {
-        res.push((res[res.len() - samples_amount] as u16 + *iter.next().unwrap() as u16) as u8);
+pub fn unfilter_scanlines(data: &[u8], scanline_length: usize, height: usize, bytes_per_pixel: usize) -> Result<Vec<u8>, PNGReaderError> {
+    if data.len() < (scanline_length + 1) * height {
+        return Result::Err(PNGReaderError::BadImageData {
+            description: format!("Bad samples amount. Expected: {} but got {}", (scanline_length + 1) * height, data.len())
+        });
     }
-}
 
-fn unfilter_up(width: usize, samples_amount: usize, iter: &mut Iter<u8>, res: &mut Vec<u8>) {
-    for _ in 0..width {
-        for _ in 0..samples_amount {
-            res.push((*iter.next().unwrap() as u16 + get_upper(width, samples_amount, &res) as u16) as u8);
-        }
-    }
-}
+    let zero_scanline = vec![0u8; scanline_length];
+    let mut result = vec![0u8; scanline_length * height];
+    for row in 0..height {
+        let offset = row * (scanline_length + 1);
+        let filter_type = FilterType::from_number(data[offset])?;
 
-fn unfilter_average(width: usize, samples_amount: usize, iter: &mut Iter<u8>, res: &mut Vec<u8>) {
-    for _ in 0..(width * samples_amount) {
-        res.push((*iter.next().unwrap() as u16 + ((get_left(width, samples_amount, res) + get_upper(width, samples_amount, res)) as u16 / 2)) as u8);
-    }
-}
+        let (unfiltered, rest) = result.split_at_mut(row * scanline_length);
+        let scanline = &mut rest[0..scanline_length];
+        scanline.copy_from_slice(&data[(offset + 1)..(offset + 1 + scanline_length)]);
+        let previous = if row == 0 {
+            &zero_scanline[..]
+        } else {
+            &unfiltered[((row - 1) * scanline_length)..]
+        };
 
-fn unfilter_peath(width: usize, samples_amount: usize, iter: &mut Iter<u8>) -> Vec<u8> {
-    let mut res = Vec::new();
-    for i in 0..(width * samples_amount) {
-        let val = *iter.next().unwrap();
-        res.push((val as u16 + predict_paeth(
-            get_left(width, samples_amount, &res),
-            get_upper(width as usize, samples_amount, &res),
-            get_upper_left(i, width as usize, samples_amount, &res)
-        ) as u16) as u8);
-    }
-    res
+        unfilter_scanline(filter_type, bytes_per_pixel, scanline, previous);
+    }
+    Result::Ok(result)
 }
 
 pub fn unfilter(ihdr: &IHDRChunk, data: Vec<u8>) -> Result<Vec<u8>, PNGReaderError> {
@@ -92,50 +131,92 @@ pub fn unfilter(ihdr: &IHDRChunk, data: Vec<u8>) -> Result<Vec<u8>, PNGReaderErr
             description: format!("Filter method {} is not supported. Only 0 filter method allowed", filter_method)
         });
     }
-    let width = ihdr.width as usize;
+    let scanline_length = ihdr.scanline_length(ihdr.width as usize);
     let heigth = ihdr.height as usize;
-    let samples_amount = ihdr.colour_type.get_samples_amount();
-    if data.len() - heigth != width * heigth * samples_amount {
+    if data.len() != (scanline_length + 1) * heigth {
         return Result::Err(PNGReaderError::BadImageData {
-            description: format!("Bad samples amount. Expected: {} but got {}", width * heigth * samples_amount, data.len() - heigth)
+            description: format!("Bad samples amount. Expected: {} but got {}", (scanline_length + 1) * heigth, data.len())
         });
     }
-    println!("unfilter data len: {}. width: {}", data.len(), width);
-    let mut k = 0;
-    for _ in 0..heigth {
-        print!("{} ", data[k]);
-        k += 1;
-        for _ in 0..(width * 3) {
-            print!("{} ", data[k]);
-            k += 1;
+    unfilter_scanlines(&data, scanline_length, heigth, ihdr.bytes_per_pixel())
+}
+
+// Prepends every scanline with a filter type byte. With adaptive filtering the best filter is chosen for each row separately,
+// otherwise scanlines are left as is.
+pub fn filter(data: &[u8], scanline_length: usize, bytes_per_pixel: usize, adaptive: bool) -> Vec<u8> {
+    if scanline_length == 0 {
+        return Vec::new();
+    }
+
+    let height = data.len() / scanline_length;
+    let zero_scanline = vec![0u8; scanline_length];
+    let mut result = Vec::with_capacity((scanline_length + 1) * height);
+    let mut candidate = Vec::with_capacity(scanline_length + 1);
+    let mut best_candidate = Vec::with_capacity(scanline_length + 1);
+
+    for row in 0..height {
+        let scanline = &data[(row * scanline_length)..((row + 1) * scanline_length)];
+        let previous = if row == 0 {
+            &zero_scanline[..]
+        } else {
+            &data[((row - 1) * scanline_length)..(row * scanline_length)]
+        };
+
+        if !adaptive {
+            filter_scanline(FilterType::None, bytes_per_pixel, scanline, previous, &mut result);
+            continue;
         }
-        println!("");
-    }
-    let mut iter = data.iter();
-    let mut res = Vec::new();
-    let mut scanline_count = 1;
-    loop {
-        println!("scanline_count: {}", scanline_count);
-        match iter.next() {
-            Some(filter_type) => {
-                println!("filter_type: {}", filter_type);
-                if *filter_type == 0 {
-                    res.append(&mut unfilter_none(width, samples_amount, &mut iter));
-                } else if *filter_type == 1 {
-                    unfilter_sub(width, samples_amount, &mut iter, &mut res);
-                } else if *filter_type == 2 {
-                    unfilter_up(width, samples_amount, &mut iter, &mut res);
-                } else if *filter_type == 3 {
-                    unfilter_average(width, samples_amount, &mut iter, &mut res);
-                } else if *filter_type == 4 {
-                    res.append(&mut unfilter_peath(width, samples_amount, &mut iter));
-                } else {
-                    panic!("Unsupported filter type {}", filter_type);
-                }
-            },
-            None => break,
+
+        let mut best_cost = usize::MAX;
+        for filter_type in FilterType::all().iter() {
+            candidate.clear();
+            filter_scanline(*filter_type, bytes_per_pixel, scanline, previous, &mut candidate);
+            let cost = filtered_scanline_cost(&candidate);
+            if cost < best_cost {
+                best_cost = cost;
+                std::mem::swap(&mut candidate, &mut best_candidate);
+            }
         }
-        scanline_count += 1;
+        result.extend_from_slice(&best_candidate);
     }
-    Result::Ok(res)
-}
\ No newline at end of file
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_predict_paeth() {
+        assert_eq!(predict_paeth(10, 20, 15), 15);
+        assert_eq!(predict_paeth(10, 20, 10), 20);
+        assert_eq!(predict_paeth(200, 100, 250), 100);
+    }
+
+    #[test]
+    fn test_filter_unfilter() {
+        let data: Vec<u8> = (0..60).map(|i: u32| (i * 37 % 256) as u8).collect();
+        for filter_type in FilterType::all().iter() {
+            let mut filtered = Vec::new();
+            for row in 0..5 {
+                let previous = if row == 0 { vec![0; 12] } else { data[((row - 1) * 12)..(row * 12)].to_vec() };
+                filter_scanline(*filter_type, 3, &data[(row * 12)..((row + 1) * 12)], &previous, &mut filtered);
+            }
+            assert_eq!(unfilter_scanlines(&filtered, 12, 5, 3).unwrap(), data);
+        }
+    }
+
+    #[test]
+    fn test_adaptive_filter() {
+        let data: Vec<u8> = (0..400).map(|i: u32| ((i % 20) * (i % 20) * 7 % 200 + i / 20) as u8).collect();
+        let filtered = filter(&data, 20, 1, true);
+        assert_eq!(filtered.len(), 21 * 20);
+        assert_eq!(filtered[21], FilterType::Up.to_number());
+        assert_eq!(unfilter_scanlines(&filtered, 20, 20, 1).unwrap(), data);
+    }
+
+    #[test]
+    fn test_unknown_filter_type() {
+        assert!(unfilter_scanlines(&[5, 1, 2, 3], 3, 1, 1).is_err());
+    }
+}
@@ -1,72 +1,203 @@
 use crate::reader::PNGReaderError;
-use bit_vec::BitVec;
+use crate::checksum::adler32;
 use byteorder::{ByteOrder, BigEndian};
-use std::iter::FromIterator;
-use std::collections::{HashMap, BTreeSet};
 
-struct BitStream {
+// see:
+// https://tools.ietf.org/html/rfc1950
+// https://tools.ietf.org/html/rfc1951
+
+// the bit buffer is refilled byte by byte while there is room for one more byte
+const BIT_BUFFER_REFILL_LIMIT: u32 = 56;
+
+// codes up to this length are decoded with a single table lookup, longer ones fall back to canonical decoding
+const FAST_LOOKUP_BITS: u32 = 9;
+const MAX_CODE_LENGTH: usize = 15;
+
+const WINDOW_SIZE: usize = 32768;
+const END_OF_BLOCK: u16 = 256;
+const MAX_LITERAL_LENGTH_CODES: usize = 286;
+const MAX_DISTANCE_CODES: usize = 30;
+const CODE_LENGTH_ALPHABET_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+struct BitReader<'a> {
+    data: &'a [u8],
     position: usize,
-    bits: BitVec,
+    buffer: u64,
+    bits: u32,
 }
 
-impl BitStream {
-    fn new(data: &[u8]) -> Self {
-        let position = if data.len() > 0 { 7 } else { 0 };
-        BitStream {
-            position,
-            bits: BitVec::from_bytes(data),
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            position: 0,
+            buffer: 0,
+            bits: 0,
         }
     }
 
-    pub fn get_next_bit(&mut self) -> bool {
-        // println!("pos: {}", self.position);
-        let mut next_position = 0;
-        if self.position % 8 == 0 {
-            next_position = self.position + 15;
-        } else {
-            next_position = self.position - 1;
+    fn refill(&mut self) {
+        while self.bits <= BIT_BUFFER_REFILL_LIMIT && self.position < self.data.len() {
+            self.buffer |= (self.data[self.position] as u64) << self.bits;
+            self.position += 1;
+            self.bits += 8;
         }
-        let bit = self.bits[self.position];
-        self.position = next_position;
-        bit
     }
 
-    pub fn get_bits(&mut self, amount: usize) -> BitVec {
-        let mut result = Vec::new();
-        for _ in 0..amount {
-            result.insert(0, self.get_next_bit());
-            // result.push(self.get_next_bit());
+    // bits past the end of the stream are read as zeros, consume checks that they were never actually used
+    fn peek(&self, amount: u32) -> u32 {
+        (self.buffer & ((1u64 << amount) - 1)) as u32
+    }
+
+    fn consume(&mut self, amount: u32) -> Result<(), PNGReaderError> {
+        if amount > self.bits {
+            return Result::Err(PNGReaderError::InvalidCompressedData {
+                description: "unexpected end of deflate stream".to_owned()
+            });
         }
-        BitVec::from_iter(&mut result.into_iter())
+        self.buffer >>= amount;
+        self.bits -= amount;
+        Result::Ok(())
+    }
+
+    fn read_bits(&mut self, amount: u32) -> Result<u32, PNGReaderError> {
+        if amount == 0 {
+            return Result::Ok(0);
+        }
+        self.refill();
+        let value = self.peek(amount);
+        self.consume(amount)?;
+        Result::Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        let padding = self.bits % 8;
+        self.buffer >>= padding;
+        self.bits -= padding;
+    }
+
+    // must only be called when aligned to a byte boundary
+    fn read_bytes(&mut self, amount: usize, output: &mut Vec<u8>) -> Result<(), PNGReaderError> {
+        let mut remaining = amount;
+        while remaining > 0 && self.bits >= 8 {
+            output.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+            remaining -= 1;
+        }
+        if self.position + remaining > self.data.len() {
+            return Result::Err(PNGReaderError::InvalidCompressedData {
+                description: format!("stored block is truncated: {} bytes are missing", self.position + remaining - self.data.len())
+            });
+        }
+        output.extend_from_slice(&self.data[self.position..(self.position + remaining)]);
+        self.position += remaining;
+        Result::Ok(())
     }
-}
 
-fn print_code(vec: &Vec<bool>) {
-    for elem in vec.iter() {
-        print!("{}", if *elem { 1 } else { 0 });
+    // amount of bytes of the input which were actually used, including the partially read last byte
+    fn bytes_consumed(&self) -> usize {
+        self.position - (self.bits / 8) as usize
     }
 }
 
-fn number_to_bits(number: u32, length: usize) -> Vec<bool> {
-    let mut buff = [0, 0, 0, 0];
-    // NativeEndian::write_u32(&mut buff, number);
-    BigEndian::write_u32(&mut buff, number);
-    let vec = BitVec::from_bytes(&buff);
-    // println!("{:?}", &vec);
-    let mut result = Vec::new();
-    for i in (32 - length)..32 {
-        result.push(vec.get(i).unwrap());
-    }
-    result
+struct HuffmanTable {
+    // symbol << 4 | code length, zero for codes which are longer than FAST_LOOKUP_BITS
+    fast_lookup: Vec<u16>,
+    counts: [u16; MAX_CODE_LENGTH + 1],
+    symbols: Vec<u16>,
 }
 
-fn read_uncompressed_block(data: &[u8]) -> (&[u8], &[u8]) {
-    let block_len = BigEndian::read_u16(&data[0..2]) as usize;
-    println!("block len: {}", block_len);
-    (&data[2..(2 + block_len)], &data[(2 + block_len)..])
+impl HuffmanTable {
+    fn from_code_lengths(code_lengths: &[u8]) -> Result<HuffmanTable, PNGReaderError> {
+        let mut counts = [0u16; MAX_CODE_LENGTH + 1];
+        for length in code_lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut left: i32 = 1;
+        for length in 1..=MAX_CODE_LENGTH {
+            left = (left << 1) - counts[length] as i32;
+            if left < 0 {
+                return Result::Err(PNGReaderError::InvalidCompressedData {
+                    description: "Huffman code lengths are over-subscribed".to_owned()
+                });
+            }
+        }
+
+        let mut offsets = [0u16; MAX_CODE_LENGTH + 2];
+        for length in 1..=MAX_CODE_LENGTH {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0u16; offsets[MAX_CODE_LENGTH + 1] as usize];
+        for (symbol, length) in code_lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+
+        let mut fast_lookup = vec![0u16; 1 << FAST_LOOKUP_BITS];
+        let mut code: u32 = 0;
+        let mut index = 0;
+        for length in 1..=FAST_LOOKUP_BITS {
+            for _ in 0..counts[length as usize] {
+                let entry = (symbols[index] << 4) | length as u16;
+                let mut reversed = reverse_bits(code, length) as usize;
+                while reversed < fast_lookup.len() {
+                    fast_lookup[reversed] = entry;
+                    reversed += 1 << length;
+                }
+                code += 1;
+                index += 1;
+            }
+            code <<= 1;
+        }
+
+        Result::Ok(HuffmanTable {
+            fast_lookup,
+            counts,
+            symbols,
+        })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, PNGReaderError> {
+        reader.refill();
+        let entry = self.fast_lookup[reader.peek(FAST_LOOKUP_BITS) as usize];
+        if entry != 0 {
+            reader.consume((entry & 0xF) as u32)?;
+            return Result::Ok(entry >> 4);
+        }
+
+        // canonical decoding, codes are stored starting from the most significant bit
+        let bits = reader.peek(MAX_CODE_LENGTH as u32);
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for length in 1..=MAX_CODE_LENGTH {
+            code |= ((bits >> (length - 1)) & 1) as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                reader.consume(length as u32)?;
+                return Result::Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Result::Err(PNGReaderError::InvalidCompressedData {
+            description: "invalid Huffman code".to_owned()
+        })
+    }
 }
 
-fn get_len_extra_bits_amount(len: usize) -> usize {
+fn reverse_bits(code: u32, length: u32) -> u32 {
+    code.reverse_bits() >> (32 - length)
+}
+
+pub(crate) fn get_len_extra_bits_amount(len: usize) -> usize {
     if len < 8 || len == 28 {
         0
     } else {
@@ -74,7 +205,7 @@ fn get_len_extra_bits_amount(len: usize) -> usize {
     }
 }
 
-fn get_dis_extra_bits_amount(dis: usize) -> usize {
+pub(crate) fn get_dis_extra_bits_amount(dis: usize) -> usize {
     if dis < 4 {
         0
     } else {
@@ -82,280 +213,238 @@ fn get_dis_extra_bits_amount(dis: usize) -> usize {
     }
 }
 
-const LEN_LOWER: [usize; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
-
-fn get_lower_len(len: usize) -> usize {
-    LEN_LOWER[len]
-}
-
-const DIS_LOWER: [usize; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4007, 6145, 8193, 12289, 16385, 24577];
+pub(crate) const LEN_LOWER: [usize; 29] = [3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258];
 
-fn get_lower_dis(dis: usize) -> usize {
-    DIS_LOWER[dis]
-}
+pub(crate) const DIS_LOWER: [usize; 30] = [1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
 
-fn bits_to_number(bits: BitVec) -> usize {
-    if bits.len() == 0 {
-        return 0;
-    }
-    let r = 8 - bits.len() % 8;
-    let mut bytes = Vec::new();
-    bytes.extend_from_slice(&bits.to_bytes());
-    while bytes.len() < 4 {
-        bytes.insert(0, 0);
+fn fixed_huffman_tables() -> Result<(HuffmanTable, HuffmanTable), PNGReaderError> {
+    let mut literal_length_code_lengths = [0u8; 288];
+    for (symbol, length) in literal_length_code_lengths.iter_mut().enumerate() {
+        *length = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
     }
-    (BigEndian::read_u32(bytes.as_mut()) >> r) as usize
+    Result::Ok((
+        HuffmanTable::from_code_lengths(&literal_length_code_lengths)?,
+        HuffmanTable::from_code_lengths(&[5; 32])?,
+    ))
 }
 
-fn get_slice(dis: usize, len: usize, data: &Vec<u8>) -> Vec<u8> {
-    let pos = data.len();
-    let mut slice = Vec::new();
-    let start = pos - dis;
-    let mut x = start;
-    for _ in 0..len {
-        slice.push(data[x]);
-        x += 1;
-        if x == pos {
-            x = start;
-        }
+fn read_dynamic_huffman_tables(reader: &mut BitReader) -> Result<(HuffmanTable, HuffmanTable), PNGReaderError> {
+    let hlit = reader.read_bits(5)? as usize + 257;
+    let hdist = reader.read_bits(5)? as usize + 1;
+    let hclen = reader.read_bits(4)? as usize + 4;
+    if hlit > MAX_LITERAL_LENGTH_CODES || hdist > MAX_DISTANCE_CODES {
+        return Result::Err(PNGReaderError::InvalidCompressedData {
+            description: format!("too many literal/length ({}) or distance ({}) codes", hlit, hdist)
+        });
     }
-    slice
-}
 
-fn read_compressed_block(let_len_tree: HashMap<Vec<bool>, usize>, dis_tree: HashMap<Vec<bool>, usize>, data: &mut BitStream) -> Vec<u8> {
-    let mut decompressed_data = Vec::new();
-    // let mut pos = 0;
-    loop {
-        let value = read_huffman_code(&let_len_tree, data);
-        if value < 256 {
-            // println!("value: {}", value);
-            decompressed_data.push(value as u8);
-            // pos += 1;
-        } else if value == 256 {
-            println!("end of the block");
-            break;
-        } else {
-            // print!("<{}, ", value);
-            let len = value - 257;
-            let len = get_lower_len(len) + bits_to_number(data.get_bits(get_len_extra_bits_amount(len)));
-            // println!("{:?}", data.get_bits(10));
-            // return decompressed_data;
-            let dis = read_huffman_code(&dis_tree, data);
-            // println!("{}> ", dis);
-            let dis = get_lower_dis(dis) + bits_to_number(data.get_bits(get_dis_extra_bits_amount(dis)));
-            // println!("pos: {}, len: {}, dis: {}", pos, len, dis);
-            decompressed_data.append(&mut get_slice(dis, len, &decompressed_data));
-            // pos += len;
-        }
+    let mut code_length_code_lengths = [0u8; 19];
+    for i in 0..hclen {
+        code_length_code_lengths[CODE_LENGTH_ALPHABET_ORDER[i]] = reader.read_bits(3)? as u8;
     }
-    decompressed_data
-}
-
-fn generate_huffman_tree(code_lengths: Vec<usize>) -> HashMap<Vec<bool>, usize> {
-    let n = code_lengths.len();
-    let mut distinct_lengths = BTreeSet::new();
-    for len in code_lengths.iter() {
-        if *len > 0 {
-            distinct_lengths.insert(*len);
+    let code_length_table = HuffmanTable::from_code_lengths(&code_length_code_lengths)?;
+
+    let mut code_lengths = Vec::with_capacity(hlit + hdist);
+    while code_lengths.len() < hlit + hdist {
+        let (value, times) = match code_length_table.decode(reader)? {
+            code @ 0..=15 => (code as u8, 1),
+            16 => match code_lengths.last() {
+                Some(previous) => (*previous, reader.read_bits(2)? + 3),
+                None => return Result::Err(PNGReaderError::InvalidCompressedData {
+                    description: "code length repeat without a previous code length".to_owned()
+                }),
+            },
+            17 => (0, reader.read_bits(3)? + 3),
+            18 => (0, reader.read_bits(7)? + 11),
+            code => return Result::Err(PNGReaderError::InvalidCompressedData {
+                description: format!("code length code must be < 19 but got {}", code)
+            }),
+        };
+        if code_lengths.len() + times as usize > hlit + hdist {
+            return Result::Err(PNGReaderError::InvalidCompressedData {
+                description: "code lengths repeat past the end of the alphabet".to_owned()
+            });
         }
-    } 
-    let mut next_code = 0;
-    let mut last_shift = 0;
-    let mut codes = HashMap::new();
-    for len in distinct_lengths.iter() {
-        next_code <<= *len - last_shift;
-        last_shift = *len;
-        for i in 0..n {
-            if code_lengths[i] == *len {
-                codes.insert(number_to_bits(next_code, *len), i);
-                next_code += 1;
-            }
+        for _ in 0..times {
+            code_lengths.push(value);
         }
     }
-    codes
-}
-
-fn read_huffman_code(tree: &HashMap<Vec<bool>, usize>, stream: &mut BitStream) -> usize {
-    let mut code = vec![stream.get_next_bit(); 1];
-    while !tree.contains_key(&code) {
-        // print_code(&code);
-        // println!("");
-        // code.insert(0, stream.get_next_bit());
-        code.push(stream.get_next_bit());
-    }
-    // print_code(&code);
-    // println!("");
-    *tree.get(&code).unwrap()
-}
 
-fn read_huffman_trees(data: &mut BitStream) -> Result<(HashMap<Vec<bool>, usize>, HashMap<Vec<bool>, usize>), PNGReaderError> {
-    let hlit = data.get_bits(5);
-    println!("hlit: {:?}", hlit);
-    let hlit = (hlit.to_bytes()[0] >> 3) as usize + 257;
-    println!("hlit: {}", hlit);
+    if code_lengths[END_OF_BLOCK as usize] == 0 {
+        return Result::Err(PNGReaderError::InvalidCompressedData {
+            description: "end of block code is missing".to_owned()
+        });
+    }
 
-    let hdist = data.get_bits(5);
-    println!("hdist: {:?}", &hdist);
-    let hdist = (hdist.to_bytes()[0] >> 3) as usize + 1;
-    println!("hdist: {}", &hdist);
+    Result::Ok((
+        HuffmanTable::from_code_lengths(&code_lengths[0..hlit])?,
+        HuffmanTable::from_code_lengths(&code_lengths[hlit..])?,
+    ))
+}
 
-    let hclen = data.get_bits(4);
-    println!("hclen: {:?}", hclen);
-    let hclen = (hclen.to_bytes()[0] >> 4) as usize + 4;
-    println!("hclen: {}", hclen);
+fn copy_match(output: &mut Vec<u8>, distance: usize, length: usize) -> Result<(), PNGReaderError> {
+    if distance > output.len() || distance > WINDOW_SIZE {
+        return Result::Err(PNGReaderError::InvalidCompressedData {
+            description: format!("distance {} is too far back, only {} bytes are available", distance, output.len())
+        });
+    }
+    let start = output.len() - distance;
+    output.reserve(length);
+    for i in start..(start + length) {
+        let byte = output[i];
+        output.push(byte);
+    }
+    Result::Ok(())
+}
 
-    let mut code_lengths = vec![0; 19];
-    let alphabet_order = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
-    for i in 0..hclen {
-        let length = data.get_bits(3);
-        println!("{:?}", &length);
-        let length = (length.to_bytes()[0] >> 5) as usize;
-        println!("{:?}", &length);
-        code_lengths[alphabet_order[i as usize]] = length;
-    }
-    println!("code_length: {:?}", code_lengths);
-    let tree = generate_huffman_tree(code_lengths);
-    println!("{:?}", tree);
-    println!("position: {}", data.position);
-    let mut index = 0;
-    let mut codes = Vec::new();
-    println!("hlit + hdist: {}", hlit + hdist);
-    while index < hlit + hdist {
-        let code = read_huffman_code(&tree, data);
-        // println!("code: {}", code);
-        if code < 15 {
-            codes.push(code);
-            index += 1;
-        } else if code == 16 {
-            let times = data.get_bits(2);
-            // println!("{:?} times binary", times);
-            let times = times.to_bytes()[0] >> 6;
-            // println!("{:?} times decimal", times + 3);
-            index += times as usize + 3;
-            for _ in 0..(times + 3) {
-                codes.push(codes.last().unwrap().clone());
-            }
-        } else if code == 17 {
-            let times = data.get_bits(3);
-            // println!("{:?} times binary", times);
-            let times = times.to_bytes()[0] >> 5;
-            // println!("{:?} times decimal", times + 3);
-            index += times as usize + 3;
-            for _ in 0..(times + 3) {
-                codes.push(0);
+fn read_compressed_block(reader: &mut BitReader, literal_length_table: &HuffmanTable, distance_table: &HuffmanTable, output: &mut Vec<u8>) -> Result<(), PNGReaderError> {
+    loop {
+        let symbol = literal_length_table.decode(reader)?;
+        if symbol < END_OF_BLOCK {
+            output.push(symbol as u8);
+        } else if symbol == END_OF_BLOCK {
+            return Result::Ok(());
+        } else {
+            let length_code = (symbol - 257) as usize;
+            if length_code >= LEN_LOWER.len() {
+                return Result::Err(PNGReaderError::InvalidCompressedData {
+                    description: format!("invalid length symbol {}", symbol)
+                });
             }
-        } else if code == 18 {
-            let times = data.get_bits(7);
-            // println!("{:?} times binary", times);
-            let times = times.to_bytes()[0] >> 1;
-            // println!("{:?} times decimal", times + 11);
-            index += times as usize + 11;
-            for _ in 0..(times + 11) {
-                codes.push(0);
+            let length = LEN_LOWER[length_code] + reader.read_bits(get_len_extra_bits_amount(length_code) as u32)? as usize;
+
+            let distance_code = distance_table.decode(reader)? as usize;
+            if distance_code >= DIS_LOWER.len() {
+                return Result::Err(PNGReaderError::InvalidCompressedData {
+                    description: format!("invalid distance symbol {}", distance_code)
+                });
             }
-        } else {
-            panic!("Error: code must be < 18 but got {}", code);
+            let distance = DIS_LOWER[distance_code] + reader.read_bits(get_dis_extra_bits_amount(distance_code) as u32)? as usize;
+
+            copy_match(output, distance, length)?;
         }
     }
-    println!("{:?}", &codes[0..hlit]);
-    let lit_len_tree = generate_huffman_tree(codes[0..hlit].to_vec());
-    println!("{:?}", &codes[hlit..]);
-    let dis_tree = generate_huffman_tree(codes[hlit..].to_vec());
-    Result::Ok((lit_len_tree, dis_tree))
 }
 
-fn load_default_codes() -> (HashMap<Vec<bool>, usize>, HashMap<Vec<bool>, usize>) {
-    let mut lit_len_tree = HashMap::new();
-    let mut next_code = 0;
-    for i in 256..=279 {
-        lit_len_tree.insert(number_to_bits(next_code, 7), i);
-        next_code += 1;
-    }
-    next_code <<= 1;
-    for i in 0..=143 {
-        lit_len_tree.insert(number_to_bits(next_code, 8), i);
-        next_code += 1;
-    }
-    for i in 280..=285 {
-        lit_len_tree.insert(number_to_bits(next_code, 8), i);
-        next_code += 1;
-    }
-    next_code += 2;
-    next_code <<= 1;
-    for i in 144..=255 {
-        lit_len_tree.insert(number_to_bits(next_code, 9), i);
-        next_code += 1;
-    }
-    let mut dis_tree = HashMap::new();
-    for i in 0..=29 {
-        dis_tree.insert(number_to_bits(i, 5), i as usize);
-    }
-    (lit_len_tree, dis_tree)
+fn read_uncompressed_block(reader: &mut BitReader, output: &mut Vec<u8>) -> Result<(), PNGReaderError> {
+    reader.align_to_byte();
+    let length = reader.read_bits(16)?;
+    let length_complement = reader.read_bits(16)?;
+    if length != !length_complement & 0xFFFF {
+        return Result::Err(PNGReaderError::InvalidCompressedData {
+            description: format!("stored block length {} does not match its complement {}", length, length_complement)
+        });
+    }
+    reader.read_bytes(length as usize, output)
 }
 
-fn decompress(mut data: &[u8]) -> Result<Vec<u8>, PNGReaderError> {
-    let mut decompressed_data = Vec::new();
-    let mut bits = BitStream::new(data);
+// Decompresses raw deflate data. Back references may point into the dictionary, which is not part of the output.
+// Returns the decompressed data and the amount of input bytes the deflate stream took.
+pub fn decompress(data: &[u8], dictionary: &[u8]) -> Result<(Vec<u8>, usize), PNGReaderError> {
+    let mut output = Vec::with_capacity(dictionary.len() + data.len() * 4);
+    output.extend_from_slice(dictionary);
+    let mut reader = BitReader::new(data);
     loop {
-        let bfinal = bits.get_next_bit();
-        let btype = if bits.get_next_bit() { 1 } else { 0 };
-        let btype = btype + if bits.get_next_bit() { 2 } else { 0 };
-        match btype {
-            0 => {
-                let (block_data, rest_data) = read_uncompressed_block(&data[3..]);
-                decompressed_data.extend_from_slice(block_data);
-                data = rest_data;
-            },
+        let bfinal = reader.read_bits(1)? == 1;
+        match reader.read_bits(2)? {
+            0 => read_uncompressed_block(&mut reader, &mut output)?,
             1 => {
-                let (let_len_tree, dis_tree) = load_default_codes();
-                decompressed_data.append(&mut read_compressed_block(let_len_tree, dis_tree, &mut bits));
+                let (literal_length_table, distance_table) = fixed_huffman_tables()?;
+                read_compressed_block(&mut reader, &literal_length_table, &distance_table, &mut output)?;
             },
             2 => {
-                let (let_len_tree, dis_tree) = read_huffman_trees(&mut bits)?;
-                decompressed_data.append(&mut read_compressed_block(let_len_tree, dis_tree, &mut bits));
+                let (literal_length_table, distance_table) = read_dynamic_huffman_tables(&mut reader)?;
+                read_compressed_block(&mut reader, &literal_length_table, &distance_table, &mut output)?;
             },
-            3 => panic!("error. reserved"),
-            _ => {},
+            _ => return Result::Err(PNGReaderError::InvalidCompressedData {
+                description: "reserved block type".to_owned()
+            }),
         };
         if bfinal {
             break;
         }
     }
-    println!("{:?}", decompressed_data);
-    Result::Ok(decompressed_data)
+    output.drain(0..dictionary.len());
+    Result::Ok((output, reader.bytes_consumed()))
+}
+
+fn checksum_mismatch(strict_checksums: bool, description: String) -> Result<(), PNGReaderError> {
+    if strict_checksums {
+        Result::Err(PNGReaderError::ChecksumMismatch { description })
+    } else {
+        Result::Ok(())
+    }
 }
 
 pub fn inflate_decompress(data: &[u8]) -> Result<Vec<u8>, PNGReaderError> {
-    let mut compression_method_flag = BitVec::from_bytes(&[data[0]]);
-    println!("cm_flag: {:?}", compression_method_flag);
-    compression_method_flag.and(&BitVec::from_bytes(&[0b00001111]));
-    let cm_flag = compression_method_flag.to_bytes()[0];
-    println!("cm_flag: {}", cm_flag);
-
-    let mut compression_method_flag = BitVec::from_bytes(&[data[0]]);
-    compression_method_flag.and(&BitVec::from_bytes(&[0b11110000]));
-    let cinfo_flag = compression_method_flag.to_bytes()[0] >> 4;
-    println!("cinfo_flag: {}", cinfo_flag);
-    if cinfo_flag > 7 {
-        panic!("cinfo_flag > 7: not allowed");
-    }
-
-    let mut additional_bits = BitVec::from_bytes(&[data[1]]);
-    // TODO: check FCHECK flag (0 - 4 bits)
-    let fdict_flag = additional_bits.get(2);
-    if let Some(bit) = fdict_flag {
-        println!("fdict_flag: {}", bit);
-        if bit {
-            panic!("Not supported");
-            // TODO: read preset dictionary
-        }
+    inflate_decompress_with_dictionary(data, Option::None, true)
+}
+
+// Decompresses a zlib stream. When strict_checksums is false, FCHECK and Adler32 mismatches
+// (including a missing Adler32 trailer) are ignored.
+pub fn inflate_decompress_with_dictionary(data: &[u8], dictionary: Option<&[u8]>, strict_checksums: bool) -> Result<Vec<u8>, PNGReaderError> {
+    if data.len() < 2 {
+        return Result::Err(PNGReaderError::InvalidCompressedData {
+            description: "zlib header is missing".to_owned()
+        });
+    }
+
+    let compression_method = data[0] & 0x0F;
+    let compression_info = data[0] >> 4;
+    if compression_method != 8 {
+        return Result::Err(PNGReaderError::UnsupportedOption {
+            description: format!("compression method {} is not supported, only deflate (8) is allowed", compression_method)
+        });
     }
-    additional_bits.and(&BitVec::from_bytes(&[0b11000000]));
-    let flevel_flag = additional_bits.to_bytes()[0] >> 6;
-    println!("flevel_flag: {}", flevel_flag);
+    if compression_info > 7 {
+        return Result::Err(PNGReaderError::InvalidCompressedData {
+            description: format!("window size 2^{} is larger than allowed", compression_info as u32 + 8)
+        });
+    }
+    if (data[0] as u16 * 256 + data[1] as u16) % 31 != 0 {
+        checksum_mismatch(strict_checksums, format!("zlib header check bits are invalid: {:?}", &data[0..2]))?;
+    }
+
+    let mut offset = 2;
+    let preset_dictionary = data[1] & 0x20 != 0;
+    let dictionary = if preset_dictionary {
+        if data.len() < 6 {
+            return Result::Err(PNGReaderError::InvalidCompressedData {
+                description: "preset dictionary identifier is missing".to_owned()
+            });
+        }
+        let dictionary_id = BigEndian::read_u32(&data[2..6]);
+        offset = 6;
+        match dictionary {
+            Some(dictionary) if adler32(dictionary) == dictionary_id => dictionary,
+            Some(_) => return Result::Err(PNGReaderError::InvalidCompressedData {
+                description: format!("preset dictionary does not match dictionary identifier {:#010x}", dictionary_id)
+            }),
+            None => return Result::Err(PNGReaderError::UnsupportedOption {
+                description: format!("stream requires a preset dictionary with identifier {:#010x}", dictionary_id)
+            }),
+        }
+    } else {
+        &[]
+    };
     // The information in FLEVEL is not needed for decompression;
-    let decompressed_data = decompress(&data[2..(data.len() - 4)]).unwrap();
-    // TODO: read ADLER32 block
+    let (decompressed_data, consumed) = decompress(&data[offset..], dictionary)?;
+
+    let trailer_offset = offset + consumed;
+    if data.len() < trailer_offset + 4 {
+        checksum_mismatch(strict_checksums, "Adler32 checksum is missing".to_owned())?;
+    } else {
+        let expected = BigEndian::read_u32(&data[trailer_offset..(trailer_offset + 4)]);
+        let actual = adler32(&decompressed_data);
+        if expected != actual {
+            checksum_mismatch(strict_checksums, format!("Adler32 checksum {:#010x} does not match computed {:#010x}", expected, actual))?;
+        }
+    }
     Result::Ok(decompressed_data)
 }
 
@@ -364,41 +453,106 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_number_to_bits() {
-        let bit_vec = number_to_bits(10, 4);
-        println!("{:?}", bit_vec);
+    fn test_bit_reader() {
+        let mut reader = BitReader::new(&[0b1010_1101, 0b0000_0011]);
+        assert_eq!(reader.read_bits(1).unwrap(), 1);
+        assert_eq!(reader.read_bits(2).unwrap(), 0b10);
+        assert_eq!(reader.read_bits(7).unwrap(), 0b11_10101);
+        assert_eq!(reader.read_bits(6).unwrap(), 0);
+        assert!(reader.read_bits(1).is_err());
+    }
+
+    #[test]
+    fn test_huffman_table() {
+        // example from RFC 1951 section 3.2.2: A 3, B 3, C 3, D 3, E 3, F 2, G 4, H 4
+        let table = HuffmanTable::from_code_lengths(&[3, 3, 3, 3, 3, 2, 4, 4]).unwrap();
+        // codes are packed starting with the most significant bit: F = 00, G = 1110, A = 010
+        let mut reader = BitReader::new(&[0b1001_1100, 0b0000_0000]);
+        assert_eq!(table.decode(&mut reader).unwrap(), 5);
+        assert_eq!(table.decode(&mut reader).unwrap(), 6);
+        assert_eq!(table.decode(&mut reader).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_huffman_table_long_codes() {
+        let mut code_lengths = vec![0u8; 20];
+        for i in 0..16 {
+            code_lengths[i] = std::cmp::min(i as u8 + 1, 15);
+        }
+        let table = HuffmanTable::from_code_lengths(&code_lengths).unwrap();
+        // the last code is 15 ones
+        let mut reader = BitReader::new(&[0xFF, 0x7F]);
+        assert_eq!(table.decode(&mut reader).unwrap(), 15);
+    }
+
+    #[test]
+    fn test_over_subscribed_code_lengths() {
+        assert!(HuffmanTable::from_code_lengths(&[1, 1, 1]).is_err());
+    }
+
+    #[test]
+    fn test_copy_match() {
+        let mut output = vec![0, 1, 2, 3, 4, 5, 6, 7];
+        copy_match(&mut output, 4, 13).unwrap();
+        assert_eq!(output[8..], [4, 5, 6, 7, 4, 5, 6, 7, 4, 5, 6, 7, 4]);
+
+        let mut output = vec![1, 0];
+        assert!(copy_match(&mut output, 4, 13).is_err());
+    }
+
+    #[test]
+    fn test_stored_block() {
+        let data = [0x78, 0x01, 0x01, 0x03, 0x00, 0xFC, 0xFF, 0x61, 0x62, 0x63, 0x02, 0x4D, 0x01, 0x27];
+        assert_eq!(inflate_decompress(&data).unwrap(), b"abc".to_vec());
     }
 
     #[test]
-    fn test2() {
-        let mut data = BitStream::new(&[2]);
-        println!("{:?}", data.bits);
-        let bits = data.get_bits(2);
-        println!("{:?}", bits);
-        let bits = bits.to_bytes()[0] >> 6;
-        println!("{:?}", bits);
+    fn test_fixed_block() {
+        // python: zlib.compress(b"hello hello hello")
+        let data = [0x78, 0x9C, 0xCB, 0x48, 0xCD, 0xC9, 0xC9, 0x57, 0xC8, 0x40, 0x90, 0x00, 0x3A, 0x2E, 0x06, 0x7D];
+        assert_eq!(inflate_decompress(&data).unwrap(), b"hello hello hello".to_vec());
     }
 
     #[test]
-    fn test_bits_to_number() {
-        let mut bits = BitVec::new();
-        bits.push(true);
-        bits.push(false);
-        bits.push(true);
-        bits.push(true);
-        bits.push(false);
-        assert_eq!(22, bits_to_number(bits));
-        let mut bits = BitVec::new();
-        bits.push(true);
-        bits.push(true);
-        bits.push(true);
-        bits.push(false);
-        bits.push(false);
-        bits.push(true);
-        bits.push(false);
-        bits.push(false);
-        bits.push(true);
-        assert_eq!(457, bits_to_number(bits));
+    fn test_reserved_block_type() {
+        assert!(inflate_decompress(&[0x78, 0x01, 0b0000_0111, 0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_truncated_stream() {
+        let data = [0x78, 0x9C, 0xCB, 0x48, 0xCD, 0xC9];
+        assert!(inflate_decompress(&data).is_err());
+    }
+
+    #[test]
+    fn test_adler32_mismatch() {
+        let data = [0x78, 0x01, 0x01, 0x03, 0x00, 0xFC, 0xFF, 0x61, 0x62, 0x63, 0x02, 0x4D, 0x01, 0x28];
+        assert!(inflate_decompress(&data).is_err());
+        assert_eq!(inflate_decompress_with_dictionary(&data, Option::None, false).unwrap(), b"abc".to_vec());
+        assert!(inflate_decompress_with_dictionary(&data[0..12], Option::None, true).is_err());
+    }
+
+    #[test]
+    fn test_fcheck_mismatch() {
+        let data = [0x78, 0x02, 0x01, 0x03, 0x00, 0xFC, 0xFF, 0x61, 0x62, 0x63, 0x02, 0x4D, 0x01, 0x27];
+        assert!(inflate_decompress(&data).is_err());
+        assert_eq!(inflate_decompress_with_dictionary(&data, Option::None, false).unwrap(), b"abc".to_vec());
+    }
+
+    #[test]
+    fn test_preset_dictionary() {
+        // python: zlib.compressobj(zdict=b"turbocow renders cows") applied to b"turbocow renders cows fast"
+        let data = [
+            0x78, 0xBB, 0x5D, 0x9A, 0x08, 0x65, 0x2B, 0xC1, 0x26, 0xA8,
+            0x90, 0x96, 0x58, 0x5C, 0x02, 0x00, 0x8C, 0x48, 0x0A, 0x33
+        ];
+        let dictionary = b"turbocow renders cows";
+        assert_eq!(
+            inflate_decompress_with_dictionary(&data, Option::Some(dictionary), true).unwrap(),
+            b"turbocow renders cows fast".to_vec()
+        );
+        assert!(inflate_decompress(&data).is_err());
+        assert!(inflate_decompress_with_dictionary(&data, Option::Some(b"cows"), true).is_err());
     }
 
     #[test]
@@ -433,15 +587,4 @@ mod tests {
         assert_eq!(get_dis_extra_bits_amount(27), 12);
         assert_eq!(get_dis_extra_bits_amount(29), 13);
     }
-
-    #[test]
-    fn test_get_slice() {
-        let vec = vec![0, 1, 2, 3, 4, 5, 6, 7];
-        let slice = get_slice(4, 13, &vec);
-        assert_eq!(slice, vec![4, 5, 6, 7, 4, 5, 6, 7, 4, 5, 6, 7, 4]);
-
-        let vec = vec![1, 0];
-        let slice = get_slice(4, 13, &vec);
-        assert_eq!(slice, vec![4, 5, 6, 7, 4, 5, 6, 7, 4, 5, 6, 7, 4]);
-    }
 }
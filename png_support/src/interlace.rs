@@ -0,0 +1,115 @@
+use core::models::pixel::Pixel;
+use crate::reader::PNGReaderError;
+use crate::chunk::IHDRChunk;
+use crate::filter::unfilter_scanlines;
+
+// see:
+// https://www.w3.org/TR/PNG/#8Interlace
+
+pub struct Adam7Pass {
+    pub x_start: usize,
+    pub y_start: usize,
+    pub x_step: usize,
+    pub y_step: usize,
+}
+
+pub const ADAM7_PASSES: [Adam7Pass; 7] = [
+    Adam7Pass { x_start: 0, y_start: 0, x_step: 8, y_step: 8 },
+    Adam7Pass { x_start: 4, y_start: 0, x_step: 8, y_step: 8 },
+    Adam7Pass { x_start: 0, y_start: 4, x_step: 4, y_step: 8 },
+    Adam7Pass { x_start: 2, y_start: 0, x_step: 4, y_step: 4 },
+    Adam7Pass { x_start: 0, y_start: 2, x_step: 2, y_step: 4 },
+    Adam7Pass { x_start: 1, y_start: 0, x_step: 2, y_step: 2 },
+    Adam7Pass { x_start: 0, y_start: 1, x_step: 1, y_step: 2 },
+];
+
+pub struct ReducedImage {
+    pub pass: &'static Adam7Pass,
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+}
+
+impl Adam7Pass {
+    pub fn reduced_size(&self, width: usize, height: usize) -> (usize, usize) {
+        (reduced_dimension(width, self.x_start, self.x_step), reduced_dimension(height, self.y_start, self.y_step))
+    }
+
+    pub fn scatter(&self, pass_pixels: &[Pixel], pass_width: usize, pixels: &mut [Pixel], width: usize) {
+        for (i, pixel) in pass_pixels.iter().enumerate() {
+            let x = self.x_start + (i % pass_width) * self.x_step;
+            let y = self.y_start + (i / pass_width) * self.y_step;
+            pixels[y * width + x] = *pixel;
+        }
+    }
+}
+
+fn reduced_dimension(size: usize, start: usize, step: usize) -> usize {
+    if size > start {
+        (size - start + step - 1) / step
+    } else {
+        0
+    }
+}
+
+// Splits inflated data of an interlaced image into seven reduced images and unfilters each of them separately.
+// Passes which contain no pixels are skipped, as they have no scanlines (and no filter type bytes) in the stream.
+pub fn unfilter_interlaced(ihdr: &IHDRChunk, data: &[u8]) -> Result<Vec<ReducedImage>, PNGReaderError> {
+    let mut reduced_images = Vec::new();
+    let mut offset = 0;
+    for pass in ADAM7_PASSES.iter() {
+        let (width, height) = pass.reduced_size(ihdr.width as usize, ihdr.height as usize);
+        if width == 0 || height == 0 {
+            continue;
+        }
+
+        let scanline_length = ihdr.scanline_length(width);
+        let pass_length = (scanline_length + 1) * height;
+        if data.len() < offset + pass_length {
+            return Result::Err(PNGReaderError::BadImageData {
+                description: format!("Interlaced image data is too short. Expected at least {} bytes but got {}", offset + pass_length, data.len())
+            });
+        }
+
+        let unfiltered = unfilter_scanlines(&data[offset..(offset + pass_length)], scanline_length, height, ihdr.bytes_per_pixel())?;
+        reduced_images.push(ReducedImage { pass, width, height, data: unfiltered });
+        offset += pass_length;
+    }
+
+    if offset != data.len() {
+        return Result::Err(PNGReaderError::BadImageData {
+            description: format!("Bad samples amount. Expected: {} but got {}", offset, data.len())
+        });
+    }
+    Result::Ok(reduced_images)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reduced_size() {
+        let sizes: Vec<(usize, usize)> = ADAM7_PASSES.iter().map(|pass| pass.reduced_size(10, 3)).collect();
+        assert_eq!(sizes, vec![(2, 1), (1, 1), (3, 0), (2, 1), (5, 1), (5, 2), (10, 1)]);
+
+        let sizes: Vec<(usize, usize)> = ADAM7_PASSES.iter().map(|pass| pass.reduced_size(1, 1)).collect();
+        assert_eq!(sizes, vec![(1, 1), (0, 1), (1, 0), (0, 1), (1, 0), (0, 1), (1, 0)]);
+    }
+
+    #[test]
+    fn test_passes_cover_every_pixel_once() {
+        let (width, height) = (13, 11);
+        let mut pixels = vec![Pixel::from_rgba(0, 0, 0, 0); width * height];
+        for pass in ADAM7_PASSES.iter() {
+            let (pass_width, pass_height) = pass.reduced_size(width, height);
+            let pass_pixels = vec![Pixel::from_rgba(0, 0, 0, 1); pass_width * pass_height];
+            let mut layer = vec![Pixel::from_rgba(0, 0, 0, 0); width * height];
+            pass.scatter(&pass_pixels, pass_width, &mut layer, width);
+            for (pixel, added) in pixels.iter_mut().zip(layer.iter()) {
+                pixel.alpha += added.alpha;
+            }
+        }
+        assert!(pixels.iter().all(|pixel| pixel.alpha == 1));
+    }
+}
@@ -10,8 +10,13 @@ use writer::PNGWriter;
 pub mod reader;
 pub mod writer;
 pub mod inflate;
+pub mod deflate;
+pub mod checksum;
 pub mod chunk;
 pub mod filter;
+pub mod interlace;
+pub mod apng;
+pub mod metadata;
 
 pub struct PNGFormatSupportPlugin {
 }
@@ -26,11 +31,11 @@ impl PNGFormatSupportPlugin {
 impl ImageFormatSupportPlugin for PNGFormatSupportPlugin {
 
     fn format_name(&self) -> String {
-        "PPM".to_string()
+        "PNG".to_string()
     }
 
     fn reader(&self) -> Box<dyn ImageReader> {
-        box PNGReader {}
+        box PNGReader::new()
     }
 
     fn writer(&self) -> Box<dyn ImageWriter> {
@@ -0,0 +1,196 @@
+use core::models::metadata::{ImageMetadata, TextEntry, PhysicalDimensions, PhysicalUnit, ModificationTime, IccProfile, Exif};
+use byteorder::{ByteOrder, BigEndian};
+use crate::reader::{PNGImage, PNGImageType};
+use crate::deflate::deflate_compress;
+use crate::writer::write_chunk;
+
+// see:
+// https://www.w3.org/TR/PNG/#11Ancillary-chunks
+// https://ftp-osl.osuosl.org/pub/libpng/documents/pngext-1.5.0.html#C.eXIf
+
+const TEXT_COMPRESSION_LEVEL: u32 = 9;
+
+pub fn read_metadata(image: &PNGImage) -> ImageMetadata {
+    let mut text = Vec::new();
+    for chunk in &image.text {
+        text.push(TextEntry::new(&chunk.keyword, &chunk.text));
+    }
+    for chunk in &image.ztxt {
+        text.push(TextEntry {
+            compressed: true,
+            ..TextEntry::new(&chunk.keyword, &chunk.text)
+        });
+    }
+    for chunk in &image.itxt {
+        text.push(TextEntry {
+            keyword: chunk.keyword.clone(),
+            text: chunk.text.clone(),
+            compressed: chunk.compressed,
+            language_tag: Some(chunk.language_tag.clone()),
+            translated_keyword: Some(chunk.translated_keyword.clone()),
+        });
+    }
+
+    ImageMetadata {
+        text,
+        physical_dimensions: image.phys.as_ref().map(|phys| PhysicalDimensions {
+            pixels_per_unit_x: phys.pixels_per_unit_x,
+            pixels_per_unit_y: phys.pixels_per_unit_y,
+            unit: if phys.unit_specifier == 1 { PhysicalUnit::Meter } else { PhysicalUnit::Unknown },
+        }),
+        modification_time: image.time.as_ref().map(|time| ModificationTime {
+            year: time.year,
+            month: time.month,
+            day: time.day,
+            hour: time.hour,
+            minute: time.minute,
+            second: time.second,
+        }),
+        significant_bits: image.sbit.as_ref().map(|sbit| sbit.sample_depths.clone()),
+        icc_profile: image.iccp.as_ref().map(|iccp| IccProfile {
+            name: iccp.name.clone(),
+            profile: iccp.profile.clone(),
+        }),
+        // TIFF structure is kept as is for writers, entries are only parsed by the JPEG reader
+        exif: image.exif.as_ref().map(|exif| Exif {
+            entries: Vec::new(),
+            data: exif.data.clone(),
+        }),
+    }
+}
+
+fn is_latin1(text: &str) -> bool {
+    text.chars().all(|c| (c as u32) < 256)
+}
+
+fn string_to_latin1(text: &str) -> Vec<u8> {
+    text.chars().map(|c| c as u8).collect()
+}
+
+// keywords are 1-79 latin-1 characters
+fn keyword_bytes(keyword: &str) -> Vec<u8> {
+    let mut keyword: Vec<u8> = string_to_latin1(keyword).into_iter().filter(|byte| *byte != 0).take(79).collect();
+    if keyword.is_empty() {
+        keyword = b"Comment".to_vec();
+    }
+    keyword
+}
+
+// sBIT has one entry per sample of the written colour type, so the source entries are adjusted to it
+fn significant_bits_for_colour_type(significant_bits: &[u8], colour_type: PNGImageType, bit_depth: u8) -> Option<Vec<u8>> {
+    let colour: Vec<u8> = match significant_bits.len() {
+        1 | 2 => vec![significant_bits[0]; 3],
+        3 | 4 => significant_bits[0..3].to_vec(),
+        _ => return None,
+    };
+    let alpha = match significant_bits.len() {
+        2 => significant_bits[1],
+        4 => significant_bits[3],
+        _ => bit_depth,
+    };
+
+    let mut result = colour;
+    if colour_type == PNGImageType::TruecolourAlpha {
+        result.push(alpha);
+    }
+    if result.iter().any(|bits| *bits == 0) {
+        return None;
+    }
+    Some(result.into_iter().map(|bits| std::cmp::min(bits, bit_depth)).collect())
+}
+
+fn write_text_chunk(output: &mut Vec<u8>, entry: &TextEntry) {
+    let international = entry.language_tag.is_some() || entry.translated_keyword.is_some() || !is_latin1(&entry.text);
+    let mut data = keyword_bytes(&entry.keyword);
+    data.push(0);
+
+    if international {
+        data.push(if entry.compressed { 1 } else { 0 });
+        data.push(0);
+        data.extend_from_slice(&string_to_latin1(entry.language_tag.as_ref().map(|tag| tag.as_str()).unwrap_or("")));
+        data.push(0);
+        data.extend_from_slice(entry.translated_keyword.as_ref().map(|keyword| keyword.as_bytes()).unwrap_or(&[]));
+        data.push(0);
+        if entry.compressed {
+            data.extend_from_slice(&deflate_compress(entry.text.as_bytes(), TEXT_COMPRESSION_LEVEL));
+        } else {
+            data.extend_from_slice(entry.text.as_bytes());
+        }
+        write_chunk(output, b"iTXt", &data);
+    } else if entry.compressed {
+        data.push(0);
+        data.extend_from_slice(&deflate_compress(&string_to_latin1(&entry.text), TEXT_COMPRESSION_LEVEL));
+        write_chunk(output, b"zTXt", &data);
+    } else {
+        data.extend_from_slice(&string_to_latin1(&entry.text));
+        write_chunk(output, b"tEXt", &data);
+    }
+}
+
+// Writes metadata chunks which are allowed to be placed before PLTE and IDAT, so this should be called right after IHDR.
+pub fn write_metadata_chunks(output: &mut Vec<u8>, metadata: &ImageMetadata, colour_type: PNGImageType, bit_depth: u8) {
+    if let Some(icc_profile) = &metadata.icc_profile {
+        let mut data = keyword_bytes(&icc_profile.name);
+        data.push(0);
+        data.push(0);
+        data.extend_from_slice(&deflate_compress(&icc_profile.profile, TEXT_COMPRESSION_LEVEL));
+        write_chunk(output, b"iCCP", &data);
+    }
+
+    let significant_bits = metadata.significant_bits.as_ref()
+        .and_then(|significant_bits| significant_bits_for_colour_type(significant_bits, colour_type, bit_depth));
+    if let Some(significant_bits) = significant_bits {
+        write_chunk(output, b"sBIT", &significant_bits);
+    }
+
+    if let Some(physical_dimensions) = &metadata.physical_dimensions {
+        let mut data = vec![0; 9];
+        BigEndian::write_u32(&mut data[0..4], physical_dimensions.pixels_per_unit_x);
+        BigEndian::write_u32(&mut data[4..8], physical_dimensions.pixels_per_unit_y);
+        data[8] = match physical_dimensions.unit {
+            PhysicalUnit::Meter => 1,
+            PhysicalUnit::Unknown => 0,
+        };
+        write_chunk(output, b"pHYs", &data);
+    }
+
+    if let Some(time) = &metadata.modification_time {
+        let mut data = vec![0; 7];
+        BigEndian::write_u16(&mut data[0..2], time.year);
+        data[2] = time.month;
+        data[3] = time.day;
+        data[4] = time.hour;
+        data[5] = time.minute;
+        data[6] = time.second;
+        write_chunk(output, b"tIME", &data);
+    }
+
+    for entry in &metadata.text {
+        write_text_chunk(output, entry);
+    }
+
+    if let Some(exif) = &metadata.exif {
+        write_chunk(output, b"eXIf", &exif.data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_significant_bits_for_colour_type() {
+        assert_eq!(significant_bits_for_colour_type(&[5, 6, 5], PNGImageType::Truecolour, 8), Some(vec![5, 6, 5]));
+        assert_eq!(significant_bits_for_colour_type(&[5, 6, 5], PNGImageType::TruecolourAlpha, 8), Some(vec![5, 6, 5, 8]));
+        assert_eq!(significant_bits_for_colour_type(&[4, 2], PNGImageType::TruecolourAlpha, 8), Some(vec![4, 4, 4, 2]));
+        assert_eq!(significant_bits_for_colour_type(&[12, 12, 12, 16], PNGImageType::Truecolour, 8), Some(vec![8, 8, 8]));
+        assert_eq!(significant_bits_for_colour_type(&[1, 2, 3, 4, 5], PNGImageType::Truecolour, 8), None);
+    }
+
+    #[test]
+    fn test_keyword_bytes() {
+        assert_eq!(keyword_bytes("Title"), b"Title".to_vec());
+        assert_eq!(keyword_bytes(""), b"Comment".to_vec());
+        assert_eq!(keyword_bytes(&"a".repeat(100)).len(), 79);
+    }
+}
@@ -2,11 +2,14 @@ use core::models::io::{ImageReader, ImageIOError, ImageWriter, ImageWriterOption
 use core::models::image::Image;
 use core::models::pixel::Pixel;
 use custom_error::custom_error;
-use byteorder::{ByteOrder, LittleEndian};
+use byteorder::{ByteOrder, LittleEndian, BigEndian};
 use std::iter::*;
-use crate::inflate::inflate_decompress;
+use crate::inflate::inflate_decompress_with_dictionary;
 use crate::chunk::*;
 use crate::filter::*;
+use crate::interlace::unfilter_interlaced;
+use crate::apng::{DecodedFrame, compose_frames};
+use crate::metadata::read_metadata;
 
 custom_error! {pub PNGReaderError
     InvalidSignature {description: String} = "Invalid signature: {description}",
@@ -15,46 +18,68 @@ custom_error! {pub PNGReaderError
     InvalidChunk {description: String} = "Invalid PNG chunk: {description}",
     InvalidImageType {description: String} = "Invalid PNG image type: {description}",
     BadImageData {description: String} = "Image data is corrupted: {description}",
+    InvalidCompressedData {description: String} = "Compressed data is corrupted: {description}",
+    ChecksumMismatch {description: String} = "Checksum mismatch: {description}",
 }
 
 pub struct PNGReader {
+    strict_checksums: bool,
 }
 
 impl PNGReader {
-    const fn new() -> Self {
-        PNGReader {}
+    pub const fn new() -> Self {
+        PNGReader::with_strict_checksums(true)
+    }
+
+    // lenient reader ignores chunk CRC, zlib FCHECK and Adler32 mismatches
+    pub const fn with_strict_checksums(strict_checksums: bool) -> Self {
+        PNGReader {
+            strict_checksums,
+        }
     }
 }
 
 pub struct PNGImage {
     pub ihdr: Option<IHDRChunk>,
+    pub plte: Option<PLTEChunk>,
+    pub trns: Option<TRNSChunk>,
     pub sbit: Option<SBITChunk>,
     pub phys: Option<PHYSChunk>,
-    pub text: Option<TEXTChunk>,
+    pub text: Vec<TEXTChunk>,
     pub iccp: Option<ICCPChunk>,
     pub time: Option<TIMEChunk>,
-    pub ztxt: Option<ZTXTChunk>,
-    pub itxt: Option<ITXTChunk>,
+    pub exif: Option<EXIFChunk>,
+    pub ztxt: Vec<ZTXTChunk>,
+    pub itxt: Vec<ITXTChunk>,
     pub idat: IDATChunk,
+    pub actl: Option<ACTLChunk>,
+    pub frames: Vec<APNGFrame>,
+    pub fdat_seen: bool,
 }
 
 impl PNGImage {
     pub const fn new() -> Self {
         PNGImage {
             ihdr: Option::None,
+            plte: Option::None,
+            trns: Option::None,
             sbit:  Option::None,
             phys:  Option::None,
-            text:  Option::None,
+            text: Vec::new(),
             iccp: Option::None,
             time: Option::None,
-            ztxt: Option::None,
-            itxt: Option::None,
+            exif: Option::None,
+            ztxt: Vec::new(),
+            itxt: Vec::new(),
             idat:  IDATChunk::new(),
+            actl: Option::None,
+            frames: Vec::new(),
+            fdat_seen: false,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum PNGImageType {
     Greyscale,
     Truecolour,
@@ -77,6 +102,16 @@ impl PNGImageType {
         }
     }
 
+    pub fn to_number(&self) -> u8 {
+        match &self {
+            PNGImageType::Greyscale => 0,
+            PNGImageType::Truecolour => 2,
+            PNGImageType::IndexedColour => 3,
+            PNGImageType::GreyscaleAlpha => 4,
+            PNGImageType::TruecolourAlpha => 6,
+        }
+    }
+
     pub fn get_samples_amount(&self) -> usize {
         match &self {
             PNGImageType::Greyscale => 1,
@@ -89,11 +124,11 @@ impl PNGImageType {
 }
 
 fn validate_signature(data: &Vec<u8>) -> Result<(), PNGReaderError> {
-    if data[0..8] != [137, 80, 78, 71, 13, 10, 26, 10] {
+    if data.len() < 8 || data[0..8] != [137, 80, 78, 71, 13, 10, 26, 10] {
         Result::Err(PNGReaderError::InvalidSignature {
             description: format!(
                 "PNG signature must be [137, 80, 78, 71, 13, 10, 26, 10] but got {:?}",
-                &data[0..8]
+                &data[0..std::cmp::min(8, data.len())]
             )
         })
     } else {
@@ -101,88 +136,184 @@ fn validate_signature(data: &Vec<u8>) -> Result<(), PNGReaderError> {
     }
 }
 
-fn truecolor_samples_to_pixels(data: Vec<u8>) -> Vec<Pixel> {
-    let mut pixels = Vec::new();
-    let mut iter = data.iter();
-    loop {
-        let mut pixel = Pixel::zero();
-        match iter.next() {
-            Some(red) => pixel.red = *red,
-            None => return pixels,
-        };
-        match iter.next() {
-            Some(green) => pixel.green = *green,
-            None => return pixels,
-        };
-        match iter.next() {
-            Some(blue) => pixel.blue = *blue,
-            None => return pixels,
-        };
-        println!("{:?}", pixel);
-        pixels.push(pixel);
+fn read_samples(ihdr: &IHDRChunk, data: &[u8], width: usize, height: usize) -> Vec<u16> {
+    let samples_per_row = width * ihdr.colour_type.get_samples_amount();
+    let scanline_length = ihdr.scanline_length(width);
+    let bit_depth = ihdr.bit_depth as usize;
+    let mut samples = Vec::with_capacity(samples_per_row * height);
+    for scanline in data.chunks(scanline_length).take(height) {
+        for i in 0..samples_per_row {
+            let sample = match bit_depth {
+                16 => BigEndian::read_u16(&scanline[(i * 2)..(i * 2 + 2)]),
+                8 => scanline[i] as u16,
+                _ => {
+                    let bit_position = i * bit_depth;
+                    let shift = 8 - bit_depth - bit_position % 8;
+                    ((scanline[bit_position / 8] >> shift) & ((1 << bit_depth) - 1) as u8) as u16
+                }
+            };
+            samples.push(sample);
+        }
     }
+    samples
 }
 
-fn truecolor_alpha_samples_to_pixels(data: Vec<u8>) -> Vec<Pixel> {
-    let mut pixels = Vec::new();
-    let mut iter = data.iter();
-    loop {
-        let mut pixel = Pixel::zero();
-        match iter.next() {
-            Some(red) => pixel.red = *red,
-            None => return pixels,
-        };
-        match iter.next() {
-            Some(green) => pixel.green = *green,
-            None => return pixels,
-        };
-        match iter.next() {
-            Some(blue) => pixel.blue = *blue,
-            None => return pixels,
-        };
-        match iter.next() {
-            Some(alpha) => pixel.alpha = *alpha,
-            None => return pixels,
-        };
-        println!("{:?}", pixel);
-        pixels.push(pixel);
+fn scale_sample(sample: u16, bit_depth: u8) -> u8 {
+    match bit_depth {
+        16 => (sample >> 8) as u8,
+        8 => sample as u8,
+        _ => (sample as u32 * 255 / ((1 << bit_depth) - 1)) as u8,
+    }
+}
+
+fn greyscale_samples_to_pixels(samples: &[u16], bit_depth: u8, trns: Option<&TRNSChunk>) -> Vec<Pixel> {
+    let transparent = trns
+        .filter(|trns| trns.data.len() >= 2)
+        .map(|trns| BigEndian::read_u16(&trns.data[0..2]));
+    samples.iter().map(|sample| {
+        let grey = scale_sample(*sample, bit_depth);
+        let alpha = if transparent == Some(*sample) { 0 } else { 255 };
+        Pixel::from_rgba(grey, grey, grey, alpha)
+    }).collect()
+}
+
+fn greyscale_alpha_samples_to_pixels(samples: &[u16], bit_depth: u8) -> Vec<Pixel> {
+    samples.chunks(2).map(|sample| {
+        let grey = scale_sample(sample[0], bit_depth);
+        Pixel::from_rgba(grey, grey, grey, scale_sample(sample[1], bit_depth))
+    }).collect()
+}
+
+fn truecolor_samples_to_pixels(samples: &[u16], bit_depth: u8, trns: Option<&TRNSChunk>) -> Vec<Pixel> {
+    let transparent = trns
+        .filter(|trns| trns.data.len() >= 6)
+        .map(|trns| [
+            BigEndian::read_u16(&trns.data[0..2]),
+            BigEndian::read_u16(&trns.data[2..4]),
+            BigEndian::read_u16(&trns.data[4..6]),
+        ]);
+    samples.chunks(3).map(|sample| {
+        let alpha = if transparent.map(|colour| colour == sample).unwrap_or(false) { 0 } else { 255 };
+        Pixel::from_rgba(
+            scale_sample(sample[0], bit_depth),
+            scale_sample(sample[1], bit_depth),
+            scale_sample(sample[2], bit_depth),
+            alpha
+        )
+    }).collect()
+}
+
+fn truecolor_alpha_samples_to_pixels(samples: &[u16], bit_depth: u8) -> Vec<Pixel> {
+    samples.chunks(4).map(|sample| Pixel::from_rgba(
+        scale_sample(sample[0], bit_depth),
+        scale_sample(sample[1], bit_depth),
+        scale_sample(sample[2], bit_depth),
+        scale_sample(sample[3], bit_depth),
+    )).collect()
+}
+
+fn indexed_samples_to_pixels(samples: &[u16], plte: Option<&PLTEChunk>, trns: Option<&TRNSChunk>) -> Result<Vec<Pixel>, PNGReaderError> {
+    let plte = plte.ok_or(PNGReaderError::InvalidChunk {
+        description: "PLTE chunk is required for indexed colour images".to_owned()
+    })?;
+    let alphas: &[u8] = trns.map(|trns| &trns.data[..]).unwrap_or(&[]);
+    samples.iter().map(|sample| {
+        let index = *sample as usize;
+        match plte.entries.get(index) {
+            Some(entry) => Result::Ok(Pixel::from_rgba(
+                entry[0],
+                entry[1],
+                entry[2],
+                alphas.get(index).copied().unwrap_or(255)
+            )),
+            None => Result::Err(PNGReaderError::BadImageData {
+                description: format!("Palette index {} is out of range, palette size is {}", index, plte.entries.len())
+            }),
+        }
+    }).collect()
+}
+
+fn convert_to_pixels(ihdr: &IHDRChunk, plte: Option<&PLTEChunk>, trns: Option<&TRNSChunk>, data: &[u8], width: usize, height: usize) -> Result<Vec<Pixel>, PNGReaderError> {
+    let samples = read_samples(ihdr, data, width, height);
+    let bit_depth = ihdr.bit_depth;
+    match ihdr.colour_type {
+        PNGImageType::Greyscale => Result::Ok(greyscale_samples_to_pixels(&samples, bit_depth, trns)),
+        PNGImageType::GreyscaleAlpha => Result::Ok(greyscale_alpha_samples_to_pixels(&samples, bit_depth)),
+        PNGImageType::Truecolour => Result::Ok(truecolor_samples_to_pixels(&samples, bit_depth, trns)),
+        PNGImageType::TruecolourAlpha => Result::Ok(truecolor_alpha_samples_to_pixels(&samples, bit_depth)),
+        PNGImageType::IndexedColour => indexed_samples_to_pixels(&samples, plte, trns),
     }
 }
 
-fn convert_to_pixels(ihdr: &IHDRChunk, data: Vec<u8>) -> Result<Vec<Pixel>, PNGReaderError> {
-    println!("convert_to_pixels data len: {}", data.len());
-    println!("{:?}", &data);
-    let image_type = &ihdr.colour_type;
-    match image_type {
-        PNGImageType::Truecolour => Result::Ok(truecolor_samples_to_pixels(data)),
-        PNGImageType::TruecolourAlpha => Result::Ok(
-            truecolor_alpha_samples_to_pixels(data)
-        ),
-        _ => Result::Err(PNGReaderError::UnsupportedOption {
-            description: format!("Color type {:?} is not supported yet", image_type)
+fn decode_pixels(ihdr: &IHDRChunk, plte: Option<&PLTEChunk>, trns: Option<&TRNSChunk>, data: Vec<u8>) -> Result<Vec<Pixel>, PNGReaderError> {
+    let width = ihdr.width as usize;
+    let height = ihdr.height as usize;
+    match ihdr.interlace_method {
+        0 => {
+            let unfiltered_data = unfilter(ihdr, data)?;
+            convert_to_pixels(ihdr, plte, trns, &unfiltered_data, width, height)
+        },
+        1 => {
+            let mut pixels = vec![Pixel::zero(); width * height];
+            for reduced_image in unfilter_interlaced(ihdr, &data)? {
+                let pass_pixels = convert_to_pixels(ihdr, plte, trns, &reduced_image.data, reduced_image.width, reduced_image.height)?;
+                reduced_image.pass.scatter(&pass_pixels, reduced_image.width, &mut pixels, width);
+            }
+            Result::Ok(pixels)
+        },
+        other => Result::Err(PNGReaderError::UnsupportedOption {
+            description: format!("Interlace method {} is not supported", other)
         }),
     }
 }
 
+// Decodes every frame of an APNG. The default image is only returned when it is the first frame of the animation.
+fn read_animation(image: PNGImage, strict_checksums: bool) -> Result<Vec<Image>, PNGReaderError> {
+    let ihdr = image.ihdr.unwrap();
+    let mut decoded_frames = Vec::with_capacity(image.frames.len());
+    for frame in image.frames {
+        let frame_ihdr = IHDRChunk {
+            width: frame.fctl.width,
+            height: frame.fctl.height,
+            ..ihdr.clone()
+        };
+        let uncompressed_data = inflate_decompress_with_dictionary(&frame.data.data, Option::None, strict_checksums)?;
+        let pixels = decode_pixels(&frame_ihdr, image.plte.as_ref(), image.trns.as_ref(), uncompressed_data)?;
+        decoded_frames.push(DecodedFrame { fctl: frame.fctl, pixels });
+    }
+    compose_frames(ihdr.width as usize, ihdr.height as usize, decoded_frames)
+}
+
 impl ImageReader for PNGReader {
 
     fn read(&self, data: &Vec<u8>) -> Result<Vec<Image>, ImageIOError> {
         validate_signature(data).map_err(|err| ImageIOError::FailedToRead {
             description: format!("File is corrupted or this is not a PNG file: {}", err)
         })?;
-        let image = read_chunks(&data[8..]).map_err(|err| ImageIOError::FailedToRead {
+        let image = read_chunks(&data[8..], self.strict_checksums).map_err(|err| ImageIOError::FailedToRead {
             description: format!("Bad chunks: {}", err)
         })?;
+        let metadata = read_metadata(&image);
+        if image.actl.is_some() && !image.frames.is_empty() {
+            let mut frames = read_animation(image, self.strict_checksums).map_err(|err| ImageIOError::FailedToRead {
+                description: format!("Failed to decode animation: {}", err)
+            })?;
+            for frame in frames.iter_mut() {
+                frame.metadata = metadata.clone();
+            }
+            return Result::Ok(frames);
+        }
+
         let ihdr = &image.ihdr.unwrap();
-        let uncompressed_data = inflate_decompress(&image.idat.data[0..]).unwrap();
-        let unfiltered_data = unfilter(ihdr, uncompressed_data).map_err(|err| ImageIOError::FailedToRead {
-            description: format!("Failed to unfilter data: {}", err)
+        let uncompressed_data = inflate_decompress_with_dictionary(&image.idat.data[0..], Option::None, self.strict_checksums).map_err(|err| ImageIOError::FailedToRead {
+            description: format!("Failed to decompress image data: {}", err)
         })?;
-        let pixels = convert_to_pixels(ihdr, unfiltered_data).map_err(|err| ImageIOError::FailedToRead {
-            description: format!("Failed to construxt pixels: {}", err)
+        let pixels = decode_pixels(ihdr, image.plte.as_ref(), image.trns.as_ref(), uncompressed_data).map_err(|err| ImageIOError::FailedToRead {
+            description: format!("Failed to decode image data: {}", err)
         })?;
-        println!("{:?}", pixels);
-        Result::Ok(vec![Image { width: ihdr.width as usize, height: ihdr.height as usize, pixels }])
+        let mut result = Image::from_pixels(ihdr.width as usize, ihdr.height as usize, pixels);
+        result.metadata = metadata;
+        Result::Ok(vec![result])
     }
 
 }
@@ -281,6 +412,9 @@ mod tests {
     use bit_vec::BitVec;
     use std::io::prelude::*;
     use std::fs::File;
+    use crate::deflate::deflate_compress;
+    use crate::writer::write_chunk;
+    use crate::interlace::ADAM7_PASSES;
 
     #[test]
     fn test() {
@@ -294,6 +428,157 @@ mod tests {
         res_bmp.write_all(&bytes[0..]).unwrap();
     }
 
+    fn encode_png(width: u32, height: u32, bit_depth: u8, colour_type: u8, interlace_method: u8, chunks: &[(&[u8; 4], Vec<u8>)], scanlines: &[Vec<u8>]) -> Vec<u8> {
+        let mut ihdr = vec![0; 13];
+        BigEndian::write_u32(&mut ihdr[0..4], width);
+        BigEndian::write_u32(&mut ihdr[4..8], height);
+        ihdr[8] = bit_depth;
+        ihdr[9] = colour_type;
+        ihdr[12] = interlace_method;
+
+        let mut filtered = Vec::new();
+        for scanline in scanlines {
+            filtered.push(0);
+            filtered.extend_from_slice(scanline);
+        }
+
+        let mut data = vec![137, 80, 78, 71, 13, 10, 26, 10];
+        write_chunk(&mut data, b"IHDR", &ihdr);
+        for (chunk_type, chunk_data) in chunks {
+            write_chunk(&mut data, chunk_type, chunk_data);
+        }
+        write_chunk(&mut data, b"IDAT", &deflate_compress(&filtered, 6));
+        write_chunk(&mut data, b"IEND", &[]);
+        data
+    }
+
+    #[test]
+    fn test_read_shisui() {
+        let shisui_png = read("assets/shisui.png").expect("Failed to load assets/shisui.png");
+        let image = &PNGReader::new().read(&shisui_png).unwrap()[0];
+        assert_eq!((image.width, image.height), (303, 303));
+        assert_eq!(image.get_pixel(150, 150), Pixel::from_rgba(0, 0, 0, 255));
+
+        let samples_sum: u64 = image.pixels.iter()
+            .map(|pixel| pixel.red as u64 + pixel.green as u64 + pixel.blue as u64 + pixel.alpha as u64)
+            .sum();
+        assert_eq!(samples_sum % 1000003, 404772);
+    }
+
+    #[test]
+    fn test_read_strict_and_lenient_checksums() {
+        let mut png = encode_png(1, 1, 8, 0, 0, &[], &[vec![42]]);
+        let iend_crc_position = png.len() - 1;
+        png[iend_crc_position] ^= 0xFF;
+        assert!(PNGReader::new().read(&png).is_err());
+
+        let image = &PNGReader::with_strict_checksums(false).read(&png).unwrap()[0];
+        assert_eq!(image.pixels, vec![Pixel::from_rgba(42, 42, 42, 255)]);
+    }
+
+    #[test]
+    fn test_read_invalid_signature() {
+        assert!(PNGReader::new().read(&vec![137, 80, 78]).is_err());
+        assert!(PNGReader::new().read(&vec![0; 16]).is_err());
+    }
+
+    #[test]
+    fn test_read_greyscale_1_bit() {
+        let png = encode_png(10, 1, 1, 0, 0, &[], &[vec![0b1011_0000, 0b0100_0000]]);
+        let image = &PNGReader::new().read(&png).unwrap()[0];
+        let greys: Vec<u8> = image.pixels.iter().map(|pixel| pixel.red).collect();
+        assert_eq!(greys, vec![255, 0, 255, 255, 0, 0, 0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn test_read_greyscale_2_bit_with_transparency() {
+        let png = encode_png(4, 2, 2, 0, 0, &[(b"tRNS", vec![0, 1])], &[vec![0b00_01_10_11], vec![0b11_10_01_00]]);
+        let image = &PNGReader::new().read(&png).unwrap()[0];
+        assert_eq!(image.pixels[0..4], [
+            Pixel::from_rgba(0, 0, 0, 255),
+            Pixel::from_rgba(85, 85, 85, 0),
+            Pixel::from_rgba(170, 170, 170, 255),
+            Pixel::from_rgba(255, 255, 255, 255),
+        ]);
+        assert_eq!(image.pixels[4], Pixel::from_rgba(255, 255, 255, 255));
+    }
+
+    #[test]
+    fn test_read_greyscale_alpha_16_bit() {
+        let png = encode_png(1, 1, 16, 4, 0, &[], &[vec![0x12, 0x34, 0x80, 0x00]]);
+        let image = &PNGReader::new().read(&png).unwrap()[0];
+        assert_eq!(image.pixels, vec![Pixel::from_rgba(0x12, 0x12, 0x12, 0x80)]);
+    }
+
+    #[test]
+    fn test_read_truecolour_16_bit_with_transparency() {
+        let png = encode_png(2, 1, 16, 2, 0, &[(b"tRNS", vec![0, 1, 0, 2, 0, 3])], &[vec![0, 1, 0, 2, 0, 3, 0xAB, 0xCD, 0x12, 0x34, 0xFF, 0xFF]]);
+        let image = &PNGReader::new().read(&png).unwrap()[0];
+        assert_eq!(image.pixels, vec![Pixel::from_rgba(0, 0, 0, 0), Pixel::from_rgba(0xAB, 0x12, 0xFF, 255)]);
+    }
+
+    #[test]
+    fn test_read_indexed_4_bit() {
+        let palette = vec![255, 0, 0, 0, 255, 0, 0, 0, 255];
+        let png = encode_png(3, 1, 4, 3, 0, &[(b"PLTE", palette), (b"tRNS", vec![100])], &[vec![0x01, 0x20]]);
+        let image = &PNGReader::new().read(&png).unwrap()[0];
+        assert_eq!(image.pixels, vec![
+            Pixel::from_rgba(255, 0, 0, 100),
+            Pixel::from_rgba(0, 255, 0, 255),
+            Pixel::from_rgba(0, 0, 255, 255),
+        ]);
+    }
+
+    #[test]
+    fn test_read_indexed_out_of_palette() {
+        let png = encode_png(1, 1, 8, 3, 0, &[(b"PLTE", vec![1, 2, 3])], &[vec![1]]);
+        assert!(PNGReader::new().read(&png).is_err());
+    }
+
+    #[test]
+    fn test_read_invalid_bit_depth() {
+        let png = encode_png(1, 1, 4, 2, 0, &[], &[vec![0, 0]]);
+        assert!(PNGReader::new().read(&png).is_err());
+    }
+
+    #[test]
+    fn test_read_interlaced() {
+        let (width, height) = (5, 3);
+        let greys: Vec<u8> = (0..(width * height) as u8).map(|i| i * 10).collect();
+        let mut scanlines = Vec::new();
+        for pass in ADAM7_PASSES.iter() {
+            let (pass_width, pass_height) = pass.reduced_size(width, height);
+            if pass_width == 0 {
+                continue;
+            }
+            for y in 0..pass_height {
+                scanlines.push((0..pass_width)
+                    .map(|x| greys[(pass.y_start + y * pass.y_step) * width + pass.x_start + x * pass.x_step])
+                    .collect());
+            }
+        }
+
+        let png = encode_png(width as u32, height as u32, 8, 0, 1, &[], &scanlines);
+        let image = &PNGReader::new().read(&png).unwrap()[0];
+        let read_greys: Vec<u8> = image.pixels.iter().map(|pixel| pixel.red).collect();
+        assert_eq!(read_greys, greys);
+    }
+
+    #[test]
+    fn test_read_interlaced_1_bit() {
+        // 3x2 image, only passes 1, 4 (x = 2) and 6 (x = 1) and 7 (y = 1) have pixels
+        let png = encode_png(3, 2, 1, 0, 1, &[], &[vec![0b1000_0000], vec![0b0000_0000], vec![0b1000_0000], vec![0b1010_0000]]);
+        let image = &PNGReader::new().read(&png).unwrap()[0];
+        let greys: Vec<u8> = image.pixels.iter().map(|pixel| pixel.red).collect();
+        assert_eq!(greys, vec![255, 255, 0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn test_read_interlaced_truncated() {
+        let png = encode_png(5, 3, 8, 0, 1, &[], &[vec![1, 2]]);
+        assert!(PNGReader::new().read(&png).is_err());
+    }
+
     #[test]
     fn bit_vec_test() {
         let mut vec = BitVec::new();
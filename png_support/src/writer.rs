@@ -1,24 +1,355 @@
 use core::models::io::{ImageWriter, ImageIOError, ImageWriterOptions};
 use core::models::image::Image;
+use byteorder::{ByteOrder, BigEndian};
+use crate::checksum::crc32;
+use crate::deflate::{deflate_compress, DEFAULT_COMPRESSION_LEVEL, MAX_COMPRESSION_LEVEL};
+use crate::filter::filter;
+use crate::reader::PNGImageType;
+use crate::chunk::{FCTLChunk, DisposeOp, BlendOp};
+use crate::apng::{changed_region, crop};
+use crate::metadata::write_metadata_chunks;
+
+// see:
+// https://www.w3.org/TR/PNG/#5DataRep
+// https://www.w3.org/TR/PNG/#11Critical-chunks
+
+pub const OPTION_COMPRESSION_LEVEL: &str = "compression_level";
+pub const OPTION_ADAPTIVE_FILTERING: &str = "adaptive_filtering";
+pub const OPTION_LOOP_COUNT: &str = "loop_count";
+pub const OPTION_FRAME_DELAY: &str = "frame_delay";
+
+const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+const IDAT_CHUNK_SIZE: usize = 8192;
+const BIT_DEPTH: u8 = 8;
+// milliseconds, used for animation frames which do not have their own delay
+const DEFAULT_FRAME_DELAY: u32 = 100;
+
 pub struct PNGWriter {
 }
 
 impl PNGWriter {
-    const fn new() -> Self {
+    pub fn new() -> Self {
         PNGWriter {}
     }
 }
 
 impl ImageWriter for PNGWriter {
-    
-    fn write(&self, _image: &Image, _options: &ImageWriterOptions) -> Result<Vec<u8>, ImageIOError> {
-        Result::Ok(Vec::new())
+
+    fn write(&self, image: &Image, options: &ImageWriterOptions) -> Result<Vec<u8>, ImageIOError> {
+        let (compression_level, adaptive_filtering) = compression_options(options)?;
+        let colour_type = choose_colour_type(std::slice::from_ref(image));
+        let compressed = encode_image_data(image, colour_type, compression_level, adaptive_filtering);
+
+        let mut data = PNG_SIGNATURE.to_vec();
+        write_chunk(&mut data, b"IHDR", &ihdr_data(image, colour_type));
+        write_metadata_chunks(&mut data, &image.metadata, colour_type, BIT_DEPTH);
+        for idat in compressed.chunks(IDAT_CHUNK_SIZE) {
+            write_chunk(&mut data, b"IDAT", idat);
+        }
+        write_chunk(&mut data, b"IEND", &[]);
+
+        Result::Ok(data)
+    }
+
+    // Writes an APNG. Every frame after the first one only stores the region which changed since the previous frame.
+    fn write_animation(&self, frames: &[Image], options: &ImageWriterOptions) -> Result<Vec<u8>, ImageIOError> {
+        if frames.len() < 2 {
+            return match frames.first() {
+                Some(frame) => self.write(frame, options),
+                None => Result::Err(ImageIOError::FailedToWrite {
+                    description: "animation has no frames".to_owned()
+                }),
+            };
+        }
+        let first_frame = &frames[0];
+        if frames.iter().any(|frame| frame.width != first_frame.width || frame.height != first_frame.height) {
+            return Result::Err(ImageIOError::FailedToWrite {
+                description: "all animation frames should have the same size".to_owned()
+            });
+        }
+
+        let (compression_level, adaptive_filtering) = compression_options(options)?;
+        let loop_count = options.get_u32(OPTION_LOOP_COUNT, 0)?;
+        let default_frame_delay = options.get_u32(OPTION_FRAME_DELAY, DEFAULT_FRAME_DELAY)?;
+        let colour_type = choose_colour_type(frames);
+
+        let mut data = PNG_SIGNATURE.to_vec();
+        write_chunk(&mut data, b"IHDR", &ihdr_data(first_frame, colour_type));
+        write_metadata_chunks(&mut data, &first_frame.metadata, colour_type, BIT_DEPTH);
+
+        let mut actl = vec![0; 8];
+        BigEndian::write_u32(&mut actl[0..4], frames.len() as u32);
+        BigEndian::write_u32(&mut actl[4..8], loop_count);
+        write_chunk(&mut data, b"acTL", &actl);
+
+        let mut sequence_number = 0;
+        for (index, frame) in frames.iter().enumerate() {
+            let (x_offset, y_offset, width, height) = if index == 0 {
+                (0, 0, frame.width, frame.height)
+            } else {
+                changed_region(&frames[index - 1], frame)
+            };
+            let delay = frame.animation.as_ref()
+                .map(|animation| animation.delay.as_millis() as u32)
+                .unwrap_or(default_frame_delay);
+
+            let fctl = FCTLChunk {
+                sequence_number,
+                width: width as u32,
+                height: height as u32,
+                x_offset: x_offset as u32,
+                y_offset: y_offset as u32,
+                delay_num: std::cmp::min(delay, u16::MAX as u32) as u16,
+                delay_den: 1000,
+                dispose_op: DisposeOp::None,
+                blend_op: BlendOp::Source,
+            };
+            write_chunk(&mut data, b"fcTL", &fctl.to_bytes());
+            sequence_number += 1;
+
+            let region = crop(frame, x_offset, y_offset, width, height);
+            let compressed = encode_image_data(&region, colour_type, compression_level, adaptive_filtering);
+            for part in compressed.chunks(IDAT_CHUNK_SIZE) {
+                if index == 0 {
+                    write_chunk(&mut data, b"IDAT", part);
+                } else {
+                    let mut fdat = vec![0; 4];
+                    BigEndian::write_u32(&mut fdat[0..4], sequence_number);
+                    fdat.extend_from_slice(part);
+                    write_chunk(&mut data, b"fdAT", &fdat);
+                    sequence_number += 1;
+                }
+            }
+        }
+        write_chunk(&mut data, b"IEND", &[]);
+
+        Result::Ok(data)
+    }
+
+    fn supports_animation(&self) -> bool {
+        true
+    }
+}
+
+fn compression_options(options: &ImageWriterOptions) -> Result<(u32, bool), ImageIOError> {
+    let compression_level = options.get_u32(OPTION_COMPRESSION_LEVEL, DEFAULT_COMPRESSION_LEVEL)?;
+    if compression_level > MAX_COMPRESSION_LEVEL {
+        return Result::Err(ImageIOError::InvalidOptions {
+            description: format!("compression level should be in range 0..={}, got {}", MAX_COMPRESSION_LEVEL, compression_level)
+        });
+    }
+    let adaptive_filtering = options.get_bool(OPTION_ADAPTIVE_FILTERING, true)?;
+    Result::Ok((compression_level, adaptive_filtering))
+}
+
+fn choose_colour_type(images: &[Image]) -> PNGImageType {
+    if images.iter().any(|image| image.pixels.iter().any(|pixel| pixel.alpha != 255)) {
+        PNGImageType::TruecolourAlpha
+    } else {
+        PNGImageType::Truecolour
+    }
+}
+
+fn encode_image_data(image: &Image, colour_type: PNGImageType, compression_level: u32, adaptive_filtering: bool) -> Vec<u8> {
+    let samples_amount = colour_type.get_samples_amount();
+    let samples = image_to_samples(image, colour_type);
+    let filtered = filter(&samples, image.width * samples_amount, samples_amount, adaptive_filtering);
+    deflate_compress(&filtered, compression_level)
+}
+
+fn image_to_samples(image: &Image, colour_type: PNGImageType) -> Vec<u8> {
+    let with_alpha = colour_type == PNGImageType::TruecolourAlpha;
+    let mut samples = Vec::with_capacity(image.pixels.len() * colour_type.get_samples_amount());
+    for pixel in &image.pixels {
+        samples.push(pixel.red);
+        samples.push(pixel.green);
+        samples.push(pixel.blue);
+        if with_alpha {
+            samples.push(pixel.alpha);
+        }
     }
+    samples
+}
+
+fn ihdr_data(image: &Image, colour_type: PNGImageType) -> Vec<u8> {
+    let mut data = vec![0; 13];
+    BigEndian::write_u32(&mut data[0..4], image.width as u32);
+    BigEndian::write_u32(&mut data[4..8], image.height as u32);
+    data[8] = BIT_DEPTH;
+    data[9] = colour_type.to_number();
+    // compression method, filter method and interlace method are all 0
+    data
+}
+
+pub(crate) fn write_chunk(output: &mut Vec<u8>, chunk_type: &[u8; 4], data: &[u8]) {
+    let mut length = [0; 4];
+    BigEndian::write_u32(&mut length, data.len() as u32);
+    output.extend_from_slice(&length);
+
+    let crc_start = output.len();
+    output.extend_from_slice(chunk_type);
+    output.extend_from_slice(data);
+
+    let mut crc = [0; 4];
+    BigEndian::write_u32(&mut crc, crc32(&output[crc_start..]));
+    output.extend_from_slice(&crc);
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use core::models::io::ImageReader;
+    use crate::reader::PNGReader;
+    use core::models::{animation::AnimationFrame, pixel::Pixel};
+    use core::models::metadata::{ImageMetadata, TextEntry, PhysicalDimensions, PhysicalUnit, ModificationTime, IccProfile, Exif};
+    use std::time::Duration;
+
+    #[test]
+    fn test_write_chunk() {
+        let mut data = Vec::new();
+        write_chunk(&mut data, b"IEND", &[]);
+        assert_eq!(data, vec![0, 0, 0, 0, 73, 69, 78, 68, 0xAE, 0x42, 0x60, 0x82]);
+    }
+
+    #[test]
+    fn test_write_read() {
+        let image = Image::test_image();
+        let written = PNGWriter::new().write(&image, &ImageWriterOptions::default()).unwrap();
+        assert_eq!(&written[0..8], &PNG_SIGNATURE);
+        assert_eq!(written[25], PNGImageType::Truecolour.to_number());
+
+        let read = PNGReader::new().read(&written).unwrap();
+        assert_eq!(read[0].width, image.width);
+        assert_eq!(read[0].height, image.height);
+        assert_eq!(read[0].pixels, image.pixels);
+    }
+
+    #[test]
+    fn test_write_read_with_alpha() {
+        let image = Image::test_image_with_alpha();
+        let written = PNGWriter::new().write(&image, &ImageWriterOptions::default()).unwrap();
+        assert_eq!(written[25], PNGImageType::TruecolourAlpha.to_number());
+
+        let read = PNGReader::new().read(&written).unwrap();
+        assert_eq!(read[0].pixels, image.pixels);
+    }
+
+    #[test]
+    fn test_write_without_adaptive_filtering() {
+        let image = Image::test_image();
+        let options = ImageWriterOptions::default()
+            .with_option_bool(OPTION_ADAPTIVE_FILTERING, false)
+            .with_option_u32(OPTION_COMPRESSION_LEVEL, 9);
+        let written = PNGWriter::new().write(&image, &options).unwrap();
+
+        let read = PNGReader::new().read(&written).unwrap();
+        assert_eq!(read[0].pixels, image.pixels);
+    }
+
+    #[test]
+    fn test_write_read_animation() {
+        let first = Image::test_image();
+        let mut second = first.clone();
+        second.set_pixel(3, 3, Pixel::from_rgba(10, 20, 30, 40));
+        second.animation = Some(AnimationFrame::with_delay(Duration::from_millis(250)));
+        let third = second.clone();
+
+        let options = ImageWriterOptions::default().with_option_u32(OPTION_LOOP_COUNT, 3);
+        let written = PNGWriter::new().write_animation(&[first.clone(), second.clone(), third.clone()], &options).unwrap();
+
+        let read = PNGReader::new().read(&written).unwrap();
+        assert_eq!(read.len(), 3);
+        assert_eq!(read[0].pixels, first.pixels);
+        assert_eq!(read[1].pixels, second.pixels);
+        assert_eq!(read[2].pixels, third.pixels);
+        assert_eq!(read[0].animation, Some(AnimationFrame::with_delay(Duration::from_millis(100))));
+        assert_eq!(read[1].animation, Some(AnimationFrame::with_delay(Duration::from_millis(250))));
+    }
+
+    #[test]
+    fn test_write_animation_with_single_frame() {
+        let image = Image::test_image();
+        let options = ImageWriterOptions::default();
+        assert_eq!(
+            PNGWriter::new().write_animation(&[image.clone()], &options).unwrap(),
+            PNGWriter::new().write(&image, &options).unwrap()
+        );
+        assert!(PNGWriter::new().write_animation(&[], &options).is_err());
+    }
+
+    #[test]
+    fn test_write_animation_with_different_sizes() {
+        let frames = [Image::test_image(), Image::new(2, 2)];
+        assert!(PNGWriter::new().write_animation(&frames, &ImageWriterOptions::default()).is_err());
+    }
+
     #[test]
-    fn simple_test() {
+    fn test_write_read_metadata() {
+        let mut image = Image::test_image();
+        image.metadata = ImageMetadata {
+            text: vec![
+                TextEntry::new("Title", "Turbocow"),
+                TextEntry { compressed: true, ..TextEntry::new("Description", &"moo ".repeat(50)) },
+                TextEntry {
+                    keyword: "Author".to_string(),
+                    text: "Корова".to_string(),
+                    compressed: true,
+                    language_tag: Some("uk".to_string()),
+                    translated_keyword: Some("Автор".to_string()),
+                },
+            ],
+            physical_dimensions: Some(PhysicalDimensions {
+                pixels_per_unit_x: 11811,
+                pixels_per_unit_y: 11811,
+                unit: PhysicalUnit::Meter,
+            }),
+            modification_time: Some(ModificationTime { year: 2021, month: 4, day: 20, hour: 13, minute: 37, second: 0 }),
+            significant_bits: Some(vec![5, 6, 5]),
+            icc_profile: Some(IccProfile { name: "test profile".to_string(), profile: vec![1, 2, 3, 4, 5] }),
+            exif: None,
+        };
+
+        let written = PNGWriter::new().write(&image, &ImageWriterOptions::default()).unwrap();
+        let read = PNGReader::new().read(&written).unwrap();
+        assert_eq!(read[0].pixels, image.pixels);
+        assert_eq!(read[0].metadata, image.metadata);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_write_exif() {
+        let exif_data = vec![0x4D, 0x4D, 0x00, 0x2A, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        let mut image = Image::test_image();
+        image.metadata.exif = Some(Exif { entries: Vec::new(), data: exif_data.clone() });
+
+        let written = PNGWriter::new().write(&image, &ImageWriterOptions::default()).unwrap();
+        let chunk_type = written.windows(4).position(|window| window == b"eXIf").expect("expected eXIf chunk to be written");
+        assert_eq!(BigEndian::read_u32(&written[(chunk_type - 4)..chunk_type]) as usize, exif_data.len());
+        assert_eq!(&written[(chunk_type + 4)..(chunk_type + 4 + exif_data.len())], &exif_data[..]);
+
+        // PNG to PNG conversion keeps it
+        let read = PNGReader::new().read(&written).unwrap();
+        assert_eq!(read[0].metadata.exif, image.metadata.exif);
+        let rewritten = PNGWriter::new().write(&read[0], &ImageWriterOptions::default()).unwrap();
+        assert!(rewritten.windows(exif_data.len()).any(|window| window == &exif_data[..]));
+    }
+
+    #[test]
+    fn test_read_write_asset_metadata() {
+        let simple3 = std::fs::read("assets/simple3.png").expect("Failed to load assets/simple3.png");
+        let image = &PNGReader::new().read(&simple3).unwrap()[0];
+        assert!(image.metadata.physical_dimensions.is_some());
+        assert!(image.metadata.modification_time.is_some());
+        assert!(image.metadata.icc_profile.is_some());
+        assert!(!image.metadata.text.is_empty());
+
+        let written = PNGWriter::new().write(image, &ImageWriterOptions::default()).unwrap();
+        let read = PNGReader::new().read(&written).unwrap();
+        assert_eq!(read[0].metadata, image.metadata);
+    }
+
+    #[test]
+    fn test_invalid_compression_level() {
+        let options = ImageWriterOptions::default().with_option_u32(OPTION_COMPRESSION_LEVEL, 10);
+        assert!(PNGWriter::new().write(&Image::test_image(), &options).is_err());
+    }
+}
@@ -152,7 +152,7 @@ impl ImageReader for PPMReader {
             })?.read_raster(header, data).map_err(|err| ImageIOError::FailedToRead {
                 description: format!("Can not read pixels data: {}", err)
             })?;
-        Result::Ok(vec![Image { width, height, pixels }])
+        Result::Ok(vec![Image::from_pixels(width, height, pixels)])
     }
 
 }
@@ -59,11 +59,7 @@ mod tests {
             Pixel::from_rgb(248, 40, 129),
             Pixel::from_rgb(176, 45, 2),
         ];
-        let image = Image {
-            width: 3,
-            height: 3,
-            pixels
-        };
+        let image = Image::from_pixels(3, 3, pixels);
         let writer = PPMWriter::new();
         let data = writer.write(&image, &ImageWriterOptions::default())
             .expect("Failed to write test image");
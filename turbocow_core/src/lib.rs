@@ -2,4 +2,5 @@
 
 pub mod models;
 pub mod plugins;
+pub mod quantization;
 pub mod utils;
\ No newline at end of file
@@ -0,0 +1,15 @@
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnimationFrame {
+    pub delay: Duration,
+}
+
+impl AnimationFrame {
+
+    pub fn with_delay(delay: Duration) -> Self {
+        AnimationFrame {
+            delay,
+        }
+    }
+}
@@ -1,19 +1,27 @@
-use super::pixel::Pixel;
+use super::{animation::AnimationFrame, metadata::ImageMetadata, pixel::Pixel};
 
 #[derive(Clone)]
 pub struct Image {
     pub width: usize,
     pub height: usize,
     pub pixels: Vec<Pixel>, // starting at top left pixel of the image, pos = y * width + x
+    pub animation: Option<AnimationFrame>, // set for frames of animated images
+    pub metadata: ImageMetadata,
 }
 
 impl Image {
 
     pub fn new(width: usize, height: usize) -> Self {
+        Self::from_pixels(width, height, vec![Pixel::zero(); width as usize * height as usize])
+    }
+
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<Pixel>) -> Self {
         Image {
             width,
             height,
-            pixels: vec![Pixel::zero(); width as usize * height as usize],
+            pixels,
+            animation: None,
+            metadata: ImageMetadata::default(),
         }
     }
 
@@ -47,6 +55,19 @@ impl Image {
         image
     }
 
+    // red changes along x and green along y, so that there are plenty of distinct colors
+    pub fn test_gradient(width: usize, height: usize) -> Self {
+        let mut image = Self::new(width, height);
+
+        for y in 0..height {
+            for x in 0..width {
+                image.set_pixel(x, y, Pixel::from_rgb((x * 256 / width) as u8, (y * 256 / height) as u8, 100));
+            }
+        }
+
+        image
+    }
+
     pub fn set_pixel(&mut self, x: usize, y: usize, pixel: Pixel) {
         self.pixels[y * self.width + x] = pixel;
     }
@@ -18,6 +18,72 @@ pub trait ImageReader {
 pub trait ImageWriter {
 
     fn write(&self, image: &Image, options: &ImageWriterOptions) -> Result<Vec<u8>, ImageIOError>;
+
+    // formats without animation support write the first frame only
+    fn write_animation(&self, frames: &[Image], options: &ImageWriterOptions) -> Result<Vec<u8>, ImageIOError> {
+        match frames.first() {
+            Some(frame) => self.write(frame, options),
+            None => Err(ImageIOError::FailedToWrite {
+                description: "animation has no frames".to_string(),
+            }),
+        }
+    }
+
+    fn supports_animation(&self) -> bool {
+        false
+    }
+}
+
+// Changes which some formats can make to encoded data directly, without losing quality on re-encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LosslessTransform {
+    // clockwise
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    FlipHorizontal,
+    FlipVertical,
+    Crop { x: usize, y: usize, width: usize, height: usize },
+}
+
+impl LosslessTransform {
+
+    // crop is specified as jpegtran does: WxH+X+Y
+    pub fn from_option(value: &str) -> Result<Self, ImageIOError> {
+        let value = value.trim().to_lowercase();
+
+        match value.as_str() {
+            "rotate90" | "rotate-90" => return Ok(LosslessTransform::Rotate90),
+            "rotate180" | "rotate-180" => return Ok(LosslessTransform::Rotate180),
+            "rotate270" | "rotate-270" => return Ok(LosslessTransform::Rotate270),
+            "flip-horizontal" => return Ok(LosslessTransform::FlipHorizontal),
+            "flip-vertical" => return Ok(LosslessTransform::FlipVertical),
+            _ => {},
+        }
+
+        let invalid = || ImageIOError::InvalidOptions {
+            description: format!("Lossless transform is not supported: {}", value),
+        };
+
+        let geometry = value.strip_prefix("crop:").ok_or_else(invalid)?;
+        let parts: Vec<&str> = geometry.split(|c| c == 'x' || c == '+').collect();
+        if parts.len() != 2 && parts.len() != 4 {
+            return Err(invalid());
+        }
+
+        let parse = |v: &str| v.parse::<usize>().map_err(|_| invalid());
+        Ok(LosslessTransform::Crop {
+            x: if parts.len() == 4 { parse(parts[2])? } else { 0 },
+            y: if parts.len() == 4 { parse(parts[3])? } else { 0 },
+            width: parse(parts[0])?,
+            height: parse(parts[1])?,
+        })
+    }
+}
+
+pub trait ImageTransformer {
+
+    fn transform(&self, data: &Vec<u8>, transform: &LosslessTransform) -> Result<Vec<u8>, ImageIOError>;
 }
 
 pub struct ImageWriterOptions {
@@ -77,4 +143,8 @@ impl ImageWriterOptions {
             description: format!("failed to parse option as u32: {}", err),
         })
     }
+
+    pub fn get_string(&self, key: &str, default: &str) -> String {
+        self.options.get(key).map(|v| v.clone()).unwrap_or(default.to_string())
+    }
 }
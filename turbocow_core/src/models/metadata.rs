@@ -0,0 +1,131 @@
+// format-independent image metadata, readers fill in what the file contains and writers store what the format supports
+
+#[derive(Clone, Debug, PartialEq, Default)]
+pub struct ImageMetadata {
+    pub text: Vec<TextEntry>,
+    pub physical_dimensions: Option<PhysicalDimensions>,
+    pub modification_time: Option<ModificationTime>,
+    pub significant_bits: Option<Vec<u8>>, // per sample, in the order of samples in the source image
+    pub icc_profile: Option<IccProfile>,
+    pub exif: Option<Exif>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextEntry {
+    pub keyword: String,
+    pub text: String,
+    pub compressed: bool,
+    pub language_tag: Option<String>, // only international (UTF-8) text has language tag and translated keyword
+    pub translated_keyword: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PhysicalDimensions {
+    pub pixels_per_unit_x: u32,
+    pub pixels_per_unit_y: u32,
+    pub unit: PhysicalUnit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PhysicalUnit {
+    Unknown, // only aspect ratio is known
+    Meter,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModificationTime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct IccProfile {
+    pub name: String,
+    pub profile: Vec<u8>,
+}
+
+pub const EXIF_TAG_ORIENTATION: u16 = 0x0112;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Exif {
+    pub entries: Vec<ExifEntry>,
+    pub data: Vec<u8>, // TIFF structure the entries were read from, which writers store as is
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExifEntry {
+    pub ifd: ExifIfd,
+    pub tag: u16,
+    pub value: ExifValue,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExifIfd {
+    Primary, // tags describing the main image (IFD0)
+    Exif,
+    Gps,
+    Interoperability,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExifValue {
+    Byte(Vec<u8>),
+    Ascii(String),
+    Short(Vec<u16>),
+    Long(Vec<u32>),
+    Rational(Vec<(u32, u32)>), // numerator, denominator
+    SignedByte(Vec<i8>),
+    Undefined(Vec<u8>),
+    SignedShort(Vec<i16>),
+    SignedLong(Vec<i32>),
+    SignedRational(Vec<(i32, i32)>),
+    Float(Vec<f32>),
+    Double(Vec<f64>),
+}
+
+impl Exif {
+
+    pub fn get(&self, ifd: ExifIfd, tag: u16) -> Option<&ExifValue> {
+        self.entries.iter()
+            .find(|entry| entry.ifd == ifd && entry.tag == tag)
+            .map(|entry| &entry.value)
+    }
+
+    // 1 is the normal orientation, 2-8 are combinations of rotation and mirroring
+    pub fn orientation(&self) -> Option<u16> {
+        match self.get(ExifIfd::Primary, EXIF_TAG_ORIENTATION) {
+            Some(ExifValue::Short(values)) => values.first().cloned(),
+            _ => None,
+        }
+    }
+}
+
+impl TextEntry {
+
+    pub fn new(keyword: &str, text: &str) -> Self {
+        TextEntry {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+            compressed: false,
+            language_tag: None,
+            translated_keyword: None,
+        }
+    }
+}
+
+impl PhysicalDimensions {
+
+    pub fn dots_per_inch(&self) -> Option<(f64, f64)> {
+        match self.unit {
+            PhysicalUnit::Meter => Some((
+                self.pixels_per_unit_x as f64 * 0.0254,
+                self.pixels_per_unit_y as f64 * 0.0254,
+            )),
+            PhysicalUnit::Unknown => None,
+        }
+    }
+}
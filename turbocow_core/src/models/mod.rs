@@ -1,3 +1,5 @@
+pub mod animation;
 pub mod image;
 pub mod io;
+pub mod metadata;
 pub mod pixel;
\ No newline at end of file
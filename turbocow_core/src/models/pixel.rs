@@ -46,6 +46,30 @@ impl Pixel {
         )
     }
 
+    // "over" operator for a possibly transparent background
+    pub fn blend_over(&self, background: &Pixel) -> Self {
+        if self.alpha == 255 || background.alpha == 0 {
+            return *self;
+        }
+        if self.alpha == 0 {
+            return *background;
+        }
+
+        let foreground_alpha = self.alpha as u32;
+        let background_alpha = background.alpha as u32 * (255 - foreground_alpha) / 255;
+        let alpha = foreground_alpha + background_alpha;
+        let blend = |foreground: u8, background: u8| {
+            ((foreground as u32 * foreground_alpha + background as u32 * background_alpha + alpha / 2) / alpha) as u8
+        };
+
+        Self::from_rgba(
+            blend(self.red, background.red),
+            blend(self.green, background.green),
+            blend(self.blue, background.blue),
+            alpha as u8,
+        )
+    }
+
     pub fn with_alpha_channel(&self, alpha: u8) -> Self {
         Self::from_rgba(self.red, self.green, self.blue, alpha)
     }
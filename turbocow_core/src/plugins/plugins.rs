@@ -5,7 +5,7 @@ use custom_error::custom_error;
 use libloading::{Library, Symbol};
 use log::*;
 
-use crate::models::io::{ImageReader, ImageWriter};
+use crate::models::io::{ImageReader, ImageWriter, ImageTransformer};
 
 custom_error! {pub PluginManagerError
     FailedToLoadLibrary{description: String} = "Failed to load library: {description}",
@@ -19,6 +19,11 @@ pub trait ImageFormatSupportPlugin {
 
     fn reader(&self) -> Box<dyn ImageReader>;
     fn writer(&self) -> Box<dyn ImageWriter>;
+
+    // only formats which support lossless transforms provide a transformer
+    fn transformer(&self) -> Option<Box<dyn ImageTransformer>> {
+        None
+    }
 }
 
 pub type PluginInit = unsafe fn () -> Box<dyn ImageFormatSupportPlugin>;
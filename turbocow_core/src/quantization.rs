@@ -0,0 +1,415 @@
+use std::collections::HashMap;
+
+use crate::models::{io::ImageIOError, pixel::Pixel};
+
+// see:
+// https://en.wikipedia.org/wiki/Median_cut
+// https://en.wikipedia.org/wiki/Octree#Color_quantization
+// https://www.kaggle.com/andyxie/k-means-clustering-implementation-in-python
+// https://en.wikipedia.org/wiki/Color_difference
+// https://gist.github.com/ryancat/9972419b2a78f329ce3aebb7f1a09152
+
+// quantizer used by writers when none is set in options
+pub const DEFAULT_QUANTIZER: &str = "median-cut";
+
+const KMEANS_MIN_ITERATIONS: usize = 1;
+const KMEANS_MAX_ITERATIONS: usize = 20;
+// octree is split by one bit of every channel on each level
+const OCTREE_DEPTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Quantizer {
+    MedianCut,
+    Octree,
+    KMeans,
+}
+
+impl Quantizer {
+
+    pub fn from_option(value: &str) -> Result<Self, ImageIOError> {
+        match value.trim().to_lowercase().as_str() {
+            "median-cut" | "median_cut" => Ok(Quantizer::MedianCut),
+            "octree" => Ok(Quantizer::Octree),
+            "kmeans" | "k-means" => Ok(Quantizer::KMeans),
+            other => Err(ImageIOError::InvalidOptions {
+                description: format!("Unknown quantizer: {}, expected one of: median-cut, octree, kmeans", other),
+            }),
+        }
+    }
+}
+
+// Selects at most max_colors colors to represent pixels with. Result only depends on pixels.
+pub fn quantize(pixels: &[Pixel], max_colors: usize, quantizer: Quantizer) -> Vec<Pixel> {
+    let mut counts = HashMap::new();
+    count_colors(&mut counts, pixels);
+    quantize_counts(&counts, max_colors, quantizer)
+}
+
+// Same as quantize, for colors counted with count_colors, so that pixels of several images can share a palette.
+pub fn quantize_counts(counts: &HashMap<(u8, u8, u8), u32>, max_colors: usize, quantizer: Quantizer) -> Vec<Pixel> {
+    let histogram = sorted_histogram(counts);
+    match quantizer {
+        Quantizer::MedianCut => median_cut_histogram(histogram, max_colors),
+        Quantizer::Octree => octree_histogram(histogram, max_colors),
+        Quantizer::KMeans => cluster_histogram(histogram, max_colors, 0, KMEANS_MIN_ITERATIONS, KMEANS_MAX_ITERATIONS),
+    }
+}
+
+// Adds number of times every color is used to counts.
+pub fn count_colors<'a>(counts: &mut HashMap<(u8, u8, u8), u32>, pixels: impl IntoIterator<Item = &'a Pixel>) {
+    for pixel in pixels {
+        let count = counts.entry((pixel.red, pixel.green, pixel.blue)).or_insert(0);
+        *count = count.saturating_add(1);
+    }
+}
+
+// Unique colors with number of times they are used, sorted by color.
+pub fn color_histogram(pixels: &[Pixel]) -> Vec<((u8, u8, u8), u32)> {
+    let mut counts = HashMap::new();
+    count_colors(&mut counts, pixels);
+    sorted_histogram(&counts)
+}
+
+fn sorted_histogram(counts: &HashMap<(u8, u8, u8), u32>) -> Vec<((u8, u8, u8), u32)> {
+    let mut histogram: Vec<((u8, u8, u8), u32)> = counts.iter().map(|(color, count)| (*color, *count)).collect();
+    histogram.sort();
+    histogram
+}
+
+fn histogram_colors(histogram: &[((u8, u8, u8), u32)]) -> Vec<Pixel> {
+    histogram.iter().map(|(color, _)| Pixel::from_rgb(color.0, color.1, color.2)).collect()
+}
+
+// Box with the widest range of values in some channel is split in two at its median, until there are enough boxes.
+pub fn median_cut(pixels: &[Pixel], max_colors: usize) -> Vec<Pixel> {
+    median_cut_histogram(color_histogram(pixels), max_colors)
+}
+
+fn median_cut_histogram(histogram: Vec<((u8, u8, u8), u32)>, max_colors: usize) -> Vec<Pixel> {
+    if histogram.len() <= max_colors {
+        return histogram_colors(&histogram);
+    }
+
+    let mut boxes = vec![histogram];
+
+    while boxes.len() < max_colors {
+        let widest = boxes.iter()
+            .enumerate()
+            .filter(|(_, colors)| colors.len() > 1)
+            .map(|(index, colors)| (index, widest_channel(colors)))
+            .max_by_key(|(index, (_, range))| (*range, std::cmp::Reverse(*index)));
+
+        let (index, (channel, _)) = match widest {
+            Some(v) => v,
+            None => break,
+        };
+
+        let mut colors = boxes.remove(index);
+        colors.sort_by_key(|(color, _)| (channel_value(color, channel), *color));
+
+        let total: u64 = colors.iter().map(|(_, count)| *count as u64).sum();
+        let mut split_at = 0;
+        let mut accumulated = 0;
+        while split_at < colors.len() - 1 && accumulated * 2 < total {
+            accumulated += colors[split_at].1 as u64;
+            split_at += 1;
+        }
+
+        let second_half = colors.split_off(split_at.max(1));
+        boxes.insert(index, second_half);
+        boxes.insert(index, colors);
+    }
+
+    boxes.iter()
+        .map(|colors| average_color(colors.iter().map(|(color, count)| (*color, *count as u64))))
+        .collect()
+}
+
+fn widest_channel(colors: &[((u8, u8, u8), u32)]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = colors.iter().map(|(color, _)| channel_value(color, channel));
+            let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+            (channel, range)
+        })
+        .max_by_key(|(channel, range)| (*range, std::cmp::Reverse(*channel)))
+        .unwrap_or((0, 0))
+}
+
+fn channel_value(color: &(u8, u8, u8), channel: usize) -> u8 {
+    match channel {
+        0 => color.0,
+        1 => color.1,
+        _ => color.2,
+    }
+}
+
+fn average_color(colors: impl Iterator<Item = ((u8, u8, u8), u64)>) -> Pixel {
+    let (red, green, blue, total) = colors.fold((0u64, 0u64, 0u64, 0u64), |sum, (color, count)| (
+        sum.0 + color.0 as u64 * count,
+        sum.1 + color.1 as u64 * count,
+        sum.2 + color.2 as u64 * count,
+        sum.3 + count,
+    ));
+
+    if total == 0 {
+        return Pixel::black();
+    }
+
+    Pixel::from_rgb(
+        ((red + total / 2) / total) as u8,
+        ((green + total / 2) / total) as u8,
+        ((blue + total / 2) / total) as u8
+    )
+}
+
+struct OctreeNode {
+    children: [Option<usize>; 8],
+    // sums of colors of all pixels below this node
+    red: u64,
+    green: u64,
+    blue: u64,
+    count: u64,
+    is_leaf: bool,
+}
+
+impl OctreeNode {
+
+    fn new(is_leaf: bool) -> Self {
+        OctreeNode {
+            children: [None; 8],
+            red: 0,
+            green: 0,
+            blue: 0,
+            count: 0,
+            is_leaf,
+        }
+    }
+}
+
+// Every color is a path from the root, picking a child by one bit of each channel on every level. Nodes on the
+// deepest level are merged into their parents (least used first) until there are only max_colors leaves left.
+pub fn octree(pixels: &[Pixel], max_colors: usize) -> Vec<Pixel> {
+    octree_histogram(color_histogram(pixels), max_colors)
+}
+
+fn octree_histogram(histogram: Vec<((u8, u8, u8), u32)>, max_colors: usize) -> Vec<Pixel> {
+    if histogram.len() <= max_colors {
+        return histogram_colors(&histogram);
+    }
+
+    let mut nodes = vec![OctreeNode::new(false)];
+    // nodes which have children, by level
+    let mut reducible: Vec<Vec<usize>> = vec![Vec::new(); OCTREE_DEPTH];
+    reducible[0].push(0);
+    let mut leaves = 0;
+
+    for (color, count) in &histogram {
+        let count = *count as u64;
+        let mut node = 0;
+
+        for level in 0..=OCTREE_DEPTH {
+            nodes[node].red += color.0 as u64 * count;
+            nodes[node].green += color.1 as u64 * count;
+            nodes[node].blue += color.2 as u64 * count;
+            nodes[node].count += count;
+
+            if level == OCTREE_DEPTH {
+                break;
+            }
+
+            let shift = 7 - level;
+            let child_index = (((color.0 >> shift) & 1) << 2 | ((color.1 >> shift) & 1) << 1 | ((color.2 >> shift) & 1)) as usize;
+            node = match nodes[node].children[child_index] {
+                Some(child) => child,
+                None => {
+                    let is_leaf = level + 1 == OCTREE_DEPTH;
+                    nodes.push(OctreeNode::new(is_leaf));
+                    let child = nodes.len() - 1;
+                    nodes[node].children[child_index] = Some(child);
+
+                    if is_leaf {
+                        leaves += 1;
+                    } else {
+                        reducible[level + 1].push(child);
+                    }
+
+                    child
+                }
+            };
+        }
+    }
+
+    for level in (0..OCTREE_DEPTH).rev() {
+        if leaves <= max_colors {
+            break;
+        }
+
+        // merging a node does not change counts of other nodes on the same level, so the order stays valid
+        let mut level_nodes = reducible[level].clone();
+        level_nodes.sort_by_key(|node| (nodes[*node].count, *node));
+
+        for node in level_nodes {
+            if leaves <= max_colors {
+                break;
+            }
+
+            let children = nodes[node].children.iter().filter(|child| child.is_some()).count();
+            nodes[node].children = [None; 8];
+            nodes[node].is_leaf = true;
+            leaves = leaves + 1 - children;
+        }
+    }
+
+    let mut palette = Vec::new();
+    collect_leaves(&nodes, 0, &mut palette);
+    palette
+}
+
+fn collect_leaves(nodes: &Vec<OctreeNode>, node: usize, palette: &mut Vec<Pixel>) {
+    let current = &nodes[node];
+    if current.is_leaf {
+        palette.push(Pixel::from_rgb(
+            ((current.red + current.count / 2) / current.count) as u8,
+            ((current.green + current.count / 2) / current.count) as u8,
+            ((current.blue + current.count / 2) / current.count) as u8
+        ));
+        return;
+    }
+
+    for child in current.children.iter().filter_map(|child| *child) {
+        collect_leaves(nodes, child, palette);
+    }
+}
+
+// K-means over unique colors weighted by how often they are used. It starts from median cut palette
+// instead of random centers, so that the same image always gets the same palette.
+pub fn cluster(pixels: &[Pixel], total_clusters: usize, min_error: u32, min_iterations: usize, max_iterations: usize) -> Vec<Pixel> {
+    cluster_histogram(color_histogram(pixels), total_clusters, min_error, min_iterations, max_iterations)
+}
+
+fn cluster_histogram(histogram: Vec<((u8, u8, u8), u32)>, total_clusters: usize, min_error: u32, min_iterations: usize, max_iterations: usize) -> Vec<Pixel> {
+    if histogram.len() <= total_clusters {
+        return histogram_colors(&histogram);
+    }
+
+    let mut centers: Vec<(u8, u8, u8)> = median_cut_histogram(histogram.clone(), total_clusters).iter()
+        .map(|v| (v.red, v.green, v.blue))
+        .collect();
+    let total_clusters = centers.len();
+
+    let mut centers_old;
+
+    // sum of all point coordinates inside cluster:
+    let mut cluster_sums = vec![(0 as u64, 0 as u64, 0 as u64, 0 as u64); total_clusters];
+
+    let mut error = u32::MAX;
+    let mut iteration = 0;
+
+    while (error > min_error && iteration < max_iterations) || iteration < min_iterations {
+        for i in 0..cluster_sums.len() {
+            cluster_sums[i] = (0, 0, 0, 0);
+        }
+
+        for (pixel, count) in &histogram {
+            let mut closest = 0;
+            let mut closest_distance = i32::MAX;
+
+            // Measure the distance to every center
+            for cluster in 0..total_clusters {
+                let distance = distance_u8(*pixel, centers[cluster]);
+
+                if distance < closest_distance {
+                    closest_distance = distance;
+                    closest = cluster;
+                }
+            }
+
+            let count = *count as u64;
+            let prev = cluster_sums[closest];
+            cluster_sums[closest] = (
+                prev.0 + pixel.0 as u64 * count,
+                prev.1 + pixel.1 as u64 * count,
+                prev.2 + pixel.2 as u64 * count,
+                prev.3 + count
+            );
+        }
+
+        centers_old = centers.clone();
+
+        // center without pixels stays where it was
+        for cluster in 0..total_clusters {
+            let entry = cluster_sums[cluster];
+            if entry.3 != 0 {
+                centers[cluster] = (
+                    ((entry.0 + entry.3 / 2) / entry.3) as u8,
+                    ((entry.1 + entry.3 / 2) / entry.3) as u8,
+                    ((entry.2 + entry.3 / 2) / entry.3) as u8
+                );
+            }
+        }
+
+        let mut sum = 0;
+        for center in 0..total_clusters {
+            let prev_center = centers_old[center];
+            let new_center = centers[center];
+            
+            sum += (prev_center.0 as i32 - new_center.0 as i32).pow(2) + 
+                (prev_center.1 as i32 - new_center.1 as i32).pow(2) + 
+                (prev_center.2 as i32 - new_center.2 as i32).pow(2);
+        }
+        error = sum as u32;
+        
+        iteration += 1;
+    }
+
+    centers.iter()
+        .map(|v| Pixel::from_rgb(v.0, v.1, v.2))
+        .collect()
+}
+
+pub fn pixel_to_f64s(pixel: &Pixel) -> (f64, f64, f64) {
+    (pixel.red as f64, pixel.green as f64, pixel.blue as f64)
+}
+
+fn distance_u8(a: (u8, u8, u8), b: (u8, u8, u8)) -> i32 {
+    let drp2 = (a.0 as i32 - b.0 as i32).pow(2);
+    let dgp2 = (a.1 as i32 - b.1 as i32).pow(2);
+    let dbp2 = (a.2 as i32 - b.2 as i32).pow(2);
+
+    let t = (a.0 as i32 + b.0 as i32) / 2;
+
+    2 * drp2 + 4 * dgp2 + 3 * dbp2 + t * (drp2 - dbp2) / 256
+}
+
+pub fn distance(a: (f64, f64, f64), b: (f64, f64, f64)) -> f64 {
+    let drp2 = (a.0 - b.0).powi(2);
+    let dgp2 = (a.1 - b.1).powi(2);
+    let dbp2 = (a.2 - b.2).powi(2);
+
+    let t = (a.0 + b.0) / 2.0;
+
+    (2.0 * drp2 + 4.0 * dgp2 + 3.0 * dbp2 + t * (drp2 - dbp2) / 256.0).sqrt()
+}
+
+// Index of the closest color, ties go to the first one. None when there are no colors.
+pub fn nearest_color(colors: &[Pixel], color: (f64, f64, f64)) -> Option<usize> {
+    colors.iter()
+        .map(|candidate| distance(pixel_to_f64s(candidate), color))
+        .enumerate()
+        .fold(None, |best: Option<(usize, f64)>, (index, dist)| match best {
+            Some((_, best_dist)) if best_dist <= dist => best,
+            _ => Some((index, dist)),
+        })
+        .map(|(index, _)| index)
+}
+
+// Index of the closest color of the color table for every pixel, color table has at most 256 colors.
+pub fn color_indexes(pixels: &[Pixel], colors: &[Pixel]) -> Vec<u8> {
+    let mut index_by_color: HashMap<(u8, u8, u8), u8> = HashMap::new();
+
+    pixels.iter()
+        .map(|pixel| *index_by_color.entry((pixel.red, pixel.green, pixel.blue))
+            .or_insert_with(|| nearest_color(colors, pixel_to_f64s(pixel)).unwrap_or(0) as u8))
+        .collect()
+}